@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE newsletter_issues (
+    newsletter_issue_id uuid NOT NULL,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    published_at TIMESTAMPTZ NULL,
+    PRIMARY KEY (newsletter_issue_id)
+);
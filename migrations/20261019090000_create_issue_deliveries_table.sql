@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE issue_deliveries (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    status TEXT NOT NULL,
+    provider_message_id TEXT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    last_error TEXT NULL,
+    queued_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_attempted_at TIMESTAMPTZ NULL,
+    sent_at TIMESTAMPTZ NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
+
+CREATE INDEX issue_deliveries_status_idx ON issue_deliveries (newsletter_issue_id, status);
@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE TABLE email_events (
+    id uuid NOT NULL,
+    subscriber_id uuid NULL REFERENCES subscriptions (id),
+    email TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    provider_message_id TEXT NULL,
+    payload JSONB NOT NULL,
+    occurred_at TIMESTAMPTZ NOT NULL,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX email_events_subscriber_id_idx ON email_events (subscriber_id);
@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE suppressions (
+    email TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    suppressed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (email)
+);
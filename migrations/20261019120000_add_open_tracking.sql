@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN track_opens BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE issue_deliveries ADD COLUMN tracking_token TEXT NULL UNIQUE;
+ALTER TABLE issue_deliveries ADD COLUMN first_opened_at TIMESTAMPTZ NULL;
+ALTER TABLE issue_deliveries ADD COLUMN last_opened_at TIMESTAMPTZ NULL;
+ALTER TABLE issue_deliveries ADD COLUMN open_count INT NOT NULL DEFAULT 0;
@@ -0,0 +1,17 @@
+ALTER TABLE newsletter_issues ADD COLUMN track_clicks BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE issue_links(
+    link_id BIGSERIAL PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    url TEXT NOT NULL,
+    UNIQUE (newsletter_issue_id, url)
+);
+
+CREATE TABLE link_clicks(
+    link_id BIGINT NOT NULL REFERENCES issue_links (link_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    clicked_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX link_clicks_link_id_idx ON link_clicks (link_id);
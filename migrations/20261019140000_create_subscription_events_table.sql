@@ -0,0 +1,23 @@
+CREATE TABLE subscription_events(
+    id uuid NOT NULL,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    event_type TEXT NOT NULL,
+    newsletter_issue_id uuid NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX subscription_events_type_occurred_at_idx
+    ON subscription_events (event_type, occurred_at);
+CREATE INDEX subscription_events_newsletter_issue_id_idx
+    ON subscription_events (newsletter_issue_id)
+    WHERE newsletter_issue_id IS NOT NULL;
+CREATE INDEX subscriptions_subscribed_at_idx ON subscriptions (subscribed_at);
+CREATE INDEX email_events_type_occurred_at_idx ON email_events (event_type, occurred_at);
+
+-- The confirmation time of existing subscribers was never recorded: assume they confirmed
+-- straight away.
+INSERT INTO subscription_events (id, subscriber_id, event_type, occurred_at)
+SELECT gen_random_uuid(), id, 'confirmed', subscribed_at
+FROM subscriptions
+WHERE status = 'confirmed';
@@ -0,0 +1,12 @@
+ALTER TABLE newsletter_issues ADD COLUMN is_public BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL UNIQUE;
+
+UPDATE newsletter_issues
+SET slug = trim(BOTH '-' FROM lower(regexp_replace(title, '[^a-zA-Z0-9]+', '-', 'g')))
+    || '-' || left(newsletter_issue_id::text, 8);
+
+ALTER TABLE newsletter_issues ALTER COLUMN slug SET NOT NULL;
+
+CREATE INDEX newsletter_issues_public_published_at_idx
+    ON newsletter_issues (published_at DESC)
+    WHERE is_public AND published_at IS NOT NULL;
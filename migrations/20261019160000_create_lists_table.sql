@@ -0,0 +1,54 @@
+CREATE TABLE lists(
+    list_id uuid NOT NULL,
+    name TEXT NOT NULL,
+    -- NULL: use the sender configured for the email client.
+    sender_email TEXT NULL,
+    -- NULL: use the default confirmation email.
+    confirmation_subject TEXT NULL,
+    confirmation_text TEXT NULL,
+    confirmation_html TEXT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (list_id)
+);
+
+-- The list every existing subscriber, token and issue belonged to before lists were introduced.
+INSERT INTO lists (list_id, name)
+VALUES ('00000000-0000-0000-0000-000000000001', 'Newsletter');
+
+CREATE TABLE list_memberships(
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    status TEXT NOT NULL,
+    unsubscribe_token TEXT NOT NULL UNIQUE,
+    subscribed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    confirmed_at TIMESTAMPTZ NULL,
+    unsubscribed_at TIMESTAMPTZ NULL,
+    PRIMARY KEY (list_id, subscriber_id)
+);
+
+CREATE INDEX list_memberships_subscriber_id_idx ON list_memberships (subscriber_id);
+
+INSERT INTO list_memberships (
+    list_id, subscriber_id, status, unsubscribe_token, subscribed_at, confirmed_at,
+    unsubscribed_at
+)
+SELECT
+    '00000000-0000-0000-0000-000000000001',
+    id,
+    status,
+    replace(gen_random_uuid()::text, '-', ''),
+    subscribed_at,
+    CASE WHEN status <> 'pending_confirmation' THEN subscribed_at END,
+    CASE WHEN status = 'unsubscribed' THEN NOW() END
+FROM subscriptions;
+
+ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+UPDATE subscription_tokens SET list_id = '00000000-0000-0000-0000-000000000001';
+ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+
+ALTER TABLE newsletter_issues ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+UPDATE newsletter_issues SET list_id = '00000000-0000-0000-0000-000000000001';
+ALTER TABLE newsletter_issues ALTER COLUMN list_id SET NOT NULL;
+
+ALTER TABLE subscription_events ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+UPDATE subscription_events SET list_id = '00000000-0000-0000-0000-000000000001';
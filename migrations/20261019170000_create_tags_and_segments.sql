@@ -0,0 +1,20 @@
+CREATE TABLE subscriber_tags(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    tag TEXT NOT NULL,
+    tagged_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (subscriber_id, tag)
+);
+
+CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+
+-- A saved filter over the confirmed members of a list, see `SegmentFilter`.
+CREATE TABLE segments(
+    segment_id uuid NOT NULL,
+    name TEXT NOT NULL,
+    filter JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (segment_id)
+);
+
+-- NULL: the issue goes to every confirmed member of its list.
+ALTER TABLE newsletter_issues ADD COLUMN segment_id uuid NULL REFERENCES segments (segment_id);
@@ -0,0 +1,24 @@
+CREATE TABLE custom_fields(
+    -- The name of the form field and template variable holding the value.
+    key TEXT NOT NULL,
+    label TEXT NOT NULL,
+    field_type TEXT NOT NULL,
+    required BOOLEAN NOT NULL DEFAULT FALSE,
+    -- The choices of an `enum` field.
+    options TEXT[] NOT NULL DEFAULT '{}',
+    -- The longest accepted `text` value, in characters.
+    max_length INT NULL,
+    -- The accepted range of a `number` field.
+    min_value DOUBLE PRECISION NULL,
+    max_value DOUBLE PRECISION NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (key)
+);
+
+-- Values are stored in their canonical text form, as produced by `CustomField::parse_value`.
+CREATE TABLE subscriber_field_values(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    field_key TEXT NOT NULL REFERENCES custom_fields (key) ON DELETE CASCADE,
+    value TEXT NOT NULL,
+    PRIMARY KEY (subscriber_id, field_key)
+);
@@ -0,0 +1,9 @@
+-- Lets a subscriber reach their preference center from any email, without logging in.
+ALTER TABLE subscriptions
+    ADD COLUMN preferences_token TEXT NOT NULL UNIQUE
+    DEFAULT replace(gen_random_uuid()::text, '-', '');
+
+-- How often a subscriber is willing to receive issues, see `DeliveryFrequency`.
+ALTER TABLE subscriptions ADD COLUMN frequency TEXT NOT NULL DEFAULT 'every_issue';
+
+CREATE INDEX issue_deliveries_subscriber_id_sent_at_idx ON issue_deliveries (subscriber_id, sent_at);
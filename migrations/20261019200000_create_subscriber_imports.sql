@@ -0,0 +1,17 @@
+-- One row per bulk import, recording how its subscribers were added and on whose word.
+CREATE TABLE subscriber_imports (
+    import_id uuid PRIMARY KEY,
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    -- 'confirmed' or 'double_opt_in', see `ImportMode`.
+    mode TEXT NOT NULL,
+    -- The operator's statement that the imported subscribers consented, for confirmed imports.
+    consent_attestation TEXT NULL,
+    imported_by uuid NOT NULL REFERENCES users (id),
+    imported_count INT NOT NULL DEFAULT 0,
+    duplicate_count INT NOT NULL DEFAULT 0,
+    error_count INT NOT NULL DEFAULT 0,
+    created_at timestamptz NOT NULL DEFAULT NOW(),
+    completed_at timestamptz NULL
+);
+
+ALTER TABLE subscriptions ADD COLUMN import_id uuid NULL REFERENCES subscriber_imports (import_id);
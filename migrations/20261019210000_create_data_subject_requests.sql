@@ -0,0 +1,13 @@
+-- Access and erasure requests made by subscribers themselves, pending proof that they own the
+-- address: the token is only ever sent to it.
+CREATE TABLE data_subject_requests (
+    token TEXT NOT NULL,
+    email TEXT NOT NULL,
+    -- 'access' or 'erasure', see `DataSubjectRequestKind`.
+    kind TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT NOW(),
+    expires_at timestamptz NOT NULL,
+    PRIMARY KEY (token)
+);
+
+CREATE INDEX data_subject_requests_email_idx ON data_subject_requests (email);
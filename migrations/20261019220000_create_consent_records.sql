@@ -0,0 +1,34 @@
+-- The consent statement shown next to the list's sign-up forms, the default one when NULL.
+ALTER TABLE lists ADD COLUMN consent_text TEXT NULL;
+
+-- Proof of how and when each subscriber opted in. Rows are never changed once written.
+CREATE TABLE consent_records (
+    consent_id uuid PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    -- 'subscribe' or 'confirm', see `ConsentAction`.
+    action TEXT NOT NULL,
+    recorded_at timestamptz NOT NULL DEFAULT NOW(),
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    -- Identifies the form or page the subscriber signed up from.
+    source TEXT NULL,
+    consent_text TEXT NOT NULL
+);
+
+CREATE INDEX consent_records_subscriber_id_idx ON consent_records (subscriber_id, recorded_at);
+
+-- Consent records may only go away when the data of their subscriber is erased, which is done in
+-- a transaction setting `newsletter.erasing`.
+CREATE FUNCTION protect_consent_records() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' AND current_setting('newsletter.erasing', true) = 'on' THEN
+        RETURN OLD;
+    END IF;
+    RAISE EXCEPTION 'consent records cannot be changed';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER consent_records_are_immutable
+    BEFORE UPDATE OR DELETE ON consent_records
+    FOR EACH ROW EXECUTE FUNCTION protect_consent_records();
@@ -0,0 +1,15 @@
+-- Every change an admin makes to a subscriber by hand.
+CREATE TABLE admin_audit_log (
+    id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (id),
+    -- See `AuditAction`.
+    action TEXT NOT NULL,
+    -- Not a foreign key: entries outlive the subscribers deleted through them.
+    subscriber_id uuid NOT NULL,
+    -- The list the action was limited to, if any.
+    list_id uuid NULL REFERENCES lists (list_id),
+    occurred_at timestamptz NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX admin_audit_log_subscriber_id_idx ON admin_audit_log (subscriber_id, occurred_at);
+CREATE INDEX admin_audit_log_occurred_at_idx ON admin_audit_log (occurred_at);
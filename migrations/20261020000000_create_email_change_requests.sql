@@ -0,0 +1,14 @@
+-- Requests to move a subscription to another address, pending proof that the subscriber owns it:
+-- the token is only ever sent to the new address.
+CREATE TABLE email_change_requests (
+    token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    new_email TEXT NOT NULL,
+    -- The admin who asked for the change, NULL when the subscriber did.
+    requested_by uuid NULL REFERENCES users (id),
+    created_at timestamptz NOT NULL DEFAULT NOW(),
+    expires_at timestamptz NOT NULL,
+    PRIMARY KEY (token)
+);
+
+CREATE INDEX email_change_requests_subscriber_id_idx ON email_change_requests (subscriber_id);
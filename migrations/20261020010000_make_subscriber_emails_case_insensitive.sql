@@ -0,0 +1,31 @@
+-- Subscribers are told apart by their address regardless of case, as `SubscriberEmail::identity`
+-- does. Addresses that only differ by case or surrounding whitespace have to be merged or erased by
+-- hand first: which one to keep is not ours to guess, so they are reported instead.
+DO $$
+DECLARE
+    duplicates TEXT;
+BEGIN
+    SELECT string_agg(addresses, '; ')
+    INTO duplicates
+    FROM (
+        SELECT string_agg(email || ' (' || id || ')', ', ' ORDER BY subscribed_at) AS addresses
+        FROM subscriptions
+        GROUP BY lower(btrim(email))
+        HAVING COUNT(*) > 1
+    ) d;
+    IF duplicates IS NOT NULL THEN
+        RAISE EXCEPTION 'Some subscribers share an address: %', duplicates
+            USING HINT = 'Merge or erase the duplicates, then run the migration again.';
+    END IF;
+END
+$$;
+
+-- Normalize the addresses stored so far the way `SubscriberEmail::parse` now does, but for the
+-- conversion of international domains to punycode.
+UPDATE subscriptions
+SET email =
+    substring(btrim(email) FROM '^(.*)@') || '@' || lower(substring(btrim(email) FROM '@([^@]*)$'))
+WHERE email LIKE '%@%';
+
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+CREATE UNIQUE INDEX subscriptions_email_key ON subscriptions (lower(email));
@@ -0,0 +1,7 @@
+-- Daily tallies of the subscriptions turned away as automated, by reason, see `BotSignal`.
+CREATE TABLE rejected_signups (
+    day DATE NOT NULL,
+    reason TEXT NOT NULL,
+    count BIGINT NOT NULL,
+    PRIMARY KEY (day, reason)
+);
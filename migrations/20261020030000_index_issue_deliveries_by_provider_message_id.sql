@@ -0,0 +1,2 @@
+-- Email events find the delivery they are about by the ID the provider gave the message.
+CREATE INDEX issue_deliveries_provider_message_id_idx ON issue_deliveries (provider_message_id);
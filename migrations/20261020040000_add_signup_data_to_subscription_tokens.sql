@@ -0,0 +1,4 @@
+-- Tags and custom field values submitted for an address that was already known, kept until the
+-- owner of the address confirms: anyone can submit the form with someone else's address.
+ALTER TABLE subscription_tokens ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE subscription_tokens ADD COLUMN field_values JSONB NOT NULL DEFAULT '{}';
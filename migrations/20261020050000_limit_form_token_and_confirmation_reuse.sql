@@ -0,0 +1,11 @@
+-- Form tokens already used to subscribe, so that each is only used once, see `BotProtection`.
+CREATE TABLE used_form_tokens (
+    nonce uuid NOT NULL,
+    issued_at timestamptz NOT NULL,
+    PRIMARY KEY (nonce)
+);
+
+CREATE INDEX used_form_tokens_issued_at_idx ON used_form_tokens (issued_at);
+
+-- When each confirmation email was sent, to limit how many an address gets.
+ALTER TABLE subscription_tokens ADD COLUMN created_at timestamptz NOT NULL DEFAULT NOW();
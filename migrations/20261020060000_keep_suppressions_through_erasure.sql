@@ -0,0 +1,15 @@
+-- Suppressions are looked up by a hash of the address, which outlives the erasure of the address.
+CREATE FUNCTION hash_email(email TEXT) RETURNS TEXT
+LANGUAGE sql IMMUTABLE STRICT
+AS $$ SELECT encode(sha256(convert_to(lower(email), 'UTF8')), 'hex') $$;
+
+ALTER TABLE suppressions ADD COLUMN email_hash TEXT NULL;
+UPDATE suppressions SET email_hash = hash_email(email);
+ALTER TABLE suppressions ALTER COLUMN email_hash SET NOT NULL;
+ALTER TABLE suppressions DROP CONSTRAINT suppressions_pkey;
+ALTER TABLE suppressions ADD PRIMARY KEY (email_hash);
+-- NULL once the address has been erased.
+ALTER TABLE suppressions ALTER COLUMN email DROP NOT NULL;
+
+-- NULL for the erasure of an address no subscriber used.
+ALTER TABLE admin_audit_log ALTER COLUMN subscriber_id DROP NOT NULL;
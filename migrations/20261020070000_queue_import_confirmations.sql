@@ -0,0 +1,8 @@
+-- The confirmation emails of double opt-in imports are sent in the background, see
+-- `import_confirmation`: 'queued', 'sending', then 'sent' or 'failed'. NULL for the tokens whose
+-- email is sent by the request that created them.
+ALTER TABLE subscription_tokens ADD COLUMN send_status TEXT NULL;
+ALTER TABLE subscription_tokens ADD COLUMN send_claimed_at timestamptz NULL;
+
+CREATE INDEX subscription_tokens_send_status_idx ON subscription_tokens (send_status)
+    WHERE send_status IN ('queued', 'sending');
@@ -0,0 +1,8 @@
+-- The confirmation emails of imports are retried like newsletter deliveries when the email
+-- provider cannot be reached, see `send_queue`.
+ALTER TABLE subscription_tokens ADD COLUMN send_attempts INT NOT NULL DEFAULT 0;
+ALTER TABLE subscription_tokens ADD COLUMN send_last_error TEXT NULL;
+
+-- Interrupted sends are settled across all issues before every batch.
+CREATE INDEX issue_deliveries_sending_idx ON issue_deliveries (last_attempted_at)
+    WHERE status = 'sending';
@@ -0,0 +1,2 @@
+-- Imports run with `zero2prod import` are not made by any admin user.
+ALTER TABLE subscriber_imports ALTER COLUMN imported_by DROP NOT NULL;
@@ -0,0 +1,3 @@
+-- Confirmation links are kept once followed, so that following one again still succeeds while
+-- the subscription it confirmed stands.
+ALTER TABLE subscription_tokens ADD COLUMN used_at timestamptz NULL;
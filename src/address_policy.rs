@@ -0,0 +1,253 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::mail_domain::MailDomainCheck;
+
+/// Local parts reaching a role or a machine rather than a person, after RFC 2142.
+const ROLE_LOCAL_PARTS: &[&str] = &[
+    "abuse",
+    "admin",
+    "administrator",
+    "do-not-reply",
+    "donotreply",
+    "hostmaster",
+    "mailer-daemon",
+    "no-reply",
+    "noreply",
+    "postmaster",
+    "root",
+    "webmaster",
+];
+
+/// Decides which addresses may sign up, from their domain and local part, and optionally whether
+/// their domain can receive email at all.
+///
+/// Domain lists are files with a domain per line, `#` starting a comment. They are read again
+/// whenever they change on disk, so they can be updated without a restart. A listed domain also
+/// covers its subdomains.
+///
+/// Clones share the same lists.
+#[derive(Clone, Default)]
+pub struct AddressPolicy {
+    blocked_domains: Option<Arc<DomainList>>,
+    allowed_domains: Option<Arc<DomainList>>,
+    reject_role_addresses: bool,
+    mail_domain_check: Option<MailDomainCheck>,
+}
+
+impl AddressPolicy {
+    /// Fails if a list cannot be read: a typo in a path should not silently let everyone in.
+    pub fn new(
+        blocked_domains_file: Option<PathBuf>,
+        allowed_domains_file: Option<PathBuf>,
+        reject_role_addresses: bool,
+        mail_domain_check: Option<MailDomainCheck>,
+    ) -> std::io::Result<Self> {
+        Ok(Self {
+            blocked_domains: blocked_domains_file
+                .map(DomainList::load)
+                .transpose()?
+                .map(Arc::new),
+            allowed_domains: allowed_domains_file
+                .map(DomainList::load)
+                .transpose()?
+                .map(Arc::new),
+            reject_role_addresses,
+            mail_domain_check,
+        })
+    }
+
+    /// Returns why `email` cannot sign up, if it cannot.
+    pub async fn check(&self, email: &SubscriberEmail) -> Result<(), String> {
+        let domain = email.domain().to_lowercase();
+        if self.reject_role_addresses {
+            let identity = email.identity();
+            let local_part = identity.rsplit_once('@').unwrap_or_default().0;
+            let role = local_part.split('+').next().unwrap_or_default();
+            if ROLE_LOCAL_PARTS.contains(&role) {
+                return Err(format!(
+                    "{}@ addresses cannot subscribe, please use a personal address",
+                    role
+                ));
+            }
+        }
+        if let Some(allowed) = &self.allowed_domains {
+            if !allowed.contains(&domain) {
+                return Err(format!("Addresses at {} cannot subscribe", domain));
+            }
+        }
+        if let Some(blocked) = &self.blocked_domains {
+            if blocked.contains(&domain) {
+                return Err(format!("Addresses at {} cannot subscribe", domain));
+            }
+        }
+        if let Some(mail_domain_check) = &self.mail_domain_check {
+            mail_domain_check.check(&domain).await?;
+        }
+        Ok(())
+    }
+}
+
+struct DomainList {
+    path: PathBuf,
+    loaded: RwLock<LoadedDomains>,
+}
+
+struct LoadedDomains {
+    /// Modification time and length of the file when it was read, to notice when it changes.
+    version: (Option<SystemTime>, u64),
+    domains: HashSet<String>,
+}
+
+impl DomainList {
+    fn load(path: PathBuf) -> std::io::Result<Self> {
+        let loaded = read_domains(&path)?;
+        Ok(Self {
+            path,
+            loaded: RwLock::new(loaded),
+        })
+    }
+
+    /// Whether `domain` or one of its parents is listed.
+    fn contains(&self, domain: &str) -> bool {
+        self.refresh();
+        let loaded = self.loaded.read().unwrap();
+        let mut domain = domain;
+        loop {
+            if loaded.domains.contains(domain) {
+                return true;
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Reads the file again if it changed. The previous domains stay in use if it cannot be read.
+    fn refresh(&self) {
+        let version = match std::fs::metadata(&self.path) {
+            Ok(metadata) => (metadata.modified().ok(), metadata.len()),
+            Err(e) => {
+                tracing::error!(error.cause_chain = ?e, path = ?self.path, "Failed to check the domain list");
+                return;
+            }
+        };
+        if self.loaded.read().unwrap().version == version {
+            return;
+        }
+        match read_domains(&self.path) {
+            Ok(loaded) => {
+                tracing::info!(path = ?self.path, domains = loaded.domains.len(), "Reloaded the domain list");
+                *self.loaded.write().unwrap() = loaded;
+            }
+            Err(e) => {
+                tracing::error!(error.cause_chain = ?e, path = ?self.path, "Failed to reload the domain list");
+            }
+        }
+    }
+}
+
+fn read_domains(path: &Path) -> std::io::Result<LoadedDomains> {
+    let metadata = std::fs::metadata(path)?;
+    let contents = std::fs::read_to_string(path)?;
+    Ok(LoadedDomains {
+        version: (metadata.modified().ok(), metadata.len()),
+        domains: parse_domains(&contents),
+    })
+}
+
+/// Domains are compared the way `SubscriberEmail` stores them: lowercase, in punycode.
+fn parse_domains(contents: &str) -> HashSet<String> {
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|domain| !domain.is_empty())
+        .filter_map(|domain| idna::domain_to_ascii(domain.trim_start_matches("*.")).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use claims::{assert_err, assert_ok};
+    use uuid::Uuid;
+
+    use crate::address_policy::{parse_domains, AddressPolicy};
+    use crate::domain::subscriber_email::SubscriberEmail;
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    fn domain_file(contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("domains-{}.txt", Uuid::new_v4()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_ignored() {
+        let domains = parse_domains("# Throwaway\nMailinator.com  # and friends\n\n*.Bücher.de\n");
+        assert_eq!(domains.len(), 2);
+        assert!(domains.contains("mailinator.com"));
+        assert!(domains.contains("xn--bcher-kva.de"));
+    }
+
+    #[tokio::test]
+    async fn blocked_domains_and_their_subdomains_are_rejected() {
+        let path = domain_file("mailinator.com\n");
+        let policy = AddressPolicy::new(Some(path), None, false, None).unwrap();
+
+        assert_err!(policy.check(&email("ursula@mailinator.com")).await);
+        assert_err!(policy.check(&email("ursula@eu.Mailinator.com")).await);
+        assert_ok!(policy.check(&email("ursula@notmailinator.com")).await);
+    }
+
+    #[tokio::test]
+    async fn only_allowed_domains_are_accepted_when_there_is_an_allowlist() {
+        let path = domain_file("example.com\n");
+        let policy = AddressPolicy::new(None, Some(path), false, None).unwrap();
+
+        assert_ok!(policy.check(&email("ursula@example.com")).await);
+        assert_err!(policy.check(&email("ursula@example.org")).await);
+    }
+
+    #[tokio::test]
+    async fn role_addresses_are_rejected_when_asked_to() {
+        let policy = AddressPolicy::new(None, None, true, None).unwrap();
+
+        assert_err!(policy.check(&email("NoReply@example.com")).await);
+        assert_err!(policy.check(&email("postmaster+list@example.com")).await);
+        assert_ok!(policy.check(&email("ursula@example.com")).await);
+        assert_ok!(
+            AddressPolicy::default()
+                .check(&email("postmaster@example.com"))
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn lists_are_read_again_when_they_change() {
+        let path = domain_file("mailinator.com\n");
+        let policy = AddressPolicy::new(Some(path.clone()), None, false, None).unwrap();
+        assert_ok!(policy.check(&email("ursula@example.com")).await);
+
+        std::fs::write(&path, "mailinator.com\nexample.com\n").unwrap();
+        assert_err!(policy.check(&email("ursula@example.com")).await);
+
+        // A list that went missing keeps the last domains read.
+        std::fs::remove_file(&path).unwrap();
+        assert_err!(policy.check(&email("ursula@example.com")).await);
+    }
+
+    #[test]
+    fn a_missing_list_is_an_error() {
+        let path = std::env::temp_dir().join(format!("domains-{}.txt", Uuid::new_v4()));
+        assert!(AddressPolicy::new(Some(path), None, false, None).is_err());
+    }
+}
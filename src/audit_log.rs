@@ -0,0 +1,89 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+/// A change an admin made to a subscriber by hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Confirm,
+    Unsubscribe,
+    ResendConfirmation,
+    /// Erased what is stored about the subscriber or an address, see `data_subject::erase_data`.
+    Delete,
+    /// Sent a link moving the subscription to another address, see `email_change`.
+    RequestEmailChange,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Confirm => "confirm",
+            AuditAction::Unsubscribe => "unsubscribe",
+            AuditAction::ResendConfirmation => "resend_confirmation",
+            AuditAction::Delete => "delete",
+            AuditAction::RequestEmailChange => "request_email_change",
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AuditEntry {
+    pub user_id: Uuid,
+    pub username: String,
+    pub action: String,
+    /// `None` for the erasure of an address no subscriber used.
+    pub subscriber_id: Option<Uuid>,
+    pub list_id: Option<Uuid>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Record admin action", skip(executor))]
+pub async fn record_admin_action(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    action: AuditAction,
+    subscriber_id: Option<Uuid>,
+    list_id: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO admin_audit_log (id, user_id, action, subscriber_id, list_id)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        action.as_str(),
+        subscriber_id,
+        list_id,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Returns a page of the audit log, most recent first, optionally only about one subscriber.
+#[tracing::instrument(name = "Get audit log", skip(executor))]
+pub async fn get_audit_entries(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Option<Uuid>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<AuditEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        AuditEntry,
+        r#"
+        SELECT a.user_id, u.username, a.action, a.subscriber_id, a.list_id, a.occurred_at
+        FROM admin_audit_log a
+        JOIN users u ON u.id = a.user_id
+        WHERE $1::uuid IS NULL OR a.subscriber_id = $1
+        ORDER BY a.occurred_at DESC, a.id
+        LIMIT $2 OFFSET $3
+        "#,
+        subscriber_id,
+        limit,
+        offset,
+    )
+    .fetch_all(executor)
+    .await
+}
@@ -1,5 +1,7 @@
+use actix_http::header::HeaderMap;
 use anyhow::Context;
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use base64::{engine::general_purpose, Engine};
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 
@@ -84,3 +86,35 @@ pub async fn get_credentials(
 
     Ok(row)
 }
+
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing.")?
+        .to_str()
+        .context("The 'Authorization' header was not valid UTF-8 string.")?;
+
+    let base64_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = general_purpose::STANDARD
+        .decode(base64_segment)
+        .context("Failed to base64 decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credentials were not valid UTF-8 encoded.")?;
+
+    let mut credentials = decoded_credentials.splitn(2, ':');
+    let username = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' authorization"))?
+        .to_string();
+    let password = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' authorization"))?
+        .to_string();
+
+    Ok(Credentials {
+        username,
+        password: Secret::new(password),
+    })
+}
@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, PgExecutor};
+use uuid::Uuid;
+
+/// How long a form token stays usable, so that they cannot be stockpiled.
+const FORM_TOKEN_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Why a submission of the subscription form looks automated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotSignal {
+    /// The field hidden from people was filled in.
+    Honeypot,
+    /// The form token is missing, was not issued by us or expired.
+    InvalidFormToken,
+    /// The form was submitted faster than a person could fill it in.
+    TooFast,
+    /// The proof of work is missing or does not hold.
+    InvalidProofOfWork,
+    /// The form token was already used to subscribe.
+    ReusedFormToken,
+}
+
+impl BotSignal {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BotSignal::Honeypot => "honeypot",
+            BotSignal::InvalidFormToken => "invalid_form_token",
+            BotSignal::TooFast => "too_fast",
+            BotSignal::InvalidProofOfWork => "invalid_proof_of_work",
+            BotSignal::ReusedFormToken => "reused_form_token",
+        }
+    }
+}
+
+/// Tells people filling in the subscription form from bots using it to send confirmation emails
+/// to arbitrary addresses. Each defense is optional:
+/// - a honeypot field, hidden from people, that bots fill in;
+/// - a form token signed with the time it was issued at, which cannot be sent back too quickly;
+/// - a proof of work: a nonce such that the SHA-256 of `{form_token}:{email}:{nonce}` starts with
+///   enough zero bits, cheap for a single subscription but not for thousands.
+///
+/// Forms get their token, and the number of zero bits, from `GET /subscriptions/challenge`.
+#[derive(Clone)]
+pub struct BotProtection {
+    honeypot_field: Option<String>,
+    min_fill_time: Option<Duration>,
+    proof_of_work_bits: Option<u8>,
+    hmac_secret: Secret<String>,
+}
+
+/// A valid form token, to be claimed with [`claim_form_token`] when the subscription goes ahead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormToken {
+    nonce: Uuid,
+    issued_at: DateTime<Utc>,
+}
+
+/// What forms need to send back.
+#[derive(Debug, serde::Serialize)]
+pub struct FormChallenge {
+    pub form_token: String,
+    /// Zero bits the proof of work must start with, if one is needed.
+    pub proof_of_work_bits: Option<u8>,
+}
+
+impl BotProtection {
+    pub fn new(
+        honeypot_field: Option<String>,
+        min_fill_time: Option<Duration>,
+        proof_of_work_bits: Option<u8>,
+        hmac_secret: Secret<String>,
+    ) -> Self {
+        Self {
+            honeypot_field,
+            min_fill_time,
+            proof_of_work_bits: proof_of_work_bits.filter(|bits| *bits > 0),
+            hmac_secret,
+        }
+    }
+
+    /// The field of the subscription form that people leave empty, if there is one.
+    pub fn honeypot_field(&self) -> Option<&str> {
+        self.honeypot_field.as_deref()
+    }
+
+    pub fn challenge(&self, now: DateTime<Utc>) -> FormChallenge {
+        let payload = format!("{}.{}", now.timestamp_millis(), Uuid::new_v4().simple());
+        let signature = hex::encode(self.mac(&payload).finalize().into_bytes());
+        FormChallenge {
+            form_token: format!("{}.{}", payload, signature),
+            proof_of_work_bits: self.proof_of_work_bits,
+        }
+    }
+
+    /// Checks a submission of the form: its `email`, the token and proof of work it carries and
+    /// the fields no other check knows about, which hold the honeypot.
+    ///
+    /// Returns the form token when one is required: it still has to be claimed, as a token is
+    /// only good for a single subscription.
+    pub fn check(
+        &self,
+        email: &str,
+        form_token: Option<&str>,
+        proof_of_work: Option<&str>,
+        fields: &HashMap<String, String>,
+        now: DateTime<Utc>,
+    ) -> Result<Option<FormToken>, BotSignal> {
+        let Some(verified) = self.check_own_form(form_token, fields, now)? else {
+            return Ok(None);
+        };
+        if let Some(bits) = self.proof_of_work_bits {
+            let nonce = proof_of_work.ok_or(BotSignal::InvalidProofOfWork)?;
+            if !is_proof_of_work(form_token.unwrap_or_default(), email, nonce, bits) {
+                return Err(BotSignal::InvalidProofOfWork);
+            }
+        }
+        Ok(Some(verified))
+    }
+
+    /// Checks a submission of a form the application serves itself, such as the privacy request
+    /// page, which embeds its form token: the honeypot, the token and how fast the form was
+    /// filled in. There is no proof of work, which would take a script to compute.
+    pub fn check_own_form(
+        &self,
+        form_token: Option<&str>,
+        fields: &HashMap<String, String>,
+        now: DateTime<Utc>,
+    ) -> Result<Option<FormToken>, BotSignal> {
+        if let Some(honeypot_field) = &self.honeypot_field {
+            if fields
+                .get(honeypot_field)
+                .is_some_and(|value| !value.trim().is_empty())
+            {
+                return Err(BotSignal::Honeypot);
+            }
+        }
+        if self.min_fill_time.is_none() && self.proof_of_work_bits.is_none() {
+            return Ok(None);
+        }
+
+        let form_token = form_token.ok_or(BotSignal::InvalidFormToken)?;
+        let verified = self
+            .verify_form_token(form_token)
+            .ok_or(BotSignal::InvalidFormToken)?;
+        let age = (now - verified.issued_at).to_std().unwrap_or_default();
+        if age > FORM_TOKEN_LIFETIME {
+            return Err(BotSignal::InvalidFormToken);
+        }
+        if self.min_fill_time.is_some_and(|min| age < min) {
+            return Err(BotSignal::TooFast);
+        }
+        Ok(Some(verified))
+    }
+
+    /// Returns the nonce of a form token and when it was issued, or `None` if it was not signed
+    /// by us.
+    fn verify_form_token(&self, token: &str) -> Option<FormToken> {
+        let (payload, signature) = token.rsplit_once('.')?;
+        let signature = hex::decode(signature).ok()?;
+        self.mac(payload).verify_slice(&signature).ok()?;
+
+        let (issued_at, nonce) = payload.split_once('.')?;
+        Some(FormToken {
+            nonce: Uuid::parse_str(nonce).ok()?,
+            issued_at: DateTime::from_timestamp_millis(issued_at.parse().ok()?)?,
+        })
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.hmac_secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        // Keeps form tokens from being mistaken for anything else signed with the same secret.
+        mac.update(b"form_token:");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+/// Whether the SHA-256 of `{form_token}:{email}:{nonce}` starts with `bits` zero bits.
+pub fn is_proof_of_work(form_token: &str, email: &str, nonce: &str, bits: u8) -> bool {
+    let hash = Sha256::digest(format!("{}:{}:{}", form_token, email, nonce));
+    let mut remaining = u32::from(bits);
+    for byte in hash {
+        if remaining == 0 {
+            return true;
+        }
+        if remaining < 8 {
+            return byte.leading_zeros() >= remaining;
+        }
+        if byte != 0 {
+            return false;
+        }
+        remaining -= 8;
+    }
+    remaining == 0
+}
+
+/// Records the use of a form token, returning `false` if it had already been used.
+///
+/// Tokens past their lifetime are forgotten along the way: they are turned away as expired.
+#[tracing::instrument(name = "Claim form token", skip(executor))]
+pub async fn claim_form_token(
+    executor: &mut PgConnection,
+    form_token: FormToken,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM used_form_tokens WHERE issued_at < $1",
+        Utc::now() - FORM_TOKEN_LIFETIME,
+    )
+    .execute(&mut *executor)
+    .await?;
+    let claimed = sqlx::query!(
+        r#"
+        INSERT INTO used_form_tokens (nonce, issued_at)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        form_token.nonce,
+        form_token.issued_at,
+    )
+    .execute(executor)
+    .await?;
+    Ok(claimed.rows_affected() > 0)
+}
+
+/// Counts a turned away submission in the day's tally for its reason.
+#[tracing::instrument(name = "Record rejected signup", skip(executor))]
+pub async fn record_rejected_signup(
+    executor: impl PgExecutor<'_>,
+    signal: BotSignal,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO rejected_signups (day, reason, count)
+        VALUES ((NOW() AT TIME ZONE 'UTC')::date, $1, 1)
+        ON CONFLICT (day, reason) DO UPDATE SET count = rejected_signups.count + 1
+        "#,
+        signal.as_str(),
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use claims::assert_ok;
+    use secrecy::Secret;
+
+    use crate::bot_protection::{is_proof_of_work, BotProtection, BotSignal};
+
+    const EMAIL: &str = "ursula@example.com";
+
+    fn protection(min_fill_seconds: Option<u64>, proof_of_work_bits: Option<u8>) -> BotProtection {
+        BotProtection::new(
+            Some("website".into()),
+            min_fill_seconds.map(Duration::from_secs),
+            proof_of_work_bits,
+            Secret::new("secret".into()),
+        )
+    }
+
+    /// Finds a nonce proving work for `EMAIL`, but not for `other_email`.
+    fn solve(form_token: &str, bits: u8, other_email: &str) -> String {
+        (0u64..)
+            .map(|nonce| nonce.to_string())
+            .find(|nonce| {
+                is_proof_of_work(form_token, EMAIL, nonce, bits)
+                    && !is_proof_of_work(form_token, other_email, nonce, bits)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn a_filled_in_honeypot_gives_bots_away() {
+        let protection = protection(None, None);
+        let filled = HashMap::from([("website".to_string(), "http://spam".to_string())]);
+        let empty = HashMap::from([("website".to_string(), " ".to_string())]);
+
+        let check = |fields| protection.check(EMAIL, None, None, fields, Utc::now());
+        assert_eq!(check(&filled), Err(BotSignal::Honeypot));
+        assert_ok!(check(&empty));
+        assert_ok!(check(&HashMap::new()));
+    }
+
+    #[test]
+    fn forms_cannot_be_submitted_too_quickly() {
+        let protection = protection(Some(3), None);
+        let issued_at = Utc::now();
+        let token = protection.challenge(issued_at).form_token;
+        let check = |token, now| protection.check(EMAIL, token, None, &HashMap::new(), now);
+
+        assert_eq!(
+            check(Some(&token), issued_at + chrono::Duration::seconds(1)),
+            Err(BotSignal::TooFast)
+        );
+        assert_ok!(check(
+            Some(&token),
+            issued_at + chrono::Duration::seconds(3)
+        ));
+        assert_eq!(
+            check(Some(&token), issued_at + chrono::Duration::days(2)),
+            Err(BotSignal::InvalidFormToken)
+        );
+        assert_eq!(check(None, issued_at), Err(BotSignal::InvalidFormToken));
+    }
+
+    #[test]
+    fn form_tokens_must_be_signed_by_us() {
+        let protection = protection(Some(0), None);
+        let token = protection.challenge(Utc::now()).form_token;
+        let (payload, _) = token.rsplit_once('.').unwrap();
+        let forged = format!("{}.{}", payload, hex::encode([0u8; 32]));
+
+        let check = |token| protection.check(EMAIL, Some(token), None, &HashMap::new(), Utc::now());
+        assert_ok!(check(&token));
+        assert_eq!(check(&forged), Err(BotSignal::InvalidFormToken));
+        assert_eq!(check("garbage"), Err(BotSignal::InvalidFormToken));
+    }
+
+    #[test]
+    fn proofs_of_work_are_bound_to_the_token_and_address() {
+        let protection = protection(None, Some(8));
+        let challenge = protection.challenge(Utc::now());
+        assert_eq!(challenge.proof_of_work_bits, Some(8));
+        let token = challenge.form_token;
+        let nonce = solve(&token, 8, "victim@example.com");
+
+        let check = |email, nonce| {
+            protection.check(email, Some(&token), nonce, &HashMap::new(), Utc::now())
+        };
+        assert_ok!(check(EMAIL, Some(&nonce)));
+        assert_eq!(check(EMAIL, None), Err(BotSignal::InvalidProofOfWork));
+        // The same work does not hold for another victim.
+        assert_eq!(
+            check("victim@example.com", Some(&nonce)),
+            Err(BotSignal::InvalidProofOfWork)
+        );
+    }
+
+    #[test]
+    fn zero_bits_is_always_a_proof_of_work() {
+        assert!(is_proof_of_work("token", EMAIL, "", 0));
+        assert!((0..1000).any(|nonce| is_proof_of_work("token", EMAIL, &nonce.to_string(), 4)));
+        assert!(!(0..10).any(|nonce| is_proof_of_work("token", EMAIL, &nonce.to_string(), 64)));
+    }
+}
@@ -0,0 +1,118 @@
+use std::io::{Read, Write};
+
+use anyhow::Context;
+use uuid::Uuid;
+
+use crate::configuration::get_configuration;
+use crate::segments::get_segment;
+use crate::startup::init_db;
+use crate::subscriber_export::{export_subscribers, ExportFormat, ExportQuery};
+use crate::subscriber_import::{ImportMode, Importer};
+
+pub const USAGE: &str = "\
+Usage:
+    zero2prod                  Run the server
+    zero2prod export [--format csv|ndjson] [--list <list_id>] [--segment <segment_id>]
+                               Write subscribers to standard output
+    zero2prod import --mode confirmed|double_opt_in [--consent <text>] [--list <list_id>]
+                     [--columns <column:target,...>]
+                               Import subscribers from a CSV file read from standard input";
+
+/// `zero2prod export`: the same export as `GET /admin/subscribers/export`, for operators with
+/// access to the database.
+pub async fn export(mut args: impl Iterator<Item = String>) -> Result<(), anyhow::Error> {
+    let mut query = ExportQuery::default();
+    let mut segment_id = None;
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .with_context(|| format!("{} expects a value\n\n{}", arg, USAGE))?;
+        match arg.as_str() {
+            "--format" => query.format = ExportFormat::parse(&value).map_err(anyhow::Error::msg)?,
+            "--list" => query.list_id = Some(parse_id(&value)?),
+            "--segment" => segment_id = Some(parse_id(&value)?),
+            _ => anyhow::bail!("Unknown option {}\n\n{}", arg, USAGE),
+        }
+    }
+
+    let config = get_configuration().context("Failed to read configuration")?;
+    let pool = init_db(&config.db);
+    if let Some(segment_id) = segment_id {
+        let segment = get_segment(&pool, segment_id)
+            .await
+            .context("Failed to retrieve the segment.")?
+            .context("There is no segment with the given identifier")?;
+        query.segment = Some(segment.filter);
+    }
+    let mut stdout = std::io::stdout().lock();
+    let mut result = Ok(());
+    export_subscribers(&pool, &query, |chunk| {
+        result = stdout.write_all(&chunk);
+        std::future::ready(result.is_ok())
+    })
+    .await?;
+    result.context("Failed to write the export.")?;
+    stdout.flush().context("Failed to write the export.")
+}
+
+/// `zero2prod import`: the same import as `POST /admin/subscribers/import`, for operators with
+/// access to the database. The confirmation emails of a double opt-in import are sent by the
+/// running application.
+pub async fn import(mut args: impl Iterator<Item = String>) -> Result<(), anyhow::Error> {
+    let mut mode = None;
+    let mut consent = None;
+    let mut list_id = None;
+    let mut columns = String::new();
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .with_context(|| format!("{} expects a value\n\n{}", arg, USAGE))?;
+        match arg.as_str() {
+            "--mode" => mode = Some(ImportMode::parse(&value).map_err(anyhow::Error::msg)?),
+            "--consent" => consent = Some(value),
+            "--list" => list_id = Some(parse_id(&value)?),
+            "--columns" => columns = value,
+            _ => anyhow::bail!("Unknown option {}\n\n{}", arg, USAGE),
+        }
+    }
+    let mode = mode.with_context(|| format!("--mode is required\n\n{}", USAGE))?;
+
+    let config = get_configuration().context("Failed to read configuration")?;
+    let pool = init_db(&config.db);
+    let address_policy = config
+        .signup
+        .address_policy()
+        .context("Failed to load the signup domain lists")?;
+    let mut importer = Importer::start(
+        &pool,
+        &address_policy,
+        mode,
+        consent,
+        list_id,
+        columns,
+        None,
+    )
+    .await?;
+    let mut stdin = std::io::stdin().lock();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = stdin
+            .read(&mut buffer)
+            .context("Failed to read the file.")?;
+        if read == 0 {
+            break;
+        }
+        importer.push(&buffer[..read]).await?;
+    }
+    let report = importer.finish().await?;
+
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, &report).context("Failed to write the report.")?;
+    writeln!(stdout).context("Failed to write the report.")
+}
+
+fn parse_id(value: &str) -> Result<Uuid, anyhow::Error> {
+    value
+        .parse()
+        .with_context(|| format!("{} is not a valid identifier", value))
+}
@@ -0,0 +1,81 @@
+use std::net::IpAddr;
+
+use actix_web::web::Data;
+use actix_web::HttpRequest;
+
+/// The reverse proxies in front of the application, see `ApplicationSettings::trusted_proxies`.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+/// The address of the client that sent `request`.
+///
+/// Anyone can send an `X-Forwarded-For` header, so it is only read when the request comes from
+/// one of the [`TrustedProxies`], and only as far back as the first address that is not one of
+/// them: that is the address the nearest trusted proxy saw.
+pub fn client_ip(request: &HttpRequest) -> Option<IpAddr> {
+    let peer = request.peer_addr()?.ip();
+    let Some(proxies) = request.app_data::<Data<TrustedProxies>>() else {
+        return Some(peer);
+    };
+    if !proxies.0.contains(&peer) {
+        return Some(peer);
+    }
+    let mut client = peer;
+    let forwarded_for = request
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect::<Vec<_>>();
+    for hop in forwarded_for.into_iter().rev() {
+        let Ok(hop) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = hop;
+        if !proxies.0.contains(&hop) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use actix_web::test::TestRequest;
+    use actix_web::web::Data;
+
+    use crate::client_ip::{client_ip, TrustedProxies};
+
+    fn request(peer: &str, forwarded_for: &str) -> TestRequest {
+        TestRequest::default()
+            .peer_addr(peer.parse::<SocketAddr>().unwrap())
+            .insert_header(("X-Forwarded-For", forwarded_for))
+            .app_data(Data::new(TrustedProxies(vec!["10.0.0.1".parse().unwrap()])))
+    }
+
+    #[test]
+    fn forwarded_addresses_are_ignored_from_untrusted_peers() {
+        let request = request("192.0.2.1:4000", "203.0.113.7").to_http_request();
+
+        assert_eq!(client_ip(&request), Some("192.0.2.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn the_address_seen_by_a_trusted_proxy_is_used() {
+        let request = request("10.0.0.1:4000", "198.51.100.1, 203.0.113.7").to_http_request();
+
+        assert_eq!(client_ip(&request), Some("203.0.113.7".parse().unwrap()));
+    }
+
+    #[test]
+    fn forwarded_addresses_are_ignored_without_trusted_proxies() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .to_http_request();
+
+        assert_eq!(client_ip(&request), Some("10.0.0.1".parse().unwrap()));
+    }
+}
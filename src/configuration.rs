@@ -1,17 +1,29 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+
 use secrecy::{ExposeSecret, Secret};
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde_aux::field_attributes::{
+    deserialize_number_from_string, deserialize_option_number_from_string,
+};
 use sqlx::{
     postgres::{PgConnectOptions, PgSslMode},
     ConnectOptions,
 };
 
+use crate::address_policy::AddressPolicy;
+use crate::bot_protection::BotProtection;
 use crate::domain::subscriber_email::SubscriberEmail;
+use crate::mail_domain::MailDomainCheck;
+use crate::rate_limiter::RateLimiter;
 
 #[derive(serde::Deserialize, Debug)]
 pub struct Settings {
     pub db: DatabaseSettings,
     pub app: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    #[serde(default)]
+    pub signup: SignupSettings,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -21,6 +33,10 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// The reverse proxies in front of the application, whose `X-Forwarded-For` header tells the
+    /// address of the client. It is ignored when none is given.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
 enum Environment {
@@ -88,6 +104,15 @@ pub struct EmailClientSettings {
     pub api_key: Secret<String>,
     pub api_secret: Secret<String>,
     pub timeout_ms: u64,
+    /// Upper bound on emails sent per second across the whole process. Unlimited when missing.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub sends_per_second: Option<f64>,
+    /// Extra per-second limits for recipients at specific domains, e.g. `"gmail.com" = 20`.
+    #[serde(default)]
+    pub domain_sends_per_second: HashMap<String, f64>,
+    /// `Basic` credentials the provider must present when posting delivery events to us.
+    pub webhook_username: String,
+    pub webhook_password: Secret<String>,
 }
 
 impl EmailClientSettings {
@@ -98,6 +123,85 @@ impl EmailClientSettings {
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_ms)
     }
+
+    pub fn rate_limiter(&self) -> RateLimiter {
+        RateLimiter::new(self.sends_per_second, self.domain_sends_per_second.clone())
+    }
+}
+
+/// Which addresses may subscribe through the public form.
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct SignupSettings {
+    /// File listing domains whose addresses cannot subscribe, e.g. throwaway ones.
+    pub blocked_domains_file: Option<PathBuf>,
+    /// File listing the only domains whose addresses can subscribe. Any domain when missing.
+    pub allowed_domains_file: Option<PathBuf>,
+    /// Whether to turn away addresses like `noreply@` or `postmaster@`.
+    #[serde(default)]
+    pub reject_role_addresses: bool,
+    /// Checks that the domains of new addresses have mail servers when given.
+    pub dns_check: Option<DnsCheckSettings>,
+    #[serde(default)]
+    pub bot_protection: BotProtectionSettings,
+}
+
+/// Defenses against bots using the subscription form, all off by default, see `BotProtection`.
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct BotProtectionSettings {
+    /// Form field hidden from people, which bots give themselves away by filling in.
+    pub honeypot_field: Option<String>,
+    /// Seconds a person needs at least to fill in the form. Requires a form token when given.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub min_fill_seconds: Option<u64>,
+    /// Zero bits the proof of work must start with. Requires a form token when given.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub proof_of_work_bits: Option<u8>,
+}
+
+impl BotProtectionSettings {
+    pub fn bot_protection(&self, hmac_secret: Secret<String>) -> BotProtection {
+        BotProtection::new(
+            self.honeypot_field.clone(),
+            self.min_fill_seconds.map(std::time::Duration::from_secs),
+            self.proof_of_work_bits,
+            hmac_secret,
+        )
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct DnsCheckSettings {
+    /// DNS server to ask, e.g. `1.1.1.1:53`.
+    pub resolver: SocketAddr,
+    /// How long to wait for an answer before accepting the domain anyway.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_ms: u64,
+    /// How long answers are remembered.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cache_seconds: u64,
+}
+
+impl DnsCheckSettings {
+    pub fn mail_domain_check(&self) -> MailDomainCheck {
+        MailDomainCheck::new(
+            self.resolver,
+            std::time::Duration::from_millis(self.timeout_ms),
+            std::time::Duration::from_secs(self.cache_seconds),
+        )
+    }
+}
+
+impl SignupSettings {
+    pub fn address_policy(&self) -> std::io::Result<AddressPolicy> {
+        AddressPolicy::new(
+            self.blocked_domains_file.clone(),
+            self.allowed_domains_file.clone(),
+            self.reject_role_addresses,
+            self.dns_check
+                .as_ref()
+                .map(DnsCheckSettings::mail_domain_check),
+        )
+    }
 }
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
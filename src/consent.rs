@@ -0,0 +1,167 @@
+use actix_web::http::header;
+use actix_web::HttpRequest;
+use chrono::{DateTime, Utc};
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::client_ip::client_ip;
+
+/// The longest user agent or source kept, longer ones are truncated.
+const MAX_FIELD_LENGTH: usize = 512;
+
+/// The step of the opt-in a consent record proves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsentAction {
+    /// The subscriber submitted a sign-up form.
+    Subscribe,
+    /// The subscriber followed the link of the confirmation email.
+    Confirm,
+    /// The subscriber ticked the list in the preference center, reached from one of our emails.
+    JoinFromPreferences,
+    /// An operator imported the subscriber as confirmed, attesting that they consented elsewhere.
+    Import,
+}
+
+impl ConsentAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Subscribe => "subscribe",
+            Self::Confirm => "confirm",
+            Self::JoinFromPreferences => "join_from_preferences",
+            Self::Import => "import",
+        }
+    }
+}
+
+/// Where a consent was given from, as told by the request giving it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsentContext {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: Option<String>,
+}
+
+impl ConsentContext {
+    /// Reads the client address and user agent of `request`. `source` identifies the form the
+    /// request was sent from, the page it was sent from is used when absent.
+    pub fn from_request(request: &HttpRequest, source: Option<&str>) -> Self {
+        let header = |name| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        let source = source
+            .map(str::trim)
+            .filter(|source| !source.is_empty())
+            .or_else(|| header(header::REFERER));
+        Self {
+            ip_address: client_ip(request).map(|ip| ip.to_string()),
+            user_agent: header(header::USER_AGENT).map(truncate),
+            source: source.map(truncate),
+        }
+    }
+}
+
+fn truncate(value: &str) -> String {
+    value.chars().take(MAX_FIELD_LENGTH).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ConsentRecord {
+    pub list_id: Uuid,
+    pub action: String,
+    pub recorded_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: Option<String>,
+    /// The consent statement exactly as it was shown.
+    pub consent_text: String,
+}
+
+#[tracing::instrument(name = "Record consent", skip(executor, context, consent_text))]
+pub async fn record_consent(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    action: ConsentAction,
+    context: &ConsentContext,
+    consent_text: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_records (
+            consent_id, subscriber_id, list_id, action, ip_address, user_agent, source,
+            consent_text
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        list_id,
+        action.as_str(),
+        context.ip_address,
+        context.user_agent,
+        context.source,
+        consent_text,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Returns the consent records of a subscriber, oldest first.
+#[tracing::instrument(name = "Get consent records", skip(executor))]
+pub async fn get_consent_records(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentRecord,
+        r#"
+        SELECT list_id, action, recorded_at, ip_address, user_agent, source, consent_text
+        FROM consent_records
+        WHERE subscriber_id = $1
+        ORDER BY recorded_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header;
+    use actix_web::test::TestRequest;
+
+    use crate::consent::ConsentContext;
+
+    #[test]
+    fn the_source_defaults_to_the_referring_page() {
+        let request = TestRequest::default()
+            .insert_header((header::USER_AGENT, "Mozilla/5.0"))
+            .insert_header((header::REFERER, "https://example.com/blog"))
+            .peer_addr("203.0.113.7:4000".parse().unwrap())
+            .to_http_request();
+
+        let context = ConsentContext::from_request(&request, None);
+        assert_eq!(context.ip_address.as_deref(), Some("203.0.113.7"));
+        assert_eq!(context.user_agent.as_deref(), Some("Mozilla/5.0"));
+        assert_eq!(context.source.as_deref(), Some("https://example.com/blog"));
+
+        let context = ConsentContext::from_request(&request, Some(" footer-form "));
+        assert_eq!(context.source.as_deref(), Some("footer-form"));
+    }
+
+    #[test]
+    fn long_user_agents_are_truncated() {
+        let request = TestRequest::default()
+            .insert_header((header::USER_AGENT, "a".repeat(2000)))
+            .to_http_request();
+
+        let context = ConsentContext::from_request(&request, None);
+        assert_eq!(context.user_agent.unwrap().len(), 512);
+        assert_eq!(context.source, None);
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::domain::custom_field::{CustomField, FieldType};
+
+#[tracing::instrument(name = "Get custom fields", skip(executor))]
+pub async fn get_custom_fields(
+    executor: impl PgExecutor<'_>,
+) -> Result<Vec<CustomField>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT key, label, field_type, required, options, max_length, min_value, max_value
+        FROM custom_fields
+        ORDER BY created_at, key
+        "#,
+    )
+    .fetch_all(executor)
+    .await?;
+    rows.into_iter()
+        .map(|row| {
+            let field_type = FieldType::parse(&row.field_type).ok_or_else(|| {
+                anyhow::anyhow!("Unknown type {} for the field {}", row.field_type, row.key)
+            })?;
+            Ok(CustomField {
+                key: row.key,
+                label: row.label,
+                field_type,
+                required: row.required,
+                options: row.options,
+                max_length: row.max_length,
+                min: row.min_value,
+                max: row.max_value,
+            })
+        })
+        .collect()
+}
+
+#[tracing::instrument(name = "Store custom field", skip(executor))]
+pub async fn insert_custom_field(
+    executor: impl PgExecutor<'_>,
+    field: &CustomField,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO custom_fields (
+            key, label, field_type, required, options, max_length, min_value, max_value
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        field.key,
+        field.label,
+        field.field_type.as_str(),
+        field.required,
+        &field.options,
+        field.max_length,
+        field.min,
+        field.max,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Stores values already checked with `CustomField::parse_value`, replacing the previous ones.
+#[tracing::instrument(name = "Store custom field values", skip(executor, values))]
+pub async fn set_field_values(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    values: &[(String, String)],
+) -> Result<(), sqlx::Error> {
+    let (keys, values): (Vec<_>, Vec<_>) = values.iter().cloned().unzip();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_field_values (subscriber_id, field_key, value)
+        SELECT $1, field_key, value FROM UNNEST($2::text[], $3::text[]) AS v (field_key, value)
+        ON CONFLICT (subscriber_id, field_key) DO UPDATE SET value = EXCLUDED.value
+        "#,
+        subscriber_id,
+        &keys,
+        &values,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Remove custom field values", skip(executor))]
+pub async fn remove_field_values(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    keys: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM subscriber_field_values WHERE subscriber_id = $1 AND field_key = ANY($2)",
+        subscriber_id,
+        keys,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get custom field values", skip(executor))]
+pub async fn get_field_values(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<BTreeMap<String, String>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT field_key, value FROM subscriber_field_values WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.field_key, row.value))
+        .collect())
+}
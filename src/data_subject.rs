@@ -0,0 +1,535 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgExecutor};
+use uuid::Uuid;
+
+use crate::consent::{get_consent_records, ConsentRecord};
+
+/// What a data subject asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataSubjectRequestKind {
+    /// A copy of everything stored about their address.
+    Access,
+    /// That everything stored about their address is erased.
+    Erasure,
+}
+
+impl DataSubjectRequestKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Access => "access",
+            Self::Erasure => "erasure",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "access" => Some(Self::Access),
+            "erasure" => Some(Self::Erasure),
+            _ => None,
+        }
+    }
+}
+
+/// Everything stored about an email address.
+#[derive(Debug, serde::Serialize)]
+pub struct DataBundle {
+    pub email: String,
+    pub generated_at: DateTime<Utc>,
+    pub subscriber: Option<SubscriberRecord>,
+    /// How the subscriber was added, when they were imported rather than signing up themselves.
+    pub import: Option<ImportRecord>,
+    /// How and when the subscriber opted in.
+    pub consents: Vec<ConsentRecord>,
+    pub memberships: Vec<MembershipRecord>,
+    pub tags: Vec<String>,
+    pub fields: BTreeMap<String, String>,
+    /// Confirmation tokens sent to the address.
+    pub confirmation_tokens: Vec<TokenRecord>,
+    /// Pending requests to move the subscription to another address.
+    pub email_changes: Vec<EmailChangeRecord>,
+    pub deliveries: Vec<DeliveryRecord>,
+    pub clicks: Vec<ClickRecord>,
+    pub subscription_events: Vec<SubscriptionEventRecord>,
+    /// Events reported by the email provider about the address.
+    pub email_events: Vec<EmailEventRecord>,
+    pub suppression: Option<SuppressionRecord>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SubscriberRecord {
+    pub id: Uuid,
+    pub name: String,
+    pub status: String,
+    pub frequency: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub preferences_token: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ImportRecord {
+    pub import_id: Uuid,
+    pub mode: String,
+    pub consent_attestation: Option<String>,
+    pub imported_at: DateTime<Utc>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct MembershipRecord {
+    pub list_id: Uuid,
+    pub list_name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub unsubscribed_at: Option<DateTime<Utc>>,
+    pub unsubscribe_token: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct TokenRecord {
+    pub list_id: Uuid,
+    pub token: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct EmailChangeRecord {
+    pub new_email: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DeliveryRecord {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub status: String,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub open_count: i32,
+    pub first_opened_at: Option<DateTime<Utc>>,
+    pub last_opened_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ClickRecord {
+    pub newsletter_issue_id: Uuid,
+    pub url: String,
+    pub clicked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SubscriptionEventRecord {
+    pub event_type: String,
+    pub list_id: Option<Uuid>,
+    pub newsletter_issue_id: Option<Uuid>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct EmailEventRecord {
+    pub event_type: String,
+    pub occurred_at: DateTime<Utc>,
+    pub payload: serde_json::Value,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SuppressionRecord {
+    pub reason: String,
+    pub suppressed_at: DateTime<Utc>,
+}
+
+impl DataBundle {
+    /// Whether nothing at all is stored about the address.
+    pub fn is_empty(&self) -> bool {
+        self.subscriber.is_none() && self.email_events.is_empty() && self.suppression.is_none()
+    }
+}
+
+/// Gathers everything stored about `email`, for a data subject access request.
+#[tracing::instrument(name = "Collect the data stored about an address", skip_all)]
+pub async fn collect_data(
+    connection: &mut PgConnection,
+    email: &str,
+) -> Result<DataBundle, sqlx::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, name, status, frequency, subscribed_at, preferences_token
+        FROM subscriptions
+        WHERE lower(email) = lower($1)
+        "#,
+        email,
+    )
+    .fetch_optional(&mut *connection)
+    .await?;
+    // No subscriber matches the nil ID, which keeps the queries below simple.
+    let subscriber_id = subscriber.as_ref().map(|s| s.id).unwrap_or_default();
+
+    let import = sqlx::query_as!(
+        ImportRecord,
+        r#"
+        SELECT i.import_id, i.mode, i.consent_attestation, i.created_at AS imported_at
+        FROM subscriber_imports i
+        JOIN subscriptions s ON s.import_id = i.import_id
+        WHERE s.id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut *connection)
+    .await?;
+    let consents = get_consent_records(&mut *connection, subscriber_id).await?;
+    let memberships = sqlx::query_as!(
+        MembershipRecord,
+        r#"
+        SELECT
+            m.list_id, l.name AS list_name, m.status, m.subscribed_at, m.confirmed_at,
+            m.unsubscribed_at, m.unsubscribe_token
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY m.subscribed_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+    let tags = sqlx::query!(
+        "SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag",
+        subscriber_id,
+    )
+    .fetch_all(&mut *connection)
+    .await?
+    .into_iter()
+    .map(|row| row.tag)
+    .collect();
+    let fields = sqlx::query!(
+        "SELECT field_key, value FROM subscriber_field_values WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .fetch_all(&mut *connection)
+    .await?
+    .into_iter()
+    .map(|row| (row.field_key, row.value))
+    .collect();
+    let confirmation_tokens = sqlx::query_as!(
+        TokenRecord,
+        "SELECT list_id, token FROM subscription_tokens WHERE id = $1",
+        subscriber_id,
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+    let email_changes = sqlx::query_as!(
+        EmailChangeRecord,
+        r#"
+        SELECT new_email, created_at, expires_at
+        FROM email_change_requests
+        WHERE subscriber_id = $1
+        ORDER BY created_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+    let deliveries = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT
+            d.newsletter_issue_id, i.title, d.status, d.sent_at, d.open_count,
+            d.first_opened_at, d.last_opened_at
+        FROM issue_deliveries d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE d.subscriber_id = $1
+        ORDER BY d.queued_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+    let clicks = sqlx::query_as!(
+        ClickRecord,
+        r#"
+        SELECT l.newsletter_issue_id, l.url, c.clicked_at
+        FROM link_clicks c
+        JOIN issue_links l ON l.link_id = c.link_id
+        WHERE c.subscriber_id = $1
+        ORDER BY c.clicked_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+    let subscription_events = sqlx::query_as!(
+        SubscriptionEventRecord,
+        r#"
+        SELECT event_type, list_id, newsletter_issue_id, occurred_at
+        FROM subscription_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+    let email_events = sqlx::query_as!(
+        EmailEventRecord,
+        r#"
+        SELECT event_type, occurred_at, payload
+        FROM email_events
+        WHERE lower(email) = lower($1) OR subscriber_id = $2
+        ORDER BY occurred_at
+        "#,
+        email,
+        subscriber_id,
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+    let suppression = sqlx::query_as!(
+        SuppressionRecord,
+        "SELECT reason, suppressed_at FROM suppressions WHERE email_hash = hash_email($1)",
+        email,
+    )
+    .fetch_optional(&mut *connection)
+    .await?;
+
+    Ok(DataBundle {
+        email: email.to_owned(),
+        generated_at: Utc::now(),
+        subscriber,
+        import,
+        consents,
+        memberships,
+        tags,
+        fields,
+        confirmation_tokens,
+        email_changes,
+        deliveries,
+        clicks,
+        subscription_events,
+        email_events,
+        suppression,
+    })
+}
+
+/// What was erased about an address.
+#[derive(Debug)]
+pub struct Erasure {
+    /// The subscriber who used the address, if there was one.
+    pub subscriber_id: Option<Uuid>,
+}
+
+/// Erases everything stored about `email`, returning `None` if there was nothing to erase.
+///
+/// Records that feed aggregate statistics (deliveries, opens, clicks, subscription and provider
+/// events) are kept, but moved to a new anonymous subscriber that shares nothing with the erased
+/// one but its sign-up time, and stripped of anything identifying. A suppression of the address
+/// only keeps the hash of it, so that a bounced or complaining address is never mailed again.
+/// Everything else is deleted.
+#[tracing::instrument(name = "Erase the data stored about an address", skip_all)]
+pub async fn erase_data(
+    transaction: &mut PgConnection,
+    email: &str,
+) -> Result<Option<Erasure>, sqlx::Error> {
+    let subscriber = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE lower(email) = lower($1) FOR UPDATE",
+        email,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let subscriber_id = subscriber.map(|subscriber| subscriber.id);
+    let anonymous_id = match subscriber_id {
+        Some(subscriber_id) => Some(anonymise_subscriber(transaction, subscriber_id).await?),
+        None => None,
+    };
+    let events = sqlx::query!(
+        r#"
+        UPDATE email_events
+        SET email = '', subscriber_id = $2, provider_message_id = NULL, payload = '{}'
+        WHERE lower(email) = lower($1)
+        "#,
+        email,
+        anonymous_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let suppression = sqlx::query!(
+        r#"
+        UPDATE suppressions SET email = NULL
+        WHERE email_hash = hash_email($1) AND email IS NOT NULL
+        "#,
+        email,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM data_subject_requests WHERE lower(email) = lower($1)",
+        email
+    )
+    .execute(&mut *transaction)
+    .await?;
+    if anonymous_id.is_none() && events.rows_affected() == 0 && suppression.rows_affected() == 0 {
+        return Ok(None);
+    }
+    Ok(Some(Erasure { subscriber_id }))
+}
+
+/// Replaces a subscriber by an anonymous one and returns its ID.
+async fn anonymise_subscriber(
+    transaction: &mut PgConnection,
+    subscriber_id: Uuid,
+) -> Result<Uuid, sqlx::Error> {
+    let anonymous_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, status, subscribed_at, import_id)
+        SELECT $2, $3, '', 'erased', subscribed_at, import_id
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        anonymous_id,
+        format!("erased-{}@erased.invalid", anonymous_id.simple()),
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET
+            subscriber_id = $2, provider_message_id = NULL, tracking_token = NULL,
+            last_error = NULL
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+        anonymous_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "UPDATE link_clicks SET subscriber_id = $2 WHERE subscriber_id = $1",
+        subscriber_id,
+        anonymous_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "UPDATE subscription_events SET subscriber_id = $2 WHERE subscriber_id = $1",
+        subscriber_id,
+        anonymous_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE email_events
+        SET email = '', subscriber_id = $2, provider_message_id = NULL, payload = '{}'
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+        anonymous_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM email_change_requests WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM subscriber_tags WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM subscriber_field_values WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM list_memberships WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    // Consent records are otherwise immutable, see the `consent_records_are_immutable` trigger.
+    sqlx::query!("SELECT set_config('newsletter.erasing', 'on', true)")
+        .fetch_one(&mut *transaction)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM consent_records WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!("DELETE FROM subscriptions WHERE id = $1", subscriber_id)
+        .execute(&mut *transaction)
+        .await?;
+    Ok(anonymous_id)
+}
+
+/// Counts the requests made through the self-service flow for `email` in the last day.
+#[tracing::instrument(name = "Count recent data subject requests", skip(executor, email))]
+pub async fn count_recent_requests(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<i64, sqlx::Error> {
+    let recent = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM data_subject_requests
+        WHERE lower(email) = lower($1) AND created_at > NOW() - INTERVAL '1 day'
+        "#,
+        email,
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(recent.count)
+}
+
+/// Records a request made through the self-service flow and returns the token proving it came
+/// from the owner of the address. Tokens are valid for a day.
+#[tracing::instrument(name = "Store data subject request", skip(executor, email))]
+pub async fn create_request(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+    kind: DataSubjectRequestKind,
+) -> Result<String, sqlx::Error> {
+    let request = sqlx::query!(
+        r#"
+        INSERT INTO data_subject_requests (token, email, kind, expires_at)
+        VALUES (replace(gen_random_uuid()::text, '-', ''), $1, $2, NOW() + INTERVAL '1 day')
+        RETURNING token
+        "#,
+        email,
+        kind.as_str(),
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(request.token)
+}
+
+/// Returns the address and kind of a request that has not expired.
+#[tracing::instrument(name = "Get data subject request", skip_all)]
+pub async fn get_request(
+    executor: impl PgExecutor<'_>,
+    token: &str,
+) -> Result<Option<(String, DataSubjectRequestKind)>, sqlx::Error> {
+    let request = sqlx::query!(
+        "SELECT email, kind FROM data_subject_requests WHERE token = $1 AND expires_at > NOW()",
+        token,
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(request.and_then(|request| {
+        DataSubjectRequestKind::parse(&request.kind).map(|kind| (request.email, kind))
+    }))
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+
+/// Template variables a custom field cannot be named after.
+const RESERVED_KEYS: [&str; 8] = [
+    "name",
+    "email",
+    "list_id",
+    "tags",
+    "list_name",
+    "confirmation_link",
+    "unsubscribe_url",
+    "preferences_url",
+];
+const MAX_KEY_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    Text,
+    Number,
+    Bool,
+    Enum,
+    Date,
+}
+
+impl FieldType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FieldType::Text => "text",
+            FieldType::Number => "number",
+            FieldType::Bool => "bool",
+            FieldType::Enum => "enum",
+            FieldType::Date => "date",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "text" => Some(FieldType::Text),
+            "number" => Some(FieldType::Number),
+            "bool" => Some(FieldType::Bool),
+            "enum" => Some(FieldType::Enum),
+            "date" => Some(FieldType::Date),
+            _ => None,
+        }
+    }
+}
+
+/// An admin-defined piece of information collected about subscribers, such as their company.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomField {
+    pub key: String,
+    pub label: String,
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub required: bool,
+    /// The choices of an `enum` field.
+    #[serde(default)]
+    pub options: Vec<String>,
+    /// The longest accepted `text` value, in characters.
+    pub max_length: Option<i32>,
+    /// The smallest accepted `number` value.
+    pub min: Option<f64>,
+    /// The largest accepted `number` value.
+    pub max: Option<f64>,
+}
+
+impl CustomField {
+    /// Checks the definition of a field, as given by an admin.
+    pub fn parse(self) -> Result<Self, String> {
+        let key = self.key.trim().to_owned();
+        let valid_key = !key.is_empty()
+            && key.len() <= MAX_KEY_LENGTH
+            && key.starts_with(|c: char| c.is_ascii_lowercase())
+            && key
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid_key {
+            return Err(format!(
+                "{} is not a valid key: use lowercase letters, digits and underscores",
+                key
+            ));
+        }
+        if RESERVED_KEYS.contains(&key.as_str()) {
+            return Err(format!("{} is reserved and cannot be used as a key", key));
+        }
+        let label = self.label.trim().to_owned();
+        if label.is_empty() {
+            return Err("The label of a field cannot be empty".into());
+        }
+        if self.field_type == FieldType::Enum
+            && (self.options.is_empty() || self.options.iter().any(|o| o.trim().is_empty()))
+        {
+            return Err("An enum field needs non-empty options".into());
+        }
+        if self.field_type != FieldType::Enum && !self.options.is_empty() {
+            return Err("Only enum fields have options".into());
+        }
+        match self.max_length {
+            Some(_) if self.field_type != FieldType::Text => {
+                return Err("Only text fields have a maximum length".into())
+            }
+            Some(max_length) if max_length <= 0 => {
+                return Err("The maximum length must be positive".into())
+            }
+            _ => {}
+        }
+        if self.field_type != FieldType::Number && (self.min.is_some() || self.max.is_some()) {
+            return Err("Only number fields have a range".into());
+        }
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            if min > max {
+                return Err("`min` must not be greater than `max`".into());
+            }
+        }
+        Ok(Self { key, label, ..self })
+    }
+
+    /// Validates a submitted value, returning it in the canonical form it is stored in.
+    pub fn parse_value(&self, value: &str) -> Result<String, String> {
+        let value = value.trim();
+        let invalid = || format!("{} is not a valid value for {}", value, self.label);
+        match self.field_type {
+            FieldType::Text => match self.max_length {
+                Some(max_length) if value.chars().count() > max_length as usize => Err(format!(
+                    "{} cannot be longer than {} characters",
+                    self.label, max_length
+                )),
+                _ => Ok(value.to_owned()),
+            },
+            FieldType::Number => {
+                let number: f64 = value.parse().map_err(|_| invalid())?;
+                if !number.is_finite() {
+                    return Err(invalid());
+                }
+                if self.min.is_some_and(|min| number < min)
+                    || self.max.is_some_and(|max| number > max)
+                {
+                    return Err(format!("{} is out of range for {}", value, self.label));
+                }
+                Ok(number.to_string())
+            }
+            FieldType::Bool => match value.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Ok("true".into()),
+                "false" | "no" | "off" | "0" => Ok("false".into()),
+                _ => Err(invalid()),
+            },
+            FieldType::Enum => self
+                .options
+                .iter()
+                .find(|option| option.as_str() == value)
+                .cloned()
+                .ok_or_else(invalid),
+            FieldType::Date => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(|date| date.to_string())
+                .map_err(|_| invalid()),
+        }
+    }
+}
+
+/// Validates the values submitted for every defined field, returning them by key. Fields left
+/// empty are absent; unknown keys are ignored.
+pub fn parse_field_values(
+    fields: &[CustomField],
+    submitted: &HashMap<String, String>,
+) -> Result<Vec<(String, String)>, String> {
+    let mut values = Vec::new();
+    for field in fields {
+        match submitted.get(&field.key).filter(|v| !v.trim().is_empty()) {
+            Some(value) => values.push((field.key.clone(), field.parse_value(value)?)),
+            None if field.required => return Err(format!("{} is required", field.label)),
+            None => {}
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::domain::custom_field::{parse_field_values, CustomField, FieldType};
+    use claims::{assert_err, assert_ok};
+
+    fn field(field_type: FieldType) -> CustomField {
+        CustomField {
+            key: "field".into(),
+            label: "Field".into(),
+            field_type,
+            required: false,
+            options: vec![],
+            max_length: None,
+            min: None,
+            max: None,
+        }
+    }
+
+    #[test]
+    fn keys_must_be_identifiers() {
+        for key in ["", "Company", "1st", "my-field", "my field"] {
+            let definition = CustomField {
+                key: key.into(),
+                ..field(FieldType::Text)
+            };
+            assert_err!(definition.parse(), "{}", key);
+        }
+        let definition = CustomField {
+            key: "company_size2".into(),
+            ..field(FieldType::Text)
+        };
+        assert_ok!(definition.parse());
+    }
+
+    #[test]
+    fn reserved_keys_are_rejected() {
+        let definition = CustomField {
+            key: "email".into(),
+            ..field(FieldType::Text)
+        };
+        assert_err!(definition.parse());
+    }
+
+    #[test]
+    fn enum_fields_need_options() {
+        assert_err!(field(FieldType::Enum).parse());
+        let definition = CustomField {
+            options: vec!["en".into()],
+            ..field(FieldType::Text)
+        };
+        assert_err!(definition.parse());
+    }
+
+    #[test]
+    fn ranges_only_apply_to_numbers() {
+        let definition = CustomField {
+            min: Some(1.0),
+            ..field(FieldType::Text)
+        };
+        assert_err!(definition.parse());
+        let definition = CustomField {
+            min: Some(2.0),
+            max: Some(1.0),
+            ..field(FieldType::Number)
+        };
+        assert_err!(definition.parse());
+    }
+
+    #[test]
+    fn text_values_respect_the_maximum_length() {
+        let definition = CustomField {
+            max_length: Some(3),
+            ..field(FieldType::Text)
+        };
+        assert_eq!(definition.parse_value(" abc "), Ok("abc".into()));
+        assert_err!(definition.parse_value("abcd"));
+    }
+
+    #[test]
+    fn number_values_are_parsed_and_range_checked() {
+        let definition = CustomField {
+            min: Some(0.0),
+            max: Some(10.0),
+            ..field(FieldType::Number)
+        };
+        assert_eq!(definition.parse_value("7.50"), Ok("7.5".into()));
+        assert_err!(definition.parse_value("11"));
+        assert_err!(definition.parse_value("seven"));
+        assert_err!(definition.parse_value("NaN"));
+    }
+
+    #[test]
+    fn bool_values_are_normalized() {
+        let definition = field(FieldType::Bool);
+        assert_eq!(definition.parse_value("on"), Ok("true".into()));
+        assert_eq!(definition.parse_value("No"), Ok("false".into()));
+        assert_err!(definition.parse_value("maybe"));
+    }
+
+    #[test]
+    fn enum_values_must_be_one_of_the_options() {
+        let definition = CustomField {
+            options: vec!["en".into(), "fr".into()],
+            ..field(FieldType::Enum)
+        };
+        assert_eq!(definition.parse_value("fr"), Ok("fr".into()));
+        assert_err!(definition.parse_value("de"));
+    }
+
+    #[test]
+    fn date_values_must_be_iso_dates() {
+        let definition = field(FieldType::Date);
+        assert_eq!(
+            definition.parse_value("2024-02-29"),
+            Ok("2024-02-29".into())
+        );
+        assert_err!(definition.parse_value("2023-02-29"));
+        assert_err!(definition.parse_value("29/02/2024"));
+    }
+
+    #[test]
+    fn missing_required_values_are_rejected() {
+        let fields = vec![CustomField {
+            required: true,
+            ..field(FieldType::Text)
+        }];
+        let submitted = HashMap::from([("field".to_owned(), " ".to_owned())]);
+        assert_err!(parse_field_values(&fields, &submitted));
+    }
+
+    #[test]
+    fn unknown_and_empty_optional_values_are_skipped() {
+        let fields = vec![field(FieldType::Text)];
+        let submitted = HashMap::from([
+            ("field".to_owned(), "".to_owned()),
+            ("other".to_owned(), "value".to_owned()),
+        ]);
+        assert_eq!(parse_field_values(&fields, &submitted), Ok(vec![]));
+    }
+}
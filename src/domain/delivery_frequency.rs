@@ -0,0 +1,57 @@
+/// How often a subscriber is willing to receive issues. Issues published while a subscriber is
+/// within their interval since the last issue they were sent are skipped for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryFrequency {
+    EveryIssue,
+    Weekly,
+    Monthly,
+}
+
+impl DeliveryFrequency {
+    pub const ALL: [DeliveryFrequency; 3] = [
+        DeliveryFrequency::EveryIssue,
+        DeliveryFrequency::Weekly,
+        DeliveryFrequency::Monthly,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryFrequency::EveryIssue => "every_issue",
+            DeliveryFrequency::Weekly => "weekly",
+            DeliveryFrequency::Monthly => "monthly",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DeliveryFrequency::EveryIssue => "Every issue",
+            DeliveryFrequency::Weekly => "At most one issue a week",
+            DeliveryFrequency::Monthly => "At most one issue a month",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|frequency| frequency.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid frequency", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::delivery_frequency::DeliveryFrequency;
+    use claims::assert_err;
+
+    #[test]
+    fn frequencies_round_trip_through_their_name() {
+        for frequency in DeliveryFrequency::ALL {
+            assert_eq!(DeliveryFrequency::parse(frequency.as_str()), Ok(frequency));
+        }
+    }
+
+    #[test]
+    fn unknown_frequencies_are_rejected() {
+        assert_err!(DeliveryFrequency::parse("daily"));
+    }
+}
@@ -1,3 +1,8 @@
+pub mod custom_field;
+pub mod delivery_frequency;
+pub mod newsletter_issue;
 pub mod subscriber;
 pub mod subscriber_email;
 pub mod subscriber_name;
+pub mod subscriber_tag;
+pub mod subscription_status;
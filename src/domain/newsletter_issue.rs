@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+const MAX_SLUG_LENGTH: usize = 80;
+
+#[derive(Debug, Clone)]
+pub struct NewsletterIssue {
+    pub id: Uuid,
+    /// The mailing list the issue is sent to.
+    pub list_id: Uuid,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub track_opens: bool,
+    pub track_clicks: bool,
+}
+
+pub struct RenderedIssue {
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+/// Values substituted for `{{ key }}` placeholders when an issue is rendered for a recipient.
+#[derive(Debug, Default, Clone)]
+pub struct TemplateVariables(HashMap<String, String>);
+
+impl TemplateVariables {
+    pub fn for_subscriber(name: &str, email: &str) -> Self {
+        let mut variables = Self::default();
+        variables.insert("name", name);
+        variables.insert("email", email);
+        variables
+    }
+
+    /// Neutral data used when an issue is shown publicly, so that nothing in it identifies a
+    /// subscriber.
+    pub fn public() -> Self {
+        Self::for_subscriber("reader", "")
+    }
+
+    /// Placeholder data used when previewing an issue that is not addressed to anyone yet.
+    pub fn sample() -> Self {
+        Self::for_subscriber("Jane Doe", "jane.doe@example.com")
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.0.insert(key.into(), value.into());
+    }
+
+    /// Replaces the placeholders of a plain-text template.
+    pub fn render_text(&self, template: &str) -> String {
+        render_template(template, |key| self.get(key).to_string())
+    }
+
+    /// Replaces the placeholders of an HTML template, escaping the values.
+    pub fn render_html(&self, template: &str) -> String {
+        render_template(template, |key| escape_html(self.get(key)))
+    }
+
+    fn get(&self, key: &str) -> &str {
+        self.0.get(key).map(String::as_str).unwrap_or_default()
+    }
+}
+
+impl NewsletterIssue {
+    pub fn render(&self, variables: &TemplateVariables) -> RenderedIssue {
+        RenderedIssue {
+            title: variables.render_text(&self.title),
+            text_content: variables.render_text(&self.text_content),
+            html_content: variables.render_html(&self.html_content),
+        }
+    }
+}
+
+fn render_template(template: &str, lookup: impl Fn(&str) -> String) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(&lookup(rest[start + 2..start + end].trim()));
+        rest = &rest[start + end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Turns an issue title into the URL path segment it is archived under.
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::with_capacity(title.len());
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= MAX_SLUG_LENGTH {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "issue".to_owned()
+    } else {
+        slug.to_owned()
+    }
+}
+
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::newsletter_issue::{slugify, NewsletterIssue, TemplateVariables};
+    use uuid::Uuid;
+
+    fn issue(content: &str) -> NewsletterIssue {
+        NewsletterIssue {
+            id: Uuid::new_v4(),
+            list_id: Uuid::new_v4(),
+            title: format!("Title: {}", content),
+            text_content: content.to_string(),
+            html_content: content.to_string(),
+            track_opens: false,
+            track_clicks: false,
+        }
+    }
+
+    #[test]
+    fn placeholders_are_replaced_with_subscriber_data() {
+        let variables = TemplateVariables::for_subscriber("Ursula", "ursula@example.com");
+        let rendered = issue("Hi {{name}} ({{ email }})").render(&variables);
+        assert_eq!(rendered.title, "Title: Hi Ursula (ursula@example.com)");
+        assert_eq!(rendered.text_content, "Hi Ursula (ursula@example.com)");
+        assert_eq!(rendered.html_content, "Hi Ursula (ursula@example.com)");
+    }
+
+    #[test]
+    fn unknown_placeholders_are_rendered_empty() {
+        let rendered = issue("Hi {{ company }}!").render(&TemplateVariables::sample());
+        assert_eq!(rendered.text_content, "Hi !");
+    }
+
+    #[test]
+    fn unterminated_placeholders_are_left_untouched() {
+        let rendered = issue("Hi {{name").render(&TemplateVariables::sample());
+        assert_eq!(rendered.text_content, "Hi {{name");
+    }
+
+    #[test]
+    fn values_are_escaped_in_the_html_part_only() {
+        let variables = TemplateVariables::for_subscriber("Tom & Jerry", "tom@example.com");
+        let rendered = issue("{{name}}").render(&variables);
+        assert_eq!(rendered.text_content, "Tom & Jerry");
+        assert_eq!(rendered.html_content, "Tom &amp; Jerry");
+    }
+
+    #[test]
+    fn slugs_keep_only_lowercase_ascii_words() {
+        assert_eq!(slugify("Hello, World! Issue #3"), "hello-world-issue-3");
+        assert_eq!(slugify("  Ünïcode -- title  "), "n-code-title");
+        assert_eq!(slugify("!!!"), "issue");
+        assert!(slugify(&"a".repeat(200)).len() <= 80);
+    }
+}
@@ -1,16 +1,35 @@
 use validator::validate_email;
 
+/// An email address, normalized: surrounding whitespace is trimmed and the domain is lowercased
+/// and converted to punycode. The local part is kept as typed, since mail servers may tell
+/// `Alice` from `alice`, and is what emails are sent to.
 #[derive(Debug, Clone)]
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
     pub fn parse(s: String) -> Result<Self, String> {
-        if validate_email(&s) {
-            Ok(Self(s))
-        } else {
-            Err(format!("{} is not a valid email", s))
+        let normalized = s.trim().rsplit_once('@').and_then(|(local_part, domain)| {
+            let domain = idna::domain_to_ascii(domain).ok()?;
+            Some(format!("{}@{}", local_part, domain))
+        });
+        match normalized {
+            Some(email) if validate_email(&email) => Ok(Self(email)),
+            _ => Err(format!("{} is not a valid email", s)),
         }
     }
+
+    /// The address as subscribers are told apart: regardless of case, like the unique index on
+    /// `lower(subscriptions.email)`.
+    pub fn identity(&self) -> String {
+        self.0.to_lowercase()
+    }
+
+    pub fn domain(&self) -> &str {
+        self.0
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or_default()
+    }
 }
 
 impl AsRef<str> for SubscriberEmail {
@@ -62,6 +81,32 @@ mod tests {
         assert_err!(SubscriberEmail::parse(email));
     }
 
+    #[test]
+    fn domain_is_the_part_after_the_last_at_symbol() {
+        let email = SubscriberEmail::parse("ursula@example.com".to_string()).unwrap();
+        assert_eq!(email.domain(), "example.com");
+    }
+
+    #[test]
+    fn email_is_trimmed_and_its_domain_lowercased() {
+        let email = SubscriberEmail::parse("  Ursula@Example.COM \n".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "Ursula@example.com");
+        assert_eq!(email.identity(), "ursula@example.com");
+    }
+
+    #[test]
+    fn international_domains_are_converted_to_punycode() {
+        let email = SubscriberEmail::parse("ursula@Bücher.de".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ursula@xn--bcher-kva.de");
+        assert_eq!(email.domain(), "xn--bcher-kva.de");
+    }
+
+    #[test]
+    fn email_with_an_invalid_domain_is_invalid() {
+        assert_err!(SubscriberEmail::parse("ursula@exa mple.com".to_string()));
+        assert_err!(SubscriberEmail::parse("ursula@".to_string()));
+    }
+
     #[quickcheck_macros::quickcheck]
     fn valid_email_is_valid(valid_email: ValidEmailFixture) -> bool {
         dbg!(valid_email.0.clone());
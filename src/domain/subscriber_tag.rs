@@ -0,0 +1,83 @@
+/// A label attached to subscribers, compared case-insensitively.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim().to_lowercase();
+        let is_empty = s.is_empty();
+        let is_long = s.chars().count() > 64;
+        let has_forbidden_chars = !s
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'));
+        if is_empty || is_long || has_forbidden_chars {
+            Err(format!("{} is not a valid tag", s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+
+    /// Parses a comma-separated list of tags, as sent by a signup form, ignoring empty entries.
+    pub fn parse_list(s: &str) -> Result<Vec<Self>, String> {
+        let mut tags = Vec::new();
+        for tag in s.split(',').filter(|tag| !tag.trim().is_empty()) {
+            let tag = Self::parse(tag)?;
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        Ok(tags)
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::subscriber_tag::SubscriberTag;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn tags_are_trimmed_and_lowercased() {
+        let tag = SubscriberTag::parse(" Beta-Tester ").unwrap();
+        assert_eq!(tag.as_ref(), "beta-tester");
+    }
+
+    #[test]
+    fn a_64_character_tag_is_valid() {
+        assert_ok!(SubscriberTag::parse(&"a".repeat(64)));
+    }
+
+    #[test]
+    fn a_65_character_tag_is_invalid() {
+        assert_err!(SubscriberTag::parse(&"a".repeat(65)));
+    }
+
+    #[test]
+    fn empty_tag_is_invalid() {
+        assert_err!(SubscriberTag::parse("  "));
+    }
+
+    #[test]
+    fn tag_with_forbidden_chars_is_invalid() {
+        for tag in &["a b", "a,b", "<a>", "a/b", "\"a\""] {
+            assert_err!(SubscriberTag::parse(tag));
+        }
+    }
+
+    #[test]
+    fn lists_skip_empty_entries_and_duplicates() {
+        let tags = SubscriberTag::parse_list("vip, ,beta,VIP,").unwrap();
+        let tags: Vec<_> = tags.iter().map(AsRef::as_ref).collect();
+        assert_eq!(tags, vec!["vip", "beta"]);
+    }
+
+    #[test]
+    fn a_list_with_an_invalid_tag_is_invalid() {
+        assert_err!(SubscriberTag::parse_list("vip,not valid"));
+    }
+}
@@ -0,0 +1,61 @@
+/// Where a subscriber stands overall, as kept in `subscriptions.status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+    /// Left behind by the erasure of a subscriber's data, see `data_subject::erase_data`.
+    Erased,
+}
+
+impl SubscriptionStatus {
+    pub const ALL: [SubscriptionStatus; 4] = [
+        SubscriptionStatus::PendingConfirmation,
+        SubscriptionStatus::Confirmed,
+        SubscriptionStatus::Unsubscribed,
+        SubscriptionStatus::Erased,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::PendingConfirmation => "pending_confirmation",
+            SubscriptionStatus::Confirmed => "confirmed",
+            SubscriptionStatus::Unsubscribed => "unsubscribed",
+            SubscriptionStatus::Erased => "erased",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::PendingConfirmation => "Pending confirmation",
+            SubscriptionStatus::Confirmed => "Confirmed",
+            SubscriptionStatus::Unsubscribed => "Unsubscribed",
+            SubscriptionStatus::Erased => "Erased",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid status", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::subscription_status::SubscriptionStatus;
+    use claims::assert_err;
+
+    #[test]
+    fn statuses_round_trip_through_their_name() {
+        for status in SubscriptionStatus::ALL {
+            assert_eq!(SubscriptionStatus::parse(status.as_str()), Ok(status));
+        }
+    }
+
+    #[test]
+    fn unknown_statuses_are_rejected() {
+        assert_err!(SubscriptionStatus::parse("active"));
+    }
+}
@@ -0,0 +1,176 @@
+use sqlx::{PgConnection, PgExecutor};
+use uuid::Uuid;
+
+use crate::address_policy::AddressPolicy;
+use crate::domain::newsletter_issue::escape_html;
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::suppression::is_suppressed;
+
+/// What following an email change link did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmailChangeOutcome {
+    /// The subscription moved to the new address.
+    Changed {
+        subscriber_id: Uuid,
+        old_email: String,
+        new_email: String,
+    },
+    /// Another subscriber already has the new address, so nothing changed.
+    AddressTaken { subscriber_id: Uuid },
+}
+
+/// Records a request to move a subscription to `new_email` and returns the token proving that the
+/// owner of the new address agrees. Tokens are valid for a day.
+#[tracing::instrument(name = "Create email change request", skip(executor, new_email))]
+pub async fn create_email_change(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    new_email: &SubscriberEmail,
+    requested_by: Option<Uuid>,
+) -> Result<String, sqlx::Error> {
+    let request = sqlx::query!(
+        r#"
+        INSERT INTO email_change_requests (
+            token, subscriber_id, new_email, requested_by, expires_at
+        )
+        VALUES (
+            replace(gen_random_uuid()::text, '-', ''), $1, $2, $3, NOW() + INTERVAL '1 day'
+        )
+        RETURNING token
+        "#,
+        subscriber_id,
+        new_email.as_ref(),
+        requested_by,
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(request.token)
+}
+
+/// Returns why a subscription cannot move from `current_email` to `new_email`, if it cannot. The
+/// new address must be one that could sign up, see [`AddressPolicy`].
+#[tracing::instrument(name = "Validate new address", skip_all)]
+pub async fn check_new_address(
+    executor: impl PgExecutor<'_>,
+    address_policy: &AddressPolicy,
+    current_email: &str,
+    new_email: &SubscriberEmail,
+) -> Result<Option<String>, sqlx::Error> {
+    if new_email.as_ref() == current_email {
+        return Ok(Some(
+            "This is already the address of the subscription".into(),
+        ));
+    }
+    if let Err(e) = address_policy.check(new_email).await {
+        return Ok(Some(e));
+    }
+    if is_suppressed(executor, new_email).await? {
+        return Ok(Some("We cannot send emails to this address".into()));
+    }
+    Ok(None)
+}
+
+/// Moves the subscription of an unexpired request to its new address, or nothing if the token is
+/// unknown or expired. Other pending requests of the subscriber are dropped once it moved.
+///
+/// The address is swapped in place, so the subscriber keeps their lists, history and tokens. When
+/// the new address turns out to be taken, the transaction must be rolled back.
+#[tracing::instrument(name = "Apply email change request", skip_all)]
+pub async fn apply_email_change(
+    transaction: &mut PgConnection,
+    token: &str,
+) -> Result<Option<EmailChangeOutcome>, sqlx::Error> {
+    let request = sqlx::query!(
+        r#"
+        SELECT r.subscriber_id, r.new_email, s.email AS old_email
+        FROM email_change_requests r
+        JOIN subscriptions s ON s.id = r.subscriber_id
+        WHERE r.token = $1 AND r.expires_at > NOW()
+        FOR UPDATE
+        "#,
+        token,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(request) = request else {
+        return Ok(None);
+    };
+    let taken = EmailChangeOutcome::AddressTaken {
+        subscriber_id: request.subscriber_id,
+    };
+
+    let updated = sqlx::query!(
+        "UPDATE subscriptions SET email = $2 WHERE id = $1",
+        request.subscriber_id,
+        request.new_email,
+    )
+    .execute(&mut *transaction)
+    .await;
+    match updated {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => return Ok(Some(taken)),
+        Err(e) => return Err(e),
+    }
+    sqlx::query!(
+        "DELETE FROM email_change_requests WHERE subscriber_id = $1",
+        request.subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(Some(EmailChangeOutcome::Changed {
+        subscriber_id: request.subscriber_id,
+        old_email: request.old_email,
+        new_email: request.new_email,
+    }))
+}
+
+/// Emails the link moving the subscription to the new address.
+#[tracing::instrument(name = "Send email change verification", skip_all)]
+pub async fn send_verification_email(
+    email_client: &EmailClient,
+    new_email: &SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> Result<(), reqwest::Error> {
+    let link = format!("{}/preferences/email/verify?token={}", base_url, token);
+    email_client
+        .send_email(
+            new_email,
+            "Confirm your new address",
+            &format!(
+                "Click <a href=\"{}\">here</a> to receive our newsletter at this address.<br />\
+                If you did not ask for this, you can ignore this email.",
+                link
+            ),
+            &format!(
+                "Visit {} to receive our newsletter at this address.\n\
+                If you did not ask for this, you can ignore this email.",
+                link
+            ),
+        )
+        .await
+        .map(|_| ())
+}
+
+/// Lets the previous address know that the subscription moved away from it.
+#[tracing::instrument(name = "Send email change notice", skip_all)]
+pub async fn send_change_notice(
+    email_client: &EmailClient,
+    old_email: &SubscriberEmail,
+    new_email: &str,
+) -> Result<(), reqwest::Error> {
+    let text = format!(
+        "Our newsletter will now be sent to {} instead of this address.",
+        new_email
+    );
+    email_client
+        .send_email(
+            old_email,
+            "Your address was changed",
+            &escape_html(&text),
+            &text,
+        )
+        .await
+        .map(|_| ())
+}
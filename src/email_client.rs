@@ -1,6 +1,11 @@
 use crate::domain::subscriber_email::SubscriberEmail;
-use reqwest::Client;
+use crate::rate_limiter::{RateLimiter, ThrottleStats};
+use reqwest::{Client, StatusCode};
 use secrecy::{ExposeSecret, Secret};
+use tokio::time::Instant;
+
+/// The largest number of messages Mailjet accepts in a single send request.
+pub const MAX_BATCH_SIZE: usize = 50;
 
 #[derive(Clone)]
 pub struct EmailClient {
@@ -9,6 +14,34 @@ pub struct EmailClient {
     base_url: String,
     api_key: Secret<String>,
     api_secret: Secret<String>,
+    rate_limiter: RateLimiter,
+}
+
+#[derive(Clone, Copy)]
+pub struct Email<'a> {
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    /// Advertised in the `List-Unsubscribe` header, for mail clients to unsubscribe in one click
+    /// (RFC 8058).
+    pub unsubscribe_url: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageOutcome {
+    Sent {
+        message_id: Option<String>,
+    },
+    /// The provider rejected the message.
+    Failed {
+        error: String,
+    },
+    /// The request carrying the message failed as a whole, e.g. the provider could not be
+    /// reached: the message was not sent and can be tried again.
+    Unsent {
+        error: String,
+    },
 }
 
 impl EmailClient {
@@ -18,6 +51,7 @@ impl EmailClient {
         api_key: Secret<String>,
         api_secret: Secret<String>,
         timeout: std::time::Duration,
+        rate_limiter: RateLimiter,
     ) -> Self {
         Self {
             sender,
@@ -25,34 +59,171 @@ impl EmailClient {
             base_url,
             api_key,
             api_secret,
+            rate_limiter,
+        }
+    }
+
+    /// A client sending from `sender` instead, sharing the connection pool and rate limits.
+    pub fn with_sender(&self, sender: SubscriberEmail) -> Self {
+        Self {
+            sender,
+            ..self.clone()
         }
     }
 
+    /// How much the rate limits held back the sends of the process so far.
+    pub fn throttle_stats(&self) -> ThrottleStats {
+        self.rate_limiter.stats()
+    }
+
+    /// Sends a single email and returns the provider's message ID, if the response carried one.
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<Option<String>, reqwest::Error> {
+        let email = Email {
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            unsubscribe_url: None,
+        };
+        self.rate_limiter.acquire(recipient.domain()).await;
+        let response_body = self
+            .post_messages(std::slice::from_ref(&email))
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        let message_id = serde_json::from_slice::<SendEmailResponse>(&response_body)
+            .ok()
+            .and_then(|response| response.messages.into_iter().next())
+            .and_then(|message| message.to.into_iter().next())
+            .map(|recipient| recipient.message_id.to_string());
+        Ok(message_id)
+    }
+
+    /// Sends `emails` in as few requests as Mailjet and the rate limits allow, and returns one
+    /// outcome per email, in the order they were given.
+    ///
+    /// Every message is given its own slot by the rate limiter and leaves with the others already
+    /// allowed, so a throttled domain does not hold back the messages to other domains.
+    ///
+    /// Messages rejected individually by the provider are reported as
+    /// [`MessageOutcome::Failed`]. Once a whole request fails, the messages it carried and those
+    /// not sent yet are reported as [`MessageOutcome::Unsent`], while the outcomes of the
+    /// requests that went through are kept.
+    pub async fn send_emails(&self, emails: &[Email<'_>]) -> Vec<MessageOutcome> {
+        let mut pending: Vec<(Instant, usize)> = emails
+            .iter()
+            .enumerate()
+            .map(|(i, email)| (self.rate_limiter.reserve(email.recipient.domain()), i))
+            .collect();
+        pending.sort();
+
+        let mut outcomes = vec![None; emails.len()];
+        let mut pending = pending.as_slice();
+        while let Some((next_slot, _)) = pending.first() {
+            tokio::time::sleep_until(*next_slot).await;
+            let now = Instant::now();
+            let ready = pending
+                .iter()
+                .take(MAX_BATCH_SIZE)
+                .take_while(|(slot, _)| *slot <= now)
+                .count();
+            let (ready, rest) = pending.split_at(ready);
+            pending = rest;
+
+            let batch: Vec<_> = ready.iter().map(|(_, i)| emails[*i]).collect();
+            match self.send_batch(&batch).await {
+                Ok(sent) => {
+                    for ((_, i), outcome) in ready.iter().zip(sent) {
+                        outcomes[*i] = Some(outcome);
+                    }
+                }
+                Err(e) => {
+                    let error = e.to_string();
+                    for (_, i) in ready.iter().chain(pending) {
+                        outcomes[*i] = Some(MessageOutcome::Unsent {
+                            error: error.clone(),
+                        });
+                    }
+                    break;
+                }
+            }
+        }
+        outcomes.into_iter().flatten().collect()
+    }
+
+    async fn send_batch(&self, batch: &[Email<'_>]) -> Result<Vec<MessageOutcome>, reqwest::Error> {
+        let response = self.post_messages(batch).await?;
+        // Mailjet answers 400 when any message of the batch is invalid, but still reports
+        // the status of every message in the body.
+        let status = response.status();
+        let response = if status == StatusCode::BAD_REQUEST {
+            response
+        } else {
+            response.error_for_status()?
+        };
+        let response_body = response.bytes().await?;
+
+        let outcomes = match serde_json::from_slice::<SendEmailResponse>(&response_body) {
+            Ok(response) if response.messages.len() == batch.len() => response
+                .messages
+                .into_iter()
+                .map(MessageOutcome::from)
+                .collect(),
+            _ if status.is_success() => batch
+                .iter()
+                .map(|_| MessageOutcome::Sent { message_id: None })
+                .collect(),
+            _ => {
+                let error = format!(
+                    "The provider rejected the batch: {}",
+                    String::from_utf8_lossy(&response_body)
+                );
+                batch
+                    .iter()
+                    .map(|_| MessageOutcome::Failed {
+                        error: error.clone(),
+                    })
+                    .collect()
+            }
+        };
+        Ok(outcomes)
+    }
+
+    async fn post_messages(
+        &self,
+        emails: &[Email<'_>],
+    ) -> Result<reqwest::Response, reqwest::Error> {
         let url = format!("{}/v3.1/send", self.base_url);
 
         let request_body = SendEmailRequest {
-            messages: vec![SendEmailRequestMessage {
-                from: SendEmailRequestEmail {
-                    email: self.sender.as_ref(),
-                },
-                to: vec![SendEmailRequestEmail {
-                    email: recipient.as_ref(),
-                }],
-                subject,
-                text_part: text_content,
-                html_part: html_content,
-            }],
+            messages: emails
+                .iter()
+                .map(|email| SendEmailRequestMessage {
+                    from: SendEmailRequestEmail {
+                        email: self.sender.as_ref(),
+                    },
+                    to: vec![SendEmailRequestEmail {
+                        email: email.recipient.as_ref(),
+                    }],
+                    subject: email.subject,
+                    text_part: email.text_content,
+                    html_part: email.html_content,
+                    headers: email.unsubscribe_url.map(|url| SendEmailRequestHeaders {
+                        list_unsubscribe: format!("<{}>", url),
+                        list_unsubscribe_post: "List-Unsubscribe=One-Click",
+                    }),
+                })
+                .collect(),
         };
 
-        println!("{}", serde_json::to_string_pretty(&request_body).unwrap());
-
         self.http_client
             .post(url)
             .basic_auth(
@@ -61,9 +232,7 @@ impl EmailClient {
             )
             .json(&request_body)
             .send()
-            .await?
-            .error_for_status()?;
-        Ok(())
+            .await
     }
 }
 
@@ -82,6 +251,16 @@ struct SendEmailRequestMessage<'a> {
     text_part: &'a str,
     #[serde(rename = "HTMLPart")]
     html_part: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headers: Option<SendEmailRequestHeaders>,
+}
+
+#[derive(serde::Serialize)]
+struct SendEmailRequestHeaders {
+    #[serde(rename = "List-Unsubscribe")]
+    list_unsubscribe: String,
+    #[serde(rename = "List-Unsubscribe-Post")]
+    list_unsubscribe_post: &'static str,
 }
 
 #[derive(serde::Serialize)]
@@ -90,9 +269,63 @@ struct SendEmailRequest<'a> {
     messages: Vec<SendEmailRequestMessage<'a>>,
 }
 
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailResponse {
+    messages: Vec<SendEmailResponseMessage>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailResponseMessage {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    to: Vec<SendEmailResponseRecipient>,
+    #[serde(default)]
+    errors: Vec<SendEmailResponseError>,
+}
+
+#[derive(serde::Deserialize)]
+struct SendEmailResponseRecipient {
+    #[serde(rename = "MessageID")]
+    message_id: u64,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailResponseError {
+    #[serde(default)]
+    error_code: String,
+    #[serde(default)]
+    error_message: String,
+}
+
+impl From<SendEmailResponseMessage> for MessageOutcome {
+    fn from(message: SendEmailResponseMessage) -> Self {
+        if message.status == "success" {
+            MessageOutcome::Sent {
+                message_id: message
+                    .to
+                    .into_iter()
+                    .next()
+                    .map(|recipient| recipient.message_id.to_string()),
+            }
+        } else {
+            let error = message
+                .errors
+                .into_iter()
+                .map(|e| format!("{}: {}", e.error_code, e.error_message))
+                .collect::<Vec<_>>()
+                .join("; ");
+            MessageOutcome::Failed { error }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use claims::{assert_err, assert_ok};
+    use claims::{assert_err, assert_ok, assert_ok_eq};
     use fake::{
         faker::{internet::en::SafeEmail, lorem::en::Sentence},
         Fake, Faker,
@@ -103,7 +336,11 @@ mod tests {
         Mock, MockServer, ResponseTemplate,
     };
 
-    use crate::{domain::subscriber_email::SubscriberEmail, email_client::EmailClient};
+    use crate::{
+        domain::subscriber_email::SubscriberEmail,
+        email_client::{Email, EmailClient, MessageOutcome},
+        rate_limiter::RateLimiter,
+    };
 
     struct SendEmailRequestBodyMatcher;
 
@@ -117,7 +354,7 @@ mod tests {
                 .and_then(|body| {
                     body.get("Messages")
                         .and_then(|messages| messages.as_array().filter(|arr| arr.len() == 1))
-                        .and_then(|messages| messages.get(0))
+                        .and_then(|messages| messages.first())
                         .and_then(|message| {
                             message
                                 .get("From")
@@ -127,7 +364,7 @@ mod tests {
                                     message
                                         .get("To")
                                         .and_then(|to| to.as_array().filter(|arr| arr.len() == 1))
-                                        .and_then(|to| to.get(0))
+                                        .and_then(|to| to.first())
                                         .and_then(|to| to.get("Email"))
                                         .and_then(|to_email| to_email.as_str())
                                 })
@@ -175,6 +412,35 @@ mod tests {
         assert_ok!(result);
     }
 
+    #[tokio::test]
+    async fn send_email_returns_the_provider_message_id() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let response_body = serde_json::json!({
+            "Messages": [{
+                "Status": "success",
+                "To": [{
+                    "Email": "ursula@example.com",
+                    "MessageUUID": "1ab23cd4-e567-8901-2345-6789f0gh1i2j",
+                    "MessageID": 456,
+                    "MessageHref": "https://api.mailjet.com/v3/message/456"
+                }]
+            }]
+        });
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(response_body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let result = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok_eq!(result, Some("456".to_string()));
+    }
+
     #[tokio::test]
     async fn send_email_fails_if_server_returns_500() {
         let mock_server = MockServer::start().await;
@@ -211,6 +477,250 @@ mod tests {
         assert_err!(result);
     }
 
+    #[tokio::test]
+    async fn send_emails_groups_recipients_into_batches() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/v3.1/send"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let (subject, content) = (subject(), content());
+        let recipients: Vec<_> = (0..120).map(|_| email()).collect();
+        let emails: Vec<_> = recipients
+            .iter()
+            .map(|recipient| Email {
+                recipient,
+                subject: &subject,
+                html_content: &content,
+                text_content: &content,
+                unsubscribe_url: None,
+            })
+            .collect();
+
+        let outcomes = email_client.send_emails(&emails).await;
+
+        assert_eq!(outcomes.len(), 120);
+        let requests = mock_server.received_requests().await.unwrap();
+        let batch_sizes: Vec<_> = requests
+            .iter()
+            .map(|r| {
+                let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+                body["Messages"].as_array().unwrap().len()
+            })
+            .collect();
+        assert_eq!(batch_sizes, vec![50, 50, 20]);
+    }
+
+    #[tokio::test]
+    async fn send_emails_maps_the_result_of_each_message() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let response_body = serde_json::json!({
+            "Messages": [
+                {
+                    "Status": "success",
+                    "To": [{"Email": "ursula@example.com", "MessageID": 1}]
+                },
+                {
+                    "Status": "error",
+                    "Errors": [{
+                        "ErrorCode": "mj-0013",
+                        "ErrorMessage": "\"invalid\" is an invalid email address.",
+                        "StatusCode": 400
+                    }]
+                }
+            ]
+        });
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400).set_body_json(response_body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (subject, content) = (subject(), content());
+        let recipients = [email(), email()];
+        let emails: Vec<_> = recipients
+            .iter()
+            .map(|recipient| Email {
+                recipient,
+                subject: &subject,
+                html_content: &content,
+                text_content: &content,
+                unsubscribe_url: None,
+            })
+            .collect();
+
+        let outcomes = email_client.send_emails(&emails).await;
+
+        assert_eq!(
+            outcomes,
+            vec![
+                MessageOutcome::Sent {
+                    message_id: Some("1".into())
+                },
+                MessageOutcome::Failed {
+                    error: "mj-0013: \"invalid\" is an invalid email address.".into()
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn send_emails_respects_the_rate_limit() {
+        let mock_server = MockServer::start().await;
+        let mut email_client = email_client(mock_server.uri());
+        email_client.rate_limiter = RateLimiter::new(Some(20.0), Default::default());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let (subject, content) = (subject(), content());
+        let recipients = [email(), email(), email()];
+        let emails: Vec<_> = recipients
+            .iter()
+            .map(|recipient| Email {
+                recipient,
+                subject: &subject,
+                html_content: &content,
+                text_content: &content,
+                unsubscribe_url: None,
+            })
+            .collect();
+
+        let start = std::time::Instant::now();
+        let outcomes = email_client.send_emails(&emails).await;
+        assert!(outcomes
+            .iter()
+            .all(|outcome| matches!(outcome, MessageOutcome::Sent { .. })));
+        assert!(start.elapsed() >= std::time::Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn a_throttled_domain_does_not_hold_back_the_rest_of_the_batch() {
+        let mock_server = MockServer::start().await;
+        let mut email_client = email_client(mock_server.uri());
+        email_client.rate_limiter = RateLimiter::new(None, [("gmail.com".to_string(), 5.0)].into());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let (subject, content) = (subject(), content());
+        let recipients = ["a@gmail.com", "b@gmail.com", "c@example.com"]
+            .map(|email| SubscriberEmail::parse(email.to_string()).unwrap());
+        let emails: Vec<_> = recipients
+            .iter()
+            .map(|recipient| Email {
+                recipient,
+                subject: &subject,
+                html_content: &content,
+                text_content: &content,
+                unsubscribe_url: None,
+            })
+            .collect();
+
+        let outcomes = email_client.send_emails(&emails).await;
+
+        assert_eq!(outcomes.len(), 3);
+        let requests = mock_server.received_requests().await.unwrap();
+        let recipients: Vec<Vec<_>> = requests
+            .iter()
+            .map(|r| {
+                let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+                body["Messages"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|message| message["To"][0]["Email"].as_str().unwrap().to_owned())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            recipients,
+            vec![
+                vec!["a@gmail.com".to_string(), "c@example.com".to_string()],
+                vec!["b@gmail.com".to_string()],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn send_emails_fails_if_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (subject, content, recipient) = (subject(), content(), email());
+        let emails = [Email {
+            recipient: &recipient,
+            subject: &subject,
+            html_content: &content,
+            text_content: &content,
+            unsubscribe_url: None,
+        }];
+
+        let outcomes = email_client.send_emails(&emails).await;
+        assert!(matches!(outcomes[..], [MessageOutcome::Unsent { .. }]));
+    }
+
+    #[tokio::test]
+    async fn a_failed_request_keeps_the_outcomes_of_the_requests_sent_before_it() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (subject, content) = (subject(), content());
+        let recipients: Vec<_> = (0..120).map(|_| email()).collect();
+        let emails: Vec<_> = recipients
+            .iter()
+            .map(|recipient| Email {
+                recipient,
+                subject: &subject,
+                html_content: &content,
+                text_content: &content,
+                unsubscribe_url: None,
+            })
+            .collect();
+
+        let outcomes = email_client.send_emails(&emails).await;
+
+        assert_eq!(outcomes.len(), 120);
+        assert!(outcomes[..50]
+            .iter()
+            .all(|outcome| matches!(outcome, MessageOutcome::Sent { .. })));
+        // The third batch is not sent once the second one failed.
+        assert!(outcomes[50..]
+            .iter()
+            .all(|outcome| matches!(outcome, MessageOutcome::Unsent { .. })));
+    }
+
     fn subject() -> String {
         Sentence(1..2).fake()
     }
@@ -232,6 +742,7 @@ mod tests {
             api_key,
             api_secret,
             std::time::Duration::from_millis(200),
+            RateLimiter::default(),
         )
     }
 }
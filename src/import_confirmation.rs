@@ -0,0 +1,200 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use anyhow::Context;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::custom_fields::get_field_values;
+use crate::domain::subscriber::Subscriber;
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::domain::subscriber_name::SubscriberName;
+use crate::email_client::EmailClient;
+use crate::mailing_list::{get_list, MailingList};
+use crate::routes::send_confirmation_email;
+use crate::send_queue::{settle_interrupted_sends, Outcome, RETRY_DELAY};
+
+/// How many confirmation emails are claimed at once.
+const BATCH_SIZE: i64 = 50;
+
+struct QueuedConfirmation {
+    token: String,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+    email: String,
+    name: String,
+    /// Including the one it was claimed for.
+    attempts: i32,
+}
+
+/// Queues the confirmation email for `token`, to be sent by `spawn_confirmation_sends` once the
+/// transaction is committed.
+#[tracing::instrument(name = "Queue confirmation email", skip_all)]
+pub async fn queue_confirmation(
+    transaction: &mut PgConnection,
+    token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE subscription_tokens SET send_status = 'queued' WHERE token = $1",
+        token,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Sends the queued confirmation emails in the background, so that an import does not wait for
+/// them, nor for the rate limit of the email provider.
+pub fn spawn_confirmation_sends(pool: PgPool, email_client: EmailClient, base_url: String) {
+    tokio::spawn(async move {
+        if let Err(e) = send_queued_confirmations(&pool, &email_client, &base_url).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                "Failed to send the queued confirmation emails"
+            );
+        }
+    });
+}
+
+/// Sends every queued confirmation email, recording the outcome of each.
+///
+/// Emails are claimed one batch at a time, and marked `sending` before they leave, so that
+/// concurrent senders never send the same one twice. See [`send_queue`](crate::send_queue).
+#[tracing::instrument(name = "Send queued confirmation emails", skip_all)]
+pub async fn send_queued_confirmations(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<(), anyhow::Error> {
+    let mut lists = HashMap::new();
+    loop {
+        settle_interrupted_sends(pool)
+            .await
+            .context("Failed to settle interrupted sends.")?;
+        let confirmations = claim_confirmations(pool)
+            .await
+            .context("Failed to claim the queued confirmation emails.")?;
+        if confirmations.is_empty() {
+            return Ok(());
+        }
+        let mut unreachable = false;
+        for confirmation in confirmations {
+            let outcome = match send(pool, email_client, base_url, &mut lists, &confirmation).await
+            {
+                Ok(Ok(())) => Outcome::Sent(None),
+                Ok(Err(e)) => Outcome::unsent(
+                    confirmation.attempts,
+                    anyhow::anyhow!(e).context("Failed to send the confirmation email."),
+                ),
+                Err(e) => Outcome::Failed(e),
+            };
+            let (status, error) = match outcome {
+                Outcome::Sent(_) => ("sent", None),
+                Outcome::Retry(e) => {
+                    unreachable = true;
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        subscriber_id = %confirmation.subscriber_id,
+                        "Failed to send the confirmation email to an imported subscriber, will try again"
+                    );
+                    ("queued", Some(format!("{:#}", e)))
+                }
+                Outcome::Failed(e) => {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        subscriber_id = %confirmation.subscriber_id,
+                        "Failed to send the confirmation email to an imported subscriber"
+                    );
+                    ("failed", Some(format!("{:#}", e)))
+                }
+            };
+            sqlx::query!(
+                r#"
+                UPDATE subscription_tokens
+                SET send_status = $2, send_last_error = COALESCE($3, send_last_error)
+                WHERE token = $1
+                "#,
+                confirmation.token,
+                status,
+                error,
+            )
+            .execute(pool)
+            .await
+            .context("Failed to record the outcome of a confirmation email.")?;
+        }
+        if unreachable {
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    }
+}
+
+/// Sends one confirmation email. The outer error is a failure to prepare it, which sending again
+/// would not fix; the inner one is a failure of the request to the email provider.
+async fn send(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    lists: &mut HashMap<Uuid, (MailingList, EmailClient)>,
+    confirmation: &QueuedConfirmation,
+) -> Result<Result<(), reqwest::Error>, anyhow::Error> {
+    let (list, email_client) = match lists.entry(confirmation.list_id) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            let list = get_list(pool, confirmation.list_id)
+                .await
+                .context("Failed to retrieve the mailing list.")?
+                .context("The mailing list does not exist anymore.")?;
+            let email_client = list.email_client(email_client)?;
+            entry.insert((list, email_client))
+        }
+    };
+    let subscriber = Subscriber {
+        email: SubscriberEmail::parse(confirmation.email.clone()).map_err(anyhow::Error::msg)?,
+        name: SubscriberName::parse(confirmation.name.clone()).map_err(anyhow::Error::msg)?,
+    };
+    let field_values: Vec<_> = get_field_values(pool, confirmation.subscriber_id)
+        .await
+        .context("Failed to retrieve the custom fields of the subscriber.")?
+        .into_iter()
+        .collect();
+    Ok(send_confirmation_email(
+        email_client,
+        list,
+        subscriber,
+        &field_values,
+        base_url,
+        &confirmation.token,
+    )
+    .await)
+}
+
+/// Marks up to `BATCH_SIZE` queued confirmation emails as `sending` and returns them.
+#[tracing::instrument(name = "Claim queued confirmation emails", skip(pool))]
+async fn claim_confirmations(pool: &PgPool) -> Result<Vec<QueuedConfirmation>, sqlx::Error> {
+    sqlx::query_as!(
+        QueuedConfirmation,
+        r#"
+        WITH claimed AS (
+            UPDATE subscription_tokens
+            SET send_status = 'sending', send_claimed_at = NOW(), send_attempts = send_attempts + 1
+            WHERE token IN (
+                SELECT token
+                FROM subscription_tokens
+                WHERE send_status = 'queued'
+                LIMIT $1
+                FOR UPDATE
+                SKIP LOCKED
+            )
+            RETURNING token, list_id, id, send_attempts
+        )
+        SELECT
+            c.token, c.list_id, s.id AS subscriber_id, s.email, s.name,
+            c.send_attempts AS attempts
+        FROM claimed c
+        JOIN subscriptions s ON s.id = c.id
+        "#,
+        BATCH_SIZE,
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,564 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use sqlx::types::Json;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::domain::newsletter_issue::{NewsletterIssue, TemplateVariables};
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::email_client::{Email, EmailClient, MessageOutcome, MAX_BATCH_SIZE};
+use crate::mailing_list::get_list;
+use crate::segments::SegmentFilter;
+use crate::send_queue::{settle_interrupted_sends, Outcome, RETRY_DELAY};
+use crate::tracking::{
+    add_open_pixel, rewrite_html_links, rewrite_text_links, trackable_links, TrackingLinks,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    Queued,
+    /// Claimed by a sender; the provider may or may not have accepted it yet.
+    Sending,
+    Sent,
+    Failed,
+    Bounced,
+    Cancelled,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Queued => "queued",
+            DeliveryStatus::Sending => "sending",
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Bounced => "bounced",
+            DeliveryStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+struct DeliveryTask {
+    subscriber_id: Uuid,
+    email: String,
+    name: String,
+    preferences_token: String,
+    tracking_token: Option<String>,
+    /// Including the one it was claimed for.
+    attempts: i32,
+    unsubscribe_token: Option<String>,
+    /// The subscriber's custom field values, by key.
+    fields: Json<HashMap<String, String>>,
+}
+
+/// Records a `queued` delivery for every non-suppressed subscriber confirmed on the list of the
+/// issue and matching its segment, if it has one, to be picked up by [`deliver_issue`].
+/// Subscribers who were sent an issue more recently than their
+/// [`DeliveryFrequency`](crate::domain::delivery_frequency::DeliveryFrequency) allows are
+/// skipped.
+#[tracing::instrument(name = "Enqueue delivery tasks", skip(transaction))]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let segment = sqlx::query!(
+        r#"
+        SELECT s.filter AS "filter?: Json<SegmentFilter>"
+        FROM newsletter_issues i
+        LEFT JOIN segments s USING (segment_id)
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    let filter = segment.filter.map(|filter| filter.0).unwrap_or_default();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (newsletter_issue_id, subscriber_id, status, tracking_token)
+        SELECT $1, s.id, 'queued', replace(gen_random_uuid()::text, '-', '')
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        JOIN newsletter_issues i ON i.list_id = m.list_id
+        WHERE i.newsletter_issue_id = $1
+            AND m.status = 'confirmed'
+            AND NOT EXISTS (SELECT 1 FROM suppressions WHERE email_hash = hash_email(s.email))
+            AND NOT EXISTS (
+                SELECT 1 FROM issue_deliveries p
+                WHERE p.subscriber_id = s.id
+                    AND p.sent_at > NOW() - CASE s.frequency
+                        WHEN 'weekly' THEN INTERVAL '7 days'
+                        WHEN 'monthly' THEN INTERVAL '1 month'
+                        ELSE INTERVAL '0'
+                    END
+            )
+            AND (
+                cardinality($2::text[]) = 0
+                OR EXISTS (
+                    SELECT 1 FROM subscriber_tags t
+                    WHERE t.subscriber_id = s.id AND t.tag = ANY($2)
+                )
+            )
+            AND NOT EXISTS (
+                SELECT 1 FROM subscriber_tags t
+                WHERE t.subscriber_id = s.id AND t.tag = ANY($3)
+            )
+            AND ($4::timestamptz IS NULL OR m.subscribed_at >= $4)
+            AND ($5::timestamptz IS NULL OR m.subscribed_at < $5)
+            AND (
+                $6::int IS NULL
+                OR EXISTS (
+                    SELECT 1 FROM issue_deliveries o
+                    WHERE o.subscriber_id = s.id
+                        AND o.last_opened_at >= NOW() - make_interval(days => $6)
+                )
+            )
+            AND (
+                $7::int IS NULL
+                OR EXISTS (
+                    SELECT 1 FROM link_clicks c
+                    WHERE c.subscriber_id = s.id
+                        AND c.clicked_at >= NOW() - make_interval(days => $7)
+                )
+            )
+        "#,
+        newsletter_issue_id,
+        &filter.include_tags,
+        &filter.exclude_tags,
+        filter.subscribed_after,
+        filter.subscribed_before,
+        filter.opened_within_days,
+        filter.clicked_within_days,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Delivers `issue` in the background, so the send is not tied to the request that started it:
+/// a client going away does not stop it halfway through a batch.
+pub fn spawn_delivery(
+    pool: PgPool,
+    email_client: EmailClient,
+    tracking_links: TrackingLinks,
+    issue: NewsletterIssue,
+) {
+    tokio::spawn(async move {
+        if let Err(e) = deliver_issue(&pool, &email_client, &tracking_links, &issue).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                newsletter_issue_id = %issue.id,
+                "Failed to deliver the newsletter issue"
+            );
+        }
+    });
+}
+
+/// Sends `issue` to every recipient whose delivery is still queued, recording the outcome of each.
+///
+/// Deliveries are claimed one batch at a time, and marked `sending` before any email leaves, so a
+/// send interrupted halfway through can be resumed by calling this again without sending to the
+/// same recipient twice, and a cancelled send stops at the next batch.
+#[tracing::instrument(
+    name = "Deliver newsletter issue",
+    skip_all,
+    fields(newsletter_issue_id = %issue.id)
+)]
+pub async fn deliver_issue(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    tracking_links: &TrackingLinks,
+    issue: &NewsletterIssue,
+) -> Result<(), anyhow::Error> {
+    let list = get_list(pool, issue.list_id)
+        .await
+        .context("Failed to retrieve the mailing list of the newsletter issue.")?
+        .context("The mailing list of the newsletter issue does not exist.")?;
+    let email_client = list.email_client(email_client)?;
+    let link_ids = if issue.track_clicks {
+        register_links(pool, issue)
+            .await
+            .context("Failed to register the links of the newsletter issue.")?
+    } else {
+        HashMap::new()
+    };
+    loop {
+        settle_interrupted_sends(pool)
+            .await
+            .context("Failed to settle interrupted sends.")?;
+        let tasks = claim_tasks(pool, issue.id, MAX_BATCH_SIZE)
+            .await
+            .context("Failed to claim delivery tasks.")?;
+        if tasks.is_empty() {
+            break;
+        }
+        let drained = tasks.len() < MAX_BATCH_SIZE;
+
+        let mut recipients = Vec::with_capacity(tasks.len());
+        let mut outcomes = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            match SubscriberEmail::parse(task.email) {
+                Ok(email) => {
+                    let mut variables =
+                        TemplateVariables::for_subscriber(&task.name, email.as_ref());
+                    for (key, value) in task.fields.0 {
+                        variables.insert(key, value);
+                    }
+                    variables.insert(
+                        "preferences_url",
+                        tracking_links.preferences_url(&task.preferences_token),
+                    );
+                    let unsubscribe_url = task
+                        .unsubscribe_token
+                        .as_ref()
+                        .map(|token| tracking_links.unsubscribe_url(token, issue.id));
+                    if let Some(url) = &unsubscribe_url {
+                        variables.insert("unsubscribe_url", url.clone());
+                    }
+                    let mut rendered = issue.render(&variables);
+                    if let Some(token) = &task.tracking_token {
+                        if issue.track_clicks {
+                            let rewrite = |url: &str| {
+                                let link_id = link_ids.get(url)?;
+                                Some(tracking_links.click_url(token, *link_id))
+                            };
+                            rendered.html_content =
+                                rewrite_html_links(&rendered.html_content, rewrite);
+                            rendered.text_content =
+                                rewrite_text_links(&rendered.text_content, rewrite);
+                        }
+                        if issue.track_opens {
+                            rendered.html_content = add_open_pixel(
+                                &rendered.html_content,
+                                &tracking_links.open_pixel_url(token),
+                            );
+                        }
+                    }
+                    recipients.push((
+                        task.subscriber_id,
+                        task.attempts,
+                        email,
+                        rendered,
+                        unsubscribe_url,
+                    ));
+                }
+                Err(e) => {
+                    let e = anyhow::anyhow!(e).context("The stored email is invalid");
+                    outcomes.push((task.subscriber_id, Outcome::Failed(e)));
+                }
+            }
+        }
+
+        let emails: Vec<_> = recipients
+            .iter()
+            .map(|(_, _, email, rendered, unsubscribe_url)| Email {
+                recipient: email,
+                subject: &rendered.title,
+                html_content: &rendered.html_content,
+                text_content: &rendered.text_content,
+                unsubscribe_url: unsubscribe_url.as_deref(),
+            })
+            .collect();
+        let mut unreachable = false;
+        let sent = email_client.send_emails(&emails).await;
+        for ((subscriber_id, attempts, email, _, _), outcome) in recipients.iter().zip(sent) {
+            let failure = |error: String| {
+                anyhow::anyhow!(error)
+                    .context(format!("Failed to send email to the subscriber {:}", email))
+            };
+            let outcome = match outcome {
+                MessageOutcome::Sent { message_id } => Outcome::Sent(message_id),
+                MessageOutcome::Failed { error } => Outcome::Failed(failure(error)),
+                MessageOutcome::Unsent { error } => {
+                    unreachable = true;
+                    Outcome::unsent(*attempts, failure(error))
+                }
+            };
+            outcomes.push((*subscriber_id, outcome));
+        }
+
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("Failed to acquire Postgres connection from the pool.")?;
+        for (subscriber_id, outcome) in outcomes {
+            match outcome {
+                Outcome::Sent(message_id) => {
+                    mark_delivery_sent(
+                        &mut transaction,
+                        issue.id,
+                        subscriber_id,
+                        message_id.as_deref(),
+                    )
+                    .await
+                    .context("Failed to record a successful delivery.")?;
+                }
+                Outcome::Retry(e) => {
+                    tracing::warn!(
+                        e.cause_chain = ?e,
+                        subscriber_id = %subscriber_id,
+                        "Failed to deliver newsletter issue, will try again"
+                    );
+                    requeue_delivery(
+                        &mut transaction,
+                        issue.id,
+                        subscriber_id,
+                        &format!("{:#}", e),
+                    )
+                    .await
+                    .context("Failed to queue a delivery again.")?;
+                }
+                Outcome::Failed(e) => {
+                    record_failure(&mut transaction, issue.id, subscriber_id, e).await?;
+                }
+            }
+        }
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to record deliveries.")?;
+
+        if unreachable {
+            tokio::time::sleep(RETRY_DELAY).await;
+        } else if drained {
+            // Nothing was left to claim, and nothing went back to the queue.
+            break;
+        }
+    }
+    Ok(())
+}
+
+async fn record_failure(
+    transaction: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    e: anyhow::Error,
+) -> Result<(), anyhow::Error> {
+    tracing::warn!(
+        e.cause_chain = ?e,
+        subscriber_id = %subscriber_id,
+        "Failed to deliver newsletter issue"
+    );
+    mark_delivery_failed(
+        transaction,
+        newsletter_issue_id,
+        subscriber_id,
+        &format!("{:#}", e),
+    )
+    .await
+    .context("Failed to record a failed delivery.")
+}
+
+/// Stops the remaining deliveries of an issue. Deliveries that already went out are left as they are.
+#[tracing::instrument(name = "Cancel queued deliveries", skip(transaction))]
+pub async fn cancel_queued_deliveries(
+    transaction: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = $2
+        WHERE newsletter_issue_id = $1 AND status = 'queued'
+        "#,
+        newsletter_issue_id,
+        DeliveryStatus::Cancelled.as_str(),
+    )
+    .execute(transaction)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Picks up the sends that were still in progress when their sender stopped.
+#[tracing::instrument(name = "Resume pending deliveries", skip_all)]
+pub async fn resume_pending_deliveries(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    tracking_links: &TrackingLinks,
+) -> Result<(), anyhow::Error> {
+    let issues = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT
+            newsletter_issue_id AS id, list_id, title, text_content, html_content, track_opens,
+            track_clicks
+        FROM newsletter_issues
+        WHERE cancelled_at IS NULL
+            AND EXISTS (
+                SELECT 1 FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = newsletter_issues.newsletter_issue_id
+                    AND d.status IN ('queued', 'sending')
+            )
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the issues with pending deliveries.")?;
+
+    for issue in issues {
+        deliver_issue(pool, email_client, tracking_links, &issue).await?;
+    }
+    Ok(())
+}
+
+/// Marks up to `limit` queued deliveries as `sending` and returns what is needed to send them.
+///
+/// The claim is committed on its own, before anything is sent, so no lock is held while the
+/// emails go out.
+#[tracing::instrument(name = "Claim delivery tasks", skip(pool))]
+async fn claim_tasks(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    limit: usize,
+) -> Result<Vec<DeliveryTask>, sqlx::Error> {
+    let tasks = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        WITH claimed AS (
+            UPDATE issue_deliveries
+            SET status = 'sending', attempts = attempts + 1, last_attempted_at = NOW()
+            WHERE (newsletter_issue_id, subscriber_id) IN (
+                SELECT newsletter_issue_id, subscriber_id
+                FROM issue_deliveries
+                WHERE newsletter_issue_id = $1 AND status = 'queued'
+                LIMIT $2
+                FOR UPDATE
+                SKIP LOCKED
+            )
+            RETURNING newsletter_issue_id, subscriber_id, tracking_token, attempts
+        )
+        SELECT
+            c.subscriber_id AS "subscriber_id!", c.attempts AS "attempts!", s.email, s.name,
+            s.preferences_token, c.tracking_token,
+            m.unsubscribe_token AS "unsubscribe_token?",
+            COALESCE(
+                (
+                    SELECT jsonb_object_agg(v.field_key, v.value)
+                    FROM subscriber_field_values v
+                    WHERE v.subscriber_id = s.id
+                ),
+                '{}'
+            ) AS "fields!: Json<HashMap<String, String>>"
+        FROM claimed c
+        JOIN subscriptions s ON s.id = c.subscriber_id
+        JOIN newsletter_issues i ON i.newsletter_issue_id = c.newsletter_issue_id
+        LEFT JOIN list_memberships m ON m.list_id = i.list_id AND m.subscriber_id = s.id
+        "#,
+        newsletter_issue_id,
+        limit as i64,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(tasks)
+}
+
+/// Records the trackable links of `issue`, returning the ID of each by URL. Links registered by
+/// an earlier, interrupted send keep their ID.
+#[tracing::instrument(name = "Register issue links", skip_all, fields(newsletter_issue_id = %issue.id))]
+async fn register_links(
+    pool: &PgPool,
+    issue: &NewsletterIssue,
+) -> Result<HashMap<String, i64>, sqlx::Error> {
+    let links = trackable_links(&issue.html_content, &issue.text_content);
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_links (newsletter_issue_id, url)
+        SELECT $1, url FROM UNNEST($2::text[]) AS url
+        ON CONFLICT DO NOTHING
+        "#,
+        issue.id,
+        &links,
+    )
+    .execute(pool)
+    .await?;
+
+    let rows = sqlx::query!(
+        "SELECT link_id, url FROM issue_links WHERE newsletter_issue_id = $1",
+        issue.id,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|row| (row.url, row.link_id)).collect())
+}
+
+#[tracing::instrument(name = "Mark delivery as sent", skip(transaction))]
+async fn mark_delivery_sent(
+    transaction: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    provider_message_id: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = $3, provider_message_id = $4, sent_at = NOW()
+        WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+        "#,
+        newsletter_issue_id,
+        subscriber_id,
+        DeliveryStatus::Sent.as_str(),
+        provider_message_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Mark delivery as failed", skip(transaction))]
+async fn mark_delivery_failed(
+    transaction: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = $3, last_error = $4
+        WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+        "#,
+        newsletter_issue_id,
+        subscriber_id,
+        DeliveryStatus::Failed.as_str(),
+        error,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Puts a delivery back in the queue, or cancels it if the issue was cancelled while it was being
+/// sent: [`cancel_queued_deliveries`] only sees the deliveries that were queued at the time.
+///
+/// The issue is locked so a cancellation either commits before this, and is seen here, or waits
+/// for it, and then cancels the requeued delivery itself.
+#[tracing::instrument(name = "Queue delivery again", skip(transaction))]
+async fn requeue_delivery(
+    transaction: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries d
+        SET status = CASE WHEN i.cancelled_at IS NULL THEN $3 ELSE $4 END, last_error = $5
+        FROM (
+            SELECT cancelled_at FROM newsletter_issues
+            WHERE newsletter_issue_id = $1
+            FOR SHARE
+        ) i
+        WHERE d.newsletter_issue_id = $1 AND d.subscriber_id = $2
+        "#,
+        newsletter_issue_id,
+        subscriber_id,
+        DeliveryStatus::Queued.as_str(),
+        DeliveryStatus::Cancelled.as_str(),
+        error,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
@@ -1,7 +1,29 @@
+pub mod address_policy;
+pub mod audit_log;
 pub mod authentication;
+pub mod bot_protection;
+pub mod cli;
+pub mod client_ip;
 pub mod configuration;
+pub mod consent;
+pub mod custom_fields;
+pub mod data_subject;
 pub mod domain;
+pub mod email_change;
 pub mod email_client;
+pub mod import_confirmation;
+pub mod issue_delivery;
+pub mod mail_domain;
+pub mod mailing_list;
+pub mod rate_limiter;
 pub mod routes;
+pub mod segments;
+pub mod send_queue;
 pub mod startup;
+pub mod subscriber_export;
+pub mod subscriber_import;
+pub mod subscription_events;
+pub mod suppression;
+pub mod tags;
 pub mod telemetry;
+pub mod tracking;
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use hickory_resolver::error::{ResolveError, ResolveErrorKind};
+use hickory_resolver::proto::op::ResponseCode;
+use hickory_resolver::{Name, TokioAsyncResolver};
+use tokio::time::Instant;
+
+/// Domains most subscribers have their address at, which unknown domains are compared to.
+const COMMON_DOMAINS: &[&str] = &[
+    "gmail.com",
+    "googlemail.com",
+    "yahoo.com",
+    "yahoo.co.uk",
+    "yahoo.fr",
+    "hotmail.com",
+    "hotmail.co.uk",
+    "hotmail.fr",
+    "outlook.com",
+    "live.com",
+    "msn.com",
+    "icloud.com",
+    "me.com",
+    "aol.com",
+    "protonmail.com",
+    "proton.me",
+    "gmx.com",
+    "gmx.de",
+    "web.de",
+    "mail.com",
+    "yandex.ru",
+    "mail.ru",
+    "orange.fr",
+    "free.fr",
+    "comcast.net",
+];
+
+/// Answers are dropped once this many domains are cached, to bound memory.
+const MAX_CACHED_DOMAINS: usize = 10_000;
+/// How long a domain found not to receive email is remembered at most, so that one whose mail
+/// servers are being set up, or that was briefly missing, is not turned away for long.
+const NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Checks that domains can receive email, i.e. have MX records or, failing that, A records, by
+/// asking a DNS server.
+///
+/// Answers are cached, the negative ones only briefly. A domain is assumed to be fine when the
+/// server does not answer in time: a slow resolver should not stop anyone from subscribing.
+///
+/// Clones share the same cache.
+#[derive(Clone)]
+pub struct MailDomainCheck {
+    resolver: TokioAsyncResolver,
+    timeout: Duration,
+    cache_ttl: Duration,
+    cache: Arc<Mutex<HashMap<String, CachedAnswer>>>,
+}
+
+struct CachedAnswer {
+    receives_mail: bool,
+    expires_at: Instant,
+}
+
+impl MailDomainCheck {
+    pub fn new(resolver: SocketAddr, timeout: Duration, cache_ttl: Duration) -> Self {
+        let config = ResolverConfig::from_parts(
+            None,
+            vec![],
+            NameServerConfigGroup::from_ips_clear(&[resolver.ip()], resolver.port(), true),
+        );
+        let mut options = ResolverOpts::default();
+        options.timeout = timeout;
+        // A single try: the whole check is bounded by `timeout` anyway.
+        options.attempts = 0;
+        // Answers are cached here, once per domain.
+        options.cache_size = 0;
+        options.use_hosts_file = false;
+        Self {
+            resolver: TokioAsyncResolver::tokio(config, options),
+            timeout,
+            cache_ttl,
+            cache: Arc::default(),
+        }
+    }
+
+    /// Returns why addresses at `domain` cannot receive email, if they cannot, with a suggestion
+    /// when it looks like a typo.
+    #[tracing::instrument(name = "Check the mail servers of a domain", skip(self))]
+    pub async fn check(&self, domain: &str) -> Result<(), String> {
+        let receives_mail = match self.cached(domain) {
+            Some(receives_mail) => receives_mail,
+            None => match tokio::time::timeout(self.timeout, self.lookup(domain)).await {
+                Ok(Ok(receives_mail)) => {
+                    self.store(domain, receives_mail);
+                    receives_mail
+                }
+                Ok(Err(e)) => {
+                    tracing::warn!(error.cause_chain = ?e, "Failed to look up the mail servers, accepting the domain");
+                    true
+                }
+                Err(_) => {
+                    tracing::warn!("Timed out looking up the mail servers, accepting the domain");
+                    true
+                }
+            },
+        };
+        if receives_mail {
+            return Ok(());
+        }
+        Err(match suggest_domain(domain) {
+            Some(suggestion) => format!(
+                "{} does not receive emails, did you mean {}?",
+                domain, suggestion
+            ),
+            None => format!("{} does not receive emails", domain),
+        })
+    }
+
+    fn cached(&self, domain: &str) -> Option<bool> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(domain)
+            .filter(|answer| answer.expires_at > Instant::now())
+            .map(|answer| answer.receives_mail)
+    }
+
+    fn store(&self, domain: &str, receives_mail: bool) {
+        let now = Instant::now();
+        let ttl = if receives_mail {
+            self.cache_ttl
+        } else {
+            self.cache_ttl.min(NEGATIVE_CACHE_TTL)
+        };
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_DOMAINS {
+            cache.retain(|_, answer| answer.expires_at > now);
+            if cache.len() >= MAX_CACHED_DOMAINS {
+                cache.clear();
+            }
+        }
+        cache.insert(
+            domain.to_owned(),
+            CachedAnswer {
+                receives_mail,
+                expires_at: now + ttl,
+            },
+        );
+    }
+
+    async fn lookup(&self, domain: &str) -> Result<bool, ResolveError> {
+        let mut name = Name::from_utf8(domain)?;
+        name.set_fqdn(true);
+        match self.resolver.mx_lookup(name.clone()).await {
+            // A "null MX", with the root as its exchange, says the domain receives no email, see
+            // RFC 7505.
+            Ok(servers) => return Ok(servers.iter().any(|mx| !mx.exchange().is_root())),
+            Err(e) => match e.kind() {
+                ResolveErrorKind::NoRecordsFound { response_code, .. } => {
+                    if *response_code == ResponseCode::NXDomain {
+                        return Ok(false);
+                    }
+                }
+                _ => return Err(e),
+            },
+        }
+        match self.resolver.ipv4_lookup(name).await {
+            Ok(_) => Ok(true),
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Returns the common domain `domain` is most likely a typo of, if any.
+fn suggest_domain(domain: &str) -> Option<&'static str> {
+    if COMMON_DOMAINS.contains(&domain) {
+        return None;
+    }
+    COMMON_DOMAINS
+        .iter()
+        .map(|candidate| (edit_distance(domain, candidate), *candidate))
+        .filter(|(distance, _)| (1..=2).contains(distance))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Counts the insertions, deletions, substitutions and swaps of adjacent characters needed to turn
+/// `a` into `b`, swaps being the most common typo.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mail_domain::{edit_distance, suggest_domain};
+
+    #[test]
+    fn typos_of_common_domains_get_a_suggestion() {
+        assert_eq!(edit_distance("gmial.com", "gmail.com"), 1);
+        assert_eq!(suggest_domain("gmial.com"), Some("gmail.com"));
+        assert_eq!(suggest_domain("hotmal.co.uk"), Some("hotmail.co.uk"));
+        assert_eq!(suggest_domain("gmail.com"), None);
+        assert_eq!(suggest_domain("example.com"), None);
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::Context;
+use sqlx::{PgConnection, PgExecutor};
+use uuid::Uuid;
+
+use crate::domain::newsletter_issue::TemplateVariables;
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::subscription_events::{record_subscription_event, SubscriptionEventType};
+
+/// The list subscribers and issues belong to when no other list is given.
+pub const DEFAULT_LIST_ID: Uuid = Uuid::from_u128(1);
+
+/// The consent statement of lists that do not have their own.
+pub const DEFAULT_CONSENT_TEXT: &str =
+    "I agree to receive this newsletter by email and know that I can unsubscribe at any time.";
+
+#[derive(Debug, Clone)]
+pub struct MailingList {
+    pub id: Uuid,
+    pub name: String,
+    /// Overrides the sender configured for the email client.
+    pub sender_email: Option<String>,
+    pub confirmation: ConfirmationTemplate,
+    /// The consent statement shown next to the list's sign-up forms.
+    pub consent_text: String,
+}
+
+/// The email asking a new subscriber to confirm, with `{{ confirmation_link }}`, `{{ list_name }}`
+/// and the subscriber's `{{ name }}` and `{{ email }}` as placeholders.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct ConfirmationTemplate {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+impl Default for ConfirmationTemplate {
+    fn default() -> Self {
+        Self {
+            subject: "Welcome to the Newsletter".into(),
+            text: "Welcome to our newsletter!\n\
+                Visit {{ confirmation_link }} to confirm your subscription."
+                .into(),
+            html: "Welcome to our newsletter!<br />\
+                Click <a href=\"{{ confirmation_link }}\">here</a> to confirm your subscription."
+                .into(),
+        }
+    }
+}
+
+impl MailingList {
+    /// The client sending on behalf of this list.
+    pub fn email_client(&self, email_client: &EmailClient) -> Result<EmailClient, anyhow::Error> {
+        match &self.sender_email {
+            Some(sender) => {
+                let sender = SubscriberEmail::parse(sender.clone())
+                    .map_err(anyhow::Error::msg)
+                    .context("The sender of the list is invalid.")?;
+                Ok(email_client.with_sender(sender))
+            }
+            None => Ok(email_client.clone()),
+        }
+    }
+
+    /// Renders the confirmation email as `(subject, html, text)`.
+    pub fn render_confirmation(
+        &self,
+        mut variables: TemplateVariables,
+        confirmation_link: &str,
+    ) -> (String, String, String) {
+        variables.insert("confirmation_link", confirmation_link);
+        variables.insert("list_name", self.name.as_str());
+        (
+            variables.render_text(&self.confirmation.subject),
+            variables.render_html(&self.confirmation.html),
+            variables.render_text(&self.confirmation.text),
+        )
+    }
+}
+
+#[tracing::instrument(name = "Get mailing list", skip(executor))]
+pub async fn get_list(
+    executor: impl PgExecutor<'_>,
+    list_id: Uuid,
+) -> Result<Option<MailingList>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            list_id, name, sender_email, confirmation_subject, confirmation_text,
+            confirmation_html, consent_text
+        FROM lists
+        WHERE list_id = $1
+        "#,
+        list_id,
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.map(|row| {
+        let default = ConfirmationTemplate::default();
+        MailingList {
+            id: row.list_id,
+            name: row.name,
+            sender_email: row.sender_email,
+            confirmation: ConfirmationTemplate {
+                subject: row.confirmation_subject.unwrap_or(default.subject),
+                text: row.confirmation_text.unwrap_or(default.text),
+                html: row.confirmation_html.unwrap_or(default.html),
+            },
+            consent_text: row
+                .consent_text
+                .unwrap_or_else(|| DEFAULT_CONSENT_TEXT.into()),
+        }
+    }))
+}
+
+/// Unsubscribes a subscriber from a list, returning whether they were still subscribed to it.
+/// Subscribers left without any list are marked as unsubscribed altogether.
+#[tracing::instrument(name = "Unsubscribe from a mailing list", skip(transaction))]
+pub async fn unsubscribe(
+    transaction: &mut PgConnection,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+    newsletter_issue_id: Option<Uuid>,
+) -> Result<bool, sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'unsubscribed', unsubscribed_at = NOW()
+        WHERE list_id = $1 AND subscriber_id = $2 AND status <> 'unsubscribed'
+        "#,
+        list_id,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed'
+        WHERE id = $1 AND NOT EXISTS (
+            SELECT 1 FROM list_memberships WHERE subscriber_id = $1 AND status <> 'unsubscribed'
+        )
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    record_subscription_event(
+        transaction,
+        subscriber_id,
+        list_id,
+        SubscriptionEventType::Unsubscribed,
+        newsletter_issue_id,
+    )
+    .await?;
+    Ok(true)
+}
+
+/// Adds a subscriber whose address is already verified to a list, without asking them to confirm
+/// again. Returns whether they were not confirmed on it yet.
+#[tracing::instrument(name = "Join a mailing list", skip(transaction))]
+pub async fn join_confirmed(
+    transaction: &mut PgConnection,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let joined = sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (
+            list_id, subscriber_id, status, unsubscribe_token, confirmed_at
+        )
+        VALUES ($1, $2, 'confirmed', replace(gen_random_uuid()::text, '-', ''), NOW())
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = 'confirmed', confirmed_at = NOW(), unsubscribed_at = NULL
+        WHERE list_memberships.status <> 'confirmed'
+        "#,
+        list_id,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    if joined.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'confirmed' WHERE id = $1",
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    record_subscription_event(
+        transaction,
+        subscriber_id,
+        list_id,
+        SubscriptionEventType::Confirmed,
+        None,
+    )
+    .await?;
+    Ok(true)
+}
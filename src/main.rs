@@ -1,4 +1,5 @@
 use zero2prod::{
+    cli,
     configuration::get_configuration,
     startup::Application,
     telemetry::{get_subscriber, init_subscriber},
@@ -6,6 +7,34 @@ use zero2prod::{
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        None => {}
+        Some("export") => {
+            // Standard output carries the export itself.
+            let subscriber = get_subscriber("zero2prod".into(), "warn".into(), std::io::stderr);
+            init_subscriber(subscriber);
+            if let Err(e) = cli::export(args).await {
+                eprintln!("{:?}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some("import") => {
+            let subscriber = get_subscriber("zero2prod".into(), "warn".into(), std::io::stderr);
+            init_subscriber(subscriber);
+            if let Err(e) = cli::import(args).await {
+                eprintln!("{:?}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(_) => {
+            eprintln!("{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    }
+
     let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
     init_subscriber(subscriber);
 
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Paces outbound sends to a global rate and, optionally, to a rate per recipient domain.
+///
+/// Clones share the same state, so a single limiter applies to every worker of the process.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    global_interval: Option<Duration>,
+    domain_intervals: Arc<HashMap<String, Duration>>,
+    schedule: Arc<Mutex<Schedule>>,
+    stats: Arc<Stats>,
+}
+
+#[derive(Default)]
+struct Schedule {
+    next_global_slot: Option<Instant>,
+    next_domain_slots: HashMap<String, Instant>,
+}
+
+#[derive(Default)]
+struct Stats {
+    throttled_sends: AtomicU64,
+    wait_ms: AtomicU64,
+}
+
+/// How much the limiter has held back sends since the process started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottleStats {
+    pub throttled_sends: u64,
+    pub total_wait: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(
+        sends_per_second: Option<f64>,
+        domain_sends_per_second: HashMap<String, f64>,
+    ) -> Self {
+        Self {
+            global_interval: sends_per_second.and_then(interval),
+            domain_intervals: Arc::new(
+                domain_sends_per_second
+                    .into_iter()
+                    .filter_map(|(domain, rate)| Some((domain.to_lowercase(), interval(rate)?)))
+                    .collect(),
+            ),
+            schedule: Arc::default(),
+            stats: Arc::default(),
+        }
+    }
+
+    /// Waits until a send to `domain` is allowed and returns how long it had to wait.
+    pub async fn acquire(&self, domain: &str) -> Duration {
+        let slot = self.reserve(domain);
+        let wait = slot.saturating_duration_since(Instant::now());
+        tokio::time::sleep_until(slot).await;
+        wait
+    }
+
+    /// Reserves the next slot for a send to `domain` and returns when it starts, without waiting
+    /// for it.
+    ///
+    /// The global and the domain schedules advance independently: a send held back by its
+    /// domain's limit does not push back the sends to other domains.
+    pub fn reserve(&self, domain: &str) -> Instant {
+        let domain = domain.to_lowercase();
+        let domain_interval = self.domain_intervals.get(&domain).copied();
+        let now = Instant::now();
+        if self.global_interval.is_none() && domain_interval.is_none() {
+            return now;
+        }
+
+        let slot = {
+            let mut schedule = self.schedule.lock().unwrap();
+            let mut slot = now;
+            if let Some(interval) = self.global_interval {
+                let global_slot = schedule.next_global_slot.map_or(now, |next| next.max(now));
+                schedule.next_global_slot = Some(global_slot + interval);
+                slot = slot.max(global_slot);
+            }
+            if let Some(interval) = domain_interval {
+                let domain_slot = schedule
+                    .next_domain_slots
+                    .get(&domain)
+                    .map_or(now, |next| (*next).max(now));
+                schedule
+                    .next_domain_slots
+                    .insert(domain.clone(), domain_slot + interval);
+                slot = slot.max(domain_slot);
+            }
+            slot
+        };
+
+        let wait = slot - now;
+        if !wait.is_zero() {
+            let wait_ms = wait.as_millis() as u64;
+            let throttled_sends = self.stats.throttled_sends.fetch_add(1, Ordering::Relaxed) + 1;
+            let total_wait_ms = self.stats.wait_ms.fetch_add(wait_ms, Ordering::Relaxed) + wait_ms;
+            tracing::info!(
+                recipient_domain = %domain,
+                wait_ms,
+                throttled_sends,
+                total_wait_ms,
+                "Throttling outbound email"
+            );
+        }
+        slot
+    }
+
+    pub fn stats(&self) -> ThrottleStats {
+        ThrottleStats {
+            throttled_sends: self.stats.throttled_sends.load(Ordering::Relaxed),
+            total_wait: Duration::from_millis(self.stats.wait_ms.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+fn interval(sends_per_second: f64) -> Option<Duration> {
+    (sends_per_second > 0.0).then(|| Duration::from_secs_f64(1.0 / sends_per_second))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::rate_limiter::RateLimiter;
+
+    #[tokio::test]
+    async fn an_unlimited_limiter_never_waits() {
+        let limiter = RateLimiter::default();
+        for _ in 0..100 {
+            assert_eq!(limiter.acquire("example.com").await, Duration::ZERO);
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_are_spaced_by_the_global_rate() {
+        let limiter = RateLimiter::new(Some(20.0), HashMap::new());
+        let start = std::time::Instant::now();
+        for _ in 0..3 {
+            limiter.acquire("example.com").await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn domain_limits_do_not_slow_down_other_domains() {
+        let domains = HashMap::from([("Gmail.com".to_string(), 5.0)]);
+        let limiter = RateLimiter::new(None, domains);
+
+        assert_eq!(limiter.acquire("gmail.com").await, Duration::ZERO);
+        assert_eq!(limiter.acquire("example.com").await, Duration::ZERO);
+        assert!(limiter.acquire("GMAIL.COM").await > Duration::from_millis(150));
+    }
+
+    #[tokio::test]
+    async fn a_throttled_domain_does_not_push_back_the_global_schedule() {
+        let domains = HashMap::from([("gmail.com".to_string(), 5.0)]);
+        let limiter = RateLimiter::new(Some(20.0), domains);
+
+        let start = limiter.reserve("gmail.com");
+        assert!(start <= Instant::now());
+        let throttled = limiter.reserve("gmail.com");
+        let other = limiter.reserve("example.com");
+
+        assert!(throttled - start >= Duration::from_millis(200));
+        assert!(other < throttled);
+        assert!(other - start <= Duration::from_millis(110));
+    }
+
+    #[tokio::test]
+    async fn throttled_sends_are_counted() {
+        let limiter = RateLimiter::new(Some(10.0), HashMap::new());
+
+        limiter.acquire("example.com").await;
+        assert_eq!(limiter.stats().throttled_sends, 0);
+        limiter.acquire("example.com").await;
+
+        let stats = limiter.stats();
+        assert_eq!(stats.throttled_sends, 1);
+        assert!(stats.total_wait >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn clones_share_the_same_schedule() {
+        let limiter = RateLimiter::new(Some(5.0), HashMap::new());
+        let clone = limiter.clone();
+
+        assert_eq!(limiter.acquire("example.com").await, Duration::ZERO);
+        assert!(clone.acquire("example.com").await > Duration::from_millis(150));
+    }
+}
@@ -0,0 +1,414 @@
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::email_client::EmailClient;
+use crate::routes::admin::{authenticate, AdminError};
+
+/// The longest range, in days, a single analytics request may cover.
+const MAX_RANGE_DAYS: i64 = 366;
+/// The range covered when none is given.
+const DEFAULT_RANGE_DAYS: i64 = 30;
+
+/// An inclusive range of UTC days, `?from=YYYY-MM-DD&to=YYYY-MM-DD`.
+#[derive(serde::Deserialize)]
+struct DateRangeParameters {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(serde::Deserialize)]
+struct SubscriberAnalyticsParameters {
+    #[serde(flatten)]
+    range: DateRangeParameters,
+    /// Limits the conversion to the signups onto this list, it covers every list otherwise.
+    list_id: Option<Uuid>,
+}
+
+struct DateRange {
+    from: NaiveDate,
+    to: NaiveDate,
+}
+
+impl DateRange {
+    fn parse(parameters: &DateRangeParameters) -> Result<Self, AdminError> {
+        let to = parameters.to.unwrap_or_else(|| Utc::now().date_naive());
+        let from = parameters
+            .from
+            .unwrap_or(to - Duration::days(DEFAULT_RANGE_DAYS - 1));
+        if from > to {
+            return Err(AdminError::ValidationError(
+                "`from` must not be after `to`.".into(),
+            ));
+        }
+        if (to - from).num_days() >= MAX_RANGE_DAYS {
+            return Err(AdminError::ValidationError(format!(
+                "The date range cannot be longer than {} days.",
+                MAX_RANGE_DAYS
+            )));
+        }
+        Ok(Self { from, to })
+    }
+
+    /// The first instant of the range.
+    fn start(&self) -> DateTime<Utc> {
+        self.from.and_hms_opt(0, 0, 0).unwrap().and_utc()
+    }
+
+    /// The first instant after the range.
+    fn end(&self) -> DateTime<Utc> {
+        (self.to + Duration::days(1))
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc()
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberAnalytics {
+    from: NaiveDate,
+    to: NaiveDate,
+    /// Current number of subscribers by status, regardless of the range.
+    by_status: Vec<StatusCount>,
+    daily: Vec<DailySubscriberActivity>,
+    conversion: ConfirmationConversion,
+    /// Subscriptions turned away as automated in the range, by reason.
+    rejected_signups: Vec<RejectionCount>,
+}
+
+#[derive(serde::Serialize)]
+struct RejectionCount {
+    reason: String,
+    count: i64,
+}
+
+#[derive(serde::Serialize)]
+struct StatusCount {
+    status: String,
+    count: i64,
+}
+
+#[derive(serde::Serialize)]
+struct DailySubscriberActivity {
+    date: NaiveDate,
+    signups: i64,
+    confirmations: i64,
+    unsubscribes: i64,
+    bounces: i64,
+}
+
+/// How many of the list memberships started in the range went on to be confirmed. A member who
+/// confirmed and later unsubscribed still counts as confirmed; one who never did, does not.
+#[derive(serde::Serialize)]
+struct ConfirmationConversion {
+    signups: i64,
+    confirmed: i64,
+    rate: f64,
+}
+
+#[derive(serde::Serialize)]
+struct SendingAnalytics {
+    /// Sends that had to wait for a slot.
+    throttled_sends: u64,
+    total_wait_ms: u64,
+}
+
+#[derive(serde::Serialize)]
+struct IssueAnalytics {
+    from: NaiveDate,
+    to: NaiveDate,
+    issues: Vec<IssueEngagement>,
+}
+
+/// Engagement with an issue published in the range. Rates are relative to the deliveries that
+/// were sent and did not bounce.
+#[derive(serde::Serialize)]
+struct IssueEngagement {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+    delivered: i64,
+    opened: i64,
+    open_rate: f64,
+    clicked: i64,
+    click_rate: f64,
+    unsubscribed: i64,
+    unsubscribe_rate: f64,
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Report subscriber analytics",
+    skip(parameters, pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/analytics/subscribers")]
+pub async fn subscriber_analytics(
+    parameters: web::Query<SubscriberAnalyticsParameters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let range = DateRange::parse(&parameters.range)?;
+
+    let by_status = get_status_counts(&pool)
+        .await
+        .context("Failed to count the subscribers by status.")?;
+    let daily = get_daily_activity(&pool, &range)
+        .await
+        .context("Failed to retrieve the daily subscriber activity.")?;
+    let conversion = get_confirmation_conversion(&pool, &range, parameters.list_id)
+        .await
+        .context("Failed to compute the confirmation conversion rate.")?;
+    let rejected_signups = get_rejection_counts(&pool, &range)
+        .await
+        .context("Failed to count the rejected signups.")?;
+
+    Ok(HttpResponse::Ok().json(SubscriberAnalytics {
+        from: range.from,
+        to: range.to,
+        by_status,
+        daily,
+        conversion,
+        rejected_signups,
+    }))
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Report issue analytics",
+    skip(parameters, pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/analytics/issues")]
+pub async fn issue_analytics(
+    parameters: web::Query<DateRangeParameters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let range = DateRange::parse(&parameters)?;
+
+    let issues = get_issue_engagement(&pool, &range)
+        .await
+        .context("Failed to compute the engagement with newsletter issues.")?;
+
+    Ok(HttpResponse::Ok().json(IssueAnalytics {
+        from: range.from,
+        to: range.to,
+        issues,
+    }))
+}
+
+/// How much the outbound rate limits, see `EmailClientSettings`, held back sending since the
+/// application started.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Report sending analytics",
+    skip(pool, email_client, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/analytics/sending")]
+pub async fn sending_analytics(
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let stats = email_client.throttle_stats();
+
+    Ok(HttpResponse::Ok().json(SendingAnalytics {
+        throttled_sends: stats.throttled_sends,
+        total_wait_ms: stats.total_wait.as_millis() as u64,
+    }))
+}
+
+fn rate(count: i64, total: i64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+#[tracing::instrument(name = "Count subscribers by status", skip(pool))]
+async fn get_status_counts(pool: &PgPool) -> Result<Vec<StatusCount>, sqlx::Error> {
+    sqlx::query_as!(
+        StatusCount,
+        r#"
+        SELECT status, COUNT(*) AS "count!"
+        FROM subscriptions
+        GROUP BY status
+        ORDER BY status
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Each source is aggregated on its own, through an index on its timestamp, before being joined
+/// to the days of the range.
+#[tracing::instrument(name = "Get daily subscriber activity", skip(pool, range))]
+async fn get_daily_activity(
+    pool: &PgPool,
+    range: &DateRange,
+) -> Result<Vec<DailySubscriberActivity>, sqlx::Error> {
+    sqlx::query_as!(
+        DailySubscriberActivity,
+        r#"
+        WITH days AS (
+            SELECT generate_series($1::date, $2::date, INTERVAL '1 day')::date AS day
+        ),
+        signups AS (
+            SELECT (subscribed_at AT TIME ZONE 'UTC')::date AS day, COUNT(*) AS count
+            FROM subscriptions
+            WHERE subscribed_at >= $3 AND subscribed_at < $4
+            GROUP BY 1
+        ),
+        lifecycle AS (
+            SELECT
+                (occurred_at AT TIME ZONE 'UTC')::date AS day,
+                COUNT(*) FILTER (WHERE event_type = 'confirmed') AS confirmations,
+                COUNT(*) FILTER (WHERE event_type = 'unsubscribed') AS unsubscribes
+            FROM subscription_events
+            WHERE event_type IN ('confirmed', 'unsubscribed')
+                AND occurred_at >= $3 AND occurred_at < $4
+            GROUP BY 1
+        ),
+        bounces AS (
+            SELECT (occurred_at AT TIME ZONE 'UTC')::date AS day, COUNT(*) AS count
+            FROM email_events
+            WHERE event_type = 'bounce' AND occurred_at >= $3 AND occurred_at < $4
+            GROUP BY 1
+        )
+        SELECT
+            days.day AS "date!",
+            COALESCE(signups.count, 0) AS "signups!",
+            COALESCE(lifecycle.confirmations, 0) AS "confirmations!",
+            COALESCE(lifecycle.unsubscribes, 0) AS "unsubscribes!",
+            COALESCE(bounces.count, 0) AS "bounces!"
+        FROM days
+        LEFT JOIN signups ON signups.day = days.day
+        LEFT JOIN lifecycle ON lifecycle.day = days.day
+        LEFT JOIN bounces ON bounces.day = days.day
+        ORDER BY days.day
+        "#,
+        range.from,
+        range.to,
+        range.start(),
+        range.end(),
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Count rejected signups", skip(pool, range))]
+async fn get_rejection_counts(
+    pool: &PgPool,
+    range: &DateRange,
+) -> Result<Vec<RejectionCount>, sqlx::Error> {
+    sqlx::query_as!(
+        RejectionCount,
+        r#"
+        SELECT reason, SUM(count)::bigint AS "count!"
+        FROM rejected_signups
+        WHERE day BETWEEN $1 AND $2
+        GROUP BY reason
+        ORDER BY reason
+        "#,
+        range.from,
+        range.to,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Compute the confirmation conversion", skip(pool, range))]
+async fn get_confirmation_conversion(
+    pool: &PgPool,
+    range: &DateRange,
+    list_id: Option<Uuid>,
+) -> Result<ConfirmationConversion, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) AS "signups!",
+            COUNT(*) FILTER (WHERE confirmed_at IS NOT NULL) AS "confirmed!"
+        FROM list_memberships
+        WHERE subscribed_at >= $1 AND subscribed_at < $2
+            AND ($3::uuid IS NULL OR list_id = $3)
+        "#,
+        range.start(),
+        range.end(),
+        list_id,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(ConfirmationConversion {
+        signups: row.signups,
+        confirmed: row.confirmed,
+        rate: rate(row.confirmed, row.signups),
+    })
+}
+
+#[tracing::instrument(name = "Get issue engagement", skip(pool, range))]
+async fn get_issue_engagement(
+    pool: &PgPool,
+    range: &DateRange,
+) -> Result<Vec<IssueEngagement>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at AS "published_at!",
+            (
+                SELECT COUNT(*)
+                FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.status = 'sent'
+            ) AS "delivered!",
+            (
+                SELECT COUNT(*)
+                FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.open_count > 0
+            ) AS "opened!",
+            (
+                SELECT COUNT(DISTINCT c.subscriber_id)
+                FROM link_clicks c
+                JOIN issue_links l ON l.link_id = c.link_id
+                WHERE l.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "clicked!",
+            (
+                SELECT COUNT(*)
+                FROM subscription_events e
+                WHERE e.newsletter_issue_id = i.newsletter_issue_id
+                    AND e.event_type = 'unsubscribed'
+            ) AS "unsubscribed!"
+        FROM newsletter_issues i
+        WHERE i.published_at >= $1 AND i.published_at < $2
+        ORDER BY i.published_at DESC
+        "#,
+        range.start(),
+        range.end(),
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| IssueEngagement {
+            newsletter_issue_id: row.newsletter_issue_id,
+            title: row.title,
+            published_at: row.published_at,
+            delivered: row.delivered,
+            opened: row.opened,
+            open_rate: rate(row.opened, row.delivered),
+            clicked: row.clicked,
+            click_rate: rate(row.clicked, row.delivered),
+            unsubscribed: row.unsubscribed,
+            unsubscribe_rate: rate(row.unsubscribed, row.delivered),
+        })
+        .collect())
+}
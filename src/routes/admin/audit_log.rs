@@ -0,0 +1,48 @@
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::audit_log::get_audit_entries;
+use crate::routes::admin::{authenticate, AdminError};
+
+const PAGE_SIZE: i64 = 100;
+
+#[derive(serde::Deserialize)]
+struct AuditLogParameters {
+    /// Only the actions taken on this subscriber.
+    subscriber_id: Option<Uuid>,
+    /// Starts at 1.
+    page: Option<i64>,
+}
+
+/// Lists the changes admins made to subscribers, most recent first.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Get the audit log",
+    skip(parameters, pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/audit_log")]
+pub async fn get_audit_log(
+    parameters: web::Query<AuditLogParameters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let page = parameters.page.unwrap_or(1);
+    if page < 1 {
+        return Err(AdminError::ValidationError(
+            "Pages are numbered from 1".into(),
+        ));
+    }
+    let entries = get_audit_entries(
+        pool.get_ref(),
+        parameters.subscriber_id,
+        PAGE_SIZE,
+        (page - 1) * PAGE_SIZE,
+    )
+    .await
+    .context("Failed to retrieve the audit log.")?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "page": page, "entries": entries })))
+}
@@ -0,0 +1,99 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::mailing_list::get_list;
+use crate::routes::admin::{authenticate, AdminError};
+use crate::segments::get_segment;
+use crate::subscriber_export::{export_subscribers, ExportFormat, ExportQuery};
+
+#[derive(serde::Deserialize)]
+struct ExportParameters {
+    #[serde(default)]
+    format: ExportFormat,
+    /// Only export the members of this list.
+    list_id: Option<Uuid>,
+    /// Only export the confirmed members matching this segment, on the default list unless
+    /// `list_id` is given.
+    segment_id: Option<Uuid>,
+}
+
+/// Streams subscribers as CSV or newline-delimited JSON. Rows are sent as they are read from the
+/// database; should reading fail halfway through, the response is cut short rather than completed.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Export subscribers",
+    skip(parameters, pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/subscribers/export")]
+pub async fn export_subscribers_file(
+    parameters: web::Query<ExportParameters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    if let Some(list_id) = parameters.list_id {
+        get_list(pool.get_ref(), list_id)
+            .await
+            .context("Failed to retrieve the mailing list.")?
+            .ok_or_else(|| {
+                AdminError::ValidationError("There is no list with the given identifier".into())
+            })?;
+    }
+    let segment = match parameters.segment_id {
+        Some(segment_id) => Some(
+            get_segment(pool.get_ref(), segment_id)
+                .await
+                .context("Failed to retrieve the segment.")?
+                .ok_or_else(|| {
+                    AdminError::ValidationError(
+                        "There is no segment with the given identifier".into(),
+                    )
+                })?
+                .filter,
+        ),
+        None => None,
+    };
+    let format = parameters.format;
+    let query = ExportQuery {
+        format,
+        list_id: parameters.list_id,
+        segment,
+    };
+
+    let (sender, mut receiver) = mpsc::channel::<Result<Bytes, std::io::Error>>(4);
+    let pool = pool.into_inner();
+    actix_web::rt::spawn(
+        async move {
+            let result = export_subscribers(&pool, &query, |chunk| {
+                let sender = sender.clone();
+                async move { sender.send(Ok(Bytes::from(chunk))).await.is_ok() }
+            })
+            .await;
+            if let Err(e) = result {
+                tracing::error!(error.cause_chain = ?e, "Failed to export subscribers");
+                let error = std::io::Error::other("The export failed");
+                let _ = sender.send(Err(error)).await;
+            }
+        }
+        .instrument(tracing::Span::current()),
+    );
+    let body = futures_util::stream::poll_fn(move |cx| receiver.poll_recv(cx));
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "subscribers.{}",
+                format.extension()
+            ))],
+        })
+        .streaming(body))
+}
@@ -0,0 +1,94 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::bot_protection::BotProtection;
+use crate::custom_fields::{get_custom_fields, insert_custom_field};
+use crate::domain::custom_field::CustomField;
+use crate::routes::admin::{authenticate, AdminError};
+use crate::routes::subscription_form_keys;
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Create a custom field",
+    skip(body, pool, bot_protection, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[post("/admin/fields")]
+pub async fn create_custom_field(
+    body: web::Json<CustomField>,
+    pool: web::Data<PgPool>,
+    bot_protection: web::Data<BotProtection>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let field = body
+        .into_inner()
+        .parse()
+        .map_err(AdminError::ValidationError)?;
+    // Values are submitted next to the other fields of the subscription form, which would take
+    // them, or trip bot protection for the honeypot.
+    if subscription_form_keys().contains(&field.key.as_str())
+        || bot_protection.honeypot_field() == Some(field.key.as_str())
+    {
+        return Err(AdminError::ValidationError(format!(
+            "{} is a field of the subscription form and cannot be used as a key",
+            field.key
+        )));
+    }
+    let existing = get_custom_fields(pool.get_ref())
+        .await
+        .context("Failed to retrieve the custom fields.")?;
+    if existing.iter().any(|f| f.key == field.key) {
+        return Err(AdminError::ValidationError(format!(
+            "There already is a field named {}",
+            field.key
+        )));
+    }
+    insert_custom_field(pool.get_ref(), &field)
+        .await
+        .context("Failed to store the custom field.")?;
+    Ok(HttpResponse::Ok().json(field))
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "List custom fields",
+    skip(pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/fields")]
+pub async fn get_custom_field_definitions(
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let fields = get_custom_fields(pool.get_ref())
+        .await
+        .context("Failed to retrieve the custom fields.")?;
+    Ok(HttpResponse::Ok().json(fields))
+}
+
+/// Deletes a field along with the values subscribers had for it.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Delete a custom field",
+    skip(pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[delete("/admin/fields/{key}")]
+pub async fn delete_custom_field(
+    key: web::Path<String>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let deleted = sqlx::query!("DELETE FROM custom_fields WHERE key = $1", key.as_str())
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to delete the custom field.")?;
+    if deleted.rows_affected() == 0 {
+        return Err(AdminError::NotFound);
+    }
+    Ok(HttpResponse::Ok())
+}
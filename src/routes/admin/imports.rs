@@ -0,0 +1,85 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use futures_util::StreamExt;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::address_policy::AddressPolicy;
+use crate::email_client::EmailClient;
+use crate::import_confirmation::spawn_confirmation_sends;
+use crate::routes::admin::{authenticate, AdminError};
+use crate::startup::ApplicationBaseUrl;
+use crate::subscriber_import::{ImportError, ImportMode, Importer};
+
+#[derive(serde::Deserialize)]
+struct ImportParameters {
+    mode: ImportMode,
+    /// Required for confirmed imports: how and when the subscribers gave their consent.
+    consent: Option<String>,
+    /// The list to import into, the default list when absent.
+    list_id: Option<Uuid>,
+    /// Comma-separated `column:target` pairs, see `ColumnMapping::parse`.
+    #[serde(default)]
+    columns: String,
+}
+
+/// Imports subscribers from a CSV file sent as the request body, e.g. with
+/// `curl --data-binary @contacts.csv`. The file is processed as it is received, so there is no
+/// limit to its size. Rows that cannot be imported are listed in the response, which does not wait
+/// for the confirmation emails of a double opt-in import to be sent.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip(parameters, payload, pool, email_client, base_url, address_policy, request),
+    fields(
+        username = tracing::field::Empty,
+        user_id = tracing::field::Empty,
+        import_id = tracing::field::Empty,
+    )
+)]
+#[post("/admin/subscribers/import")]
+pub async fn import_subscribers(
+    parameters: web::Query<ImportParameters>,
+    mut payload: web::Payload,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    address_policy: web::Data<AddressPolicy>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    let user_id = authenticate(&request, &pool).await?;
+    let parameters = parameters.into_inner();
+    let mut importer = Importer::start(
+        pool.get_ref(),
+        &address_policy,
+        parameters.mode,
+        parameters.consent,
+        parameters.list_id,
+        parameters.columns,
+        Some(user_id),
+    )
+    .await?;
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.context("Failed to read the uploaded file.")?;
+        importer.push(&chunk).await?;
+    }
+    let report = importer.finish().await?;
+
+    if report.confirmations_queued > 0 {
+        spawn_confirmation_sends(
+            pool.get_ref().clone(),
+            email_client.get_ref().clone(),
+            base_url.0.clone(),
+        );
+    }
+    Ok(HttpResponse::Ok().json(report))
+}
+
+impl From<ImportError> for AdminError {
+    fn from(e: ImportError) -> Self {
+        match e {
+            ImportError::ValidationError(message) => AdminError::ValidationError(message),
+            ImportError::UnexpectedError(e) => AdminError::UnexpectedError(e),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::mailing_list::{ConfirmationTemplate, DEFAULT_CONSENT_TEXT};
+use crate::routes::admin::{authenticate, AdminError};
+
+#[derive(serde::Deserialize)]
+struct ListBody {
+    name: String,
+    /// The address the list's emails are sent from, the configured sender when absent.
+    sender_email: Option<String>,
+    /// The email asking new subscribers to confirm, the default one when absent.
+    confirmation: Option<ConfirmationTemplate>,
+    /// The consent statement shown next to the sign-up forms, the default one when absent.
+    consent_text: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct ListSummary {
+    list_id: Uuid,
+    name: String,
+    sender_email: Option<String>,
+    consent_text: String,
+    pending_confirmation: i64,
+    confirmed: i64,
+    unsubscribed: i64,
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Create a mailing list",
+    skip(body, pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[post("/admin/lists")]
+pub async fn create_list(
+    body: web::Json<ListBody>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let body = body.0;
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(AdminError::ValidationError(
+            "The name of the list cannot be empty".into(),
+        ));
+    }
+    let sender_email = body
+        .sender_email
+        .map(SubscriberEmail::parse)
+        .transpose()
+        .map_err(AdminError::ValidationError)?;
+    let confirmation = body.confirmation;
+    let consent_text = body
+        .consent_text
+        .map(|text| text.trim().to_owned())
+        .filter(|text| !text.is_empty());
+
+    let list_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO lists (
+            list_id, name, sender_email, confirmation_subject, confirmation_text,
+            confirmation_html, consent_text
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        list_id,
+        name,
+        sender_email.as_ref().map(|email| email.as_ref()),
+        confirmation.as_ref().map(|c| c.subject.as_str()),
+        confirmation.as_ref().map(|c| c.text.as_str()),
+        confirmation.as_ref().map(|c| c.html.as_str()),
+        consent_text,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the mailing list.")?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "list_id": list_id })))
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "List mailing lists",
+    skip(pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/lists")]
+pub async fn get_lists(
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let lists = sqlx::query_as!(
+        ListSummary,
+        r#"
+        SELECT
+            l.list_id,
+            l.name,
+            l.sender_email,
+            COALESCE(l.consent_text, $1) AS "consent_text!",
+            COUNT(*) FILTER (WHERE m.status = 'pending_confirmation') AS "pending_confirmation!",
+            COUNT(*) FILTER (WHERE m.status = 'confirmed') AS "confirmed!",
+            COUNT(*) FILTER (WHERE m.status = 'unsubscribed') AS "unsubscribed!"
+        FROM lists l
+        LEFT JOIN list_memberships m USING (list_id)
+        GROUP BY l.list_id
+        ORDER BY l.created_at, l.name
+        "#,
+        DEFAULT_CONSENT_TEXT,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the mailing lists.")?;
+    Ok(HttpResponse::Ok().json(lists))
+}
@@ -0,0 +1,134 @@
+mod analytics;
+mod audit_log;
+mod exports;
+mod fields;
+mod imports;
+mod lists;
+mod newsletters;
+mod privacy;
+mod segments;
+mod subscribers;
+
+pub use analytics::*;
+pub use audit_log::*;
+pub use exports::*;
+pub use fields::*;
+pub use imports::*;
+pub use lists::*;
+pub use newsletters::*;
+pub use privacy::*;
+pub use segments::*;
+pub use subscribers::*;
+
+use actix_http::body::{BoxBody, MessageBody};
+use actix_http::header::{HeaderValue, ORIGIN};
+use actix_http::{Method, StatusCode};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{basic_authentication, validate_credentials, AuthError};
+use crate::startup::ApplicationBaseUrl;
+
+use super::error_chain_fmt;
+
+#[derive(thiserror::Error)]
+pub enum AdminError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("The requested resource does not exist")]
+    NotFound,
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for AdminError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for AdminError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::AuthError(_) => StatusCode::UNAUTHORIZED,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_http::body::BoxBody> {
+        match self {
+            Self::AuthError(_) => {
+                let mut response = HttpResponse::new(self.status_code());
+                let header_value = HeaderValue::from_str(r#"Basic realm="admin""#).unwrap();
+                response
+                    .headers_mut()
+                    .insert(actix_http::header::WWW_AUTHENTICATE, header_value);
+                response
+            }
+            Self::NotFound | Self::ValidationError(_) => {
+                HttpResponse::build(self.status_code()).body(self.to_string())
+            }
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+/// Checks the `Basic` credentials of an admin request and returns the id of the user making it.
+pub(crate) async fn authenticate(request: &HttpRequest, pool: &PgPool) -> Result<Uuid, AdminError> {
+    let credentials = basic_authentication(request.headers()).map_err(AdminError::AuthError)?;
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    let user_id = validate_credentials(credentials, pool)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => AdminError::AuthError(e.into()),
+            AuthError::UnexpectedError(_) => AdminError::UnexpectedError(e.into()),
+        })?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    Ok(user_id)
+}
+
+/// Rejects requests changing anything through the admin credentials that come from a page of
+/// another site.
+///
+/// Browsers send the `Basic` credentials they know along with forms posted from any site, so
+/// such requests must come from one of our pages. Other clients send no `Origin`.
+pub async fn reject_cross_site_admin_requests(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let path = request.path();
+    let uses_admin_credentials =
+        path == "/admin" || path.starts_with("/admin/") || path == "/newsletter";
+    let is_safe = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+    if uses_admin_credentials && !is_safe {
+        let base_url = request
+            .app_data::<web::Data<ApplicationBaseUrl>>()
+            .map(|base_url| base_url.0.clone())
+            .unwrap_or_default();
+        if !is_same_origin(request.request(), &base_url) {
+            let e =
+                AdminError::ValidationError("Requests from other sites are not accepted".into());
+            return Ok(request.error_response(e));
+        }
+    }
+    Ok(next.call(request).await?.map_into_boxed_body())
+}
+
+fn is_same_origin(request: &HttpRequest, base_url: &str) -> bool {
+    let Some(origin) = request.headers().get(ORIGIN) else {
+        return true;
+    };
+    let origin = origin.to_str().unwrap_or_default();
+    let base_url = base_url.trim_end_matches('/');
+    !origin.is_empty() && (base_url == origin || base_url.starts_with(&format!("{}/", origin)))
+}
@@ -0,0 +1,52 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::issue_delivery::cancel_queued_deliveries;
+use crate::routes::admin::{authenticate, AdminError};
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Cancel the delivery of a newsletter issue",
+    skip(pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[post("/admin/newsletters/{newsletter_issue_id}/cancel")]
+pub async fn cancel_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET cancelled_at = COALESCE(cancelled_at, NOW())
+        WHERE newsletter_issue_id = $1
+        "#,
+        *newsletter_issue_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to mark the newsletter issue as cancelled.")?;
+    if updated.rows_affected() == 0 {
+        return Err(AdminError::NotFound);
+    }
+    let cancelled_deliveries = cancel_queued_deliveries(&mut transaction, *newsletter_issue_id)
+        .await
+        .context("Failed to cancel the queued deliveries.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to cancel a newsletter issue.")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "cancelled_deliveries": cancelled_deliveries,
+    })))
+}
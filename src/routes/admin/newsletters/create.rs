@@ -0,0 +1,33 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use super::{insert_newsletter_issue, unknown_audience, NewsletterBody};
+use crate::routes::admin::{authenticate, AdminError};
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Create a draft newsletter issue",
+    skip(body, pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[post("/admin/newsletters")]
+pub async fn create_newsletter_issue(
+    body: web::Json<NewsletterBody>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    if let Some(e) = unknown_audience(&pool, &body)
+        .await
+        .context("Failed to check the audience of the newsletter issue.")?
+    {
+        return Err(AdminError::ValidationError(e));
+    }
+    let newsletter_issue_id = insert_newsletter_issue(pool.get_ref(), &body, false)
+        .await
+        .context("Failed to store the draft newsletter issue.")?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "newsletter_issue_id": newsletter_issue_id,
+    })))
+}
@@ -0,0 +1,139 @@
+mod cancel;
+mod create;
+mod preview;
+mod publish;
+mod report;
+mod send_test;
+
+pub use cancel::cancel_newsletter_issue;
+pub use create::create_newsletter_issue;
+pub use preview::preview_newsletter_issue;
+pub use publish::publish_newsletter_issue;
+pub use report::newsletter_issue_report;
+pub use send_test::send_test_newsletter_issue;
+
+use sqlx::{Acquire, PgExecutor, PgPool, Postgres};
+use uuid::Uuid;
+
+use crate::domain::newsletter_issue::{slugify, NewsletterIssue};
+use crate::mailing_list::{get_list, DEFAULT_LIST_ID};
+use crate::segments::get_segment;
+
+#[derive(serde::Deserialize)]
+pub struct NewsletterBody {
+    /// The mailing list the issue is sent to, the default list when absent.
+    #[serde(default)]
+    pub list_id: Option<Uuid>,
+    /// The saved segment narrowing the recipients, every confirmed member of the list when absent.
+    #[serde(default)]
+    pub segment_id: Option<Uuid>,
+    pub title: String,
+    pub content: NewsletterContent,
+    /// Whether each recipient's copy carries a pixel recording when it is opened.
+    #[serde(default)]
+    pub track_opens: bool,
+    /// Whether links are rewritten to redirects recording each click.
+    #[serde(default)]
+    pub track_clicks: bool,
+    /// Whether the issue is listed in the public web archive once it has been sent.
+    #[serde(default)]
+    pub public: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewsletterContent {
+    pub html: String,
+    pub text: String,
+}
+
+impl NewsletterBody {
+    pub fn list_id(&self) -> Uuid {
+        self.list_id.unwrap_or(DEFAULT_LIST_ID)
+    }
+}
+
+/// Returns why the issue cannot be sent to the list and segment it targets, if they do not exist.
+#[tracing::instrument(name = "Checking the audience of a newsletter issue", skip(pool, body))]
+pub(crate) async fn unknown_audience(
+    pool: &PgPool,
+    body: &NewsletterBody,
+) -> Result<Option<String>, sqlx::Error> {
+    if get_list(pool, body.list_id()).await?.is_none() {
+        return Ok(Some("There is no list with the given identifier".into()));
+    }
+    if let Some(segment_id) = body.segment_id {
+        if get_segment(pool, segment_id).await?.is_none() {
+            return Ok(Some("There is no segment with the given identifier".into()));
+        }
+    }
+    Ok(None)
+}
+
+/// Issues sharing a title are told apart by the start of their ID in their slug. The plain slug is
+/// claimed with `ON CONFLICT`, so that two issues published together with the same title cannot
+/// both pick it.
+#[tracing::instrument(name = "Saving newsletter issue in the database", skip(executor, body))]
+pub(crate) async fn insert_newsletter_issue(
+    executor: impl Acquire<'_, Database = Postgres>,
+    body: &NewsletterBody,
+    published: bool,
+) -> Result<Uuid, anyhow::Error> {
+    let mut connection = executor.acquire().await?;
+    let newsletter_issue_id = Uuid::new_v4();
+    let slug = slugify(&body.title);
+    let fallback_slug = format!(
+        "{}-{}",
+        slug,
+        &newsletter_issue_id.simple().to_string()[..8]
+    );
+    for slug in [slug, fallback_slug] {
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issues (
+                newsletter_issue_id, list_id, segment_id, title, text_content, html_content,
+                track_opens, track_clicks, is_public, slug, published_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, CASE WHEN $11 THEN NOW() END)
+            ON CONFLICT (slug) DO NOTHING
+            "#,
+            newsletter_issue_id,
+            body.list_id(),
+            body.segment_id,
+            body.title,
+            body.content.text,
+            body.content.html,
+            body.track_opens,
+            body.track_clicks,
+            body.public,
+            slug,
+            published,
+        )
+        .execute(&mut *connection)
+        .await?;
+        if inserted.rows_affected() > 0 {
+            return Ok(newsletter_issue_id);
+        }
+    }
+    anyhow::bail!("Both slugs of the newsletter issue are taken.")
+}
+
+#[tracing::instrument(name = "Getting newsletter issue from the database", skip(executor))]
+pub(crate) async fn get_newsletter_issue(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<NewsletterIssue>, sqlx::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT
+            newsletter_issue_id AS id, list_id, title, text_content, html_content, track_opens,
+            track_clicks
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(issue)
+}
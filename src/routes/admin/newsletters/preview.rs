@@ -0,0 +1,53 @@
+use actix_web::{get, http::header::ContentType, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::get_newsletter_issue;
+use crate::domain::newsletter_issue::TemplateVariables;
+use crate::routes::admin::{authenticate, AdminError};
+
+#[derive(serde::Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+enum PreviewFormat {
+    #[default]
+    Html,
+    Text,
+}
+
+#[derive(serde::Deserialize)]
+struct PreviewParameters {
+    #[serde(default)]
+    format: PreviewFormat,
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Preview a newsletter issue",
+    skip(parameters, pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/newsletters/{newsletter_issue_id}/preview")]
+pub async fn preview_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    parameters: web::Query<PreviewParameters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let issue = get_newsletter_issue(pool.get_ref(), *newsletter_issue_id)
+        .await
+        .context("Failed to retrieve the newsletter issue.")?
+        .ok_or(AdminError::NotFound)?;
+    let rendered = issue.render(&TemplateVariables::sample());
+
+    let response = match parameters.format {
+        PreviewFormat::Html => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(rendered.html_content),
+        PreviewFormat::Text => HttpResponse::Ok()
+            .content_type(ContentType::plaintext())
+            .body(rendered.text_content),
+    };
+    Ok(response)
+}
@@ -0,0 +1,103 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use super::get_newsletter_issue;
+use crate::email_client::EmailClient;
+use crate::issue_delivery::{enqueue_delivery_tasks, spawn_delivery};
+use crate::routes::admin::{authenticate, AdminError};
+use crate::tracking::TrackingLinks;
+
+/// Publishes a draft issue to every confirmed subscriber.
+///
+/// The issue is delivered in the background, so the request is answered with `202 Accepted` as
+/// soon as the deliveries are queued; the report of the issue follows their progress.
+///
+/// Publishing an issue whose send was interrupted resumes it: only the recipients that have not
+/// been delivered to yet are sent the issue.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip(pool, email_client, tracking_links, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[post("/admin/newsletters/{newsletter_issue_id}/publish")]
+pub async fn publish_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    tracking_links: web::Data<TrackingLinks>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let issue = get_newsletter_issue(pool.get_ref(), *newsletter_issue_id)
+        .await
+        .context("Failed to retrieve the newsletter issue.")?
+        .ok_or(AdminError::NotFound)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    let first_publication = mark_issue_as_published(&mut transaction, issue.id).await?;
+    if first_publication {
+        enqueue_delivery_tasks(&mut transaction, issue.id)
+            .await
+            .context("Failed to enqueue the delivery tasks.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to publish a newsletter issue.")?;
+
+    spawn_delivery(
+        pool.get_ref().clone(),
+        email_client.get_ref().clone(),
+        tracking_links.get_ref().clone(),
+        issue,
+    );
+    Ok(HttpResponse::Accepted())
+}
+
+/// Returns whether the issue was a draft until now, so its deliveries still have to be queued.
+#[tracing::instrument(name = "Mark newsletter issue as published", skip(transaction))]
+async fn mark_issue_as_published(
+    transaction: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+) -> Result<bool, AdminError> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT published_at, cancelled_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        FOR UPDATE
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to retrieve the publication state of the newsletter issue.")?;
+
+    if issue.cancelled_at.is_some() {
+        return Err(AdminError::ValidationError(
+            "The delivery of this newsletter issue was cancelled".into(),
+        ));
+    }
+    if issue.published_at.is_some() {
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET published_at = NOW()
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to mark the newsletter issue as published.")?;
+    Ok(true)
+}
@@ -0,0 +1,281 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::get_newsletter_issue;
+use crate::routes::admin::{authenticate, AdminError};
+
+#[derive(serde::Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+enum ReportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(serde::Deserialize)]
+struct ReportParameters {
+    #[serde(default)]
+    format: ReportFormat,
+}
+
+#[derive(serde::Serialize)]
+struct DeliveryReport {
+    newsletter_issue_id: Uuid,
+    title: String,
+    totals: DeliveryTotals,
+    opens: OpenTotals,
+    clicks: ClickTotals,
+    failures: Vec<DeliveryRecord>,
+}
+
+#[derive(serde::Serialize, Default)]
+struct DeliveryTotals {
+    queued: i64,
+    sending: i64,
+    sent: i64,
+    failed: i64,
+    bounced: i64,
+    cancelled: i64,
+}
+
+/// Opens recorded by the tracking pixel; only populated for issues sent with open tracking.
+#[derive(serde::Serialize)]
+struct OpenTotals {
+    /// Recipients who opened the issue at least once.
+    unique: i64,
+    /// Every recorded open, including repeated ones.
+    total: i64,
+}
+
+/// Clicks recorded through tracked links; only populated for issues sent with click tracking.
+#[derive(serde::Serialize)]
+struct ClickTotals {
+    /// Recipients who clicked at least one link.
+    unique: i64,
+    /// Every recorded click, including repeated ones.
+    total: i64,
+    links: Vec<LinkClicks>,
+}
+
+#[derive(serde::Serialize)]
+struct LinkClicks {
+    url: String,
+    unique: i64,
+    total: i64,
+}
+
+#[derive(serde::Serialize)]
+struct DeliveryRecord {
+    subscriber_email: String,
+    status: String,
+    provider_message_id: Option<String>,
+    attempts: i32,
+    queued_at: DateTime<Utc>,
+    last_attempted_at: Option<DateTime<Utc>>,
+    sent_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    first_opened_at: Option<DateTime<Utc>>,
+    last_opened_at: Option<DateTime<Utc>>,
+    open_count: i32,
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Report on the delivery of a newsletter issue",
+    skip(parameters, pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/newsletters/{newsletter_issue_id}/report")]
+pub async fn newsletter_issue_report(
+    newsletter_issue_id: web::Path<Uuid>,
+    parameters: web::Query<ReportParameters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let issue = get_newsletter_issue(pool.get_ref(), *newsletter_issue_id)
+        .await
+        .context("Failed to retrieve the newsletter issue.")?
+        .ok_or(AdminError::NotFound)?;
+
+    let response = match parameters.format {
+        ReportFormat::Json => {
+            let totals = get_delivery_totals(&pool, issue.id)
+                .await
+                .context("Failed to count the deliveries of the newsletter issue.")?;
+            let opens = get_open_totals(&pool, issue.id)
+                .await
+                .context("Failed to count the opens of the newsletter issue.")?;
+            let clicks = get_click_totals(&pool, issue.id)
+                .await
+                .context("Failed to count the clicks of the newsletter issue.")?;
+            let failures = get_delivery_records(&pool, issue.id, true)
+                .await
+                .context("Failed to retrieve the failed deliveries of the newsletter issue.")?;
+            HttpResponse::Ok().json(DeliveryReport {
+                newsletter_issue_id: issue.id,
+                title: issue.title,
+                totals,
+                opens,
+                clicks,
+                failures,
+            })
+        }
+        ReportFormat::Csv => {
+            let records = get_delivery_records(&pool, issue.id, false)
+                .await
+                .context("Failed to retrieve the deliveries of the newsletter issue.")?;
+            let mut writer = csv::Writer::from_writer(vec![]);
+            for record in records {
+                writer
+                    .serialize(record)
+                    .context("Failed to serialize a delivery record as CSV.")?;
+            }
+            let body = writer
+                .into_inner()
+                .context("Failed to flush the CSV delivery report.")?;
+            HttpResponse::Ok()
+                .content_type("text/csv; charset=utf-8")
+                .insert_header(ContentDisposition {
+                    disposition: DispositionType::Attachment,
+                    parameters: vec![DispositionParam::Filename(format!(
+                        "{}-deliveries.csv",
+                        issue.id
+                    ))],
+                })
+                .body(body)
+        }
+    };
+    Ok(response)
+}
+
+#[tracing::instrument(name = "Count deliveries by status", skip(pool))]
+async fn get_delivery_totals(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<DeliveryTotals, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT status, COUNT(*) AS "count!"
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1
+        GROUP BY status
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut totals = DeliveryTotals::default();
+    for row in rows {
+        match row.status.as_str() {
+            "queued" => totals.queued = row.count,
+            "sending" => totals.sending = row.count,
+            "sent" => totals.sent = row.count,
+            "failed" => totals.failed = row.count,
+            "bounced" => totals.bounced = row.count,
+            "cancelled" => totals.cancelled = row.count,
+            _ => tracing::warn!("Unknown delivery status: {}", row.status),
+        }
+    }
+    Ok(totals)
+}
+
+#[tracing::instrument(name = "Count opens", skip(pool))]
+async fn get_open_totals(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<OpenTotals, sqlx::Error> {
+    sqlx::query_as!(
+        OpenTotals,
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE open_count > 0) AS "unique!",
+            COALESCE(SUM(open_count), 0) AS "total!"
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Count clicks", skip(pool))]
+async fn get_click_totals(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<ClickTotals, sqlx::Error> {
+    let totals = sqlx::query!(
+        r#"
+        SELECT COUNT(DISTINCT c.subscriber_id) AS "unique!", COUNT(*) AS "total!"
+        FROM link_clicks c
+        JOIN issue_links l ON l.link_id = c.link_id
+        WHERE l.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(pool)
+    .await?;
+    let links = sqlx::query_as!(
+        LinkClicks,
+        r#"
+        SELECT
+            l.url,
+            COUNT(DISTINCT c.subscriber_id) AS "unique!",
+            COUNT(c.link_id) AS "total!"
+        FROM issue_links l
+        LEFT JOIN link_clicks c ON c.link_id = l.link_id
+        WHERE l.newsletter_issue_id = $1
+        GROUP BY l.link_id
+        ORDER BY l.link_id
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(ClickTotals {
+        unique: totals.unique,
+        total: totals.total,
+        links,
+    })
+}
+
+#[tracing::instrument(name = "Get delivery records", skip(pool))]
+async fn get_delivery_records(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    failures_only: bool,
+) -> Result<Vec<DeliveryRecord>, sqlx::Error> {
+    let records = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT
+            s.email AS subscriber_email,
+            d.status,
+            d.provider_message_id,
+            d.attempts,
+            d.queued_at,
+            d.last_attempted_at,
+            d.sent_at,
+            d.last_error,
+            d.first_opened_at,
+            d.last_opened_at,
+            d.open_count
+        FROM issue_deliveries d
+        JOIN subscriptions s ON s.id = d.subscriber_id
+        WHERE d.newsletter_issue_id = $1
+            AND (NOT $2 OR d.status IN ('failed', 'bounced'))
+        ORDER BY s.email
+        "#,
+        newsletter_issue_id,
+        failures_only,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(records)
+}
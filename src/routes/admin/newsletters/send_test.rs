@@ -0,0 +1,84 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::get_newsletter_issue;
+use crate::domain::newsletter_issue::TemplateVariables;
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::mailing_list::get_list;
+use crate::routes::admin::{authenticate, AdminError};
+
+#[derive(serde::Deserialize)]
+struct SendTestBody {
+    #[serde(default)]
+    recipients: Vec<String>,
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Send a test newsletter issue",
+    skip(body, pool, email_client, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[post("/admin/newsletters/{newsletter_issue_id}/test")]
+pub async fn send_test_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Json<SendTestBody>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    let user_id = authenticate(&request, &pool).await?;
+    let issue = get_newsletter_issue(pool.get_ref(), *newsletter_issue_id)
+        .await
+        .context("Failed to retrieve the newsletter issue.")?
+        .ok_or(AdminError::NotFound)?;
+
+    let recipients = if body.0.recipients.is_empty() {
+        let email = get_user_email(&pool, user_id)
+            .await
+            .context("Failed to retrieve the email address of the current user.")?
+            .ok_or_else(|| {
+                AdminError::ValidationError(
+                    "No recipients were given and the current user has no email address".into(),
+                )
+            })?;
+        vec![email]
+    } else {
+        body.0.recipients
+    };
+    let recipients = recipients
+        .into_iter()
+        .map(SubscriberEmail::parse)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AdminError::ValidationError)?;
+
+    let email_client = get_list(pool.get_ref(), issue.list_id)
+        .await
+        .context("Failed to retrieve the mailing list of the newsletter issue.")?
+        .context("The mailing list of the newsletter issue does not exist.")?
+        .email_client(&email_client)?;
+    let rendered = issue.render(&TemplateVariables::sample());
+    for recipient in recipients {
+        email_client
+            .send_email(
+                &recipient,
+                &rendered.title,
+                &rendered.html_content,
+                &rendered.text_content,
+            )
+            .await
+            .with_context(|| format!("Failed to send the test issue to {}", recipient))?;
+    }
+    Ok(HttpResponse::Ok())
+}
+
+#[tracing::instrument(name = "Getting the email address of a user", skip(pool))]
+async fn get_user_email(pool: &PgPool, user_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(r#"SELECT email FROM users WHERE id = $1"#, user_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(row.email)
+}
@@ -0,0 +1,76 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::audit_log::{record_admin_action, AuditAction};
+use crate::data_subject::{collect_data, erase_data};
+use crate::routes::admin::{authenticate, AdminError};
+
+#[derive(serde::Deserialize)]
+struct EmailParameters {
+    email: String,
+}
+
+/// Answers a data subject access request received by other means than the self-service page.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Get the personal data of an address",
+    skip(parameters, pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/privacy/access")]
+pub async fn get_personal_data(
+    parameters: web::Query<EmailParameters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    let bundle = collect_data(&mut connection, &parameters.email)
+        .await
+        .context("Failed to collect the data stored about the address.")?;
+    if bundle.is_empty() {
+        return Err(AdminError::NotFound);
+    }
+    Ok(HttpResponse::Ok().json(bundle))
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Erase the personal data of an address",
+    skip(body, pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[post("/admin/privacy/erase")]
+pub async fn erase_personal_data_of(
+    body: web::Json<EmailParameters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    let user_id = authenticate(&request, &pool).await?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    let erasure = erase_data(&mut transaction, &body.email)
+        .await
+        .context("Failed to erase the data stored about the address.")?
+        .ok_or(AdminError::NotFound)?;
+    record_admin_action(
+        &mut *transaction,
+        user_id,
+        AuditAction::Delete,
+        erasure.subscriber_id,
+        None,
+    )
+    .await
+    .context("Failed to record the erasure in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase personal data.")?;
+    Ok(HttpResponse::Ok().finish())
+}
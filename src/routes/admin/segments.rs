@@ -0,0 +1,84 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use sqlx::types::Json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::routes::admin::{authenticate, AdminError};
+use crate::segments::{Segment, SegmentFilter};
+
+#[derive(serde::Deserialize)]
+struct SegmentBody {
+    name: String,
+    filter: SegmentFilter,
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Create a segment",
+    skip(body, pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[post("/admin/segments")]
+pub async fn create_segment(
+    body: web::Json<SegmentBody>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let body = body.into_inner();
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(AdminError::ValidationError(
+            "The name of the segment cannot be empty".into(),
+        ));
+    }
+    let filter = body.filter.parse().map_err(AdminError::ValidationError)?;
+
+    let segment_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO segments (segment_id, name, filter)
+        VALUES ($1, $2, $3)
+        "#,
+        segment_id,
+        name,
+        Json(filter) as _,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the segment.")?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "segment_id": segment_id })))
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "List segments",
+    skip(pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/segments")]
+pub async fn get_segments(
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let segments = sqlx::query!(
+        r#"
+        SELECT segment_id, name, filter AS "filter: Json<SegmentFilter>"
+        FROM segments
+        ORDER BY created_at, name
+        "#,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the segments.")?
+    .into_iter()
+    .map(|row| Segment {
+        segment_id: row.segment_id,
+        name: row.name,
+        filter: row.filter.0,
+    })
+    .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().json(segments))
+}
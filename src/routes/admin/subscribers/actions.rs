@@ -0,0 +1,387 @@
+use actix_http::header::LOCATION;
+use actix_web::{post, web, Either, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{subscriber_page, ResponseFormat};
+use crate::address_policy::AddressPolicy;
+use crate::audit_log::{record_admin_action, AuditAction};
+use crate::custom_fields::get_field_values;
+use crate::data_subject::erase_data;
+use crate::domain::subscriber::Subscriber;
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::domain::subscriber_name::SubscriberName;
+use crate::email_change::{check_new_address, create_email_change, send_verification_email};
+use crate::email_client::EmailClient;
+use crate::mailing_list::{get_list, join_confirmed, unsubscribe};
+use crate::routes::admin::{authenticate, AdminError};
+use crate::routes::{generate_subscription_token, send_confirmation_email, store_token_in_db};
+use crate::startup::ApplicationBaseUrl;
+use crate::suppression::is_suppressed;
+
+#[derive(serde::Deserialize)]
+struct ActionParameters {
+    /// Limits the action to one of the subscriber's lists, it applies to all of them otherwise.
+    list_id: Option<Uuid>,
+    /// Pages get redirected back to the subscriber, with the outcome as a flash message.
+    #[serde(default)]
+    format: ResponseFormat,
+}
+
+#[derive(serde::Deserialize)]
+struct EmailChangeBody {
+    email: String,
+}
+
+#[derive(serde::Serialize)]
+struct ActionOutcome {
+    action: &'static str,
+    /// The lists the action changed something on.
+    lists: Vec<Uuid>,
+}
+
+/// Confirms, unsubscribes, sends the confirmation email again to or deletes a subscriber. Deleting
+/// goes the way their own erasure request would, see `erase_data`.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Apply an action to a subscriber",
+    skip(parameters, pool, email_client, base_url, address_policy, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[post(
+    "/admin/subscribers/{subscriber_id}/{action:confirm|unsubscribe|resend_confirmation|delete}"
+)]
+pub async fn apply_subscriber_action(
+    path: web::Path<(Uuid, AuditAction)>,
+    parameters: web::Query<ActionParameters>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    address_policy: web::Data<AddressPolicy>,
+    request: HttpRequest,
+) -> Result<HttpResponse, AdminError> {
+    let (subscriber_id, action) = path.into_inner();
+    run_action(
+        action,
+        subscriber_id,
+        &parameters,
+        &pool,
+        &email_client,
+        &base_url,
+        &address_policy,
+        &request,
+        None,
+    )
+    .await
+}
+
+/// Sends a link to the new address; the subscription only moves once it is followed. The body is
+/// either JSON or a form.
+#[allow(
+    clippy::async_yields_async,
+    clippy::let_with_type_underscore,
+    clippy::too_many_arguments
+)]
+#[tracing::instrument(
+    name = "Request an email change for a subscriber",
+    skip(parameters, body, pool, email_client, base_url, address_policy, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[post("/admin/subscribers/{subscriber_id}/change_email")]
+pub async fn change_subscriber_email(
+    subscriber_id: web::Path<Uuid>,
+    parameters: web::Query<ActionParameters>,
+    body: Either<web::Json<EmailChangeBody>, web::Form<EmailChangeBody>>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    address_policy: web::Data<AddressPolicy>,
+    request: HttpRequest,
+) -> Result<HttpResponse, AdminError> {
+    let action = AuditAction::RequestEmailChange;
+    let subscriber_id = subscriber_id.into_inner();
+    let new_email = match body {
+        Either::Left(body) => body.into_inner().email,
+        Either::Right(body) => body.into_inner().email,
+    };
+    run_action(
+        action,
+        subscriber_id,
+        &parameters,
+        &pool,
+        &email_client,
+        &base_url,
+        &address_policy,
+        &request,
+        Some(new_email),
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_action(
+    action: AuditAction,
+    subscriber_id: Uuid,
+    parameters: &ActionParameters,
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &ApplicationBaseUrl,
+    address_policy: &AddressPolicy,
+    request: &HttpRequest,
+    new_email: Option<String>,
+) -> Result<HttpResponse, AdminError> {
+    let user_id = authenticate(request, pool).await?;
+    let outcome = apply_action(
+        action,
+        user_id,
+        subscriber_id,
+        parameters.list_id,
+        new_email,
+        pool,
+        email_client,
+        &base_url.0,
+        address_policy,
+    )
+    .await;
+
+    match parameters.format {
+        ResponseFormat::Json => Ok(HttpResponse::Ok().json(ActionOutcome {
+            action: action.as_str(),
+            lists: outcome?,
+        })),
+        ResponseFormat::Html => {
+            let mut location = subscriber_page(subscriber_id);
+            match outcome {
+                Ok(lists) => {
+                    if action == AuditAction::Delete {
+                        location = "/admin/subscribers?format=html".into();
+                    }
+                    FlashMessage::info(success_message(action, lists.len())).send();
+                }
+                Err(AdminError::ValidationError(e)) => FlashMessage::error(e).send(),
+                Err(e) => return Err(e),
+            }
+            Ok(HttpResponse::SeeOther()
+                .insert_header((LOCATION, location))
+                .finish())
+        }
+    }
+}
+
+fn success_message(action: AuditAction, lists: usize) -> String {
+    match action {
+        AuditAction::Confirm => format!("Confirmed the subscriber on {} list(s).", lists),
+        AuditAction::Unsubscribe => format!("Unsubscribed the subscriber from {} list(s).", lists),
+        AuditAction::ResendConfirmation => {
+            format!("Sent {} confirmation email(s) again.", lists)
+        }
+        AuditAction::Delete => "Deleted the subscriber.".into(),
+        AuditAction::RequestEmailChange => {
+            "Sent a link to the new address to confirm the change.".into()
+        }
+    }
+}
+
+/// Carries out `action` and records it in the audit log, returning the lists it changed.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Apply an admin action",
+    skip(new_email, pool, email_client, base_url, address_policy)
+)]
+async fn apply_action(
+    action: AuditAction,
+    user_id: Uuid,
+    subscriber_id: Uuid,
+    list_id: Option<Uuid>,
+    new_email: Option<String>,
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    address_policy: &AddressPolicy,
+) -> Result<Vec<Uuid>, AdminError> {
+    let applies_to_lists = !matches!(
+        action,
+        AuditAction::Delete | AuditAction::RequestEmailChange
+    );
+    if !applies_to_lists && list_id.is_some() {
+        return Err(AdminError::ValidationError(format!(
+            "The {} action applies to all the lists of the subscriber",
+            action.as_str()
+        )));
+    }
+    let new_email = new_email
+        .map(SubscriberEmail::parse)
+        .transpose()
+        .map_err(AdminError::ValidationError)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT email, name FROM subscriptions
+        WHERE id = $1 AND status <> 'erased'
+        FOR UPDATE
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the subscriber.")?
+    .ok_or(AdminError::NotFound)?;
+    let parse_subscriber = || -> Result<Subscriber, AdminError> {
+        Ok(Subscriber {
+            email: SubscriberEmail::parse(subscriber.email.clone())
+                .map_err(AdminError::ValidationError)?,
+            name: SubscriberName::parse(subscriber.name.clone())
+                .map_err(AdminError::ValidationError)?,
+        })
+    };
+    let memberships = sqlx::query!(
+        r#"
+        SELECT list_id, status FROM list_memberships
+        WHERE subscriber_id = $1 AND ($2::uuid IS NULL OR list_id = $2)
+        ORDER BY subscribed_at
+        "#,
+        subscriber_id,
+        list_id,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to retrieve the lists of the subscriber.")?;
+    if list_id.is_some() && memberships.is_empty() {
+        return Err(AdminError::ValidationError(
+            "The subscriber is not on this list".into(),
+        ));
+    }
+
+    let mut lists = Vec::new();
+    let mut tokens = Vec::new();
+    let mut email_change = None;
+    match action {
+        AuditAction::Confirm => {
+            for membership in memberships
+                .iter()
+                .filter(|m| m.status == "pending_confirmation")
+            {
+                if join_confirmed(&mut transaction, membership.list_id, subscriber_id)
+                    .await
+                    .context("Failed to confirm the subscriber.")?
+                {
+                    lists.push(membership.list_id);
+                }
+            }
+        }
+        AuditAction::Unsubscribe => {
+            for membership in memberships.iter().filter(|m| m.status != "unsubscribed") {
+                if unsubscribe(&mut transaction, membership.list_id, subscriber_id, None)
+                    .await
+                    .context("Failed to unsubscribe the subscriber.")?
+                {
+                    lists.push(membership.list_id);
+                }
+            }
+        }
+        AuditAction::ResendConfirmation => {
+            let email = parse_subscriber()?.email;
+            if is_suppressed(&mut *transaction, &email)
+                .await
+                .context("Failed to check the suppression list.")?
+            {
+                return Err(AdminError::ValidationError(
+                    "The address is suppressed, no email can be sent to it".into(),
+                ));
+            }
+            for membership in memberships
+                .iter()
+                .filter(|m| m.status == "pending_confirmation")
+            {
+                let token = generate_subscription_token();
+                store_token_in_db(&mut transaction, subscriber_id, membership.list_id, &token)
+                    .await
+                    .context("Failed to store confirmation token in the database.")?;
+                lists.push(membership.list_id);
+                tokens.push((membership.list_id, token));
+            }
+        }
+        AuditAction::Delete => {
+            erase_data(&mut transaction, &subscriber.email)
+                .await
+                .context("Failed to erase the data stored about the subscriber.")?;
+            lists = memberships.iter().map(|m| m.list_id).collect();
+        }
+        AuditAction::RequestEmailChange => {
+            let new_email = new_email
+                .ok_or_else(|| AdminError::ValidationError("The new address is missing".into()))?;
+            if let Some(e) = check_new_address(
+                &mut *transaction,
+                address_policy,
+                &subscriber.email,
+                &new_email,
+            )
+            .await
+            .context("Failed to check the new address.")?
+            {
+                return Err(AdminError::ValidationError(e));
+            }
+            let token =
+                create_email_change(&mut *transaction, subscriber_id, &new_email, Some(user_id))
+                    .await
+                    .context("Failed to store the email change request.")?;
+            email_change = Some((new_email, token));
+        }
+    }
+    if lists.is_empty() && applies_to_lists {
+        let message = match action {
+            AuditAction::Confirm => "The subscriber has nothing to confirm",
+            AuditAction::Unsubscribe => "The subscriber is already unsubscribed",
+            _ => "The subscriber has no subscription awaiting confirmation",
+        };
+        return Err(AdminError::ValidationError(message.into()));
+    }
+    record_admin_action(
+        &mut *transaction,
+        user_id,
+        action,
+        Some(subscriber_id),
+        list_id,
+    )
+    .await
+    .context("Failed to record the action in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to apply an admin action.")?;
+
+    if let Some((new_email, token)) = email_change {
+        send_verification_email(email_client, &new_email, base_url, &token)
+            .await
+            .context("Failed to send the email change verification.")?;
+    }
+    if !tokens.is_empty() {
+        let field_values: Vec<_> = get_field_values(pool, subscriber_id)
+            .await
+            .context("Failed to retrieve the custom fields of the subscriber.")?
+            .into_iter()
+            .collect();
+        for (list_id, token) in tokens {
+            let list = get_list(pool, list_id)
+                .await
+                .context("Failed to retrieve the mailing list.")?
+                .context("The list of the subscription no longer exists.")?;
+            send_confirmation_email(
+                &list.email_client(email_client)?,
+                &list,
+                parse_subscriber()?,
+                &field_values,
+                base_url,
+                &token,
+            )
+            .await
+            .context("Failed to send the confirmation email again.")?;
+        }
+    }
+    Ok(lists)
+}
@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{flash_messages_html, ResponseFormat};
+use crate::consent::{get_consent_records, ConsentRecord};
+use crate::custom_fields::get_field_values;
+use crate::domain::newsletter_issue::escape_html;
+use crate::routes::admin::{authenticate, AdminError};
+use crate::tags::get_tags;
+
+#[derive(serde::Deserialize)]
+struct DetailParameters {
+    #[serde(default)]
+    format: ResponseFormat,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberDetail {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    frequency: String,
+    subscribed_at: DateTime<Utc>,
+    memberships: Vec<MembershipDetail>,
+    tags: Vec<String>,
+    fields: BTreeMap<String, String>,
+    /// How and when the subscriber opted in, oldest first.
+    consents: Vec<ConsentRecord>,
+    /// Everything that happened to the subscriber, oldest first.
+    history: Vec<HistoryEntry>,
+}
+
+#[derive(serde::Serialize)]
+struct MembershipDetail {
+    list_id: Uuid,
+    list_name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+    unsubscribed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+struct HistoryEntry {
+    occurred_at: DateTime<Utc>,
+    /// e.g. `subscribed`, `confirmed`, `issue_sent`, `link_clicked`, `email_bounce` or
+    /// `admin_unsubscribe`.
+    event: String,
+    list_id: Option<Uuid>,
+    newsletter_issue_id: Option<Uuid>,
+    /// The clicked URL, or the admin who acted.
+    detail: Option<String>,
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Get a subscriber",
+    skip(parameters, pool, request, flash_messages),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/subscribers/{subscriber_id}")]
+pub async fn get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    parameters: web::Query<DetailParameters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+    flash_messages: IncomingFlashMessages,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT id, email, name, status, frequency, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber.")?
+    .ok_or(AdminError::NotFound)?;
+    let memberships = sqlx::query_as!(
+        MembershipDetail,
+        r#"
+        SELECT
+            m.list_id, l.name AS list_name, m.status, m.subscribed_at, m.confirmed_at,
+            m.unsubscribed_at
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY m.subscribed_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the lists of the subscriber.")?;
+    let tags = get_tags(pool.get_ref(), subscriber_id)
+        .await
+        .context("Failed to retrieve the tags of the subscriber.")?;
+    let fields = get_field_values(pool.get_ref(), subscriber_id)
+        .await
+        .context("Failed to retrieve the custom fields of the subscriber.")?;
+    let consents = get_consent_records(pool.get_ref(), subscriber_id)
+        .await
+        .context("Failed to retrieve the consent records of the subscriber.")?;
+    let history = get_history(&pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the history of the subscriber.")?;
+
+    let subscriber = SubscriberDetail {
+        id: subscriber.id,
+        email: subscriber.email,
+        name: subscriber.name,
+        status: subscriber.status,
+        frequency: subscriber.frequency,
+        subscribed_at: subscriber.subscribed_at,
+        memberships,
+        tags,
+        fields,
+        consents,
+        history,
+    };
+    Ok(match parameters.format {
+        ResponseFormat::Json => HttpResponse::Ok().json(subscriber),
+        ResponseFormat::Html => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+            .body(render_page(&subscriber, &flash_messages)),
+    })
+}
+
+#[tracing::instrument(name = "Get the history of a subscriber", skip(pool))]
+async fn get_history(pool: &PgPool, subscriber_id: Uuid) -> Result<Vec<HistoryEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        HistoryEntry,
+        r#"
+        SELECT
+            occurred_at AS "occurred_at!",
+            event AS "event!",
+            list_id,
+            newsletter_issue_id,
+            detail
+        FROM (
+            SELECT
+                subscribed_at AS occurred_at, 'subscribed' AS event, list_id,
+                NULL::uuid AS newsletter_issue_id, NULL AS detail
+            FROM list_memberships
+            WHERE subscriber_id = $1
+            UNION ALL
+            SELECT occurred_at, event_type, list_id, newsletter_issue_id, NULL
+            FROM subscription_events
+            WHERE subscriber_id = $1
+            UNION ALL
+            SELECT sent_at, 'issue_sent', NULL, newsletter_issue_id, NULL
+            FROM issue_deliveries
+            WHERE subscriber_id = $1 AND sent_at IS NOT NULL
+            UNION ALL
+            SELECT first_opened_at, 'issue_opened', NULL, newsletter_issue_id, NULL
+            FROM issue_deliveries
+            WHERE subscriber_id = $1 AND first_opened_at IS NOT NULL
+            UNION ALL
+            SELECT c.clicked_at, 'link_clicked', NULL, l.newsletter_issue_id, l.url
+            FROM link_clicks c
+            JOIN issue_links l ON l.link_id = c.link_id
+            WHERE c.subscriber_id = $1
+            UNION ALL
+            SELECT occurred_at, 'email_' || event_type, NULL, NULL, NULL
+            FROM email_events
+            WHERE subscriber_id = $1
+            UNION ALL
+            SELECT a.occurred_at, 'admin_' || a.action, a.list_id, NULL, u.username
+            FROM admin_audit_log a
+            JOIN users u ON u.id = a.user_id
+            WHERE a.subscriber_id = $1
+        ) history
+        ORDER BY occurred_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+fn render_page(subscriber: &SubscriberDetail, flash_messages: &IncomingFlashMessages) -> String {
+    let messages_html = flash_messages_html(flash_messages);
+    let mut memberships_html = String::new();
+    for membership in &subscriber.memberships {
+        writeln!(
+            memberships_html,
+            r#"            <tr><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            escape_html(&membership.list_name),
+            membership.status,
+            membership.subscribed_at.format("%Y-%m-%d %H:%M"),
+        )
+        .unwrap();
+    }
+    let mut consents_html = String::new();
+    for consent in &subscriber.consents {
+        writeln!(
+            consents_html,
+            r#"            <tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            consent.recorded_at.format("%Y-%m-%d %H:%M:%S"),
+            consent.action,
+            escape_html(consent.ip_address.as_deref().unwrap_or_default()),
+            escape_html(consent.user_agent.as_deref().unwrap_or_default()),
+            escape_html(consent.source.as_deref().unwrap_or_default()),
+            escape_html(&consent.consent_text),
+        )
+        .unwrap();
+    }
+    let mut history_html = String::new();
+    for entry in &subscriber.history {
+        writeln!(
+            history_html,
+            r#"            <tr><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            entry.occurred_at.format("%Y-%m-%d %H:%M:%S"),
+            escape_html(&entry.event),
+            escape_html(entry.detail.as_deref().unwrap_or_default()),
+        )
+        .unwrap();
+    }
+    let mut actions_html = String::new();
+    for (action, label) in [
+        ("confirm", "Confirm"),
+        ("unsubscribe", "Unsubscribe"),
+        ("resend_confirmation", "Resend the confirmation email"),
+        ("delete", "Delete"),
+    ] {
+        writeln!(
+            actions_html,
+            r#"        <form action="/admin/subscribers/{}/{}?format=html" method="post"><button type="submit">{}</button></form>"#,
+            subscriber.id, action, label,
+        )
+        .unwrap();
+    }
+    writeln!(
+        actions_html,
+        r#"        <form action="/admin/subscribers/{}/change_email?format=html" method="post"><input type="email" name="email" placeholder="New address"><button type="submit">Change the address</button></form>"#,
+        subscriber.id,
+    )
+    .unwrap();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Subscriber</title>
+    </head>
+    <body>
+        <p><a href="/admin/subscribers?format=html">All subscribers</a></p>
+        <h1>{email}</h1>
+        {messages_html}
+        <p>{name}, {status}, subscribed on {subscribed_at}</p>
+        <p>Tags: {tags}</p>
+{actions_html}        <h2>Lists</h2>
+        <table>
+            <tr><th>List</th><th>Status</th><th>Subscribed</th></tr>
+{memberships_html}        </table>
+        <h2>Consent</h2>
+        <table>
+            <tr><th>When</th><th>Step</th><th>IP address</th><th>User agent</th><th>Source</th><th>Text</th></tr>
+{consents_html}        </table>
+        <h2>History</h2>
+        <table>
+            <tr><th>When</th><th>Event</th><th>Detail</th></tr>
+{history_html}        </table>
+    </body>
+</html>
+"#,
+        email = escape_html(&subscriber.email),
+        name = escape_html(&subscriber.name),
+        status = subscriber.status,
+        subscribed_at = subscriber.subscribed_at.format("%Y-%m-%d %H:%M"),
+        tags = escape_html(&subscriber.tags.join(", ")),
+    )
+}
@@ -0,0 +1,257 @@
+use std::fmt::Write;
+
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{flash_messages_html, subscriber_page, ResponseFormat};
+use crate::domain::newsletter_issue::escape_html;
+use crate::domain::subscription_status::SubscriptionStatus;
+use crate::routes::admin::{authenticate, AdminError};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+struct ListParameters {
+    #[serde(default)]
+    #[serde(skip_serializing)]
+    format: ResponseFormat,
+    /// Part of the email address or name of the subscribers to list.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    search: Option<String>,
+    /// Only lists subscribers with this status; erased subscribers are left out otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+    /// Starts at 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    per_page: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberPage {
+    subscribers: Vec<SubscriberSummary>,
+    page: i64,
+    per_page: i64,
+    /// How many subscribers match, over all pages.
+    total: i64,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberSummary {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+/// Lists subscribers, most recent first.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "List subscribers",
+    skip(parameters, pool, request, flash_messages),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/subscribers")]
+pub async fn list_subscribers(
+    parameters: web::Query<ListParameters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+    flash_messages: IncomingFlashMessages,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let parameters = parameters.into_inner();
+    let status = parameters
+        .status
+        .as_deref()
+        .filter(|status| !status.is_empty())
+        .map(SubscriptionStatus::parse)
+        .transpose()
+        .map_err(AdminError::ValidationError)?;
+    let page = parameters.page.unwrap_or(1);
+    if page < 1 {
+        return Err(AdminError::ValidationError(
+            "Pages are numbered from 1".into(),
+        ));
+    }
+    let per_page = parameters.per_page.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&per_page) {
+        return Err(AdminError::ValidationError(format!(
+            "Pages hold between 1 and {} subscribers",
+            MAX_PAGE_SIZE
+        )));
+    }
+    let pattern = parameters
+        .search
+        .as_deref()
+        .map(str::trim)
+        .filter(|search| !search.is_empty())
+        .map(like_pattern);
+    let status = status.map(|status| status.as_str());
+
+    let total = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1)
+            AND CASE WHEN $2::text IS NULL THEN status <> 'erased' ELSE status = $2 END
+        "#,
+        pattern,
+        status,
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to count the subscribers.")?
+    .count;
+    let subscribers = sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1)
+            AND CASE WHEN $2::text IS NULL THEN status <> 'erased' ELSE status = $2 END
+        ORDER BY subscribed_at DESC, id
+        LIMIT $3 OFFSET $4
+        "#,
+        pattern,
+        status,
+        per_page,
+        (page - 1) * per_page,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscribers.")?;
+
+    let subscribers = SubscriberPage {
+        subscribers,
+        page,
+        per_page,
+        total,
+    };
+    Ok(match parameters.format {
+        ResponseFormat::Json => HttpResponse::Ok().json(subscribers),
+        ResponseFormat::Html => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+            .body(render_page(&subscribers, &parameters, &flash_messages)),
+    })
+}
+
+/// Matches values containing `search`, taken literally.
+fn like_pattern(search: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in search.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+fn render_page(
+    subscribers: &SubscriberPage,
+    parameters: &ListParameters,
+    flash_messages: &IncomingFlashMessages,
+) -> String {
+    let messages_html = flash_messages_html(flash_messages);
+    let search = escape_html(parameters.search.as_deref().unwrap_or_default());
+    let mut statuses_html = String::new();
+    for status in SubscriptionStatus::ALL {
+        let selected = if parameters.status.as_deref() == Some(status.as_str()) {
+            " selected"
+        } else {
+            ""
+        };
+        writeln!(
+            statuses_html,
+            r#"                    <option value="{}"{}>{}</option>"#,
+            status.as_str(),
+            selected,
+            status.label(),
+        )
+        .unwrap();
+    }
+    let mut rows_html = String::new();
+    for subscriber in &subscribers.subscribers {
+        writeln!(
+            rows_html,
+            r#"            <tr><td><a href="{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            subscriber_page(subscriber.id),
+            escape_html(&subscriber.email),
+            escape_html(&subscriber.name),
+            subscriber.status,
+            subscriber.subscribed_at.format("%Y-%m-%d %H:%M"),
+        )
+        .unwrap();
+    }
+    let mut pages_html = String::new();
+    if subscribers.page > 1 {
+        writeln!(
+            pages_html,
+            r#"<a href="{}">Previous</a>"#,
+            page_location(parameters, subscribers.page - 1)
+        )
+        .unwrap();
+    }
+    if subscribers.page * subscribers.per_page < subscribers.total {
+        writeln!(
+            pages_html,
+            r#"<a href="{}">Next</a>"#,
+            page_location(parameters, subscribers.page + 1)
+        )
+        .unwrap();
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Subscribers</title>
+    </head>
+    <body>
+        <h1>Subscribers</h1>
+        {messages_html}
+        <form action="/admin/subscribers" method="get">
+            <input type="hidden" name="format" value="html">
+            <label>
+                Email or name
+                <input type="search" name="search" value="{search}">
+            </label>
+            <label>
+                Status
+                <select name="status">
+                    <option value="">Any but erased</option>
+{statuses_html}                </select>
+            </label>
+            <button type="submit">Search</button>
+        </form>
+        <p>{total} subscribers</p>
+        <table>
+            <tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed</th></tr>
+{rows_html}        </table>
+        {pages_html}
+    </body>
+</html>
+"#,
+        total = subscribers.total,
+    )
+}
+
+fn page_location(parameters: &ListParameters, page: i64) -> String {
+    let parameters = ListParameters {
+        page: Some(page),
+        ..parameters.clone()
+    };
+    let query = serde_urlencoded::to_string(&parameters).unwrap_or_default();
+    escape_html(&format!("/admin/subscribers?format=html&{}", query))
+}
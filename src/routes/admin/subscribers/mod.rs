@@ -0,0 +1,226 @@
+mod actions;
+mod detail;
+mod list;
+
+pub use actions::*;
+pub use detail::*;
+pub use list::*;
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use actix_web::{delete, get, patch, post, web, HttpRequest, HttpResponse, Responder};
+use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::custom_fields::{
+    get_custom_fields, get_field_values, remove_field_values, set_field_values,
+};
+use crate::domain::newsletter_issue::escape_html;
+use crate::domain::subscriber_tag::SubscriberTag;
+use crate::routes::admin::{authenticate, AdminError};
+use crate::tags::{add_tags, get_tags, remove_tag};
+
+#[derive(serde::Deserialize)]
+struct TagsBody {
+    tags: Vec<String>,
+}
+
+/// How the subscriber management endpoints answer: JSON for scripts, or pages for browsers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ResponseFormat {
+    #[default]
+    Json,
+    Html,
+}
+
+/// Renders the flash messages left by the previous request.
+fn flash_messages_html(flash_messages: &IncomingFlashMessages) -> String {
+    let mut html = String::new();
+    for message in flash_messages.iter() {
+        let class = if message.level() == Level::Error {
+            "error"
+        } else {
+            "info"
+        };
+        writeln!(
+            html,
+            r#"<p class="{}"><i>{}</i></p>"#,
+            class,
+            escape_html(message.content())
+        )
+        .unwrap();
+    }
+    html
+}
+
+/// The location of the page of a subscriber.
+fn subscriber_page(subscriber_id: Uuid) -> String {
+    format!("/admin/subscribers/{}?format=html", subscriber_id)
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Get the custom fields of a subscriber",
+    skip(pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/subscribers/{subscriber_id}/fields")]
+pub async fn get_subscriber_fields(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    ensure_subscriber_exists(&pool, *subscriber_id).await?;
+    let values = get_field_values(pool.get_ref(), *subscriber_id)
+        .await
+        .context("Failed to retrieve the custom fields of the subscriber.")?;
+    Ok(HttpResponse::Ok().json(values))
+}
+
+/// Updates the given custom fields of a subscriber; `null` or an empty string clears a value.
+/// Fields that are not part of the body are left as they are.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Update the custom fields of a subscriber",
+    skip(body, pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[patch("/admin/subscribers/{subscriber_id}/fields")]
+pub async fn update_subscriber_fields(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<HashMap<String, Option<String>>>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let custom_fields = get_custom_fields(pool.get_ref())
+        .await
+        .context("Failed to retrieve the custom fields.")?;
+    let mut updated = Vec::new();
+    let mut cleared = Vec::new();
+    for (key, value) in body.into_inner() {
+        let field = custom_fields
+            .iter()
+            .find(|field| field.key == key)
+            .ok_or_else(|| {
+                AdminError::ValidationError(format!("There is no field named {}", key))
+            })?;
+        match value.filter(|value| !value.trim().is_empty()) {
+            Some(value) => {
+                let value = field
+                    .parse_value(&value)
+                    .map_err(AdminError::ValidationError)?;
+                updated.push((key, value));
+            }
+            None if field.required => {
+                return Err(AdminError::ValidationError(format!(
+                    "{} is required",
+                    field.label
+                )))
+            }
+            None => cleared.push(key),
+        }
+    }
+    ensure_subscriber_exists(&pool, *subscriber_id).await?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    set_field_values(&mut *transaction, *subscriber_id, &updated)
+        .await
+        .context("Failed to store the custom fields of the subscriber.")?;
+    remove_field_values(&mut *transaction, *subscriber_id, &cleared)
+        .await
+        .context("Failed to clear the custom fields of the subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update the custom fields.")?;
+    Ok(HttpResponse::Ok())
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Get the tags of a subscriber",
+    skip(pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[get("/admin/subscribers/{subscriber_id}/tags")]
+pub async fn get_subscriber_tags(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    ensure_subscriber_exists(&pool, *subscriber_id).await?;
+    let tags = get_tags(pool.get_ref(), *subscriber_id)
+        .await
+        .context("Failed to retrieve the tags of the subscriber.")?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "tags": tags })))
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Tag a subscriber",
+    skip(body, pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[post("/admin/subscribers/{subscriber_id}/tags")]
+pub async fn tag_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<TagsBody>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let tags = body
+        .tags
+        .iter()
+        .map(|tag| SubscriberTag::parse(tag))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AdminError::ValidationError)?;
+    ensure_subscriber_exists(&pool, *subscriber_id).await?;
+    add_tags(pool.get_ref(), *subscriber_id, &tags)
+        .await
+        .context("Failed to tag the subscriber.")?;
+    Ok(HttpResponse::Ok())
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Untag a subscriber",
+    skip(pool, request),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[delete("/admin/subscribers/{subscriber_id}/tags/{tag}")]
+pub async fn untag_subscriber(
+    path: web::Path<(Uuid, String)>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, AdminError> {
+    authenticate(&request, &pool).await?;
+    let (subscriber_id, tag) = path.into_inner();
+    let tag = SubscriberTag::parse(&tag).map_err(AdminError::ValidationError)?;
+    let removed = remove_tag(pool.get_ref(), subscriber_id, &tag)
+        .await
+        .context("Failed to untag the subscriber.")?;
+    if !removed {
+        return Err(AdminError::NotFound);
+    }
+    Ok(HttpResponse::Ok())
+}
+
+async fn ensure_subscriber_exists(pool: &PgPool, subscriber_id: Uuid) -> Result<(), AdminError> {
+    sqlx::query!("SELECT id FROM subscriptions WHERE id = $1", subscriber_id)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to retrieve the subscriber.")?
+        .ok_or(AdminError::NotFound)?;
+    Ok(())
+}
@@ -0,0 +1,217 @@
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use actix_http::StatusCode;
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType, HttpDate, LastModified};
+use actix_web::{get, web, HttpResponse, Responder, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use super::error_chain_fmt;
+use crate::domain::newsletter_issue::{escape_html, NewsletterIssue, TemplateVariables};
+
+const ISSUES_PER_PAGE: i64 = 20;
+/// How long, in seconds, shared caches may serve the archive before checking for newer issues.
+const LIST_MAX_AGE: u32 = 300;
+/// Archived issues do not change once sent.
+const ISSUE_MAX_AGE: u32 = 3600;
+
+#[derive(thiserror::Error)]
+pub enum ArchiveError {
+    #[error("There is no public issue with the given slug")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ArchiveError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ArchiveError::NotFound => StatusCode::NOT_FOUND,
+            ArchiveError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ArchiveParameters {
+    page: Option<u32>,
+}
+
+struct ArchivedIssue {
+    slug: String,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Serve the newsletter archive", skip(parameters, pool))]
+#[get("/archive")]
+pub async fn archive(
+    parameters: web::Query<ArchiveParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder, ArchiveError> {
+    let page = parameters.page.unwrap_or(1).max(1);
+    let mut issues = get_archived_issues(&pool, page)
+        .await
+        .context("Failed to retrieve the archived issues.")?;
+    let has_older = issues.len() as i64 > ISSUES_PER_PAGE;
+    issues.truncate(ISSUES_PER_PAGE as usize);
+
+    let mut issues_html = String::new();
+    for issue in &issues {
+        writeln!(
+            issues_html,
+            r#"<li><a href="/archive/{}">{}</a> <time datetime="{}">{}</time></li>"#,
+            issue.slug,
+            escape_html(&issue.title),
+            issue.published_at.to_rfc3339(),
+            issue.published_at.format("%B %-d, %Y"),
+        )
+        .unwrap();
+    }
+    let mut navigation_html = String::new();
+    if page > 1 {
+        write!(
+            navigation_html,
+            r#"<a href="/archive?page={}">Newer issues</a> "#,
+            page - 1
+        )
+        .unwrap();
+    }
+    if has_older {
+        write!(
+            navigation_html,
+            r#"<a href="/archive?page={}">Older issues</a>"#,
+            page + 1
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(LIST_MAX_AGE),
+        ]))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Newsletter archive</title>
+    </head>
+    <body>
+        <h1>Newsletter archive</h1>
+        <ul>
+{issues_html}        </ul>
+        <nav>{navigation_html}</nav>
+    </body>
+</html>
+"#,
+        )))
+}
+
+/// Serves a sent issue as it was written, never as delivered to a subscriber: tracking pixels,
+/// tracked links and per-recipient tokens are added at delivery time and are not part of it, and
+/// personal placeholders are filled with neutral values.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Serve an archived issue", skip(pool))]
+#[get("/archive/{slug}")]
+pub async fn archived_issue(
+    slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder, ArchiveError> {
+    let (issue, published_at) = get_archived_issue(&pool, &slug)
+        .await
+        .context("Failed to retrieve the archived issue.")?
+        .ok_or(ArchiveError::NotFound)?;
+    let rendered = issue.render(&TemplateVariables::public());
+
+    let body = if rendered.html_content.to_ascii_lowercase().contains("<html") {
+        rendered.html_content
+    } else {
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>{}</title>
+    </head>
+    <body>
+{}
+    </body>
+</html>
+"#,
+            escape_html(&rendered.title),
+            rendered.html_content,
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(ISSUE_MAX_AGE),
+        ]))
+        .insert_header(LastModified(HttpDate::from(SystemTime::from(published_at))))
+        .body(body))
+}
+
+#[tracing::instrument(name = "Get archived issues", skip(pool))]
+async fn get_archived_issues(pool: &PgPool, page: u32) -> Result<Vec<ArchivedIssue>, sqlx::Error> {
+    // One extra row tells whether there is an older page.
+    sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT slug, title, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE is_public AND published_at IS NOT NULL AND cancelled_at IS NULL
+        ORDER BY published_at DESC
+        LIMIT $1 OFFSET $2
+        "#,
+        ISSUES_PER_PAGE + 1,
+        (page as i64 - 1) * ISSUES_PER_PAGE,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get archived issue", skip(pool))]
+async fn get_archived_issue(
+    pool: &PgPool,
+    slug: &str,
+) -> Result<Option<(NewsletterIssue, DateTime<Utc>)>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            newsletter_issue_id, list_id, title, text_content, html_content, track_opens,
+            track_clicks,
+            published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE slug = $1 AND is_public AND published_at IS NOT NULL AND cancelled_at IS NULL
+        "#,
+        slug,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|row| {
+        let issue = NewsletterIssue {
+            id: row.newsletter_issue_id,
+            list_id: row.list_id,
+            title: row.title,
+            text_content: row.text_content,
+            html_content: row.html_content,
+            track_opens: row.track_opens,
+            track_clicks: row.track_clicks,
+        };
+        (issue, row.published_at)
+    }))
+}
@@ -0,0 +1,238 @@
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use actix_web::http::header::{
+    self, CacheControl, CacheDirective, EntityTag, Header, HttpDate, IfModifiedSince, IfNoneMatch,
+    LastModified,
+};
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Context;
+use chrono::{DateTime, SubsecRound, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::archive::ArchiveError;
+use crate::domain::newsletter_issue::{escape_html, NewsletterIssue, TemplateVariables};
+use crate::startup::ApplicationBaseUrl;
+
+const FEED_TITLE: &str = "Newsletter";
+const FEED_ENTRIES: i64 = 20;
+/// How long, in seconds, feed readers and caches may reuse a feed before polling again.
+const FEED_MAX_AGE: u32 = 300;
+
+struct FeedEntry {
+    id: Uuid,
+    slug: String,
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Serve the RSS feed", skip(pool, base_url, request))]
+#[get("/feed.rss")]
+pub async fn rss_feed(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    request: HttpRequest,
+) -> Result<impl Responder, ArchiveError> {
+    let entries = get_feed_entries(&pool)
+        .await
+        .context("Failed to retrieve the issues of the feed.")?;
+    let base_url = &base_url.0;
+
+    let mut items = String::new();
+    for entry in &entries {
+        write!(
+            items,
+            r#"
+        <item>
+            <title>{title}</title>
+            <link>{base_url}/archive/{slug}</link>
+            <guid isPermaLink="false">urn:uuid:{id}</guid>
+            <pubDate>{published_at}</pubDate>
+            <description>{content}</description>
+        </item>"#,
+            title = escape_html(&entry.title),
+            slug = entry.slug,
+            id = entry.id,
+            published_at = entry.published_at.to_rfc2822(),
+            content = escape_html(&entry.html_content),
+        )
+        .unwrap();
+    }
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0">
+    <channel>
+        <title>{title}</title>
+        <link>{base_url}/archive</link>
+        <description>Issues of the {title}</description>
+        <lastBuildDate>{updated}</lastBuildDate>{items}
+    </channel>
+</rss>
+"#,
+        title = FEED_TITLE,
+        updated = last_updated(&entries).to_rfc2822(),
+    );
+
+    Ok(feed_response(
+        &request,
+        "application/rss+xml; charset=utf-8",
+        &entries,
+        body,
+    ))
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Serve the Atom feed", skip(pool, base_url, request))]
+#[get("/feed.atom")]
+pub async fn atom_feed(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    request: HttpRequest,
+) -> Result<impl Responder, ArchiveError> {
+    let entries = get_feed_entries(&pool)
+        .await
+        .context("Failed to retrieve the issues of the feed.")?;
+    let base_url = &base_url.0;
+
+    let mut atom_entries = String::new();
+    for entry in &entries {
+        write!(
+            atom_entries,
+            r#"
+    <entry>
+        <title>{title}</title>
+        <link rel="alternate" type="text/html" href="{base_url}/archive/{slug}"/>
+        <id>urn:uuid:{id}</id>
+        <published>{published_at}</published>
+        <updated>{published_at}</updated>
+        <content type="html">{content}</content>
+    </entry>"#,
+            title = escape_html(&entry.title),
+            slug = entry.slug,
+            id = entry.id,
+            published_at = entry.published_at.to_rfc3339(),
+            content = escape_html(&entry.html_content),
+        )
+        .unwrap();
+    }
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+    <title>{title}</title>
+    <id>{base_url}/feed.atom</id>
+    <link rel="self" href="{base_url}/feed.atom"/>
+    <link rel="alternate" type="text/html" href="{base_url}/archive"/>
+    <author><name>{title}</name></author>
+    <updated>{updated}</updated>{atom_entries}
+</feed>
+"#,
+        title = FEED_TITLE,
+        updated = last_updated(&entries).to_rfc3339(),
+    );
+
+    Ok(feed_response(
+        &request,
+        "application/atom+xml; charset=utf-8",
+        &entries,
+        body,
+    ))
+}
+
+/// When the feed last changed: the publication of its latest entry.
+fn last_updated(entries: &[FeedEntry]) -> DateTime<Utc> {
+    entries
+        .first()
+        .map(|entry| entry.published_at)
+        .unwrap_or_default()
+}
+
+/// Answers with `304 Not Modified` when the reader already has this version of the feed, as told
+/// by `If-None-Match` or, failing that, `If-Modified-Since`.
+fn feed_response(
+    request: &HttpRequest,
+    content_type: &str,
+    entries: &[FeedEntry],
+    body: String,
+) -> HttpResponse {
+    let etag = EntityTag::new_strong(hex::encode(Sha256::digest(body.as_bytes())));
+    // HTTP dates have no sub-second precision: a reader echoing ours back must compare equal.
+    let last_modified = HttpDate::from(SystemTime::from(last_updated(entries).trunc_subsecs(0)));
+
+    let not_modified = if request.headers().contains_key(header::IF_NONE_MATCH) {
+        match IfNoneMatch::parse(request) {
+            Ok(IfNoneMatch::Any) => true,
+            Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+            Err(_) => false,
+        }
+    } else {
+        match IfModifiedSince::parse(request) {
+            Ok(IfModifiedSince(since)) => {
+                SystemTime::from(last_modified) <= SystemTime::from(since)
+            }
+            Err(_) => false,
+        }
+    };
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header(header::ETag(etag))
+        .insert_header(LastModified(last_modified))
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(FEED_MAX_AGE),
+        ]));
+    if not_modified {
+        response.finish()
+    } else {
+        response.content_type(content_type).body(body)
+    }
+}
+
+#[tracing::instrument(name = "Get feed entries", skip(pool))]
+async fn get_feed_entries(pool: &PgPool) -> Result<Vec<FeedEntry>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            newsletter_issue_id, list_id, slug, title, text_content, html_content, track_opens,
+            track_clicks, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE is_public AND published_at IS NOT NULL AND cancelled_at IS NULL
+        ORDER BY published_at DESC
+        LIMIT $1
+        "#,
+        FEED_ENTRIES,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let issue = NewsletterIssue {
+                id: row.newsletter_issue_id,
+                list_id: row.list_id,
+                title: row.title,
+                text_content: row.text_content,
+                html_content: row.html_content,
+                track_opens: row.track_opens,
+                track_clicks: row.track_clicks,
+            };
+            // Like the archive, feeds show the issue as written, with neutral placeholder values.
+            let rendered = issue.render(&TemplateVariables::public());
+            FeedEntry {
+                id: issue.id,
+                slug: row.slug,
+                title: rendered.title,
+                html_content: rendered.html_content,
+                published_at: row.published_at,
+            }
+        })
+        .collect())
+}
@@ -43,7 +43,7 @@ pub async fn login(
 
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
             let mut resp = HttpResponse::SeeOther();
             resp.insert_header((header::LOCATION, "/"));
             Ok(resp)
@@ -1,13 +1,29 @@
+pub mod admin;
+pub mod archive;
+pub mod feeds;
 pub mod health_check;
 pub mod home;
 pub mod login;
 pub mod newsletter;
+pub mod preferences;
+pub mod privacy;
 pub mod subscriptions;
 pub mod subscriptions_confirm;
+pub mod subscriptions_unsubscribe;
+pub mod tracking;
+pub mod webhooks;
 
+pub use admin::*;
+pub use archive::*;
+pub use feeds::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
 pub use newsletter::*;
+pub use preferences::*;
+pub use privacy::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
+pub use tracking::*;
+pub use webhooks::*;
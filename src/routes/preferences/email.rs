@@ -0,0 +1,146 @@
+use actix_http::header::LOCATION;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use super::{get_subscriber_from_token, preferences_location, PreferencesError};
+use crate::address_policy::AddressPolicy;
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::email_change::{
+    apply_email_change, check_new_address, create_email_change, send_change_notice,
+    send_verification_email, EmailChangeOutcome,
+};
+use crate::email_client::EmailClient;
+use crate::startup::ApplicationBaseUrl;
+
+#[derive(serde::Deserialize)]
+struct EmailChangeForm {
+    token: String,
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+struct Parameters {
+    token: String,
+}
+
+/// Sends a link to the new address; the subscription only moves once it is followed.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Request an email change",
+    skip(form, pool, email_client, base_url, address_policy)
+)]
+#[post("/preferences/email")]
+pub async fn request_email_change(
+    form: web::Form<EmailChangeForm>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    address_policy: web::Data<AddressPolicy>,
+) -> Result<impl Responder, PreferencesError> {
+    let form = form.into_inner();
+    let subscriber = get_subscriber_from_token(pool.get_ref(), &form.token)
+        .await
+        .context("Failed to retrieve the subscriber for the given token.")?
+        .ok_or(PreferencesError::InvalidToken)?;
+    let redirect = HttpResponse::SeeOther()
+        .insert_header((LOCATION, preferences_location(&form.token)))
+        .finish();
+    let new_email = match SubscriberEmail::parse(form.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(redirect);
+        }
+    };
+    if let Some(e) = check_new_address(
+        pool.get_ref(),
+        &address_policy,
+        &subscriber.email,
+        &new_email,
+    )
+    .await
+    .context("Failed to check the new address.")?
+    {
+        FlashMessage::error(e).send();
+        return Ok(redirect);
+    }
+
+    let token = create_email_change(pool.get_ref(), subscriber.id, &new_email, None)
+        .await
+        .context("Failed to store the email change request.")?;
+    send_verification_email(&email_client, &new_email, &base_url.0, &token)
+        .await
+        .context("Failed to send the email change verification.")?;
+    FlashMessage::info(format!(
+        "We sent a link to {} to confirm the change. It is valid for a day.",
+        new_email.as_ref()
+    ))
+    .send();
+    Ok(redirect)
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Verify an email change", skip(parameters, pool, email_client))]
+#[get("/preferences/email/verify")]
+pub async fn verify_email_change(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+) -> Result<impl Responder, PreferencesError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    let outcome = apply_email_change(&mut transaction, &parameters.token)
+        .await
+        .context("Failed to change the address of the subscriber.")?
+        .ok_or(PreferencesError::InvalidToken)?;
+    let subscriber_id = match outcome {
+        EmailChangeOutcome::Changed {
+            subscriber_id,
+            old_email,
+            new_email,
+        } => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to change an address.")?;
+            if let Ok(old_email) = SubscriberEmail::parse(old_email) {
+                if let Err(e) = send_change_notice(&email_client, &old_email, &new_email).await {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        "Failed to let the previous address know about the change"
+                    );
+                }
+            }
+            FlashMessage::info(format!("Our newsletter will now be sent to {}.", new_email)).send();
+            subscriber_id
+        }
+        EmailChangeOutcome::AddressTaken { subscriber_id } => {
+            transaction
+                .rollback()
+                .await
+                .context("Failed to roll back SQL transaction to change an address.")?;
+            FlashMessage::error(
+                "This address already has its own subscription. Unsubscribe it or erase its \
+                data first, then follow the link again.",
+            )
+            .send();
+            subscriber_id
+        }
+    };
+
+    let token = sqlx::query!(
+        "SELECT preferences_token FROM subscriptions WHERE id = $1",
+        subscriber_id,
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to retrieve the preferences token of the subscriber.")?
+    .preferences_token;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, preferences_location(&token)))
+        .finish())
+}
@@ -0,0 +1,137 @@
+use std::fmt::Write;
+
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{get, web, HttpResponse, Responder};
+use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use super::{get_preference_lists, get_subscriber_from_token, PreferencesError};
+use crate::domain::delivery_frequency::DeliveryFrequency;
+use crate::domain::newsletter_issue::escape_html;
+
+#[derive(serde::Deserialize)]
+struct Parameters {
+    token: String,
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Serve the preference center",
+    skip(parameters, pool, flash_messages)
+)]
+#[get("/preferences")]
+pub async fn preferences_form(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<impl Responder, PreferencesError> {
+    let subscriber = get_subscriber_from_token(pool.get_ref(), &parameters.token)
+        .await
+        .context("Failed to retrieve the subscriber for the given token.")?
+        .ok_or(PreferencesError::InvalidToken)?;
+    let lists = get_preference_lists(pool.get_ref(), subscriber.id)
+        .await
+        .context("Failed to retrieve the mailing lists.")?;
+
+    let mut messages_html = String::new();
+    for message in flash_messages.iter() {
+        let class = if message.level() == Level::Error {
+            "error"
+        } else {
+            "info"
+        };
+        writeln!(
+            messages_html,
+            r#"<p class="{}"><i>{}</i></p>"#,
+            class,
+            escape_html(message.content())
+        )
+        .unwrap();
+    }
+    let mut lists_html = String::new();
+    for list in &lists {
+        let confirmed = list.status.as_deref() == Some("confirmed");
+        writeln!(
+            lists_html,
+            r#"                <label><input type="checkbox" name="list:{}"{}> {}</label><br>"#,
+            list.list_id,
+            if confirmed { " checked" } else { "" },
+            escape_html(&list.name),
+        )
+        .unwrap();
+        // Ticking a list joins it on the word of its consent statement.
+        if !confirmed {
+            writeln!(
+                lists_html,
+                r#"                <small>{}</small><br>"#,
+                escape_html(&list.consent_text),
+            )
+            .unwrap();
+        }
+    }
+    let mut frequencies_html = String::new();
+    for frequency in DeliveryFrequency::ALL {
+        let selected = if frequency.as_str() == subscriber.frequency {
+            " selected"
+        } else {
+            ""
+        };
+        writeln!(
+            frequencies_html,
+            r#"                    <option value="{}"{}>{}</option>"#,
+            frequency.as_str(),
+            selected,
+            frequency.label(),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Your preferences</title>
+    </head>
+    <body>
+        <h1>Your preferences</h1>
+        <p>Subscribed as {email}</p>
+        {messages_html}
+        <form action="/preferences" method="post">
+            <input type="hidden" name="token" value="{token}">
+            <label>
+                Name
+                <input type="text" name="name" value="{name}">
+            </label>
+            <fieldset>
+                <legend>Lists you receive</legend>
+{lists_html}            </fieldset>
+            <label>
+                Frequency
+                <select name="frequency">
+{frequencies_html}                </select>
+            </label>
+            <button type="submit" name="action" value="save">Save</button>
+            <button type="submit" name="action" value="unsubscribe_all">Unsubscribe from everything</button>
+        </form>
+        <form action="/preferences/email" method="post">
+            <input type="hidden" name="token" value="{token}">
+            <label>
+                New email address
+                <input type="email" name="email" placeholder="Enter your new address">
+            </label>
+            <button type="submit">Change my address</button>
+        </form>
+        <p><a href="/privacy">Download or erase your data</a></p>
+    </body>
+</html>
+"#,
+            email = escape_html(&subscriber.email),
+            token = escape_html(&parameters.token),
+            name = escape_html(&subscriber.name),
+        )))
+}
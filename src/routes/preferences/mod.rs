@@ -0,0 +1,95 @@
+mod email;
+mod get;
+mod post;
+
+pub use email::{request_email_change, verify_email_change};
+pub use get::preferences_form;
+pub use post::update_preferences;
+
+use actix_http::StatusCode;
+use actix_web::ResponseError;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use super::error_chain_fmt;
+use crate::mailing_list::DEFAULT_CONSENT_TEXT;
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error("There is no subscriber with the given token")]
+    InvalidToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidToken => StatusCode::UNAUTHORIZED,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+struct PreferencesSubscriber {
+    id: Uuid,
+    name: String,
+    email: String,
+    frequency: String,
+}
+
+#[tracing::instrument(name = "Getting subscriber from preferences token", skip_all)]
+async fn get_subscriber_from_token(
+    executor: impl PgExecutor<'_>,
+    token: &str,
+) -> Result<Option<PreferencesSubscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        PreferencesSubscriber,
+        "SELECT id, name, email, frequency FROM subscriptions WHERE preferences_token = $1",
+        token,
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// A list offered in the preference center, with the subscriber's membership of it if any.
+struct PreferenceList {
+    list_id: Uuid,
+    name: String,
+    /// Shown next to the lists the subscriber can join.
+    consent_text: String,
+    status: Option<String>,
+}
+
+/// Returns the lists the preference center shows, the only ones it can join or leave.
+#[tracing::instrument(name = "Getting the lists of the preference center", skip(executor))]
+async fn get_preference_lists(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Vec<PreferenceList>, sqlx::Error> {
+    sqlx::query_as!(
+        PreferenceList,
+        r#"
+        SELECT
+            l.list_id, l.name, COALESCE(l.consent_text, $2) AS "consent_text!",
+            m.status AS "status?"
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.list_id AND m.subscriber_id = $1
+        ORDER BY l.created_at, l.name
+        "#,
+        subscriber_id,
+        DEFAULT_CONSENT_TEXT,
+    )
+    .fetch_all(executor)
+    .await
+}
+
+fn preferences_location(token: &str) -> String {
+    format!("/preferences?token={}", token)
+}
@@ -0,0 +1,135 @@
+use std::collections::{HashMap, HashSet};
+
+use actix_http::header::LOCATION;
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{
+    get_preference_lists, get_subscriber_from_token, preferences_location, PreferencesError,
+};
+use crate::consent::{record_consent, ConsentAction, ConsentContext};
+use crate::domain::delivery_frequency::DeliveryFrequency;
+use crate::domain::subscriber_name::SubscriberName;
+use crate::mailing_list::{join_confirmed, unsubscribe};
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum PreferencesAction {
+    Save,
+    UnsubscribeAll,
+}
+
+#[derive(serde::Deserialize)]
+struct PreferencesForm {
+    token: String,
+    name: String,
+    frequency: String,
+    action: PreferencesAction,
+    /// One `list:{list_id}` entry per list ticked.
+    #[serde(flatten)]
+    lists: HashMap<String, String>,
+}
+
+/// Applies the changes made in the preference center. Invalid input is reported back on the page
+/// rather than as an error status, so the subscriber can correct it.
+///
+/// Only the lists the page shows can be joined or left, and joining one records the consent given
+/// by ticking it.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Update subscriber preferences", skip(form, pool, request))]
+#[post("/preferences")]
+pub async fn update_preferences(
+    form: web::Form<PreferencesForm>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<impl Responder, PreferencesError> {
+    let form = form.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    let subscriber = get_subscriber_from_token(&mut *transaction, &form.token)
+        .await
+        .context("Failed to retrieve the subscriber for the given token.")?
+        .ok_or(PreferencesError::InvalidToken)?;
+    let redirect = HttpResponse::SeeOther()
+        .insert_header((LOCATION, preferences_location(&form.token)))
+        .finish();
+
+    let lists = get_preference_lists(&mut *transaction, subscriber.id)
+        .await
+        .context("Failed to retrieve the mailing lists.")?;
+
+    let selected: HashSet<Uuid> = match form.action {
+        PreferencesAction::UnsubscribeAll => HashSet::new(),
+        PreferencesAction::Save => {
+            let name = match SubscriberName::parse(form.name) {
+                Ok(name) => name,
+                Err(e) => {
+                    FlashMessage::error(e).send();
+                    return Ok(redirect);
+                }
+            };
+            let frequency = match DeliveryFrequency::parse(&form.frequency) {
+                Ok(frequency) => frequency,
+                Err(e) => {
+                    FlashMessage::error(e).send();
+                    return Ok(redirect);
+                }
+            };
+            sqlx::query!(
+                "UPDATE subscriptions SET name = $2, frequency = $3 WHERE id = $1",
+                subscriber.id,
+                name.as_ref(),
+                frequency.as_str(),
+            )
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to update the subscriber.")?;
+
+            form.lists
+                .keys()
+                .filter_map(|key| key.strip_prefix("list:")?.parse().ok())
+                .collect()
+        }
+    };
+
+    let consent_context = ConsentContext::from_request(&request, Some("preferences"));
+    for list in lists {
+        if selected.contains(&list.list_id) {
+            if join_confirmed(&mut transaction, list.list_id, subscriber.id)
+                .await
+                .context("Failed to add the subscriber to a list.")?
+            {
+                record_consent(
+                    &mut *transaction,
+                    subscriber.id,
+                    list.list_id,
+                    ConsentAction::JoinFromPreferences,
+                    &consent_context,
+                    &list.consent_text,
+                )
+                .await
+                .context("Failed to record the consent of the subscriber.")?;
+            }
+        } else if list.status.is_some() {
+            unsubscribe(&mut transaction, list.list_id, subscriber.id, None)
+                .await
+                .context("Failed to unsubscribe the subscriber from a list.")?;
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update preferences.")?;
+
+    let message = match form.action {
+        PreferencesAction::Save => "Your preferences have been saved.",
+        PreferencesAction::UnsubscribeAll => "You have been unsubscribed from every list.",
+    };
+    FlashMessage::info(message).send();
+    Ok(redirect)
+}
@@ -0,0 +1,40 @@
+use actix_web::http::header::{
+    CacheControl, CacheDirective, ContentDisposition, DispositionParam, DispositionType,
+};
+use actix_web::{get, web, HttpResponse, Responder};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use super::{PrivacyError, TokenParameters};
+use crate::data_subject::{collect_data, get_request, DataSubjectRequestKind};
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Download personal data", skip(parameters, pool))]
+#[get("/privacy/access")]
+pub async fn download_personal_data(
+    parameters: web::Query<TokenParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder, PrivacyError> {
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    let email = match get_request(&mut *connection, &parameters.token)
+        .await
+        .context("Failed to retrieve the request.")?
+    {
+        Some((email, DataSubjectRequestKind::Access)) => email,
+        _ => return Err(PrivacyError::InvalidToken),
+    };
+    let bundle = collect_data(&mut connection, &email)
+        .await
+        .context("Failed to collect the data stored about the address.")?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("personal-data.json".into())],
+        })
+        .json(bundle))
+}
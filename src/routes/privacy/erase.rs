@@ -0,0 +1,87 @@
+use actix_web::http::header::ContentType;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use super::{PrivacyError, TokenParameters};
+use crate::data_subject::{erase_data, get_request, DataSubjectRequestKind};
+use crate::domain::newsletter_issue::escape_html;
+
+/// Asks for confirmation before erasing anything: links in emails are sometimes followed by
+/// scanners, which must not be able to erase a subscriber's data.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Confirm erasure of personal data", skip(parameters, pool))]
+#[get("/privacy/erase")]
+pub async fn confirm_erasure(
+    parameters: web::Query<TokenParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder, PrivacyError> {
+    let email = erasure_email(&pool, &parameters.token).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Erase your data</title>
+    </head>
+    <body>
+        <p>Everything we hold about {email} will be erased, and you will not receive any more emails from us. This cannot be undone.</p>
+        <form action="/privacy/erase" method="post">
+            <input type="hidden" name="token" value="{token}">
+            <button type="submit">Erase my data</button>
+        </form>
+    </body>
+</html>
+"#,
+            email = escape_html(&email),
+            token = escape_html(&parameters.token),
+        )))
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Erase personal data", skip(form, pool))]
+#[post("/privacy/erase")]
+pub async fn erase_personal_data(
+    form: web::Form<TokenParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder, PrivacyError> {
+    let email = erasure_email(&pool, &form.token).await?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    erase_data(&mut transaction, &email)
+        .await
+        .context("Failed to erase the data stored about the address.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase personal data.")?;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Your data was erased</title>
+    </head>
+    <body>
+        <p>Your data was erased.</p>
+    </body>
+</html>
+"#,
+    ))
+}
+
+async fn erasure_email(pool: &PgPool, token: &str) -> Result<String, PrivacyError> {
+    match get_request(pool, token)
+        .await
+        .context("Failed to retrieve the request.")?
+    {
+        Some((email, DataSubjectRequestKind::Erasure)) => Ok(email),
+        _ => Err(PrivacyError::InvalidToken),
+    }
+}
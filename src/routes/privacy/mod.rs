@@ -0,0 +1,40 @@
+mod access;
+mod erase;
+mod request;
+
+pub use access::download_personal_data;
+pub use erase::{confirm_erasure, erase_personal_data};
+pub use request::{privacy_form, request_personal_data};
+
+use actix_http::StatusCode;
+use actix_web::ResponseError;
+
+use super::error_chain_fmt;
+
+#[derive(thiserror::Error)]
+pub enum PrivacyError {
+    #[error("The link is invalid or has expired")]
+    InvalidToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PrivacyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PrivacyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidToken => StatusCode::UNAUTHORIZED,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct TokenParameters {
+    token: String,
+}
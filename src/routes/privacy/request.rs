@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use actix_http::header::LOCATION;
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages, Level};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+
+use super::PrivacyError;
+use crate::bot_protection::{claim_form_token, record_rejected_signup, BotProtection, BotSignal};
+use crate::client_ip::client_ip;
+use crate::data_subject::{
+    collect_data, count_recent_requests, create_request, DataSubjectRequestKind,
+};
+use crate::domain::newsletter_issue::escape_html;
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::startup::ApplicationBaseUrl;
+
+/// How many links an address gets in a day, however often the form is submitted with it.
+const MAX_REQUESTS_PER_DAY: i64 = 3;
+
+/// The answer to every request, whether a link was sent or not, so that the page cannot tell who
+/// subscribed.
+const REQUEST_RECEIVED: &str =
+    "If we hold any data about this address, we sent it a link valid for a day.";
+
+#[derive(serde::Deserialize)]
+struct RequestForm {
+    #[serde(flatten)]
+    details: RequestDetails,
+    /// Whatever else the form holds, which includes the honeypot of bot protection.
+    #[serde(flatten)]
+    fields: HashMap<String, String>,
+}
+
+#[derive(serde::Deserialize)]
+struct RequestDetails {
+    email: String,
+    kind: DataSubjectRequestKind,
+    form_token: Option<String>,
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Serve the privacy request page",
+    skip(flash_messages, bot_protection)
+)]
+#[get("/privacy")]
+pub async fn privacy_form(
+    flash_messages: IncomingFlashMessages,
+    bot_protection: web::Data<BotProtection>,
+) -> impl Responder {
+    let mut messages_html = String::new();
+    for message in flash_messages.iter() {
+        let class = if message.level() == Level::Error {
+            "error"
+        } else {
+            "info"
+        };
+        writeln!(
+            messages_html,
+            r#"<p class="{}"><i>{}</i></p>"#,
+            class,
+            escape_html(message.content())
+        )
+        .unwrap();
+    }
+    // People leave the honeypot empty, as they do not see it.
+    let honeypot_html = bot_protection
+        .honeypot_field()
+        .map(|field| {
+            format!(
+                r#"<input type="text" name="{}" style="display:none" tabindex="-1" autocomplete="off">"#,
+                escape_html(field)
+            )
+        })
+        .unwrap_or_default();
+    let form_token = bot_protection.challenge(Utc::now()).form_token;
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Your data</title>
+    </head>
+    <body>
+        <h1>Your data</h1>
+        {messages_html}
+        <form action="/privacy/requests" method="post">
+            <input type="hidden" name="form_token" value="{form_token}">
+            {honeypot_html}
+            <label>
+                Email
+                <input type="email" name="email" placeholder="Enter your email address">
+            </label>
+            <label><input type="radio" name="kind" value="access" checked> Send me a copy of my data</label>
+            <label><input type="radio" name="kind" value="erasure"> Erase my data</label>
+            <button type="submit">Send me a link</button>
+        </form>
+    </body>
+</html>
+"#,
+        ))
+}
+
+/// Emails a link carrying out the request to the address it is about, so that only its owner can
+/// see or erase the data. The response is the same whether or not anything is stored about the
+/// address, so that the page cannot tell who subscribed.
+///
+/// The form is guarded by the same bot protection as the subscription form, and an address gets
+/// at most [`MAX_REQUESTS_PER_DAY`] links a day.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(
+    name = "Request personal data",
+    skip(form, pool, email_client, base_url, bot_protection, request),
+    fields(kind = ?form.details.kind)
+)]
+#[post("/privacy/requests")]
+pub async fn request_personal_data(
+    form: web::Form<RequestForm>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    bot_protection: web::Data<BotProtection>,
+    request: HttpRequest,
+) -> Result<impl Responder, PrivacyError> {
+    let redirect = HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/privacy"))
+        .finish();
+    let RequestForm { details, fields } = form.into_inner();
+    let form_token =
+        match bot_protection.check_own_form(details.form_token.as_deref(), &fields, Utc::now()) {
+            Ok(form_token) => form_token,
+            Err(signal) => return turn_away(&pool, &request, signal, redirect).await,
+        };
+    let email = match SubscriberEmail::parse(details.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(redirect);
+        }
+    };
+
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    // Only claimed once the address is valid, so that people can correct their mistakes.
+    if let Some(form_token) = form_token {
+        if !claim_form_token(&mut connection, form_token)
+            .await
+            .context("Failed to claim the form token.")?
+        {
+            return turn_away(&pool, &request, BotSignal::ReusedFormToken, redirect).await;
+        }
+    }
+    if count_recent_requests(&mut *connection, email.as_ref())
+        .await
+        .context("Failed to count the recent requests.")?
+        >= MAX_REQUESTS_PER_DAY
+    {
+        tracing::warn!("Not sending another personal data link to the same address today");
+        FlashMessage::info(REQUEST_RECEIVED).send();
+        return Ok(redirect);
+    }
+    let bundle = collect_data(&mut connection, email.as_ref())
+        .await
+        .context("Failed to collect the data stored about the address.")?;
+    if !bundle.is_empty() {
+        let token = create_request(&mut *connection, email.as_ref(), details.kind)
+            .await
+            .context("Failed to store the request.")?;
+        send_request_email(&email_client, &email, details.kind, &base_url.0, &token)
+            .await
+            .context("Failed to send the link carrying out the request.")?;
+    }
+    FlashMessage::info(REQUEST_RECEIVED).send();
+    Ok(redirect)
+}
+
+/// Answers a request that looks automated as if it had been made, without sending anything.
+async fn turn_away(
+    pool: &PgPool,
+    request: &HttpRequest,
+    signal: BotSignal,
+    redirect: HttpResponse,
+) -> Result<HttpResponse, PrivacyError> {
+    tracing::warn!(
+        reason = signal.as_str(),
+        client_ip = ?client_ip(request),
+        "Turned away a personal data request that looks automated"
+    );
+    record_rejected_signup(pool, signal)
+        .await
+        .context("Failed to count the rejected request.")?;
+    FlashMessage::info(REQUEST_RECEIVED).send();
+    Ok(redirect)
+}
+
+async fn send_request_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    kind: DataSubjectRequestKind,
+    base_url: &str,
+    token: &str,
+) -> Result<(), reqwest::Error> {
+    let (subject, action, path) = match kind {
+        DataSubjectRequestKind::Access => (
+            "Your personal data",
+            "download a copy of the data we hold about you",
+            "access",
+        ),
+        DataSubjectRequestKind::Erasure => (
+            "Erasing your personal data",
+            "erase the data we hold about you",
+            "erase",
+        ),
+    };
+    let link = format!("{}/privacy/{}?token={}", base_url, path, token);
+    email_client
+        .send_email(
+            email,
+            subject,
+            &format!(
+                "Click <a href=\"{}\">here</a> to {}.<br />\
+                If you did not ask for this, you can ignore this email.",
+                link, action
+            ),
+            &format!(
+                "Visit {} to {}.\nIf you did not ask for this, you can ignore this email.",
+                link, action
+            ),
+        )
+        .await
+        .map(|_| ())
+}
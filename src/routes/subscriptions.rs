@@ -1,32 +1,77 @@
+use std::collections::HashMap;
+
 use actix_http::StatusCode;
-use actix_web::{post, web, HttpResponse, Responder, ResponseError};
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder, ResponseError};
 use anyhow::Context;
+use chrono::Utc;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
+use sqlx::types::Json;
 use sqlx::{PgConnection, PgPool};
 use uuid::Uuid;
 
+use crate::address_policy::AddressPolicy;
+use crate::bot_protection::{claim_form_token, record_rejected_signup, BotProtection, BotSignal};
+use crate::client_ip::client_ip;
+use crate::consent::{record_consent, ConsentAction, ConsentContext};
+use crate::custom_fields::{get_custom_fields, set_field_values};
+use crate::domain::custom_field::parse_field_values;
+use crate::domain::newsletter_issue::TemplateVariables;
 use crate::domain::subscriber::Subscriber;
 use crate::domain::subscriber_email::SubscriberEmail;
 use crate::domain::subscriber_name::SubscriberName;
+use crate::domain::subscriber_tag::SubscriberTag;
 use crate::email_client::EmailClient;
+use crate::mailing_list::{get_list, MailingList, DEFAULT_LIST_ID};
+use crate::suppression::is_suppressed;
+use crate::tags::add_tags;
+
+/// How many confirmation emails an address gets for a list in a day, however often the form is
+/// submitted with it.
+const MAX_CONFIRMATIONS_PER_DAY: i64 = 3;
 
 #[derive(serde::Deserialize, Debug)]
 struct SubscriptionForm {
+    #[serde(flatten)]
+    details: SubscriptionDetails,
+    /// Values of the custom fields, by key: whatever the form holds besides the details.
+    #[serde(flatten)]
+    fields: HashMap<String, String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct SubscriptionDetails {
     name: String,
     email: String,
+    /// The list to subscribe to, the default list when absent.
+    list_id: Option<Uuid>,
+    /// Comma-separated tags to add to the subscriber, typically from a hidden form field.
+    tags: Option<String>,
+    /// Identifies the form the subscriber signed up from, kept as proof of their consent.
+    source: Option<String>,
+    /// From `GET /subscriptions/challenge`, when bot protection asks for one.
+    form_token: Option<String>,
+    /// The nonce proving work for the form token and address, when bot protection asks for one.
+    proof_of_work: Option<String>,
 }
 
-impl TryFrom<SubscriptionForm> for Subscriber {
+impl TryFrom<SubscriptionDetails> for Subscriber {
     type Error = String;
 
-    fn try_from(subscriber: SubscriptionForm) -> Result<Self, Self::Error> {
+    fn try_from(subscriber: SubscriptionDetails) -> Result<Self, Self::Error> {
         let name = SubscriberName::parse(subscriber.name)?;
         let email = SubscriberEmail::parse(subscriber.email)?;
         Ok(Self { email, name })
     }
 }
 
+/// The fields of the subscription form that are not custom fields, which custom fields therefore
+/// cannot be named after.
+pub(crate) fn subscription_form_keys() -> &'static [&'static str] {
+    serde_aux::serde_introspection::serde_introspect::<SubscriptionDetails>()
+}
+
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
     #[error("{0}")]
@@ -53,10 +98,19 @@ impl ResponseError for SubscribeError {
 #[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, connection, email_client, base_url),
+    skip(
+        form,
+        connection,
+        email_client,
+        base_url,
+        address_policy,
+        bot_protection,
+        request
+    ),
     fields(
-        subscriber_email = %form.email,
-        subscriber_name = %form.name,
+        subscriber_email = %form.details.email,
+        subscriber_name = %form.details.name,
+        list_id = tracing::field::Empty,
     )
 )]
 #[post("/subscriptions")]
@@ -65,62 +119,215 @@ async fn subscribe(
     connection: web::Data<PgPool>,
     email_client: web::Data<crate::email_client::EmailClient>,
     base_url: web::Data<crate::startup::ApplicationBaseUrl>,
-) -> Result<impl Responder, SubscribeError> {
-    let subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    address_policy: web::Data<AddressPolicy>,
+    bot_protection: web::Data<BotProtection>,
+    request: HttpRequest,
+) -> Result<HttpResponse, SubscribeError> {
+    let SubscriptionForm { details, fields } = form.into_inner();
+    let form_token = match bot_protection.check(
+        &details.email,
+        details.form_token.as_deref(),
+        details.proof_of_work.as_deref(),
+        &fields,
+        Utc::now(),
+    ) {
+        Ok(form_token) => form_token,
+        Err(signal) => return turn_away(connection.get_ref(), &request, signal).await,
+    };
+    let list_id = details.list_id.unwrap_or(DEFAULT_LIST_ID);
+    tracing::Span::current().record("list_id", tracing::field::display(&list_id));
+    let tags = SubscriberTag::parse_list(details.tags.as_deref().unwrap_or_default())
+        .map_err(SubscribeError::ValidationError)?;
+    let custom_fields = get_custom_fields(connection.get_ref())
+        .await
+        .context("Failed to retrieve the custom fields.")?;
+    let field_values =
+        parse_field_values(&custom_fields, &fields).map_err(SubscribeError::ValidationError)?;
+    let consent_context = ConsentContext::from_request(&request, details.source.as_deref());
+    let subscriber: Subscriber = details
+        .try_into()
+        .map_err(SubscribeError::ValidationError)?;
+    address_policy
+        .check(&subscriber.email)
+        .await
+        .map_err(SubscribeError::ValidationError)?;
+    let list = get_list(connection.get_ref(), list_id)
+        .await
+        .context("Failed to retrieve the mailing list.")?
+        .ok_or_else(|| {
+            SubscribeError::ValidationError("There is no list with the given identifier.".into())
+        })?;
+
+    // Only claimed once the submission is valid, so that people can correct their mistakes.
+    if let Some(form_token) = form_token {
+        let mut conn = connection
+            .acquire()
+            .await
+            .context("Failed to acquire Postgres connection from the pool.")?;
+        if !claim_form_token(&mut conn, form_token)
+            .await
+            .context("Failed to claim the form token.")?
+        {
+            return turn_away(connection.get_ref(), &request, BotSignal::ReusedFormToken).await;
+        }
+    }
+
     let mut transaction = connection
         .begin()
         .await
         .context("Failed to acquire Postgres connection from the pool.")?;
-    let subscriber_id = insert_subscription(&mut transaction, &subscriber)
+    let (subscriber_id, is_new) = insert_subscription(&mut transaction, &subscriber)
         .await
         .context("Failed to insert a new subscriber into the database.")?;
-    let subscription_token = generate_subscription_token();
-    store_token_in_db(&mut transaction, subscriber_id, &subscription_token)
+    // Anyone can submit a known address: the tags and fields of the form only go to a new
+    // subscriber right away, and otherwise wait with the confirmation token for the owner of the
+    // address to confirm.
+    if is_new {
+        add_tags(&mut *transaction, subscriber_id, &tags)
+            .await
+            .context("Failed to tag the new subscriber.")?;
+        set_field_values(&mut *transaction, subscriber_id, &field_values)
+            .await
+            .context("Failed to store the custom fields of the new subscriber.")?;
+    }
+    let awaiting_confirmation = insert_membership(&mut transaction, list.id, subscriber_id)
+        .await
+        .context("Failed to add the subscriber to the mailing list.")?;
+    if !awaiting_confirmation {
+        // With no confirmation to wait for, nothing from the form is kept.
+        transaction
+            .rollback()
+            .await
+            .context("Failed to roll back SQL transaction for a confirmed subscriber.")?;
+        tracing::info!("The subscriber already confirmed their subscription to this list");
+        return Ok(HttpResponse::Ok().finish());
+    }
+    // Keeps the form from being used to flood an address with confirmation emails.
+    if count_recent_confirmations(&mut transaction, subscriber_id, list.id)
         .await
-        .context("Failed to store confirmation token in the database.")?;
+        .context("Failed to count the confirmation emails sent recently.")?
+        >= MAX_CONFIRMATIONS_PER_DAY
+    {
+        transaction
+            .rollback()
+            .await
+            .context("Failed to roll back SQL transaction for a throttled subscriber.")?;
+        tracing::warn!("Not sending another confirmation email to the same address today");
+        return Ok(HttpResponse::Ok().finish());
+    }
+    record_consent(
+        &mut *transaction,
+        subscriber_id,
+        list.id,
+        ConsentAction::Subscribe,
+        &consent_context,
+        &list.consent_text,
+    )
+    .await
+    .context("Failed to record the consent of the new subscriber.")?;
+    let subscription_token = generate_subscription_token();
+    store_token_in_db(
+        &mut transaction,
+        subscriber_id,
+        list.id,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to store confirmation token in the database.")?;
+    if !is_new {
+        store_pending_signup_data(&mut transaction, &subscription_token, &tags, &field_values)
+            .await
+            .context("Failed to store the tags and fields awaiting confirmation.")?;
+    }
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
-    send_confirmation_email(&email_client, subscriber, &base_url.0, &subscription_token)
+    if is_suppressed(connection.get_ref(), &subscriber.email)
         .await
-        .context("Failed to send the confirmation email to a new subscriber")?;
-    Ok(HttpResponse::Ok())
+        .context("Failed to check the suppression list.")?
+    {
+        tracing::info!("Skipping the confirmation email to a suppressed address");
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let email_client = list.email_client(&email_client)?;
+    send_confirmation_email(
+        &email_client,
+        &list,
+        subscriber,
+        &field_values,
+        &base_url.0,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to send the confirmation email to a new subscriber")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Answers a submission that looks automated as if it had gone through, to give bots nothing to
+/// adjust to, and counts it.
+async fn turn_away(
+    pool: &PgPool,
+    request: &HttpRequest,
+    signal: BotSignal,
+) -> Result<HttpResponse, SubscribeError> {
+    tracing::warn!(
+        reason = signal.as_str(),
+        client_ip = ?client_ip(request),
+        "Turned away a subscription that looks automated"
+    );
+    record_rejected_signup(pool, signal)
+        .await
+        .context("Failed to count the rejected subscription.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Hands out the form token, and the proof of work difficulty, that bot protection may require.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Issue a subscription form challenge", skip(bot_protection))]
+#[get("/subscriptions/challenge")]
+async fn subscription_challenge(bot_protection: web::Data<BotProtection>) -> impl Responder {
+    HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .json(bot_protection.challenge(Utc::now()))
 }
 
 #[tracing::instrument(
     name = "Sending a confirmation email",
-    skip(email_client, new_subscriber)
+    skip(email_client, new_subscriber, field_values)
 )]
-async fn send_confirmation_email(
+pub(crate) async fn send_confirmation_email(
     email_client: &EmailClient,
+    list: &MailingList,
     new_subscriber: Subscriber,
+    field_values: &[(String, String)],
     base_url: &str,
     token: &str,
 ) -> Result<(), reqwest::Error> {
     let confirmation_link = format!("{}/subscriptions/confirm?token={}", base_url, token,);
-
-    let html_content = format!(
-        "Welcome to our newsletter!<br />\
-        Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
-
-    let text_content = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
+    let mut variables = TemplateVariables::for_subscriber(
+        new_subscriber.name.as_ref(),
+        new_subscriber.email.as_ref(),
     );
+    for (key, value) in field_values {
+        variables.insert(key.as_str(), value.as_str());
+    }
+    let (subject, html_content, text_content) =
+        list.render_confirmation(variables, &confirmation_link);
 
     email_client
         .send_email(
             &new_subscriber.email,
-            "Welcome to the Newsletter",
+            &subject,
             &html_content,
             &text_content,
         )
         .await
+        .map(|_| ())
 }
 
+/// Returns the ID of the subscriber, who may already be known from another list, and whether they
+/// were just inserted.
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(subscriber, transaction)
@@ -128,24 +335,104 @@ async fn send_confirmation_email(
 async fn insert_subscription(
     transaction: &mut PgConnection,
     subscriber: &Subscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+) -> Result<(Uuid, bool), sqlx::Error> {
+    let subscriber = sqlx::query!(
         r#"
         INSERT INTO subscriptions (id, email, name, status)
         VALUES ($1, $2, $3, 'pending_confirmation')
+        ON CONFLICT ((lower(email))) DO UPDATE SET email = subscriptions.email
+        RETURNING id, xmax = 0 AS "inserted!"
         "#,
-        subscriber_id,
+        Uuid::new_v4(),
         subscriber.email.as_ref(),
         subscriber.name.as_ref(),
     )
+    .fetch_one(transaction)
+    .await?;
+    Ok((subscriber.id, subscriber.inserted))
+}
+
+/// Keeps the tags and field values submitted for a known subscriber with the confirmation token,
+/// to be applied when it is used.
+#[tracing::instrument(
+    name = "Storing signup data awaiting confirmation",
+    skip(transaction, token, field_values)
+)]
+async fn store_pending_signup_data(
+    transaction: &mut PgConnection,
+    token: &str,
+    tags: &[SubscriberTag],
+    field_values: &[(String, String)],
+) -> Result<(), sqlx::Error> {
+    let tags: Vec<_> = tags.iter().map(|tag| tag.as_ref().to_owned()).collect();
+    let field_values: HashMap<_, _> = field_values.iter().cloned().collect();
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens
+        SET tags = $2, field_values = $3
+        WHERE token = $1
+        "#,
+        token,
+        &tags,
+        Json(field_values) as _,
+    )
     .execute(transaction)
     .await?;
-    Ok(subscriber_id)
+    Ok(())
+}
+
+#[tracing::instrument(name = "Counting recent confirmation emails", skip(transaction))]
+async fn count_recent_confirmations(
+    transaction: &mut PgConnection,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<i64, sqlx::Error> {
+    let recent = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM subscription_tokens
+        WHERE id = $1 AND list_id = $2 AND created_at > NOW() - INTERVAL '1 day'
+        "#,
+        subscriber_id,
+        list_id,
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok(recent.count)
+}
+
+/// Adds the subscriber to the list pending confirmation, and returns whether they still have to
+/// confirm: subscribing again to a list one is confirmed on changes nothing.
+#[tracing::instrument(name = "Adding the subscriber to a mailing list", skip(transaction))]
+pub(crate) async fn insert_membership(
+    transaction: &mut PgConnection,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let membership = sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, unsubscribe_token)
+        VALUES ($1, $2, 'pending_confirmation', $3)
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET
+            status = CASE
+                WHEN list_memberships.status = 'confirmed' THEN 'confirmed'
+                ELSE 'pending_confirmation'
+            END,
+            unsubscribed_at = NULL
+        RETURNING status
+        "#,
+        list_id,
+        subscriber_id,
+        generate_subscription_token(),
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok(membership.status == "pending_confirmation")
 }
 
 #[tracing::instrument(name = "Generating a random subscription token")]
-fn generate_subscription_token() -> String {
+pub(crate) fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -157,17 +444,19 @@ fn generate_subscription_token() -> String {
     name = "Storing subscription token in the database",
     skip(transaction, token)
 )]
-async fn store_token_in_db(
+pub(crate) async fn store_token_in_db(
     transaction: &mut PgConnection,
     id: Uuid,
+    list_id: Uuid,
     token: &str,
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
         r#"
-        INSERT INTO subscription_tokens (id, token)
-        VALUES ($1, $2)
+        INSERT INTO subscription_tokens (id, list_id, token)
+        VALUES ($1, $2, $3)
         "#,
         id,
+        list_id,
         token,
     )
     .execute(transaction)
@@ -1,10 +1,19 @@
+use std::collections::HashMap;
+
 use actix_http::StatusCode;
-use actix_web::{get, web, HttpResponse, Responder, ResponseError};
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder, ResponseError};
 use anyhow::Context;
+use sqlx::types::Json;
 use sqlx::PgPool;
 use uuid::Uuid;
 
 use super::error_chain_fmt;
+use crate::consent::{record_consent, ConsentAction, ConsentContext};
+use crate::custom_fields::set_field_values;
+use crate::domain::subscriber_tag::SubscriberTag;
+use crate::mailing_list::get_list;
+use crate::subscription_events::{record_subscription_event, SubscriptionEventType};
+use crate::tags::add_tags;
 
 #[derive(serde::Deserialize)]
 struct Parameters {
@@ -35,17 +44,33 @@ impl ResponseError for SubscribtionConfirmError {
 }
 
 #[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, connection))]
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, connection, request)
+)]
 #[get("/subscriptions/confirm")]
 async fn confirm(
     parameters: web::Query<Parameters>,
     connection: web::Data<PgPool>,
+    request: HttpRequest,
 ) -> Result<impl Responder, SubscribtionConfirmError> {
-    let id = get_subscriber_id_from_token(&connection, &parameters.token)
+    let pending = get_pending_subscription(&connection, &parameters.token)
         .await
         .context("Failed to retrive the subscriber id for the given token.")?
         .ok_or(SubscribtionConfirmError::InvalidToken)?;
-    confirm_subscriber(&connection, id)
+    if pending.used {
+        // Followed again, e.g. by the subscriber after a link scanner of their mail provider: the
+        // link keeps working as long as the subscription it confirmed stands.
+        let confirmed = is_confirmed(&connection, pending.id, pending.list_id)
+            .await
+            .context("Failed to retrieve the status of the subscription.")?;
+        if !confirmed {
+            return Err(SubscribtionConfirmError::InvalidToken);
+        }
+        return Ok(HttpResponse::Ok());
+    }
+    let consent_context = ConsentContext::from_request(&request, None);
+    confirm_subscriber(&connection, pending, &consent_context)
         .await
         .context("Failed to change the status of the subscriber to `confirmed`.")?;
     Ok(HttpResponse::Ok())
@@ -53,30 +78,132 @@ async fn confirm(
 
 #[tracing::instrument(
     name = "Storing new subscriber details in the database",
-    skip(id, pool)
+    skip(pending, pool, consent_context)
 )]
-async fn confirm_subscriber(pool: &PgPool, id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+async fn confirm_subscriber(
+    pool: &PgPool,
+    pending: PendingSubscription,
+    consent_context: &ConsentContext,
+) -> Result<(), anyhow::Error> {
+    let PendingSubscription {
+        id,
+        list_id,
+        tags,
+        field_values,
+        ..
+    } = pending;
+    let mut transaction = pool.begin().await?;
+    let updated = sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'confirmed', confirmed_at = NOW()
+        WHERE list_id = $1 AND subscriber_id = $2 AND status = 'pending_confirmation'
+        "#,
+        list_id,
         id
     )
-    .execute(pool)
+    .execute(&mut *transaction)
+    .await?;
+    // Once the subscription is confirmed, its pending links are of no use, and an old one must
+    // not bring back a subscription that was since left.
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens
+        SET used_at = NOW()
+        WHERE id = $1 AND list_id = $2 AND used_at IS NULL
+        "#,
+        id,
+        list_id
+    )
+    .execute(&mut *transaction)
     .await?;
+    if updated.rows_affected() > 0 {
+        sqlx::query!(
+            r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+            id
+        )
+        .execute(&mut *transaction)
+        .await?;
+        // Submitted for a subscriber who was already known, they only apply now that the owner
+        // of the address confirmed.
+        let tags: Vec<_> = tags
+            .iter()
+            .filter_map(|tag| SubscriberTag::parse(tag).ok())
+            .collect();
+        add_tags(&mut *transaction, id, &tags).await?;
+        let field_values: Vec<_> = field_values.0.into_iter().collect();
+        set_field_values(&mut *transaction, id, &field_values).await?;
+        record_subscription_event(
+            &mut *transaction,
+            id,
+            list_id,
+            SubscriptionEventType::Confirmed,
+            None,
+        )
+        .await?;
+        let list = get_list(&mut *transaction, list_id)
+            .await?
+            .context("The list of the subscription no longer exists.")?;
+        record_consent(
+            &mut *transaction,
+            id,
+            list_id,
+            ConsentAction::Confirm,
+            consent_context,
+            &list.consent_text,
+        )
+        .await?;
+    }
+    transaction.commit().await?;
 
     Ok(())
 }
 
+struct PendingSubscription {
+    id: Uuid,
+    list_id: Uuid,
+    tags: Vec<String>,
+    field_values: Json<HashMap<String, String>>,
+    /// Whether the link was already followed.
+    used: bool,
+}
+
 #[tracing::instrument(name = "Getting subscriber_id from token", skip(pool, token))]
-async fn get_subscriber_id_from_token(
+async fn get_pending_subscription(
     pool: &PgPool,
     token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"SELECT id FROM subscription_tokens WHERE token = $1"#,
+) -> Result<Option<PendingSubscription>, sqlx::Error> {
+    sqlx::query_as!(
+        PendingSubscription,
+        r#"
+        SELECT
+            id, list_id, tags, field_values AS "field_values: Json<HashMap<String, String>>",
+            used_at IS NOT NULL AS "used!"
+        FROM subscription_tokens
+        WHERE token = $1
+        "#,
         token
     )
     .fetch_optional(pool)
-    .await?;
+    .await
+}
 
-    Ok(result.map(|r| r.id))
+#[tracing::instrument(name = "Checking whether a subscription is confirmed", skip(pool))]
+async fn is_confirmed(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let membership = sqlx::query!(
+        r#"
+        SELECT status
+        FROM list_memberships
+        WHERE list_id = $1 AND subscriber_id = $2
+        "#,
+        list_id,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(membership.is_some_and(|membership| membership.status == "confirmed"))
 }
@@ -0,0 +1,158 @@
+use actix_http::StatusCode;
+use actix_web::http::header::ContentType;
+use actix_web::{get, post, web, HttpResponse, Responder, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::error_chain_fmt;
+use crate::domain::newsletter_issue::escape_html;
+use crate::mailing_list;
+
+#[derive(serde::Deserialize)]
+struct Parameters {
+    token: String,
+    /// The issue the link was followed from, to attribute the unsubscription to it.
+    issue: Option<Uuid>,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("There is no subscription with the given token")]
+    InvalidToken,
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InvalidToken => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+/// Asks for confirmation before unsubscribing: links in emails are fetched by scanners and
+/// prefetchers, which must not be able to unsubscribe anyone.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Confirm unsubscribing from a list", skip(parameters, pool))]
+#[get("/subscriptions/unsubscribe")]
+async fn confirm_unsubscribe(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder, UnsubscribeError> {
+    let list = sqlx::query!(
+        r#"
+        SELECT l.name
+        FROM list_memberships m
+        JOIN lists l USING (list_id)
+        WHERE m.unsubscribe_token = $1
+        "#,
+        parameters.token,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscription for the given token.")?
+    .ok_or(UnsubscribeError::InvalidToken)?;
+
+    // The form posts back to the very URL of the link, the way mail clients do in one click.
+    let mut action = format!("/subscriptions/unsubscribe?token={}", parameters.token);
+    if let Some(issue) = parameters.issue {
+        action.push_str(&format!("&issue={}", issue));
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Unsubscribe</title>
+    </head>
+    <body>
+        <p>You will no longer receive the emails of {list}.</p>
+        <form action="{action}" method="post">
+            <button type="submit">Unsubscribe</button>
+        </form>
+    </body>
+</html>
+"#,
+            list = escape_html(&list.name),
+            action = escape_html(&action),
+        )))
+}
+
+/// Unsubscribes from the one list the token was issued for; other lists are left untouched.
+///
+/// The token comes in the query, so that mail clients can post `List-Unsubscribe=One-Click` to
+/// the URL of the `List-Unsubscribe` header (RFC 8058); the body is ignored.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Unsubscribe from a list", skip(parameters, pool))]
+#[post("/subscriptions/unsubscribe")]
+async fn unsubscribe(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder, UnsubscribeError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    let membership = sqlx::query!(
+        r#"SELECT list_id, subscriber_id FROM list_memberships WHERE unsubscribe_token = $1"#,
+        parameters.token,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the subscription for the given token.")?
+    .ok_or(UnsubscribeError::InvalidToken)?;
+
+    // Only issues sent to the list are credited with the unsubscription.
+    let newsletter_issue_id = match parameters.issue {
+        Some(newsletter_issue_id) => sqlx::query!(
+            r#"
+            SELECT newsletter_issue_id
+            FROM newsletter_issues
+            WHERE newsletter_issue_id = $1 AND list_id = $2
+            "#,
+            newsletter_issue_id,
+            membership.list_id,
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to retrieve the newsletter issue.")?
+        .map(|r| r.newsletter_issue_id),
+        None => None,
+    };
+    mailing_list::unsubscribe(
+        &mut transaction,
+        membership.list_id,
+        membership.subscriber_id,
+        newsletter_issue_id,
+    )
+    .await
+    .context("Failed to unsubscribe from the list.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe.")?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Unsubscribed</title>
+    </head>
+    <body>
+        <p>You have been unsubscribed.</p>
+    </body>
+</html>
+"#,
+    ))
+}
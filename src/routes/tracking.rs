@@ -0,0 +1,129 @@
+use actix_web::http::header::{CacheControl, CacheDirective, LOCATION};
+use actix_web::http::StatusCode;
+use actix_web::{get, web, HttpResponse, Responder, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::routes::error_chain_fmt;
+use crate::tracking::TrackingLinks;
+
+/// A transparent 1x1 GIF.
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// Serves the tracking pixel of an issue and records the open.
+///
+/// The pixel is returned even when recording fails or the token is unknown, so that a broken
+/// image never shows up in the reader's mail client.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Track an open", skip(token, pool))]
+#[get("/t/o/{token}")]
+pub async fn track_open(token: web::Path<String>, pool: web::Data<PgPool>) -> impl Responder {
+    if let Err(e) = record_open(&pool, &token).await {
+        tracing::error!(error.cause_chain = ?e, "Failed to record an open");
+    }
+
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(CacheControl(vec![
+            CacheDirective::NoStore,
+            CacheDirective::MaxAge(0),
+        ]))
+        .body(PIXEL)
+}
+
+#[tracing::instrument(name = "Record an open", skip(pool, token))]
+async fn record_open(pool: &PgPool, token: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET first_opened_at = COALESCE(first_opened_at, NOW()),
+            last_opened_at = NOW(),
+            open_count = open_count + 1
+        WHERE tracking_token = $1
+        "#,
+        token,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+pub enum ClickError {
+    #[error("Unknown link")]
+    UnknownLink,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ClickError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ClickError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ClickError::UnknownLink => StatusCode::NOT_FOUND,
+            ClickError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Records a click on a tracked link and redirects to its original URL.
+///
+/// The destination is looked up from the signed token rather than taken from the request, so the
+/// endpoint cannot be used to redirect anywhere but to links of a sent issue.
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Track a click", skip(token, pool, tracking_links))]
+#[get("/t/c/{token}")]
+pub async fn track_click(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    tracking_links: web::Data<TrackingLinks>,
+) -> Result<HttpResponse, ClickError> {
+    let click = tracking_links
+        .verify_click_token(&token)
+        .ok_or(ClickError::UnknownLink)?;
+    let link = sqlx::query!(
+        r#"
+        SELECT l.url, d.subscriber_id
+        FROM issue_links l
+        JOIN issue_deliveries d ON d.newsletter_issue_id = l.newsletter_issue_id
+        WHERE l.link_id = $1 AND d.tracking_token = $2
+        "#,
+        click.link_id,
+        click.tracking_token,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to look up the clicked link.")?
+    .ok_or(ClickError::UnknownLink)?;
+
+    if let Err(e) = record_click(&pool, click.link_id, link.subscriber_id).await {
+        tracing::error!(error.cause_chain = ?e, "Failed to record a click");
+    }
+
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, link.url))
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .finish())
+}
+
+#[tracing::instrument(name = "Record a click", skip(pool))]
+async fn record_click(pool: &PgPool, link_id: i64, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO link_clicks (link_id, subscriber_id) VALUES ($1, $2)",
+        link_id,
+        subscriber_id,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,273 @@
+use actix_http::header::HeaderValue;
+use actix_http::StatusCode;
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder, ResponseError};
+use anyhow::Context;
+use chrono::{TimeZone, Utc};
+use secrecy::ExposeSecret;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::authentication::basic_authentication;
+use crate::issue_delivery::DeliveryStatus;
+use crate::mailing_list;
+use crate::startup::WebhookCredentials;
+use crate::suppression::{suppress, SuppressionReason};
+
+use super::error_chain_fmt;
+
+/// A single event as posted by Mailjet's event API.
+#[derive(serde::Deserialize, Debug)]
+struct EmailEvent {
+    event: String,
+    time: i64,
+    email: String,
+    #[serde(rename = "MessageID", default)]
+    message_id: Option<u64>,
+    #[serde(default)]
+    hard_bounce: bool,
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::AuthError(_) => StatusCode::UNAUTHORIZED,
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_http::body::BoxBody> {
+        match self {
+            Self::AuthError(_) => {
+                let mut response = HttpResponse::new(self.status_code());
+                let header_value = HeaderValue::from_str(r#"Basic realm="webhooks""#).unwrap();
+                response
+                    .headers_mut()
+                    .insert(actix_http::header::WWW_AUTHENTICATE, header_value);
+                response
+            }
+            Self::ValidationError(_) => {
+                HttpResponse::build(self.status_code()).body(self.to_string())
+            }
+            Self::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+#[allow(clippy::async_yields_async, clippy::let_with_type_underscore)]
+#[tracing::instrument(name = "Receive email events", skip(body, pool, credentials, request))]
+#[post("/webhooks/email-events")]
+async fn receive_email_events(
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    credentials: web::Data<WebhookCredentials>,
+    request: HttpRequest,
+) -> Result<impl Responder, WebhookError> {
+    check_credentials(&request, &credentials).map_err(WebhookError::AuthError)?;
+
+    // Mailjet posts either a single event or, when grouping is enabled, an array of them.
+    let payload: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(|e| WebhookError::ValidationError(format!("Invalid JSON payload: {}", e)))?;
+    let events = match payload {
+        // A batch is retried as a whole when it is not acknowledged, so a malformed event in it is
+        // skipped rather than holding back the others forever.
+        serde_json::Value::Array(payloads) => payloads
+            .into_iter()
+            .filter_map(|payload| match parse_event(&payload) {
+                Ok(event) => Some((event, payload)),
+                Err(e) => {
+                    tracing::warn!(error = %e, payload = %payload, "Skipped a malformed email event");
+                    None
+                }
+            })
+            .collect(),
+        payload => {
+            let event = parse_event(&payload).map_err(WebhookError::ValidationError)?;
+            vec![(event, payload)]
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    for (event, payload) in events {
+        record_event(&mut transaction, &event, &payload)
+            .await
+            .context("Failed to record an email event.")?;
+        apply_event(&mut transaction, &event)
+            .await
+            .context("Failed to apply an email event.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store email events.")?;
+
+    Ok(HttpResponse::Ok())
+}
+
+fn parse_event(payload: &serde_json::Value) -> Result<EmailEvent, String> {
+    let event: EmailEvent =
+        serde_json::from_value(payload.clone()).map_err(|e| format!("Invalid event: {}", e))?;
+    if Utc.timestamp_opt(event.time, 0).single().is_none() {
+        return Err(format!(
+            "Invalid event: the time {} is out of range",
+            event.time
+        ));
+    }
+    Ok(event)
+}
+
+fn check_credentials(
+    request: &HttpRequest,
+    expected: &WebhookCredentials,
+) -> Result<(), anyhow::Error> {
+    let credentials = basic_authentication(request.headers())?;
+    let username_matches = constant_time_eq(
+        credentials.username.as_bytes(),
+        expected.username.as_bytes(),
+    );
+    let password_matches = constant_time_eq(
+        credentials.password.expose_secret().as_bytes(),
+        expected.password.expose_secret().as_bytes(),
+    );
+    if username_matches && password_matches {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Invalid webhook credentials"))
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[tracing::instrument(name = "Record email event", skip(transaction, payload))]
+async fn record_event(
+    transaction: &mut PgConnection,
+    event: &EmailEvent,
+    payload: &serde_json::Value,
+) -> Result<(), anyhow::Error> {
+    let occurred_at = Utc
+        .timestamp_opt(event.time, 0)
+        .single()
+        .context("The event timestamp is out of range.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events (
+            id, subscriber_id, email, event_type, provider_message_id, payload, occurred_at
+        )
+        VALUES (
+            $1,
+            (SELECT id FROM subscriptions WHERE lower(email) = lower($2) LIMIT 1),
+            $2, $3, $4, $5, $6
+        )
+        "#,
+        Uuid::new_v4(),
+        event.email,
+        event.event,
+        event.message_id.map(|id| id.to_string()),
+        payload,
+        occurred_at,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Apply email event", skip(transaction))]
+async fn apply_event(
+    transaction: &mut PgConnection,
+    event: &EmailEvent,
+) -> Result<(), sqlx::Error> {
+    if let ("bounce" | "blocked", Some(message_id)) = (event.event.as_str(), event.message_id) {
+        sqlx::query!(
+            r#"
+            UPDATE issue_deliveries
+            SET status = $2
+            WHERE provider_message_id = $1
+            "#,
+            message_id.to_string(),
+            DeliveryStatus::Bounced.as_str(),
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    match event.event.as_str() {
+        "bounce" if event.hard_bounce => {
+            suppress(transaction, &event.email, SuppressionReason::HardBounce).await
+        }
+        "spam" => suppress(transaction, &event.email, SuppressionReason::SpamComplaint).await,
+        "unsub" => unsubscribe(transaction, event).await,
+        _ => Ok(()),
+    }
+}
+
+/// Unsubscribes a recipient who used the provider's unsubscribe link from the list of the issue the
+/// message belonged to, or from every list when the message is unknown.
+#[tracing::instrument(name = "Unsubscribe through the provider", skip(transaction))]
+async fn unsubscribe(
+    transaction: &mut PgConnection,
+    event: &EmailEvent,
+) -> Result<(), sqlx::Error> {
+    let Some(subscriber) = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE lower(email) = lower($1)",
+        event.email,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    else {
+        return Ok(());
+    };
+
+    let issue = match event.message_id {
+        Some(message_id) => {
+            sqlx::query!(
+                r#"
+                SELECT newsletter_issues.newsletter_issue_id, newsletter_issues.list_id
+                FROM issue_deliveries
+                JOIN newsletter_issues USING (newsletter_issue_id)
+                WHERE issue_deliveries.provider_message_id = $1
+                "#,
+                message_id.to_string(),
+            )
+            .fetch_optional(&mut *transaction)
+            .await?
+        }
+        None => None,
+    };
+    let (list_ids, newsletter_issue_id) = match issue {
+        Some(issue) => (vec![issue.list_id], Some(issue.newsletter_issue_id)),
+        None => {
+            let memberships = sqlx::query!(
+                "SELECT list_id FROM list_memberships WHERE subscriber_id = $1",
+                subscriber.id,
+            )
+            .fetch_all(&mut *transaction)
+            .await?;
+            (memberships.into_iter().map(|m| m.list_id).collect(), None)
+        }
+    };
+    for list_id in list_ids {
+        mailing_list::unsubscribe(transaction, list_id, subscriber.id, newsletter_issue_id).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::domain::subscriber_tag::SubscriberTag;
+
+/// Narrows the confirmed members of a list an issue is sent to. Every condition that is set must
+/// hold; an empty filter matches everyone.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SegmentFilter {
+    /// Subscribers carrying at least one of these tags.
+    #[serde(default)]
+    pub include_tags: Vec<String>,
+    /// Subscribers carrying none of these tags.
+    #[serde(default)]
+    pub exclude_tags: Vec<String>,
+    /// Subscribers who joined the list at or after this instant.
+    pub subscribed_after: Option<DateTime<Utc>>,
+    /// Subscribers who joined the list before this instant.
+    pub subscribed_before: Option<DateTime<Utc>>,
+    /// Subscribers who opened an issue within this many days.
+    pub opened_within_days: Option<i32>,
+    /// Subscribers who clicked a link in an issue within this many days.
+    pub clicked_within_days: Option<i32>,
+}
+
+impl SegmentFilter {
+    /// Checks the filter, normalizing its tags the way subscribers' tags are stored.
+    pub fn parse(self) -> Result<Self, String> {
+        let parse_tags = |tags: Vec<String>| {
+            tags.iter()
+                .map(|tag| SubscriberTag::parse(tag).map(|tag| tag.as_ref().to_owned()))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        if let (Some(after), Some(before)) = (self.subscribed_after, self.subscribed_before) {
+            if after >= before {
+                return Err("`subscribed_after` must be before `subscribed_before`".into());
+            }
+        }
+        for days in [self.opened_within_days, self.clicked_within_days]
+            .into_iter()
+            .flatten()
+        {
+            if days <= 0 {
+                return Err(format!("{} is not a valid number of days", days));
+            }
+        }
+        Ok(Self {
+            include_tags: parse_tags(self.include_tags)?,
+            exclude_tags: parse_tags(self.exclude_tags)?,
+            ..self
+        })
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Segment {
+    pub segment_id: Uuid,
+    pub name: String,
+    pub filter: SegmentFilter,
+}
+
+#[tracing::instrument(name = "Get segment", skip(executor))]
+pub async fn get_segment(
+    executor: impl PgExecutor<'_>,
+    segment_id: Uuid,
+) -> Result<Option<Segment>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT segment_id, name, filter AS "filter: Json<SegmentFilter>"
+        FROM segments
+        WHERE segment_id = $1
+        "#,
+        segment_id,
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(row.map(|row| Segment {
+        segment_id: row.segment_id,
+        name: row.name,
+        filter: row.filter.0,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::segments::SegmentFilter;
+    use chrono::{Duration, Utc};
+    use claims::assert_err;
+
+    #[test]
+    fn an_empty_filter_is_valid() {
+        assert_eq!(
+            SegmentFilter::default().parse(),
+            Ok(SegmentFilter::default())
+        );
+    }
+
+    #[test]
+    fn tags_are_normalized() {
+        let filter = SegmentFilter {
+            include_tags: vec![" VIP ".into()],
+            exclude_tags: vec!["Churned".into()],
+            ..Default::default()
+        }
+        .parse()
+        .unwrap();
+        assert_eq!(filter.include_tags, vec!["vip"]);
+        assert_eq!(filter.exclude_tags, vec!["churned"]);
+    }
+
+    #[test]
+    fn invalid_tags_are_rejected() {
+        let filter = SegmentFilter {
+            exclude_tags: vec!["not valid".into()],
+            ..Default::default()
+        };
+        assert_err!(filter.parse());
+    }
+
+    #[test]
+    fn an_empty_subscription_range_is_rejected() {
+        let now = Utc::now();
+        let filter = SegmentFilter {
+            subscribed_after: Some(now),
+            subscribed_before: Some(now - Duration::days(1)),
+            ..Default::default()
+        };
+        assert_err!(filter.parse());
+    }
+
+    #[test]
+    fn non_positive_engagement_windows_are_rejected() {
+        let filter = SegmentFilter {
+            opened_within_days: Some(0),
+            ..Default::default()
+        };
+        assert_err!(filter.parse());
+        let filter = SegmentFilter {
+            clicked_within_days: Some(-3),
+            ..Default::default()
+        };
+        assert_err!(filter.parse());
+    }
+}
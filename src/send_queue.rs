@@ -0,0 +1,121 @@
+//! What the queues of emails sent in the background have in common: the deliveries of newsletter
+//! issues, see [`issue_delivery`](crate::issue_delivery), and the confirmation emails of imports,
+//! see [`import_confirmation`](crate::import_confirmation).
+//!
+//! An email is `queued`, then claimed as `sending` one batch at a time, and recorded as `sent` or
+//! `failed` once the provider answered. When the provider cannot be reached, it goes back to the
+//! queue until it was attempted [`MAX_SEND_ATTEMPTS`] times. An email left `sending` by a sender
+//! that stopped is recorded as failed once stale, rather than risk sending it twice.
+
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use crate::email_client::EmailClient;
+use crate::import_confirmation::send_queued_confirmations;
+use crate::issue_delivery::resume_pending_deliveries;
+use crate::tracking::TrackingLinks;
+
+/// How many times an email is attempted when the provider cannot be reached before it is
+/// recorded as failed. Messages the provider rejects are not retried.
+pub const MAX_SEND_ATTEMPTS: i32 = 3;
+
+/// How long to wait before sending again after the provider could not be reached.
+pub const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// How long an email can stay `sending` before its sender is assumed to have stopped.
+const STALE_SENDING_AFTER_SECS: f64 = 15.0 * 60.0;
+
+/// How often the queues are looked at for the emails left behind by a stopped sender.
+const RECOVERY_INTERVAL: Duration = Duration::from_secs(60);
+
+pub enum Outcome {
+    Sent(Option<String>),
+    /// The provider could not be reached; the email goes back to the queue.
+    Retry(anyhow::Error),
+    Failed(anyhow::Error),
+}
+
+impl Outcome {
+    /// The outcome of an email that did not reach the provider on its `attempts`th attempt,
+    /// including the current one.
+    pub fn unsent(attempts: i32, e: anyhow::Error) -> Self {
+        if attempts < MAX_SEND_ATTEMPTS {
+            Outcome::Retry(e)
+        } else {
+            Outcome::Failed(e)
+        }
+    }
+}
+
+/// Records the emails of both queues left `sending` by a sender that stopped as failed: the
+/// provider may already have accepted them, and sending them again could reach the same
+/// recipient twice.
+///
+/// Senders call this before claiming each batch, so emails are settled as soon as they are
+/// stale, whether or not the application restarted in between.
+#[tracing::instrument(name = "Settle interrupted sends", skip(pool))]
+pub async fn settle_interrupted_sends(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let deliveries = sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = 'failed',
+            last_error = 'The send was interrupted; the email may or may not have been sent.'
+        WHERE status = 'sending'
+            AND last_attempted_at < NOW() - make_interval(secs => $1)
+        "#,
+        STALE_SENDING_AFTER_SECS,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    let confirmations = sqlx::query!(
+        r#"
+        UPDATE subscription_tokens
+        SET send_status = 'failed',
+            send_last_error = 'The send was interrupted; the email may or may not have been sent.'
+        WHERE send_status = 'sending'
+            AND send_claimed_at < NOW() - make_interval(secs => $1)
+        "#,
+        STALE_SENDING_AFTER_SECS,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    if deliveries > 0 || confirmations > 0 {
+        tracing::warn!(
+            deliveries,
+            confirmations,
+            "Recorded interrupted sends as failed"
+        );
+    }
+    Ok(())
+}
+
+/// Works through both queues at startup, then again regularly, picking up what a stopped sender
+/// left behind: emails still queued, and those it left `sending`, which only become stale later.
+pub fn spawn_recovery(
+    pool: PgPool,
+    email_client: EmailClient,
+    tracking_links: TrackingLinks,
+    base_url: String,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RECOVERY_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = resume_pending_deliveries(&pool, &email_client, &tracking_links).await {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    "Failed to resume the pending newsletter deliveries"
+                );
+            }
+            if let Err(e) = send_queued_confirmations(&pool, &email_client, &base_url).await {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    "Failed to send the queued confirmation emails"
+                );
+            }
+        }
+    });
+}
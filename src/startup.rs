@@ -1,10 +1,30 @@
+use crate::address_policy::AddressPolicy;
+use crate::bot_protection::BotProtection;
+use crate::client_ip::TrustedProxies;
 use crate::configuration::{ApplicationSettings, DatabaseSettings, EmailClientSettings, Settings};
 use crate::email_client::EmailClient;
 use crate::routes::newsletter::publish_newsletter;
-use crate::routes::{confirm, health_check, home, login, login_form, subscribe};
+use crate::routes::{
+    apply_subscriber_action, archive, archived_issue, atom_feed, cancel_newsletter_issue,
+    change_subscriber_email, confirm, confirm_erasure, confirm_unsubscribe, create_custom_field,
+    create_list, create_newsletter_issue, create_segment, delete_custom_field,
+    download_personal_data, erase_personal_data, erase_personal_data_of, export_subscribers_file,
+    get_audit_log, get_custom_field_definitions, get_lists, get_personal_data, get_segments,
+    get_subscriber, get_subscriber_fields, get_subscriber_tags, health_check, home,
+    import_subscribers, issue_analytics, list_subscribers, login, login_form,
+    newsletter_issue_report, preferences_form, preview_newsletter_issue, privacy_form,
+    publish_newsletter_issue, receive_email_events, reject_cross_site_admin_requests,
+    request_email_change, request_personal_data, rss_feed, send_test_newsletter_issue,
+    sending_analytics, subscribe, subscriber_analytics, subscription_challenge, tag_subscriber,
+    track_click, track_open, unsubscribe, untag_subscriber, update_preferences,
+    update_subscriber_fields, verify_email_change,
+};
+use crate::send_queue::spawn_recovery;
+use crate::tracking::TrackingLinks;
 use actix_http::body::BoxBody;
 use actix_web::cookie::Key;
 use actix_web::dev::{Server, ServiceFactory, ServiceResponse};
+use actix_web::middleware::from_fn;
 use actix_web::web::{Data, ServiceConfig};
 use actix_web::{App, HttpServer};
 use actix_web_flash_messages::storage::CookieMessageStore;
@@ -19,6 +39,22 @@ pub struct ApplicationBaseUrl(pub String);
 #[derive(Clone, Debug)]
 pub struct HmacSecret(pub Secret<String>);
 
+/// `Basic` credentials expected on the email provider's event webhook.
+#[derive(Clone, Debug)]
+pub struct WebhookCredentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+impl WebhookCredentials {
+    pub fn from_settings(config: &EmailClientSettings) -> Self {
+        Self {
+            username: config.webhook_username.clone(),
+            password: config.webhook_password.clone(),
+        }
+    }
+}
+
 pub struct Application {
     config: Settings,
     server: Server,
@@ -28,7 +64,32 @@ impl Application {
     pub async fn new(config: Settings) -> Result<Self, std::io::Error> {
         let db_pool = init_db(&config.db);
         let email_client = init_email_client(&config.email_client);
-        let server = run(&config.app, db_pool, email_client).expect("Failed to bind address");
+        let webhook_credentials = WebhookCredentials::from_settings(&config.email_client);
+        let address_policy = config
+            .signup
+            .address_policy()
+            .expect("Failed to load the signup domain lists");
+        let bot_protection = config
+            .signup
+            .bot_protection
+            .bot_protection(config.app.hmac_secret.clone());
+        let tracking_links =
+            TrackingLinks::new(config.app.base_url.clone(), config.app.hmac_secret.clone());
+        spawn_recovery(
+            db_pool.clone(),
+            email_client.clone(),
+            tracking_links,
+            config.app.base_url.clone(),
+        );
+        let server = run(
+            &config.app,
+            db_pool,
+            email_client,
+            webhook_credentials,
+            address_policy,
+            bot_protection,
+        )
+        .expect("Failed to bind address");
         let app = Application { config, server };
         Ok(app)
     }
@@ -54,14 +115,20 @@ pub fn init_email_client(config: &EmailClientSettings) -> EmailClient {
         config.api_key.clone(),
         config.api_secret.clone(),
         config.timeout(),
+        config.rate_limiter(),
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn new_app(
     pool: Data<PgPool>,
     email_client: Data<EmailClient>,
     base_url: Data<ApplicationBaseUrl>,
     hmac_secret: HmacSecret,
+    webhook_credentials: Data<WebhookCredentials>,
+    address_policy: Data<AddressPolicy>,
+    bot_protection: Data<BotProtection>,
+    trusted_proxies: Data<TrustedProxies>,
 ) -> App<
     impl ServiceFactory<
         actix_web::dev::ServiceRequest,
@@ -71,28 +138,45 @@ pub fn new_app(
         InitError = (),
     >,
 > {
+    let tracking_links = Data::new(TrackingLinks::new(
+        base_url.0.clone(),
+        hmac_secret.0.clone(),
+    ));
     let message_store =
         CookieMessageStore::builder(Key::from(hmac_secret.0.expose_secret().as_bytes())).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
 
     App::new()
+        .wrap(from_fn(reject_cross_site_admin_requests))
         .wrap(message_framework)
         .wrap(TracingLogger::default())
         .configure(configure_app)
         .app_data(pool)
         .app_data(email_client)
         .app_data(base_url)
+        .app_data(webhook_credentials)
+        .app_data(address_policy)
+        .app_data(bot_protection)
+        .app_data(trusted_proxies)
+        .app_data(tracking_links)
 }
 
 fn run(
     config: &ApplicationSettings,
     pool: PgPool,
     email_client: EmailClient,
+    webhook_credentials: WebhookCredentials,
+    address_policy: AddressPolicy,
+    bot_protection: BotProtection,
 ) -> Result<Server, std::io::Error> {
     let pool = Data::new(pool);
     let email_client = Data::new(email_client);
     let base_url = Data::new(ApplicationBaseUrl(config.base_url.clone()));
     let hmac_secret = HmacSecret(config.hmac_secret.clone());
+    let webhook_credentials = Data::new(webhook_credentials);
+    let address_policy = Data::new(address_policy);
+    let bot_protection = Data::new(bot_protection);
+    let trusted_proxies = Data::new(TrustedProxies(config.trusted_proxies.clone()));
 
     let server = HttpServer::new(move || {
         new_app(
@@ -100,6 +184,10 @@ fn run(
             email_client.clone(),
             base_url.clone(),
             hmac_secret.clone(),
+            webhook_credentials.clone(),
+            address_policy.clone(),
+            bot_protection.clone(),
+            trusted_proxies.clone(),
         )
     })
     .bind((config.host.clone(), config.port))?
@@ -111,9 +199,59 @@ fn run(
 pub fn configure_app(cfg: &mut ServiceConfig) {
     cfg.service(health_check)
         .service(subscribe)
+        .service(subscription_challenge)
         .service(confirm)
+        .service(confirm_unsubscribe)
+        .service(unsubscribe)
+        .service(preferences_form)
+        .service(update_preferences)
+        .service(privacy_form)
+        .service(request_personal_data)
+        .service(download_personal_data)
+        .service(confirm_erasure)
+        .service(erase_personal_data)
         .service(publish_newsletter)
         .service(home)
         .service(login_form)
-        .service(login);
+        .service(login)
+        .service(create_newsletter_issue)
+        .service(preview_newsletter_issue)
+        .service(send_test_newsletter_issue)
+        .service(newsletter_issue_report)
+        .service(publish_newsletter_issue)
+        .service(cancel_newsletter_issue)
+        .service(receive_email_events)
+        .service(track_open)
+        .service(track_click)
+        .service(subscriber_analytics)
+        .service(issue_analytics)
+        .service(sending_analytics)
+        .service(archive)
+        .service(archived_issue)
+        .service(rss_feed)
+        .service(atom_feed)
+        .service(create_list)
+        .service(get_lists)
+        .service(create_segment)
+        .service(get_segments)
+        .service(get_subscriber_tags)
+        .service(tag_subscriber)
+        .service(untag_subscriber)
+        .service(create_custom_field)
+        .service(get_custom_field_definitions)
+        .service(delete_custom_field)
+        .service(get_subscriber_fields)
+        .service(update_subscriber_fields)
+        .service(import_subscribers)
+        .service(export_subscribers_file)
+        .service(get_personal_data)
+        .service(erase_personal_data_of)
+        .service(list_subscribers)
+        .service(apply_subscriber_action)
+        .service(get_audit_log)
+        .service(request_email_change)
+        .service(verify_email_change)
+        .service(change_subscriber_email)
+        // After the other `/admin/subscribers/...` routes, which it would otherwise shadow.
+        .service(get_subscriber);
 }
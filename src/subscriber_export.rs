@@ -0,0 +1,388 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use sqlx::types::Json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::consent::ConsentRecord;
+use crate::custom_fields::get_custom_fields;
+use crate::mailing_list::DEFAULT_LIST_ID;
+use crate::segments::SegmentFilter;
+
+/// How much output is gathered before it is handed over.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    /// One JSON object per line.
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "ndjson" => Ok(Self::Ndjson),
+            other => Err(format!("{} is not a supported export format", other)),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Which subscribers are exported: everyone by default, the members of a list, or the confirmed
+/// members of a list matching a segment.
+#[derive(Debug, Clone, Default)]
+pub struct ExportQuery {
+    pub format: ExportFormat,
+    pub list_id: Option<Uuid>,
+    pub segment: Option<SegmentFilter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ExportedSubscriber {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    /// The status on the exported list, or overall when exporting everyone.
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub tags: Vec<String>,
+    pub fields: BTreeMap<String, String>,
+    /// Oldest first. CSV exports only have the latest one.
+    pub consents: Vec<ConsentRecord>,
+}
+
+/// Turns exported subscribers into lines of the chosen format.
+pub struct ExportWriter {
+    format: ExportFormat,
+    /// Custom fields get a CSV column each, in this order.
+    field_keys: Vec<String>,
+}
+
+impl ExportWriter {
+    pub fn new(format: ExportFormat, field_keys: Vec<String>) -> Self {
+        Self { format, field_keys }
+    }
+
+    pub fn header(&self) -> Result<Vec<u8>, anyhow::Error> {
+        match self.format {
+            ExportFormat::Csv => {
+                let columns = [
+                    "id",
+                    "email",
+                    "name",
+                    "status",
+                    "subscribed_at",
+                    "confirmed_at",
+                    "tags",
+                    "consent_action",
+                    "consent_recorded_at",
+                    "consent_ip_address",
+                    "consent_user_agent",
+                    "consent_source",
+                    "consent_text",
+                ];
+                let columns = columns
+                    .into_iter()
+                    .chain(self.field_keys.iter().map(|k| &**k));
+                write_csv_record(columns)
+            }
+            ExportFormat::Ndjson => Ok(Vec::new()),
+        }
+    }
+
+    pub fn row(&self, subscriber: &ExportedSubscriber) -> Result<Vec<u8>, anyhow::Error> {
+        match self.format {
+            ExportFormat::Csv => {
+                let id = subscriber.id.to_string();
+                let subscribed_at = subscriber.subscribed_at.to_rfc3339();
+                let confirmed_at = subscriber
+                    .confirmed_at
+                    .map(|at| at.to_rfc3339())
+                    .unwrap_or_default();
+                let tags = subscriber.tags.join(",");
+                let consent = subscriber.consents.last();
+                let consent_recorded_at = consent
+                    .map(|consent| consent.recorded_at.to_rfc3339())
+                    .unwrap_or_default();
+                let fields = self.field_keys.iter().map(|key| {
+                    subscriber
+                        .fields
+                        .get(key)
+                        .map(String::as_str)
+                        .unwrap_or_default()
+                });
+                let values = [
+                    id.as_str(),
+                    &subscriber.email,
+                    &subscriber.name,
+                    &subscriber.status,
+                    &subscribed_at,
+                    &confirmed_at,
+                    &tags,
+                    consent.map(|c| c.action.as_str()).unwrap_or_default(),
+                    &consent_recorded_at,
+                    consent
+                        .and_then(|c| c.ip_address.as_deref())
+                        .unwrap_or_default(),
+                    consent
+                        .and_then(|c| c.user_agent.as_deref())
+                        .unwrap_or_default(),
+                    consent
+                        .and_then(|c| c.source.as_deref())
+                        .unwrap_or_default(),
+                    consent.map(|c| c.consent_text.as_str()).unwrap_or_default(),
+                ];
+                write_csv_record(values.into_iter().chain(fields))
+            }
+            ExportFormat::Ndjson => {
+                let mut line = serde_json::to_vec(subscriber)
+                    .context("Failed to serialize a subscriber as JSON.")?;
+                line.push(b'\n');
+                Ok(line)
+            }
+        }
+    }
+}
+
+fn write_csv_record<'a>(values: impl Iterator<Item = &'a str>) -> Result<Vec<u8>, anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer
+        .write_record(values)
+        .context("Failed to write a CSV record.")?;
+    writer.into_inner().context("Failed to flush a CSV record.")
+}
+
+/// Streams the subscribers matching `query` to `write` a chunk at a time, so that the table is
+/// never held in memory. `write` returns whether to carry on, e.g. `false` once the client has
+/// gone away.
+#[tracing::instrument(name = "Export subscribers", skip(pool, write))]
+pub async fn export_subscribers<F, Fut>(
+    pool: &PgPool,
+    query: &ExportQuery,
+    mut write: F,
+) -> Result<(), anyhow::Error>
+where
+    F: FnMut(Vec<u8>) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let field_keys = get_custom_fields(pool)
+        .await?
+        .into_iter()
+        .map(|field| field.key)
+        .collect();
+    let writer = ExportWriter::new(query.format, field_keys);
+    let list_id = match (&query.segment, query.list_id) {
+        (Some(_), None) => Some(DEFAULT_LIST_ID),
+        (_, list_id) => list_id,
+    };
+    let filter = query.segment.clone().unwrap_or_default();
+
+    let mut rows = sqlx::query!(
+        r#"
+        SELECT
+            s.id,
+            s.email,
+            s.name,
+            COALESCE(
+                (SELECT m.status FROM list_memberships m
+                    WHERE m.subscriber_id = s.id AND m.list_id = $1),
+                s.status
+            ) AS "status!",
+            s.subscribed_at,
+            (
+                SELECT MIN(m.confirmed_at) FROM list_memberships m
+                WHERE m.subscriber_id = s.id AND ($1::uuid IS NULL OR m.list_id = $1)
+            ) AS confirmed_at,
+            ARRAY(
+                SELECT t.tag FROM subscriber_tags t WHERE t.subscriber_id = s.id ORDER BY t.tag
+            ) AS "tags!",
+            COALESCE(
+                (
+                    SELECT jsonb_object_agg(v.field_key, v.value)
+                    FROM subscriber_field_values v
+                    WHERE v.subscriber_id = s.id
+                ),
+                '{}'
+            ) AS "fields!: Json<BTreeMap<String, String>>",
+            COALESCE(
+                (
+                    SELECT jsonb_agg(to_jsonb(c) ORDER BY c.recorded_at)
+                    FROM consent_records c
+                    WHERE c.subscriber_id = s.id AND ($1::uuid IS NULL OR c.list_id = $1)
+                ),
+                '[]'
+            ) AS "consents!: Json<Vec<ConsentRecord>>"
+        FROM subscriptions s
+        WHERE $1::uuid IS NULL OR EXISTS (
+            SELECT 1 FROM list_memberships m
+            WHERE m.subscriber_id = s.id
+                AND m.list_id = $1
+                AND (
+                    NOT $2
+                    OR m.status = 'confirmed'
+                    AND (
+                        cardinality($3::text[]) = 0
+                        OR EXISTS (
+                            SELECT 1 FROM subscriber_tags t
+                            WHERE t.subscriber_id = s.id AND t.tag = ANY($3)
+                        )
+                    )
+                    AND NOT EXISTS (
+                        SELECT 1 FROM subscriber_tags t
+                        WHERE t.subscriber_id = s.id AND t.tag = ANY($4)
+                    )
+                    AND ($5::timestamptz IS NULL OR m.subscribed_at >= $5)
+                    AND ($6::timestamptz IS NULL OR m.subscribed_at < $6)
+                    AND (
+                        $7::int IS NULL
+                        OR EXISTS (
+                            SELECT 1 FROM issue_deliveries o
+                            WHERE o.subscriber_id = s.id
+                                AND o.last_opened_at >= NOW() - make_interval(days => $7)
+                        )
+                    )
+                    AND (
+                        $8::int IS NULL
+                        OR EXISTS (
+                            SELECT 1 FROM link_clicks c
+                            WHERE c.subscriber_id = s.id
+                                AND c.clicked_at >= NOW() - make_interval(days => $8)
+                        )
+                    )
+                )
+        )
+        ORDER BY s.subscribed_at, s.id
+        "#,
+        list_id,
+        query.segment.is_some(),
+        &filter.include_tags,
+        &filter.exclude_tags,
+        filter.subscribed_after,
+        filter.subscribed_before,
+        filter.opened_within_days,
+        filter.clicked_within_days,
+    )
+    .fetch(pool);
+
+    let mut chunk = writer.header()?;
+    while let Some(row) = rows
+        .try_next()
+        .await
+        .context("Failed to fetch the next subscriber to export.")?
+    {
+        let subscriber = ExportedSubscriber {
+            id: row.id,
+            email: row.email,
+            name: row.name,
+            status: row.status,
+            subscribed_at: row.subscribed_at,
+            confirmed_at: row.confirmed_at,
+            tags: row.tags,
+            fields: row.fields.0,
+            consents: row.consents.0,
+        };
+        chunk.extend(writer.row(&subscriber)?);
+        if chunk.len() >= CHUNK_SIZE && !write(std::mem::take(&mut chunk)).await {
+            return Ok(());
+        }
+    }
+    if !chunk.is_empty() {
+        write(chunk).await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    use crate::consent::ConsentRecord;
+    use crate::subscriber_export::{ExportFormat, ExportWriter, ExportedSubscriber};
+
+    fn subscriber() -> ExportedSubscriber {
+        ExportedSubscriber {
+            id: Uuid::nil(),
+            email: "ann@example.com".into(),
+            name: "Ann, Jr".into(),
+            status: "confirmed".into(),
+            subscribed_at: Utc.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap(),
+            confirmed_at: None,
+            tags: vec!["a".into(), "b".into()],
+            fields: BTreeMap::from([("company".into(), "Acme".into())]),
+            consents: vec![ConsentRecord {
+                list_id: Uuid::nil(),
+                action: "subscribe".into(),
+                recorded_at: Utc.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap(),
+                ip_address: Some("203.0.113.7".into()),
+                user_agent: None,
+                source: Some("footer".into()),
+                consent_text: "Yes, please".into(),
+            }],
+        }
+    }
+
+    #[test]
+    fn csv_exports_have_a_column_per_custom_field() {
+        let writer = ExportWriter::new(ExportFormat::Csv, vec!["company".into(), "seats".into()]);
+        let header = String::from_utf8(writer.header().unwrap()).unwrap();
+        assert_eq!(
+            header,
+            "id,email,name,status,subscribed_at,confirmed_at,tags,consent_action,\
+            consent_recorded_at,consent_ip_address,consent_user_agent,consent_source,consent_text,\
+            company,seats\n"
+        );
+        let row = String::from_utf8(writer.row(&subscriber()).unwrap()).unwrap();
+        assert_eq!(
+            row,
+            "00000000-0000-0000-0000-000000000000,ann@example.com,\"Ann, Jr\",confirmed,\
+            2026-01-02T03:04:05+00:00,,\"a,b\",subscribe,2026-01-02T03:04:05+00:00,203.0.113.7,,\
+            footer,\"Yes, please\",Acme,\n"
+        );
+    }
+
+    #[test]
+    fn ndjson_exports_have_one_object_per_line() {
+        let writer = ExportWriter::new(ExportFormat::Ndjson, vec!["company".into()]);
+        assert!(writer.header().unwrap().is_empty());
+        let row = writer.row(&subscriber()).unwrap();
+        assert_eq!(row.last(), Some(&b'\n'));
+        let value: serde_json::Value = serde_json::from_slice(&row).unwrap();
+        assert_eq!(value["email"], "ann@example.com");
+        assert_eq!(value["confirmed_at"], serde_json::Value::Null);
+        assert_eq!(value["tags"], serde_json::json!(["a", "b"]));
+        assert_eq!(value["fields"]["company"], "Acme");
+        assert_eq!(value["consents"][0]["source"], "footer");
+    }
+
+    #[test]
+    fn export_formats_are_parsed_by_name() {
+        assert_eq!(ExportFormat::parse("csv"), Ok(ExportFormat::Csv));
+        assert_eq!(ExportFormat::parse("ndjson"), Ok(ExportFormat::Ndjson));
+        assert!(ExportFormat::parse("xml").is_err());
+    }
+}
@@ -0,0 +1,630 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+use csv::StringRecord;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::address_policy::AddressPolicy;
+use crate::consent::{record_consent, ConsentAction, ConsentContext};
+use crate::custom_fields::{get_custom_fields, set_field_values};
+use crate::domain::custom_field::{parse_field_values, CustomField};
+use crate::domain::subscriber::Subscriber;
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::domain::subscriber_name::SubscriberName;
+use crate::import_confirmation::queue_confirmation;
+use crate::mailing_list::{get_list, join_confirmed, MailingList, DEFAULT_LIST_ID};
+use crate::routes::{
+    error_chain_fmt, generate_subscription_token, insert_membership, store_token_in_db,
+};
+use crate::suppression::is_suppressed;
+
+/// The longest a single CSV record may be; anything longer is most likely an unterminated quote.
+pub const MAX_RECORD_LENGTH: usize = 64 * 1024;
+
+/// How imported subscribers join the list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Subscribers are confirmed straight away, on the strength of the operator's attestation that
+    /// they consented elsewhere.
+    Confirmed,
+    /// Subscribers are sent the list's confirmation email and join once they follow it.
+    DoubleOptIn,
+}
+
+impl ImportMode {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "confirmed" => Ok(Self::Confirmed),
+            "double_opt_in" => Ok(Self::DoubleOptIn),
+            other => Err(format!("{} is not a supported import mode", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Confirmed => "confirmed",
+            Self::DoubleOptIn => "double_opt_in",
+        }
+    }
+}
+
+/// The values of one CSV row, by what they are imported as.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ImportRow {
+    pub email: String,
+    pub name: String,
+    pub fields: HashMap<String, String>,
+}
+
+/// Which CSV column holds the email, the name and each custom field.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ColumnMapping {
+    email: usize,
+    name: usize,
+    fields: Vec<(usize, String)>,
+}
+
+impl ColumnMapping {
+    /// Maps the columns of `header` to `email`, `name` or a custom field key.
+    ///
+    /// `overrides` is a comma-separated list of `column:target` pairs, for files whose headers do
+    /// not match; other columns are matched by name, ignoring case, and ignored if nothing matches.
+    pub fn parse(
+        header: &StringRecord,
+        overrides: &str,
+        custom_fields: &[CustomField],
+    ) -> Result<Self, String> {
+        let mut targets = HashMap::new();
+        for pair in overrides.split(',').filter(|pair| !pair.trim().is_empty()) {
+            let (column, target) = pair
+                .rsplit_once(':')
+                .ok_or_else(|| format!("{} is not a `column:target` pair", pair.trim()))?;
+            targets.insert(column.trim().to_lowercase(), target.trim().to_lowercase());
+        }
+        for column in targets.keys() {
+            if !header.iter().any(|h| h.trim().to_lowercase() == *column) {
+                return Err(format!("There is no column named {}", column));
+            }
+        }
+
+        let mut email = None;
+        let mut name = None;
+        let mut fields = Vec::new();
+        for (index, column) in header.iter().enumerate() {
+            let column = column.trim().to_lowercase();
+            let target = targets.get(&column).unwrap_or(&column);
+            let slot = match target.as_str() {
+                "email" => &mut email,
+                "name" => &mut name,
+                key if custom_fields.iter().any(|field| field.key == key) => {
+                    if fields.iter().any(|(_, k)| k == key) {
+                        return Err(format!("Several columns are mapped to {}", key));
+                    }
+                    fields.push((index, key.to_owned()));
+                    continue;
+                }
+                _ if targets.contains_key(&column) => {
+                    return Err(format!("There is no field named {}", target));
+                }
+                _ => continue,
+            };
+            if slot.replace(index).is_some() {
+                return Err(format!("Several columns are mapped to {}", target));
+            }
+        }
+        Ok(Self {
+            email: email.ok_or("No column is mapped to email")?,
+            name: name.ok_or("No column is mapped to name")?,
+            fields,
+        })
+    }
+
+    pub fn row(&self, record: &StringRecord) -> ImportRow {
+        let get = |index| record.get(index).unwrap_or_default().to_owned();
+        ImportRow {
+            email: get(self.email),
+            name: get(self.name),
+            fields: self
+                .fields
+                .iter()
+                .map(|(index, key)| (key.clone(), get(*index)))
+                .collect(),
+        }
+    }
+}
+
+/// Buffers an uploaded CSV file as it arrives and hands it back in pieces made of whole records,
+/// so that it can be parsed without holding the whole file in memory.
+#[derive(Debug, Default)]
+pub struct RecordSplitter {
+    buffer: Vec<u8>,
+    /// How much of the buffer has been scanned for record boundaries.
+    scanned: usize,
+    in_quotes: bool,
+    /// Where the last complete record seen ends.
+    boundary: usize,
+}
+
+impl RecordSplitter {
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+        for (i, byte) in self.buffer[self.scanned..].iter().enumerate() {
+            match byte {
+                // An escaped quote toggles twice, which leaves the state unchanged.
+                b'"' => self.in_quotes = !self.in_quotes,
+                b'\n' if !self.in_quotes => self.boundary = self.scanned + i + 1,
+                _ => {}
+            }
+        }
+        self.scanned = self.buffer.len();
+    }
+
+    /// Takes the complete records received so far, if any.
+    pub fn take_records(&mut self) -> Option<Vec<u8>> {
+        if self.boundary == 0 {
+            return None;
+        }
+        let rest = self.buffer.split_off(self.boundary);
+        let records = std::mem::replace(&mut self.buffer, rest);
+        self.scanned -= self.boundary;
+        self.boundary = 0;
+        Some(records)
+    }
+
+    /// The length of the incomplete record at the end of the buffer.
+    pub fn pending_len(&self) -> usize {
+        self.buffer.len() - self.boundary
+    }
+
+    /// Takes whatever is left once the file has been received entirely.
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum ImportError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ImportReport {
+    pub import_id: Uuid,
+    pub imported: i32,
+    /// Rows whose address is already on the list, or appears earlier in the file.
+    pub duplicates: i32,
+    /// Confirmation emails of a double opt-in import, sent in the background once the rows are
+    /// stored.
+    pub confirmations_queued: i32,
+    pub errors: Vec<RowError>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RowError {
+    /// The position of the record in the file, the header being row 1.
+    pub row: u64,
+    pub error: String,
+}
+
+/// Imports a CSV file into a list as it comes in, whether it was uploaded to
+/// `POST /admin/subscribers/import` or given to `zero2prod import`.
+pub struct Importer<'a> {
+    pool: &'a PgPool,
+    address_policy: &'a AddressPolicy,
+    list: MailingList,
+    mode: ImportMode,
+    consent: Option<String>,
+    custom_fields: Vec<CustomField>,
+    /// Comma-separated `column:target` pairs, see [`ColumnMapping::parse`].
+    columns: String,
+    mapping: Option<ColumnMapping>,
+    splitter: RecordSplitter,
+    seen: HashSet<String>,
+    rows: u64,
+    report: ImportReport,
+}
+
+impl<'a> Importer<'a> {
+    /// Records the start of an import into `list_id`, the default list when absent.
+    /// `imported_by` is the admin user importing the file, none from the command line.
+    ///
+    /// Addresses that could not sign up themselves, see [`AddressPolicy`], are not imported either.
+    pub async fn start(
+        pool: &'a PgPool,
+        address_policy: &'a AddressPolicy,
+        mode: ImportMode,
+        consent: Option<String>,
+        list_id: Option<Uuid>,
+        columns: String,
+        imported_by: Option<Uuid>,
+    ) -> Result<Importer<'a>, ImportError> {
+        let consent = match mode {
+            ImportMode::Confirmed => Some(
+                consent
+                    .map(|consent| consent.trim().to_owned())
+                    .filter(|consent| !consent.is_empty())
+                    .ok_or_else(|| {
+                        ImportError::ValidationError(
+                            "Importing confirmed subscribers requires a consent attestation".into(),
+                        )
+                    })?,
+            ),
+            ImportMode::DoubleOptIn => None,
+        };
+        let list = get_list(pool, list_id.unwrap_or(DEFAULT_LIST_ID))
+            .await
+            .context("Failed to retrieve the mailing list.")?
+            .ok_or_else(|| {
+                ImportError::ValidationError("There is no list with the given identifier".into())
+            })?;
+        let custom_fields = get_custom_fields(pool)
+            .await
+            .context("Failed to retrieve the custom fields.")?;
+
+        let import_id = Uuid::new_v4();
+        tracing::Span::current().record("import_id", tracing::field::display(&import_id));
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriber_imports (
+                import_id, list_id, mode, consent_attestation, imported_by
+            )
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            import_id,
+            list.id,
+            mode.as_str(),
+            consent,
+            imported_by,
+        )
+        .execute(pool)
+        .await
+        .context("Failed to record the import.")?;
+
+        Ok(Self {
+            pool,
+            address_policy,
+            list,
+            mode,
+            consent,
+            custom_fields,
+            columns,
+            mapping: None,
+            splitter: RecordSplitter::default(),
+            seen: HashSet::new(),
+            rows: 0,
+            report: ImportReport {
+                import_id,
+                ..Default::default()
+            },
+        })
+    }
+
+    /// Imports the records completed by the next `chunk` of the file.
+    pub async fn push(&mut self, chunk: &[u8]) -> Result<(), ImportError> {
+        self.splitter.push(chunk);
+        if let Some(records) = self.splitter.take_records() {
+            self.import(&records).await?;
+        }
+        if self.splitter.pending_len() > MAX_RECORD_LENGTH {
+            return Err(ImportError::ValidationError(format!(
+                "Row {} is too long",
+                self.rows + 1
+            )));
+        }
+        Ok(())
+    }
+
+    /// Imports the last record of the file and records the outcome of the import.
+    pub async fn finish(mut self) -> Result<ImportReport, ImportError> {
+        let rest = std::mem::take(&mut self.splitter).finish();
+        self.import(&rest).await?;
+        if self.mapping.is_none() {
+            return Err(ImportError::ValidationError("The file is empty".into()));
+        }
+
+        let report = self.report;
+        sqlx::query!(
+            r#"
+            UPDATE subscriber_imports
+            SET imported_count = $2, duplicate_count = $3, error_count = $4, completed_at = NOW()
+            WHERE import_id = $1
+            "#,
+            report.import_id,
+            report.imported,
+            report.duplicates,
+            report.errors.len() as i32,
+        )
+        .execute(self.pool)
+        .await
+        .context("Failed to record the outcome of the import.")?;
+        Ok(report)
+    }
+
+    /// Imports whole records, the first of the file being its header.
+    async fn import(&mut self, records: &[u8]) -> Result<(), ImportError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(records);
+        for record in reader.records() {
+            self.rows += 1;
+            let Some(mapping) = &self.mapping else {
+                let header = record.map_err(|e| {
+                    ImportError::ValidationError(format!("The header is invalid: {}", e))
+                })?;
+                let mapping = ColumnMapping::parse(&header, &self.columns, &self.custom_fields)
+                    .map_err(ImportError::ValidationError)?;
+                self.mapping = Some(mapping);
+                continue;
+            };
+            let outcome = match record {
+                Ok(record) => {
+                    let row = mapping.row(&record);
+                    self.import_row(row).await?
+                }
+                Err(e) => Err(e.to_string()),
+            };
+            match outcome {
+                Ok(true) => self.report.imported += 1,
+                Ok(false) => self.report.duplicates += 1,
+                Err(error) => self.report.errors.push(RowError {
+                    row: self.rows,
+                    error,
+                }),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether the row was added to the list rather than skipped as a duplicate, or why
+    /// it could not be.
+    ///
+    /// An address that is already known is not stored again, but is still added to the list if it
+    /// is not on it yet. Someone who unsubscribed from the list is not added back.
+    #[tracing::instrument(name = "Import a subscriber", skip(self, row))]
+    async fn import_row(&mut self, row: ImportRow) -> Result<Result<bool, String>, ImportError> {
+        let (subscriber, field_values) = match self.parse_row(row) {
+            Ok(parsed) => parsed,
+            Err(e) => return Ok(Err(e)),
+        };
+        if !self.seen.insert(subscriber.email.identity()) {
+            return Ok(Ok(false));
+        }
+        if let Err(e) = self.address_policy.check(&subscriber.email).await {
+            return Ok(Err(e));
+        }
+
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to acquire Postgres connection from the pool.")?;
+        let (subscriber_id, inserted) = upsert_imported_subscriber(
+            &mut transaction,
+            &subscriber,
+            self.mode,
+            self.report.import_id,
+        )
+        .await
+        .context("Failed to insert an imported subscriber into the database.")?;
+        if inserted {
+            set_field_values(&mut *transaction, subscriber_id, &field_values)
+                .await
+                .context("Failed to store the custom fields of an imported subscriber.")?;
+        }
+        let membership = get_membership_status(&mut transaction, self.list.id, subscriber_id)
+            .await
+            .context("Failed to retrieve the list membership of an imported subscriber.")?;
+        match (self.mode, membership.as_deref()) {
+            (_, Some("confirmed" | "unsubscribed")) | (ImportMode::DoubleOptIn, Some(_)) => {
+                return Ok(Ok(false));
+            }
+            (ImportMode::Confirmed, _) => {
+                join_confirmed(&mut transaction, self.list.id, subscriber_id)
+                    .await
+                    .context("Failed to add an imported subscriber to the mailing list.")?;
+                let context = ConsentContext {
+                    source: Some(format!("import {}", self.report.import_id)),
+                    ..Default::default()
+                };
+                let attestation = self.consent.as_deref().unwrap_or_default();
+                record_consent(
+                    &mut *transaction,
+                    subscriber_id,
+                    self.list.id,
+                    ConsentAction::Import,
+                    &context,
+                    attestation,
+                )
+                .await
+                .context("Failed to record the consent of an imported subscriber.")?;
+            }
+            (ImportMode::DoubleOptIn, None) => {
+                insert_membership(&mut transaction, self.list.id, subscriber_id)
+                    .await
+                    .context("Failed to add an imported subscriber to the mailing list.")?;
+                let subscription_token = generate_subscription_token();
+                store_token_in_db(
+                    &mut transaction,
+                    subscriber_id,
+                    self.list.id,
+                    &subscription_token,
+                )
+                .await
+                .context("Failed to store confirmation token in the database.")?;
+                if is_suppressed(&mut *transaction, &subscriber.email)
+                    .await
+                    .context("Failed to check the suppression list.")?
+                {
+                    tracing::info!("Skipping the confirmation email to a suppressed address");
+                } else {
+                    queue_confirmation(&mut transaction, &subscription_token)
+                        .await
+                        .context("Failed to queue the confirmation email.")?;
+                    self.report.confirmations_queued += 1;
+                }
+            }
+        }
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to import a subscriber.")?;
+        Ok(Ok(true))
+    }
+
+    fn parse_row(&self, row: ImportRow) -> Result<(Subscriber, Vec<(String, String)>), String> {
+        let field_values = parse_field_values(&self.custom_fields, &row.fields)?;
+        let subscriber = Subscriber {
+            email: SubscriberEmail::parse(row.email.trim().to_owned())?,
+            name: SubscriberName::parse(row.name)?,
+        };
+        Ok((subscriber, field_values))
+    }
+}
+
+/// Returns the ID of the subscriber, who may already be known, and whether they were just
+/// inserted. Known subscribers are left as they are.
+#[tracing::instrument(name = "Saving imported subscriber in the database", skip_all)]
+async fn upsert_imported_subscriber(
+    transaction: &mut PgConnection,
+    subscriber: &Subscriber,
+    mode: ImportMode,
+    import_id: Uuid,
+) -> Result<(Uuid, bool), sqlx::Error> {
+    let status = match mode {
+        ImportMode::Confirmed => "confirmed",
+        ImportMode::DoubleOptIn => "pending_confirmation",
+    };
+    let subscriber = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, status, import_id)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT ((lower(email))) DO UPDATE SET email = subscriptions.email
+        RETURNING id, xmax = 0 AS "inserted!"
+        "#,
+        Uuid::new_v4(),
+        subscriber.email.as_ref(),
+        subscriber.name.as_ref(),
+        status,
+        import_id,
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok((subscriber.id, subscriber.inserted))
+}
+
+#[tracing::instrument(name = "Get list membership status", skip(transaction))]
+async fn get_membership_status(
+    transaction: &mut PgConnection,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let membership = sqlx::query!(
+        "SELECT status FROM list_memberships WHERE list_id = $1 AND subscriber_id = $2",
+        list_id,
+        subscriber_id,
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(membership.map(|membership| membership.status))
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use csv::StringRecord;
+
+    use crate::domain::custom_field::{CustomField, FieldType};
+    use crate::subscriber_import::{ColumnMapping, RecordSplitter};
+
+    fn custom_fields() -> Vec<CustomField> {
+        vec![CustomField {
+            key: "company".into(),
+            label: "Company".into(),
+            field_type: FieldType::Text,
+            required: false,
+            options: vec![],
+            max_length: None,
+            min: None,
+            max: None,
+        }]
+    }
+
+    #[test]
+    fn columns_are_matched_by_name_ignoring_case() {
+        let header = StringRecord::from(vec!["Email", " NAME ", "Company", "Notes"]);
+        let mapping = assert_ok!(ColumnMapping::parse(&header, "", &custom_fields()));
+        let row = mapping.row(&StringRecord::from(vec!["a@b.com", "Ann", "Acme", "x"]));
+        assert_eq!(row.email, "a@b.com");
+        assert_eq!(row.name, "Ann");
+        assert_eq!(row.fields.get("company").unwrap(), "Acme");
+        assert_eq!(row.fields.len(), 1);
+    }
+
+    #[test]
+    fn columns_can_be_mapped_explicitly() {
+        let header = StringRecord::from(vec!["E-mail Address", "Full Name", "Employer"]);
+        let overrides = "E-mail Address:email, Full Name:name,employer:company";
+        let mapping = assert_ok!(ColumnMapping::parse(&header, overrides, &custom_fields()));
+        let row = mapping.row(&StringRecord::from(vec!["a@b.com", "Ann", "Acme"]));
+        assert_eq!(row.email, "a@b.com");
+        assert_eq!(row.name, "Ann");
+        assert_eq!(row.fields.get("company").unwrap(), "Acme");
+    }
+
+    #[test]
+    fn invalid_mappings_are_rejected() {
+        let fields = custom_fields();
+        let header = StringRecord::from(vec!["email", "name", "other"]);
+        assert_err!(ColumnMapping::parse(&header, "missing:email", &fields));
+        assert_err!(ColumnMapping::parse(&header, "other:unknown", &fields));
+        assert_err!(ColumnMapping::parse(&header, "other:email", &fields));
+        assert_err!(ColumnMapping::parse(&header, "no pair", &fields));
+        let header = StringRecord::from(vec!["email", "company"]);
+        assert_err!(ColumnMapping::parse(&header, "", &fields));
+    }
+
+    #[test]
+    fn missing_values_are_read_as_empty() {
+        let header = StringRecord::from(vec!["email", "name"]);
+        let mapping = assert_ok!(ColumnMapping::parse(&header, "", &[]));
+        let row = mapping.row(&StringRecord::from(vec!["a@b.com"]));
+        assert_eq!(row.name, "");
+    }
+
+    #[test]
+    fn records_are_only_handed_back_once_complete() {
+        let mut splitter = RecordSplitter::default();
+        splitter.push(b"email,name\na@b.com,A");
+        assert_eq!(splitter.take_records().unwrap(), b"email,name\n");
+        assert_eq!(splitter.take_records(), None);
+        splitter.push(b"nn\nc@d.com,C");
+        assert_eq!(splitter.take_records().unwrap(), b"a@b.com,Ann\n");
+        assert_eq!(splitter.pending_len(), 9);
+        assert_eq!(splitter.finish(), b"c@d.com,C");
+    }
+
+    #[test]
+    fn newlines_within_quotes_do_not_end_a_record() {
+        let mut splitter = RecordSplitter::default();
+        splitter.push(b"a@b.com,\"Ann\n\"\"The\"\"");
+        assert_eq!(splitter.take_records(), None);
+        splitter.push(b" Best\"\r\nc");
+        assert_eq!(
+            splitter.take_records().unwrap(),
+            b"a@b.com,\"Ann\n\"\"The\"\" Best\"\r\n"
+        );
+        assert_eq!(splitter.finish(), b"c");
+    }
+}
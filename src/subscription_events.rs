@@ -0,0 +1,48 @@
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+/// A change in the lifecycle of a subscription, kept for growth and engagement analytics.
+///
+/// Sign-ups are not recorded here: `subscriptions.subscribed_at` already tells when they happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionEventType {
+    Confirmed,
+    Unsubscribed,
+}
+
+impl SubscriptionEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionEventType::Confirmed => "confirmed",
+            SubscriptionEventType::Unsubscribed => "unsubscribed",
+        }
+    }
+}
+
+/// Records a subscription event on a list, optionally attributed to the newsletter issue that
+/// caused it.
+#[tracing::instrument(name = "Record subscription event", skip(executor))]
+pub async fn record_subscription_event(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    event_type: SubscriptionEventType,
+    newsletter_issue_id: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_events (
+            id, subscriber_id, list_id, event_type, newsletter_issue_id
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        list_id,
+        event_type.as_str(),
+        newsletter_issue_id,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
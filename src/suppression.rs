@@ -0,0 +1,58 @@
+use sqlx::PgExecutor;
+
+use crate::domain::subscriber_email::SubscriberEmail;
+
+/// Why an address must not be mailed anymore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionReason {
+    HardBounce,
+    SpamComplaint,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::HardBounce => "hard_bounce",
+            SuppressionReason::SpamComplaint => "spam_complaint",
+        }
+    }
+}
+
+/// Suppressions are keyed on a hash of the address, see the `hash_email` SQL function: erasing the
+/// address only clears it, so an erased address that bounced or complained is never mailed again.
+#[tracing::instrument(name = "Suppress email address", skip(executor))]
+pub async fn suppress(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+    reason: SuppressionReason,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email_hash, email, reason)
+        VALUES (hash_email($1), lower($1), $2)
+        ON CONFLICT (email_hash) DO NOTHING
+        "#,
+        email,
+        reason.as_str(),
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Check whether an email address is suppressed", skip(executor))]
+pub async fn is_suppressed(
+    executor: impl PgExecutor<'_>,
+    email: &SubscriberEmail,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM suppressions WHERE email_hash = hash_email($1))
+            AS "suppressed!"
+        "#,
+        email.as_ref(),
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(row.suppressed)
+}
@@ -0,0 +1,57 @@
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::domain::subscriber_tag::SubscriberTag;
+
+/// Adds tags to a subscriber, keeping the ones it already has.
+#[tracing::instrument(name = "Tag subscriber", skip(executor))]
+pub async fn add_tags(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    tags: &[SubscriberTag],
+) -> Result<(), sqlx::Error> {
+    let tags: Vec<_> = tags.iter().map(|tag| tag.as_ref().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+        SELECT $1, tag FROM UNNEST($2::text[]) AS tag
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        &tags,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Returns whether the subscriber had the tag.
+#[tracing::instrument(name = "Untag subscriber", skip(executor))]
+pub async fn remove_tag(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    tag: &SubscriberTag,
+) -> Result<bool, sqlx::Error> {
+    let removed = sqlx::query!(
+        "DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND tag = $2",
+        subscriber_id,
+        tag.as_ref(),
+    )
+    .execute(executor)
+    .await?;
+    Ok(removed.rows_affected() > 0)
+}
+
+#[tracing::instrument(name = "Get subscriber tags", skip(executor))]
+pub async fn get_tags(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Vec<String>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag",
+        subscriber_id,
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().map(|row| row.tag).collect())
+}
@@ -0,0 +1,262 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use uuid::Uuid;
+
+/// Builds the per-recipient URLs of a delivered issue: those recording opens and clicks, the link
+/// to unsubscribe from the list it was sent to and the link to the subscriber's preferences.
+///
+/// Click URLs carry an HMAC of the delivery and link they point to, so the redirect endpoint only
+/// ever follows links that were actually sent out.
+#[derive(Clone)]
+pub struct TrackingLinks {
+    base_url: String,
+    hmac_secret: Secret<String>,
+}
+
+/// The delivery and link a verified click token refers to.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ClickToken {
+    pub tracking_token: String,
+    pub link_id: i64,
+}
+
+impl TrackingLinks {
+    pub fn new(base_url: String, hmac_secret: Secret<String>) -> Self {
+        Self {
+            base_url,
+            hmac_secret,
+        }
+    }
+
+    pub fn unsubscribe_url(&self, unsubscribe_token: &str, newsletter_issue_id: Uuid) -> String {
+        format!(
+            "{}/subscriptions/unsubscribe?token={}&issue={}",
+            self.base_url, unsubscribe_token, newsletter_issue_id
+        )
+    }
+
+    pub fn preferences_url(&self, preferences_token: &str) -> String {
+        format!("{}/preferences?token={}", self.base_url, preferences_token)
+    }
+
+    pub fn open_pixel_url(&self, tracking_token: &str) -> String {
+        format!("{}/t/o/{}", self.base_url, tracking_token)
+    }
+
+    pub fn click_url(&self, tracking_token: &str, link_id: i64) -> String {
+        let payload = format!("{}.{}", tracking_token, link_id);
+        let signature = hex::encode(self.mac(&payload).finalize().into_bytes());
+        format!("{}/t/c/{}.{}", self.base_url, payload, signature)
+    }
+
+    /// Returns the delivery and link a click token refers to, or `None` if it was not signed by us.
+    pub fn verify_click_token(&self, token: &str) -> Option<ClickToken> {
+        let (payload, signature) = token.rsplit_once('.')?;
+        let signature = hex::decode(signature).ok()?;
+        self.mac(payload).verify_slice(&signature).ok()?;
+
+        let (tracking_token, link_id) = payload.split_once('.')?;
+        Some(ClickToken {
+            tracking_token: tracking_token.to_owned(),
+            link_id: link_id.parse().ok()?,
+        })
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<sha2::Sha256> {
+        let mut mac =
+            Hmac::<sha2::Sha256>::new_from_slice(self.hmac_secret.expose_secret().as_bytes())
+                .expect("HMAC can take a key of any size");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+/// Adds an invisible 1x1 image pointing at `pixel_url` to an HTML body, so that opening the email
+/// fetches it.
+pub fn add_open_pixel(html: &str, pixel_url: &str) -> String {
+    let pixel = format!(
+        r#"<img src="{}" width="1" height="1" alt="" style="border:0;width:1px;height:1px;" />"#,
+        pixel_url
+    );
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(index) => format!("{}{}{}", &html[..index], pixel, &html[index..]),
+        None => format!("{}{}", html, pixel),
+    }
+}
+
+/// The trackable links of an issue: absolute `http(s)` URLs, in the `href` attributes of the HTML
+/// part and in the text part. Links built from template placeholders differ for every recipient
+/// and are left out.
+pub fn trackable_links(html: &str, text: &str) -> Vec<String> {
+    let mut links: Vec<String> = href_values(html)
+        .map(|(start, end)| decode_href(&html[start..end]))
+        .chain(text_links(text).map(|(start, end)| text[start..end].to_owned()))
+        .filter(|link| is_trackable(link))
+        .collect();
+    links.sort();
+    links.dedup();
+    links
+}
+
+/// Replaces every `href` attribute value for which `rewrite` returns a new URL.
+pub fn rewrite_html_links(html: &str, rewrite: impl Fn(&str) -> Option<String>) -> String {
+    replace_ranges(html, href_values(html), |value| {
+        rewrite(&decode_href(value))
+    })
+}
+
+/// Replaces every URL of a plain-text body for which `rewrite` returns a new URL.
+pub fn rewrite_text_links(text: &str, rewrite: impl Fn(&str) -> Option<String>) -> String {
+    replace_ranges(text, text_links(text), rewrite)
+}
+
+fn is_trackable(link: &str) -> bool {
+    let lowercase = link.to_ascii_lowercase();
+    (lowercase.starts_with("http://") || lowercase.starts_with("https://")) && !link.contains("{{")
+}
+
+/// Byte ranges of the quoted values of `href` attributes.
+fn href_values(html: &str) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let lowercase = html.to_ascii_lowercase();
+    let mut offset = 0;
+    std::iter::from_fn(move || loop {
+        let index = offset + lowercase[offset..].find("href")?;
+        offset = index + 4;
+        let rest = lowercase[offset..].trim_start();
+        let Some(rest) = rest.strip_prefix('=') else {
+            continue;
+        };
+        let rest = rest.trim_start();
+        let quote = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => continue,
+        };
+        let start = lowercase.len() - rest.len() + 1;
+        let Some(length) = lowercase[start..].find(quote) else {
+            continue;
+        };
+        offset = start + length + 1;
+        return Some((start, start + length));
+    })
+}
+
+fn text_links(text: &str) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let mut finder = linkify::LinkFinder::new();
+    finder.kinds(&[linkify::LinkKind::Url]);
+    finder
+        .links(text)
+        .map(|link| (link.start(), link.end()))
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+fn decode_href(value: &str) -> String {
+    value.replace("&amp;", "&")
+}
+
+fn replace_ranges(
+    s: &str,
+    ranges: impl Iterator<Item = (usize, usize)>,
+    replacement: impl Fn(&str) -> Option<String>,
+) -> String {
+    let mut replaced = String::with_capacity(s.len());
+    let mut last = 0;
+    for (start, end) in ranges {
+        if let Some(new) = replacement(&s[start..end]) {
+            replaced.push_str(&s[last..start]);
+            replaced.push_str(&new);
+            last = end;
+        }
+    }
+    replaced.push_str(&s[last..]);
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tracking::{
+        add_open_pixel, rewrite_html_links, rewrite_text_links, trackable_links, ClickToken,
+        TrackingLinks,
+    };
+    use secrecy::Secret;
+
+    fn tracking_links(secret: &str) -> TrackingLinks {
+        TrackingLinks::new("http://localhost".into(), Secret::new(secret.into()))
+    }
+
+    #[test]
+    fn the_pixel_is_added_before_the_closing_body_tag() {
+        let html = add_open_pixel("<html><BODY><p>Hi</p></BODY></html>", "http://t/o/abc");
+        assert_eq!(
+            html,
+            r#"<html><BODY><p>Hi</p><img src="http://t/o/abc" width="1" height="1" alt="" style="border:0;width:1px;height:1px;" /></BODY></html>"#
+        );
+    }
+
+    #[test]
+    fn the_pixel_is_appended_to_html_fragments() {
+        let html = add_open_pixel("<p>Hi</p>", "http://t/o/abc");
+        assert!(html.starts_with("<p>Hi</p><img src=\"http://t/o/abc\""));
+    }
+
+    #[test]
+    fn click_urls_round_trip_through_verification() {
+        let links = tracking_links("secret");
+        let url = links.click_url("abc123", 42);
+        let token = url.strip_prefix("http://localhost/t/c/").unwrap();
+        assert_eq!(
+            links.verify_click_token(token),
+            Some(ClickToken {
+                tracking_token: "abc123".into(),
+                link_id: 42
+            })
+        );
+    }
+
+    #[test]
+    fn tampered_or_foreign_click_tokens_are_rejected() {
+        let links = tracking_links("secret");
+        let url = links.click_url("abc123", 42);
+        let token = url.strip_prefix("http://localhost/t/c/").unwrap();
+
+        let tampered = token.replacen("42", "43", 1);
+        assert_eq!(links.verify_click_token(&tampered), None);
+        assert_eq!(tracking_links("other").verify_click_token(token), None);
+        assert_eq!(links.verify_click_token("abc123.42"), None);
+        assert_eq!(links.verify_click_token(""), None);
+    }
+
+    #[test]
+    fn trackable_links_skip_non_http_and_templated_links() {
+        let html = r##"<a href="https://example.com/?a=1&amp;b=2">x</a>
+            <a HREF = 'http://example.com'>y</a>
+            <a href="mailto:me@example.com">z</a>
+            <a href="https://example.com/u?e={{ email }}">u</a>
+            <a href="#top">t</a>"##;
+        let text = "Read https://example.com/text and http://example.com";
+        assert_eq!(
+            trackable_links(html, text),
+            vec![
+                "http://example.com",
+                "https://example.com/?a=1&b=2",
+                "https://example.com/text",
+            ]
+        );
+    }
+
+    #[test]
+    fn only_links_with_a_replacement_are_rewritten() {
+        let rewrite = |url: &str| (url == "https://a.com").then(|| "https://t/1".to_owned());
+
+        let html = r#"<a href="https://a.com">a</a><a href="https://b.com">b</a>"#;
+        assert_eq!(
+            rewrite_html_links(html, rewrite),
+            r#"<a href="https://t/1">a</a><a href="https://b.com">b</a>"#
+        );
+        let text = "See https://a.com or https://b.com";
+        assert_eq!(
+            rewrite_text_links(text, rewrite),
+            "See https://t/1 or https://b.com"
+        );
+    }
+}
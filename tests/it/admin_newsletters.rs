@@ -0,0 +1,562 @@
+use actix_web::{http::StatusCode, test};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::send_queue::MAX_SEND_ATTEMPTS;
+
+use crate::{
+    helpers::{add_test_user, create_confirmed_subscriber, post_newsletter, with_basic_auth},
+    init::TestApp,
+};
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Hello {{name}}!",
+            "html": "<p>Hello {{ name }}!</p>"
+        }
+    })
+}
+
+async fn create_draft_issue(app: &TestApp, username: &str, password: &str) -> String {
+    let server = app.get_server().await;
+    let req = test::TestRequest::post()
+        .uri("/admin/newsletters")
+        .set_json(newsletter_body())
+        .to_request();
+
+    let resp = test::call_service(&server, with_basic_auth(req, username, password)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    body["newsletter_issue_id"].as_str().unwrap().to_owned()
+}
+
+#[actix_web::test]
+async fn creating_a_draft_issue_does_not_send_any_email() {
+    let app = TestApp::new().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(app.get_email_server())
+        .await;
+
+    let issue_id = create_draft_issue(&app, &username, &password).await;
+
+    let saved = sqlx::query!("SELECT title, published_at FROM newsletter_issues")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(saved.title, "Newsletter title");
+    assert!(saved.published_at.is_none());
+    assert!(Uuid::parse_str(&issue_id).is_ok());
+}
+
+#[actix_web::test]
+async fn preview_renders_the_issue_with_sample_subscriber_data() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let issue_id = create_draft_issue(&app, &username, &password).await;
+
+    let test_cases = vec![
+        ("html", "text/html; charset=utf-8", "<p>Hello Jane Doe!</p>"),
+        ("text", "text/plain; charset=utf-8", "Hello Jane Doe!"),
+    ];
+
+    for (format, content_type, expected_body) in test_cases {
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/admin/newsletters/{}/preview?format={}",
+                issue_id, format
+            ))
+            .to_request();
+        let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get("content-type").unwrap(), content_type);
+        let body = test::read_body(resp).await;
+        assert_eq!(body, expected_body.as_bytes());
+    }
+}
+
+#[actix_web::test]
+async fn preview_returns_404_for_an_unknown_issue() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/admin/newsletters/{}/preview", Uuid::new_v4()))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_send_only_goes_to_the_given_recipients() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    create_confirmed_subscriber(&app).await;
+    let issue_id = create_draft_issue(&app, &username, &password).await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(app.get_email_server())
+        .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/newsletters/{}/test", issue_id))
+        .set_json(serde_json::json!({
+            "recipients": ["editor@example.com", "reviewer@example.com"]
+        }))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_send_defaults_to_the_address_of_the_current_user() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let (user_id, username, password) = add_test_user(app.get_db_conn()).await;
+    let issue_id = create_draft_issue(&app, &username, &password).await;
+
+    sqlx::query!(
+        "UPDATE users SET email = 'editor@example.com' WHERE id = $1",
+        user_id
+    )
+    .execute(app.get_db_conn())
+    .await
+    .unwrap();
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(app.get_email_server())
+        .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/newsletters/{}/test", issue_id))
+        .set_json(serde_json::json!({}))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let email_request = &app.get_email_server().received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(
+        body["Messages"][0]["To"][0]["Email"].as_str().unwrap(),
+        "editor@example.com"
+    );
+    assert_eq!(
+        body["Messages"][0]["TextPart"].as_str().unwrap(),
+        "Hello Jane Doe!"
+    );
+}
+
+#[actix_web::test]
+async fn test_send_returns_400_without_any_recipient() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let issue_id = create_draft_issue(&app, &username, &password).await;
+
+    let test_cases = vec![
+        (serde_json::json!({}), "user without an email address"),
+        (
+            serde_json::json!({"recipients": ["not-an-email"]}),
+            "invalid recipient",
+        ),
+    ];
+
+    for (body, error) in test_cases {
+        let req = test::TestRequest::post()
+            .uri(&format!("/admin/newsletters/{}/test", issue_id))
+            .set_json(body)
+            .to_request();
+        let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+        assert_eq!(
+            resp.status(),
+            StatusCode::BAD_REQUEST,
+            "The API did not return with 400 Bad request for a {}",
+            error
+        );
+    }
+}
+
+#[actix_web::test]
+async fn admin_newsletter_endpoints_require_authentication() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let issue_id = Uuid::new_v4();
+
+    let requests = vec![
+        test::TestRequest::post()
+            .uri("/admin/newsletters")
+            .set_json(newsletter_body())
+            .to_request(),
+        test::TestRequest::get()
+            .uri(&format!("/admin/newsletters/{}/preview", issue_id))
+            .to_request(),
+        test::TestRequest::post()
+            .uri(&format!("/admin/newsletters/{}/test", issue_id))
+            .set_json(serde_json::json!({}))
+            .to_request(),
+        test::TestRequest::get()
+            .uri(&format!("/admin/newsletters/{}/report", issue_id))
+            .to_request(),
+        test::TestRequest::post()
+            .uri(&format!("/admin/newsletters/{}/publish", issue_id))
+            .to_request(),
+        test::TestRequest::post()
+            .uri(&format!("/admin/newsletters/{}/cancel", issue_id))
+            .to_request(),
+    ];
+
+    for req in requests {
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            resp.headers().get("WWW-Authenticate").unwrap(),
+            r#"Basic realm="admin""#
+        );
+    }
+}
+
+async fn publish_issue_with_failing_delivery(
+    app: &TestApp,
+    username: &str,
+    password: &str,
+) -> String {
+    let server = app.get_server().await;
+    create_confirmed_subscriber(app).await;
+
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount_as_scoped(app.get_email_server())
+        .await;
+
+    let req = with_basic_auth(post_newsletter(&newsletter_body()), username, password);
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    app.wait_for_deliveries().await;
+
+    let issue = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    issue.newsletter_issue_id.to_string()
+}
+
+#[actix_web::test]
+async fn report_lists_delivery_totals_and_failures() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let issue_id = publish_issue_with_failing_delivery(&app, &username, &password).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/admin/newsletters/{}/report", issue_id))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let report: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(report["newsletter_issue_id"], issue_id);
+    assert_eq!(report["totals"]["sent"], 0);
+    assert_eq!(report["totals"]["failed"], 1);
+    assert_eq!(
+        report["failures"][0]["subscriber_email"],
+        "test@testdomain.com"
+    );
+    assert_eq!(report["failures"][0]["status"], "failed");
+    assert_eq!(report["failures"][0]["attempts"], MAX_SEND_ATTEMPTS);
+}
+
+#[actix_web::test]
+async fn report_can_be_exported_as_csv() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let issue_id = publish_issue_with_failing_delivery(&app, &username, &password).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/admin/newsletters/{}/report?format=csv",
+            issue_id
+        ))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/csv; charset=utf-8"
+    );
+
+    let body = test::read_body(resp).await;
+    let mut lines = std::str::from_utf8(&body).unwrap().lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        "subscriber_email,status,provider_message_id,attempts,queued_at,last_attempted_at,sent_at,last_error,first_opened_at,last_opened_at,open_count"
+    );
+    assert!(lines.next().unwrap().starts_with(&format!(
+        "test@testdomain.com,failed,,{},",
+        MAX_SEND_ATTEMPTS
+    )));
+    assert!(lines.next().is_none());
+}
+
+async fn publish_draft_issue(
+    app: &TestApp,
+    issue_id: &str,
+    username: &str,
+    password: &str,
+) -> StatusCode {
+    let server = app.get_server().await;
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/newsletters/{}/publish", issue_id))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, username, password)).await;
+    app.wait_for_deliveries().await;
+    resp.status()
+}
+
+/// Simulates a send that was interrupted before reaching a confirmed subscriber.
+async fn add_queued_delivery(app: &TestApp, issue_id: &str, email: &str) {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, status)
+        VALUES ($1, $2, 'Late subscriber', 'confirmed')
+        "#,
+        subscriber_id,
+        email,
+    )
+    .execute(app.get_db_conn())
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (newsletter_issue_id, subscriber_id, status)
+        VALUES ($1, $2, 'queued')
+        "#,
+        Uuid::parse_str(issue_id).unwrap(),
+        subscriber_id,
+    )
+    .execute(app.get_db_conn())
+    .await
+    .unwrap();
+}
+
+#[actix_web::test]
+async fn publishing_a_draft_sends_it_to_confirmed_subscribers() {
+    let app = TestApp::new().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    create_confirmed_subscriber(&app).await;
+    let issue_id = create_draft_issue(&app, &username, &password).await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(app.get_email_server())
+        .await;
+
+    let status = publish_draft_issue(&app, &issue_id, &username, &password).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+
+    let saved = sqlx::query!("SELECT published_at FROM newsletter_issues")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert!(saved.published_at.is_some());
+}
+
+#[actix_web::test]
+async fn publishing_again_resumes_an_interrupted_send() {
+    let app = TestApp::new().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    create_confirmed_subscriber(&app).await;
+    let issue_id = create_draft_issue(&app, &username, &password).await;
+
+    let mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(app.get_email_server())
+        .await;
+    publish_draft_issue(&app, &issue_id, &username, &password).await;
+    drop(mock_guard);
+
+    add_queued_delivery(&app, &issue_id, "late@example.com").await;
+
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .named("Resumed delivery")
+        .mount_as_scoped(app.get_email_server())
+        .await;
+    let status = publish_draft_issue(&app, &issue_id, &username, &password).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+
+    let requests = app.get_email_server().received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    assert_eq!(body["Messages"][0]["To"][0]["Email"], "late@example.com");
+}
+
+#[actix_web::test]
+async fn resuming_does_not_send_again_a_batch_whose_outcome_is_unknown() {
+    let app = TestApp::new().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    create_confirmed_subscriber(&app).await;
+    let issue_id = create_draft_issue(&app, &username, &password).await;
+
+    let mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(app.get_email_server())
+        .await;
+    publish_draft_issue(&app, &issue_id, &username, &password).await;
+    drop(mock_guard);
+
+    // The sender stopped after handing the batch to the provider, before recording the outcome.
+    add_queued_delivery(&app, &issue_id, "late@example.com").await;
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = 'sending', attempts = 1, last_attempted_at = NOW() - INTERVAL '1 hour'
+        WHERE status = 'queued'
+        "#
+    )
+    .execute(app.get_db_conn())
+    .await
+    .unwrap();
+
+    let _mock_guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount_as_scoped(app.get_email_server())
+        .await;
+    publish_draft_issue(&app, &issue_id, &username, &password).await;
+
+    let delivery = sqlx::query!(
+        r#"
+        SELECT d.status, d.last_error
+        FROM issue_deliveries d
+        JOIN subscriptions s ON s.id = d.subscriber_id
+        WHERE s.email = 'late@example.com'
+        "#
+    )
+    .fetch_one(app.get_db_conn())
+    .await
+    .unwrap();
+    assert_eq!(delivery.status, "failed");
+    assert!(delivery.last_error.unwrap().contains("interrupted"));
+}
+
+#[actix_web::test]
+async fn cancelling_a_send_stops_the_remaining_deliveries() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    create_confirmed_subscriber(&app).await;
+    let issue_id = create_draft_issue(&app, &username, &password).await;
+
+    let mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(app.get_email_server())
+        .await;
+    publish_draft_issue(&app, &issue_id, &username, &password).await;
+    drop(mock_guard);
+
+    add_queued_delivery(&app, &issue_id, "late@example.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(app.get_email_server())
+        .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/newsletters/{}/cancel", issue_id))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["cancelled_deliveries"], 1);
+
+    let status = publish_draft_issue(&app, &issue_id, &username, &password).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/admin/newsletters/{}/report", issue_id))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    let report: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(report["totals"]["sent"], 1);
+    assert_eq!(report["totals"]["cancelled"], 1);
+    assert_eq!(report["totals"]["queued"], 0);
+}
+
+#[actix_web::test]
+async fn cancelling_during_a_send_cancels_the_deliveries_that_fail_afterwards() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    create_confirmed_subscriber(&app).await;
+    let issue_id = create_draft_issue(&app, &username, &password).await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500).set_delay(std::time::Duration::from_millis(500)))
+        .expect(1)
+        .mount(app.get_email_server())
+        .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/newsletters/{}/publish", issue_id))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    while app
+        .get_email_server()
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty()
+    {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/newsletters/{}/cancel", issue_id))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    app.wait_for_deliveries().await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/admin/newsletters/{}/report", issue_id))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    let report: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(report["totals"]["cancelled"], 1);
+    assert_eq!(report["totals"]["queued"], 0);
+}
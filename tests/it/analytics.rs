@@ -0,0 +1,305 @@
+use actix_web::{http::StatusCode, test};
+use chrono::Utc;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::mailing_list::DEFAULT_LIST_ID;
+
+use crate::{
+    helpers::{
+        add_test_user, create_confirmed_subscriber, create_list, create_unconfirmed_subscriber,
+        post_subscription_request, with_basic_auth,
+    },
+    init::TestApp,
+};
+
+async fn get_analytics(app: &TestApp, uri: &str) -> (StatusCode, serde_json::Value) {
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let req = test::TestRequest::get().uri(uri).to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    let status = resp.status();
+    let body = test::read_body(resp).await;
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+async fn add_pending_subscriber(app: &TestApp, email: &str) {
+    add_subscriber_to_list(app, email, "pending_confirmation", DEFAULT_LIST_ID).await;
+}
+
+async fn add_subscriber_to_list(app: &TestApp, email: &str, status: &str, list_id: Uuid) {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, status)
+        VALUES ($1, $2, 'Pending', $3)
+        "#,
+        subscriber_id,
+        email,
+        status,
+    )
+    .execute(app.get_db_conn())
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, unsubscribe_token)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        list_id,
+        subscriber_id,
+        status,
+        subscriber_id.to_string(),
+    )
+    .execute(app.get_db_conn())
+    .await
+    .unwrap();
+}
+
+#[actix_web::test]
+async fn analytics_require_authentication() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+
+    for uri in [
+        "/admin/analytics/subscribers",
+        "/admin/analytics/issues",
+        "/admin/analytics/sending",
+    ] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn subscriber_analytics_count_daily_activity_and_conversion() {
+    let app = TestApp::new().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+    let server = app.get_server().await;
+    // Following the link twice must only count one confirmation.
+    for _ in 0..2 {
+        let req = test::TestRequest::get()
+            .uri(&confirmation_link)
+            .to_request();
+        test::call_service(&server, req).await;
+    }
+    add_pending_subscriber(&app, "pending@example.com").await;
+    let today = Utc::now().date_naive();
+
+    let (status, body) = get_analytics(
+        &app,
+        &format!("/admin/analytics/subscribers?from={}&to={}", today, today),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["daily"],
+        serde_json::json!([{
+            "date": today.to_string(),
+            "signups": 2,
+            "confirmations": 1,
+            "unsubscribes": 0,
+            "bounces": 0
+        }])
+    );
+    assert_eq!(
+        body["conversion"],
+        serde_json::json!({"signups": 2, "confirmed": 1, "rate": 0.5})
+    );
+    assert_eq!(
+        body["by_status"],
+        serde_json::json!([
+            {"status": "confirmed", "count": 1},
+            {"status": "pending_confirmation", "count": 1}
+        ])
+    );
+}
+
+#[actix_web::test]
+async fn conversion_only_counts_confirmed_memberships_of_the_requested_list() {
+    let app = TestApp::new().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+    let server = app.get_server().await;
+    let req = test::TestRequest::get()
+        .uri(&confirmation_link)
+        .to_request();
+    test::call_service(&server, req).await;
+    // Confirmed, then unsubscribed: still a conversion.
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships SET status = 'unsubscribed', unsubscribed_at = NOW()
+        "#
+    )
+    .execute(app.get_db_conn())
+    .await
+    .unwrap();
+    // Unsubscribed without ever confirming: not a conversion.
+    add_subscriber_to_list(&app, "never@example.com", "unsubscribed", DEFAULT_LIST_ID).await;
+    let weekly = create_list(&app, &serde_json::json!({ "name": "Weekly" })).await;
+    add_subscriber_to_list(&app, "weekly@example.com", "pending_confirmation", weekly).await;
+    let today = Utc::now().date_naive();
+
+    let (_, body) = get_analytics(
+        &app,
+        &format!(
+            "/admin/analytics/subscribers?from={}&to={}&list_id={}",
+            today, today, DEFAULT_LIST_ID
+        ),
+    )
+    .await;
+    assert_eq!(
+        body["conversion"],
+        serde_json::json!({"signups": 2, "confirmed": 1, "rate": 0.5})
+    );
+
+    let (_, body) = get_analytics(
+        &app,
+        &format!("/admin/analytics/subscribers?from={}&to={}", today, today),
+    )
+    .await;
+    assert_eq!(body["conversion"]["signups"], 3);
+    assert_eq!(body["conversion"]["confirmed"], 1);
+}
+
+#[actix_web::test]
+async fn subscriber_analytics_cover_every_day_of_the_range() {
+    let app = TestApp::new().await;
+
+    let (status, body) = get_analytics(
+        &app,
+        "/admin/analytics/subscribers?from=2023-01-30&to=2023-02-02",
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let dates: Vec<_> = body["daily"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|day| day["date"].as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(
+        dates,
+        vec!["2023-01-30", "2023-01-31", "2023-02-01", "2023-02-02"]
+    );
+    assert_eq!(body["conversion"]["rate"], 0.0);
+}
+
+#[actix_web::test]
+async fn invalid_date_ranges_are_rejected_with_400() {
+    let app = TestApp::new().await;
+
+    let test_cases = vec![
+        ("from=2023-02-02&to=2023-02-01", "a reversed range"),
+        ("from=2020-01-01&to=2023-01-01", "a range that is too long"),
+        ("from=yesterday", "a malformed date"),
+    ];
+    for (query, description) in test_cases {
+        let (status, _) =
+            get_analytics(&app, &format!("/admin/analytics/subscribers?{}", query)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", description);
+    }
+}
+
+#[actix_web::test]
+async fn issue_analytics_report_open_click_and_unsubscribe_rates() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app).await;
+    add_pending_subscriber(&app, "pending@example.com").await;
+    let pool = app.get_db_conn();
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, list_id, title, text_content, html_content, slug, published_at
+        )
+        VALUES ($1, $2, 'Issue', 'text', 'html', 'issue', NOW())
+        "#,
+        issue_id,
+        DEFAULT_LIST_ID,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    // Two deliveries sent, one of them opened, clicked and followed by an unsubscribe.
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (newsletter_issue_id, subscriber_id, status, open_count)
+        SELECT $1, id, 'sent', CASE WHEN status = 'confirmed' THEN 2 ELSE 0 END
+        FROM subscriptions
+        "#,
+        issue_id,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        WITH link AS (
+            INSERT INTO issue_links (newsletter_issue_id, url)
+            VALUES ($1, 'https://example.com')
+            RETURNING link_id
+        )
+        INSERT INTO link_clicks (link_id, subscriber_id)
+        SELECT link.link_id, s.id FROM link, subscriptions s WHERE s.status = 'confirmed'
+        "#,
+        issue_id,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_events (id, subscriber_id, event_type, newsletter_issue_id)
+        SELECT $1, id, 'unsubscribed', $2 FROM subscriptions WHERE status = 'confirmed'
+        "#,
+        Uuid::new_v4(),
+        issue_id,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+
+    let (status, body) = get_analytics(&app, "/admin/analytics/issues").await;
+
+    assert_eq!(status, StatusCode::OK);
+    let issue = &body["issues"][0];
+    assert_eq!(issue["newsletter_issue_id"], issue_id.to_string());
+    assert_eq!(issue["delivered"], 2);
+    assert_eq!(issue["opened"], 1);
+    assert_eq!(issue["open_rate"], 0.5);
+    assert_eq!(issue["clicked"], 1);
+    assert_eq!(issue["click_rate"], 0.5);
+    assert_eq!(issue["unsubscribed"], 1);
+    assert_eq!(issue["unsubscribe_rate"], 0.5);
+}
+
+#[actix_web::test]
+async fn sending_analytics_report_how_much_the_rate_limits_held_back() {
+    let app =
+        TestApp::with_config(|config| config.email_client.sends_per_second = Some(10.0)).await;
+    let server = app.get_server().await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(app.get_email_server())
+        .await;
+
+    let (status, body) = get_analytics(&app, "/admin/analytics/sending").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["throttled_sends"], 0);
+
+    for email in ["ann@example.com", "bob@example.com"] {
+        let form = &[("email", email), ("name", "Testing tester")];
+        let resp = test::call_service(&server, post_subscription_request(form)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    let (_, body) = get_analytics(&app, "/admin/analytics/sending").await;
+    assert_eq!(body["throttled_sends"], 1);
+    assert!(body["total_wait_ms"].as_u64().unwrap() > 0);
+}
@@ -0,0 +1,150 @@
+use actix_web::{http::StatusCode, test};
+use uuid::Uuid;
+use zero2prod::mailing_list::DEFAULT_LIST_ID;
+
+use crate::{
+    helpers::{add_test_user, send_issue, with_basic_auth},
+    init::TestApp,
+};
+
+async fn get_page(app: &TestApp, uri: &str) -> (StatusCode, String) {
+    let server = app.get_server().await;
+    let req = test::TestRequest::get().uri(uri).to_request();
+    let resp = test::call_service(&server, req).await;
+    let status = resp.status();
+    let body = test::read_body(resp).await;
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[actix_web::test]
+async fn public_sent_issues_are_listed_and_served_with_caching_headers() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let slug = send_issue(&app, "First issue!", true).await;
+    assert_eq!(slug, "first-issue");
+
+    let (status, list) = get_page(&app, "/archive").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(list.contains(r#"<a href="/archive/first-issue">First issue!</a>"#));
+
+    let req = test::TestRequest::get()
+        .uri("/archive/first-issue")
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("cache-control").unwrap(),
+        "public, max-age=3600"
+    );
+    assert!(resp.headers().get("last-modified").is_some());
+    let body = test::read_body(resp).await;
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("<p>Hello reader ()!</p>"));
+    assert!(!body.contains("/t/o/"));
+    assert!(!body.contains("/t/c/"));
+}
+
+#[actix_web::test]
+async fn private_and_unsent_issues_are_not_archived() {
+    let app = TestApp::new().await;
+    let private_slug = send_issue(&app, "Private issue", false).await;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let req = test::TestRequest::post()
+        .uri("/admin/newsletters")
+        .set_json(serde_json::json!({
+            "title": "Draft issue",
+            "content": {"text": "Draft", "html": "<p>Draft</p>"},
+            "public": true
+        }))
+        .to_request();
+    test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+
+    let (_, list) = get_page(&app, "/archive").await;
+    assert!(!list.contains("Private issue"));
+    assert!(!list.contains("Draft issue"));
+    for slug in [private_slug.as_str(), "draft-issue", "unknown"] {
+        let (status, _) = get_page(&app, &format!("/archive/{}", slug)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{}", slug);
+    }
+}
+
+#[actix_web::test]
+async fn issues_sharing_a_title_get_distinct_slugs() {
+    let app = TestApp::new().await;
+
+    let first = send_issue(&app, "Weekly update", true).await;
+    let second = send_issue(&app, "Weekly update", true).await;
+
+    assert_eq!(first, "weekly-update");
+    assert_ne!(first, second);
+    assert!(second.starts_with("weekly-update-"));
+    let (status, _) = get_page(&app, &format!("/archive/{}", second)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn issues_sharing_a_title_created_together_get_distinct_slugs() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+
+    // Another issue with the same title is being saved, but not committed yet.
+    let mut transaction = app.get_db_conn().begin().await.unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, list_id, title, text_content, html_content, slug
+        )
+        VALUES ($1, $2, 'Weekly update', 'text', 'html', 'weekly-update')
+        "#,
+        Uuid::new_v4(),
+        DEFAULT_LIST_ID,
+    )
+    .execute(&mut *transaction)
+    .await
+    .unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/admin/newsletters")
+        .set_json(serde_json::json!({
+            "title": "Weekly update",
+            "content": { "text": "text", "html": "<p>html</p>" }
+        }))
+        .to_request();
+    let commit_later = async {
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        transaction.commit().await.unwrap();
+    };
+    let (resp, _) = tokio::join!(
+        test::call_service(&server, with_basic_auth(req, &username, &password)),
+        commit_later
+    );
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let slugs = sqlx::query!("SELECT slug FROM newsletter_issues ORDER BY slug")
+        .fetch_all(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(slugs.len(), 2);
+    assert_eq!(slugs[0].slug, "weekly-update");
+    assert!(slugs[1].slug.starts_with("weekly-update-"));
+}
+
+#[actix_web::test]
+async fn the_archive_is_paginated() {
+    let app = TestApp::new().await;
+    for i in 0..21 {
+        send_issue(&app, &format!("Issue {}", i), true).await;
+    }
+
+    let (_, first_page) = get_page(&app, "/archive").await;
+    assert_eq!(first_page.matches("<li>").count(), 20);
+    assert!(first_page.contains(r#"<a href="/archive?page=2">Older issues</a>"#));
+    assert!(!first_page.contains("Newer issues"));
+
+    let (_, second_page) = get_page(&app, "/archive?page=2").await;
+    assert_eq!(second_page.matches("<li>").count(), 1);
+    assert!(second_page.contains(r#"<a href="/archive?page=1">Newer issues</a>"#));
+    assert!(!second_page.contains("Older issues"));
+}
@@ -0,0 +1,168 @@
+use actix_web::{http::StatusCode, test};
+use chrono::Utc;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::bot_protection::is_proof_of_work;
+use zero2prod::configuration::BotProtectionSettings;
+
+use crate::{
+    helpers::{add_test_user, post_subscription_request, with_basic_auth},
+    init::TestApp,
+};
+
+async fn app_protected_by(bot_protection: BotProtectionSettings) -> TestApp {
+    TestApp::with_config(|config| config.signup.bot_protection = bot_protection).await
+}
+
+async fn subscriber_count(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .count
+}
+
+async fn get_challenge(app: &TestApp) -> serde_json::Value {
+    let server = app.get_server().await;
+    let req = test::TestRequest::get()
+        .uri("/subscriptions/challenge")
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    test::read_body_json(resp).await
+}
+
+/// Posts the form, returning the status and body of the response.
+async fn subscribe(app: &TestApp, form: &[(&str, &str)]) -> (StatusCode, Vec<u8>) {
+    let server = app.get_server().await;
+    let mut form = form.to_vec();
+    form.extend([("email", "test@testdomain.com"), ("name", "Testing tester")]);
+    let resp = test::call_service(&server, post_subscription_request(form)).await;
+    (resp.status(), test::read_body(resp).await.to_vec())
+}
+
+#[actix_web::test]
+async fn bots_filling_in_the_honeypot_are_answered_as_if_they_subscribed() {
+    let app = app_protected_by(BotProtectionSettings {
+        honeypot_field: Some("website".into()),
+        ..Default::default()
+    })
+    .await;
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(app.get_email_server())
+        .await;
+
+    let rejected = subscribe(&app, &[("website", "http://spam.example")]).await;
+    assert_eq!(subscriber_count(&app).await, 0);
+    let accepted = subscribe(&app, &[("website", "")]).await;
+    assert_eq!(subscriber_count(&app).await, 1);
+    assert_eq!(rejected, accepted);
+
+    // Rejections are counted in the subscriber analytics.
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let today = Utc::now().date_naive();
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/admin/analytics/subscribers?from={}&to={}",
+            today, today
+        ))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(
+        body["rejected_signups"],
+        serde_json::json!([{"reason": "honeypot", "count": 1}])
+    );
+}
+
+#[actix_web::test]
+async fn forms_submitted_too_quickly_without_a_token_or_with_a_used_one_are_turned_away() {
+    let app = app_protected_by(BotProtectionSettings {
+        min_fill_seconds: Some(1),
+        ..Default::default()
+    })
+    .await;
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(app.get_email_server())
+        .await;
+    let challenge = get_challenge(&app).await;
+    assert_eq!(challenge["proof_of_work_bits"], serde_json::Value::Null);
+    let form_token = challenge["form_token"].as_str().unwrap();
+
+    for form in [
+        vec![],
+        vec![("form_token", "123.abc.def")],
+        vec![("form_token", form_token)],
+    ] {
+        let (status, _) = subscribe(&app, &form).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    assert_eq!(subscriber_count(&app).await, 0);
+
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    let (status, _) = subscribe(&app, &[("form_token", form_token)]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(subscriber_count(&app).await, 1);
+
+    // A token is good for a single subscription: replaying it sends no other email.
+    let (status, _) = subscribe(&app, &[("form_token", form_token)]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let reasons = sqlx::query!("SELECT reason, count FROM rejected_signups ORDER BY reason")
+        .fetch_all(app.get_db_conn())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.reason, r.count))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reasons,
+        [
+            ("invalid_form_token".to_string(), 2),
+            ("reused_form_token".to_string(), 1),
+            ("too_fast".to_string(), 1)
+        ]
+    );
+}
+
+#[actix_web::test]
+async fn subscriptions_can_require_a_proof_of_work() {
+    let app = app_protected_by(BotProtectionSettings {
+        proof_of_work_bits: Some(8),
+        ..Default::default()
+    })
+    .await;
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(app.get_email_server())
+        .await;
+    let challenge = get_challenge(&app).await;
+    assert_eq!(challenge["proof_of_work_bits"], 8);
+    let form_token = challenge["form_token"].as_str().unwrap();
+    let nonce = (0u64..)
+        .map(|nonce| nonce.to_string())
+        .find(|nonce| is_proof_of_work(form_token, "test@testdomain.com", nonce, 8))
+        .unwrap();
+
+    subscribe(&app, &[("form_token", form_token)]).await;
+    assert_eq!(subscriber_count(&app).await, 0);
+
+    let form = [
+        ("form_token", form_token),
+        ("proof_of_work", nonce.as_str()),
+    ];
+    let (status, _) = subscribe(&app, &form).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(subscriber_count(&app).await, 1);
+}
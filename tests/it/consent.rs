@@ -0,0 +1,192 @@
+use actix_http::header;
+use actix_web::{http::StatusCode, test};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{
+        add_test_user, create_confirmed_subscriber, create_list, create_unconfirmed_subscriber,
+        get_confirmation_link, with_basic_auth,
+    },
+    init::TestApp,
+};
+
+async fn admin_get(app: &TestApp, uri: &str) -> (StatusCode, String) {
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let req = test::TestRequest::get().uri(uri).to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    let status = resp.status();
+    let body = test::read_body(resp).await;
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+async fn get_subscriber(app: &TestApp) -> serde_json::Value {
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    let (status, body) = admin_get(app, &format!("/admin/subscribers/{}", subscriber.id)).await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_str(&body).unwrap()
+}
+
+/// Subscribes `test@testdomain.com` from the `footer` form and follows the confirmation link.
+async fn subscribe_and_confirm(app: &TestApp, list_id: Option<Uuid>) {
+    let server = app.get_server().await;
+    let email_server = app.get_email_server();
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(email_server)
+        .await;
+
+    let list_id = list_id.map(|id| id.to_string()).unwrap_or_default();
+    let mut form = vec![
+        ("email", "test@testdomain.com"),
+        ("name", "Testing tester"),
+        ("source", "footer"),
+    ];
+    if !list_id.is_empty() {
+        form.push(("list_id", &list_id));
+    }
+    let req = test::TestRequest::post()
+        .uri("/subscriptions")
+        .insert_header((header::USER_AGENT, "Signing up/1.0"))
+        .peer_addr("203.0.113.7:4000".parse().unwrap())
+        .set_form(form)
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let email_request = email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let req = test::TestRequest::get()
+        .uri(&get_confirmation_link(&email_request.body))
+        .insert_header((header::USER_AGENT, "Confirming/2.0"))
+        .peer_addr("198.51.100.9:4000".parse().unwrap())
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn subscribing_and_confirming_record_the_consent() {
+    let app = TestApp::new().await;
+    subscribe_and_confirm(&app, None).await;
+
+    let subscriber = get_subscriber(&app).await;
+
+    assert_eq!(subscriber["email"], "test@testdomain.com");
+    assert_eq!(subscriber["memberships"][0]["status"], "confirmed");
+    let consents = subscriber["consents"].as_array().unwrap();
+    assert_eq!(consents.len(), 2);
+    assert_eq!(consents[0]["action"], "subscribe");
+    assert_eq!(consents[0]["ip_address"], "203.0.113.7");
+    assert_eq!(consents[0]["user_agent"], "Signing up/1.0");
+    assert_eq!(consents[0]["source"], "footer");
+    assert_eq!(consents[1]["action"], "confirm");
+    assert_eq!(consents[1]["ip_address"], "198.51.100.9");
+    assert_eq!(consents[1]["user_agent"], "Confirming/2.0");
+    assert_eq!(consents[1]["source"], serde_json::Value::Null);
+    assert_eq!(consents[0]["consent_text"], consents[1]["consent_text"]);
+    assert!(!consents[0]["consent_text"].as_str().unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn the_consent_text_of_the_list_is_recorded() {
+    let app = TestApp::new().await;
+    let text = "Yes, send me the weekly digest.";
+    let list_id = create_list(
+        &app,
+        &serde_json::json!({ "name": "Weekly", "consent_text": text }),
+    )
+    .await;
+    subscribe_and_confirm(&app, Some(list_id)).await;
+
+    let subscriber = get_subscriber(&app).await;
+    for consent in subscriber["consents"].as_array().unwrap() {
+        assert_eq!(consent["list_id"], list_id.to_string());
+        assert_eq!(consent["consent_text"], text);
+    }
+    let (_, body) = admin_get(&app, "/admin/lists").await;
+    let lists: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let weekly = lists
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|list| list["name"] == "Weekly")
+        .unwrap();
+    assert_eq!(weekly["consent_text"], text);
+}
+
+#[actix_web::test]
+async fn following_the_confirmation_link_again_records_nothing() {
+    let app = TestApp::new().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+    let server = app.get_server().await;
+
+    for _ in 0..2 {
+        let req = test::TestRequest::get()
+            .uri(&confirmation_link)
+            .to_request();
+        test::call_service(&server, req).await;
+    }
+
+    let subscriber = get_subscriber(&app).await;
+    assert_eq!(subscriber["consents"].as_array().unwrap().len(), 2);
+}
+
+#[actix_web::test]
+async fn consent_records_cannot_be_changed() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app).await;
+
+    let updated = sqlx::query!("UPDATE consent_records SET consent_text = 'Something else'")
+        .execute(app.get_db_conn())
+        .await;
+    let deleted = sqlx::query!("DELETE FROM consent_records")
+        .execute(app.get_db_conn())
+        .await;
+
+    assert!(updated.is_err());
+    assert!(deleted.is_err());
+}
+
+#[actix_web::test]
+async fn consent_records_are_exported() {
+    let app = TestApp::new().await;
+    subscribe_and_confirm(&app, None).await;
+
+    let (status, body) = admin_get(&app, "/admin/subscribers/export?format=ndjson").await;
+
+    assert_eq!(status, StatusCode::OK);
+    let line: serde_json::Value = serde_json::from_str(body.trim()).unwrap();
+    assert_eq!(line["consents"][0]["source"], "footer");
+    assert_eq!(line["consents"][1]["action"], "confirm");
+
+    let (_, body) = admin_get(&app, "/admin/subscribers/export?format=csv").await;
+    let mut reader = csv::Reader::from_reader(body.as_bytes());
+    let header = reader.headers().unwrap().clone();
+    let row = reader.records().next().unwrap().unwrap();
+    let column = |name| row[header.iter().position(|h| h == name).unwrap()].to_owned();
+    assert_eq!(column("consent_action"), "confirm");
+    assert_eq!(column("consent_ip_address"), "198.51.100.9");
+}
+
+#[actix_web::test]
+async fn unknown_subscribers_are_not_found() {
+    let app = TestApp::new().await;
+
+    let (status, _) = admin_get(&app, &format!("/admin/subscribers/{}", Uuid::new_v4())).await;
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
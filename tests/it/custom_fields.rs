@@ -0,0 +1,338 @@
+use actix_web::{http::StatusCode, test};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{
+        add_test_user, create_confirmed_subscriber, create_list, get_confirmation_link,
+        post_newsletter, post_subscription_request, with_basic_auth,
+    },
+    init::TestApp,
+};
+
+async fn admin_call(app: &TestApp, request: test::TestRequest) -> (StatusCode, serde_json::Value) {
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let resp = test::call_service(
+        &server,
+        with_basic_auth(request.to_request(), &username, &password),
+    )
+    .await;
+    let status = resp.status();
+    let body = test::read_body(resp).await;
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+async fn create_fields(app: &TestApp) {
+    for field in [
+        serde_json::json!({ "key": "company", "label": "Company", "field_type": "text", "required": true }),
+        serde_json::json!({ "key": "language", "label": "Language", "field_type": "enum", "options": ["en", "fr"] }),
+        serde_json::json!({ "key": "seats", "label": "Seats", "field_type": "number", "min": 1 }),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/admin/fields")
+            .set_json(field);
+        let (status, _) = admin_call(app, req).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
+
+async fn subscriber_id(app: &TestApp) -> uuid::Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .id
+}
+
+#[actix_web::test]
+async fn admins_can_define_custom_fields() {
+    let app = TestApp::new().await;
+    create_fields(&app).await;
+
+    let (status, body) = admin_call(&app, test::TestRequest::get().uri("/admin/fields")).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let keys: Vec<_> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|field| field["key"].as_str().unwrap())
+        .collect();
+    assert_eq!(keys, vec!["company", "language", "seats"]);
+    assert_eq!(body[1]["options"], serde_json::json!(["en", "fr"]));
+}
+
+#[actix_web::test]
+async fn invalid_field_definitions_are_rejected_with_400() {
+    let app = TestApp::new().await;
+    create_fields(&app).await;
+
+    let test_cases = vec![
+        (
+            serde_json::json!({ "key": "company", "label": "Company", "field_type": "text" }),
+            "a duplicate key",
+        ),
+        (
+            serde_json::json!({ "key": "email", "label": "Email", "field_type": "text" }),
+            "a reserved key",
+        ),
+        (
+            serde_json::json!({ "key": "source", "label": "Source", "field_type": "text" }),
+            "a field of the subscription form",
+        ),
+        (
+            serde_json::json!({ "key": "form_token", "label": "Token", "field_type": "text" }),
+            "a bot protection field of the subscription form",
+        ),
+        (
+            serde_json::json!({ "key": "plan", "label": "Plan", "field_type": "enum" }),
+            "an enum without options",
+        ),
+        (
+            serde_json::json!({ "key": "plan", "label": "Plan", "field_type": "color" }),
+            "an unknown type",
+        ),
+    ];
+    for (field, description) in test_cases {
+        let req = test::TestRequest::post()
+            .uri("/admin/fields")
+            .set_json(field);
+        let (status, _) = admin_call(&app, req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", description);
+    }
+}
+
+#[actix_web::test]
+async fn the_honeypot_field_cannot_be_a_custom_field() {
+    let app = TestApp::with_config(|config| {
+        config.signup.bot_protection.honeypot_field = Some("website".into())
+    })
+    .await;
+
+    let req = test::TestRequest::post().uri("/admin/fields").set_json(
+        serde_json::json!({ "key": "website", "label": "Website", "field_type": "text" }),
+    );
+    let (status, _) = admin_call(&app, req).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn custom_fields_are_accepted_on_signup() {
+    let app = TestApp::new().await;
+    create_fields(&app).await;
+    let server = app.get_server().await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(app.get_email_server())
+        .await;
+
+    let form = &[
+        ("email", "test@testdomain.com"),
+        ("name", "Testing tester"),
+        ("company", " ACME "),
+        ("language", "fr"),
+        ("seats", "12.0"),
+    ];
+    let resp = test::call_service(&server, post_subscription_request(form)).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let uri = format!("/admin/subscribers/{}/fields", subscriber_id(&app).await);
+    let (_, body) = admin_call(&app, test::TestRequest::get().uri(&uri)).await;
+    assert_eq!(
+        body,
+        serde_json::json!({ "company": "ACME", "language": "fr", "seats": "12" })
+    );
+}
+
+#[actix_web::test]
+async fn signing_up_a_known_address_only_sets_fields_and_tags_once_confirmed() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app).await;
+    create_fields(&app).await;
+    let monthly = create_list(&app, &serde_json::json!({ "name": "Monthly" }))
+        .await
+        .to_string();
+    let server = app.get_server().await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(app.get_email_server())
+        .await;
+    let fields_uri = format!("/admin/subscribers/{}/fields", subscriber_id(&app).await);
+    let tags_uri = format!("/admin/subscribers/{}/tags", subscriber_id(&app).await);
+
+    // Submitted again for the list they are confirmed on, nothing is kept.
+    let form = &[
+        ("email", "test@testdomain.com"),
+        ("name", "Testing tester"),
+        ("company", "Initech"),
+        ("tags", "vip"),
+    ];
+    let resp = test::call_service(&server, post_subscription_request(form)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let (_, fields) = admin_call(&app, test::TestRequest::get().uri(&fields_uri)).await;
+    assert_eq!(fields, serde_json::json!({}));
+
+    // Submitted for another list, they wait for the confirmation.
+    let form = &[
+        ("email", "test@testdomain.com"),
+        ("name", "Testing tester"),
+        ("company", "ACME"),
+        ("tags", "vip"),
+        ("list_id", &monthly),
+    ];
+    let resp = test::call_service(&server, post_subscription_request(form)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let (_, fields) = admin_call(&app, test::TestRequest::get().uri(&fields_uri)).await;
+    assert_eq!(fields, serde_json::json!({}));
+    let (_, tags) = admin_call(&app, test::TestRequest::get().uri(&tags_uri)).await;
+    assert_eq!(tags, serde_json::json!({ "tags": [] }));
+
+    let email_request = app
+        .get_email_server()
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let req = test::TestRequest::get()
+        .uri(&get_confirmation_link(&email_request.body))
+        .to_request();
+    test::call_service(&server, req).await;
+
+    let (_, fields) = admin_call(&app, test::TestRequest::get().uri(&fields_uri)).await;
+    assert_eq!(fields, serde_json::json!({ "company": "ACME" }));
+    let (_, tags) = admin_call(&app, test::TestRequest::get().uri(&tags_uri)).await;
+    assert_eq!(tags, serde_json::json!({ "tags": ["vip"] }));
+}
+
+#[actix_web::test]
+async fn signups_with_invalid_custom_fields_are_rejected_with_400() {
+    let app = TestApp::new().await;
+    create_fields(&app).await;
+    let server = app.get_server().await;
+
+    let test_cases = vec![
+        (vec![("language", "fr")], "a missing required field"),
+        (
+            vec![("company", "ACME"), ("language", "de")],
+            "an unknown option",
+        ),
+        (
+            vec![("company", "ACME"), ("seats", "0")],
+            "an out of range number",
+        ),
+    ];
+    for (fields, description) in test_cases {
+        let mut form = vec![("email", "test@testdomain.com"), ("name", "Testing tester")];
+        form.extend(fields);
+        let resp = test::call_service(&server, post_subscription_request(&form)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", description);
+    }
+}
+
+#[actix_web::test]
+async fn admins_can_edit_the_custom_fields_of_a_subscriber() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app).await;
+    create_fields(&app).await;
+    let uri = format!("/admin/subscribers/{}/fields", subscriber_id(&app).await);
+
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .set_json(serde_json::json!({ "company": "ACME", "seats": "3" }));
+    assert_eq!(admin_call(&app, req).await.0, StatusCode::OK);
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .set_json(serde_json::json!({ "seats": null, "language": "en" }));
+    assert_eq!(admin_call(&app, req).await.0, StatusCode::OK);
+
+    let (_, body) = admin_call(&app, test::TestRequest::get().uri(&uri)).await;
+    assert_eq!(
+        body,
+        serde_json::json!({ "company": "ACME", "language": "en" })
+    );
+
+    let test_cases = vec![
+        (
+            serde_json::json!({ "company": null }),
+            "clearing a required field",
+        ),
+        (serde_json::json!({ "plan": "pro" }), "an unknown field"),
+        (serde_json::json!({ "seats": "many" }), "an invalid value"),
+    ];
+    for (body, description) in test_cases {
+        let req = test::TestRequest::patch().uri(&uri).set_json(body);
+        let (status, _) = admin_call(&app, req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", description);
+    }
+}
+
+#[actix_web::test]
+async fn custom_fields_can_be_used_in_issues() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app).await;
+    create_fields(&app).await;
+    let uri = format!("/admin/subscribers/{}/fields", subscriber_id(&app).await);
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .set_json(serde_json::json!({ "company": "ACME & Co" }));
+    admin_call(&app, req).await;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(app.get_email_server())
+        .await;
+
+    let newsletter_body = serde_json::json!({
+        "title": "News for {{ company }}",
+        "content": {
+            "text": "Hello {{ company }}",
+            "html": "<p>Hello {{ company }}{{ language }}</p>"
+        }
+    });
+    let req = with_basic_auth(post_newsletter(&newsletter_body), &username, &password);
+    test::call_service(&server, req).await;
+    app.wait_for_deliveries().await;
+
+    let email_request = app
+        .get_email_server()
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let message = &body["Messages"][0];
+    assert_eq!(message["Subject"], "News for ACME & Co");
+    assert_eq!(message["TextPart"], "Hello ACME & Co");
+    assert_eq!(message["HTMLPart"], "<p>Hello ACME &amp; Co</p>");
+}
+
+#[actix_web::test]
+async fn deleting_a_field_removes_its_values() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app).await;
+    create_fields(&app).await;
+    let uri = format!("/admin/subscribers/{}/fields", subscriber_id(&app).await);
+    let req = test::TestRequest::patch()
+        .uri(&uri)
+        .set_json(serde_json::json!({ "company": "ACME", "language": "fr" }));
+    admin_call(&app, req).await;
+
+    let req = test::TestRequest::delete().uri("/admin/fields/language");
+    assert_eq!(admin_call(&app, req).await.0, StatusCode::OK);
+    let req = test::TestRequest::delete().uri("/admin/fields/language");
+    assert_eq!(admin_call(&app, req).await.0, StatusCode::NOT_FOUND);
+
+    let (_, body) = admin_call(&app, test::TestRequest::get().uri(&uri)).await;
+    assert_eq!(body, serde_json::json!({ "company": "ACME" }));
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use actix_web::{http::StatusCode, test};
+use tokio::net::UdpSocket;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::configuration::DnsCheckSettings;
+
+use crate::{helpers::post_subscription_request, init::TestApp};
+
+#[derive(Clone, Copy)]
+enum Zone {
+    WithMx,
+    WithOnlyA,
+    /// Says it receives no email with a "null MX" record.
+    WithNullMx,
+    /// Never answers.
+    Silent,
+}
+
+/// A stand-in DNS server answering from a table of domains, the others not existing.
+struct FakeDnsServer {
+    address: SocketAddr,
+    queries: Arc<AtomicUsize>,
+}
+
+impl FakeDnsServer {
+    async fn start(zones: &[(&str, Zone)]) -> Self {
+        let zones: HashMap<String, Zone> = zones
+            .iter()
+            .map(|(domain, zone)| (domain.to_string(), *zone))
+            .collect();
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+        let queries = Arc::new(AtomicUsize::new(0));
+        let counter = queries.clone();
+        tokio::spawn(async move {
+            let mut buffer = [0; 512];
+            loop {
+                let (length, peer) = socket.recv_from(&mut buffer).await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                if let Some(response) = answer(&buffer[..length], &zones) {
+                    socket.send_to(&response, peer).await.unwrap();
+                }
+            }
+        });
+        Self { address, queries }
+    }
+
+    fn queries(&self) -> usize {
+        self.queries.load(Ordering::SeqCst)
+    }
+}
+
+fn answer(query: &[u8], zones: &HashMap<String, Zone>) -> Option<Vec<u8>> {
+    let mut labels = vec![];
+    let mut at = 12;
+    while query[at] != 0 {
+        let length = query[at] as usize;
+        labels.push(String::from_utf8(query[at + 1..at + 1 + length].to_vec()).unwrap());
+        at += 1 + length;
+    }
+    let record_type = u16::from_be_bytes([query[at + 1], query[at + 2]]);
+    let question_end = at + 5;
+
+    let (rcode, record): (u8, Option<&[u8]>) = match zones.get(&labels.join(".")) {
+        None => (3, None),
+        Some(Zone::Silent) => return None,
+        Some(Zone::WithMx) if record_type == 15 => (0, Some(b"\x00\x0a\x04mail\xc0\x0c")),
+        Some(Zone::WithOnlyA) if record_type == 1 => (0, Some(&[127, 0, 0, 1])),
+        Some(Zone::WithNullMx) if record_type == 15 => (0, Some(b"\x00\x00\x00")),
+        Some(_) => (0, None),
+    };
+    let mut response = query[..question_end].to_vec();
+    response[2] = 0x81;
+    response[3] = 0x80 | rcode;
+    if let Some(data) = record {
+        response[7] = 1;
+        response.extend_from_slice(&[0xC0, 12]);
+        response.extend_from_slice(&record_type.to_be_bytes());
+        response.extend_from_slice(&[0, 1, 0, 0, 1, 0]);
+        response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        response.extend_from_slice(data);
+    }
+    Some(response)
+}
+
+async fn app_checking_with(dns_server: &FakeDnsServer) -> TestApp {
+    let resolver = dns_server.address;
+    let app = TestApp::with_config(|config| {
+        config.signup.dns_check = Some(DnsCheckSettings {
+            resolver,
+            timeout_ms: 200,
+            cache_seconds: 60,
+        });
+    })
+    .await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(app.get_email_server())
+        .await;
+    app
+}
+
+fn subscription(email: &str) -> actix_http::Request {
+    post_subscription_request([("email", email), ("name", "Testing tester")])
+}
+
+#[actix_web::test]
+async fn domains_with_mail_servers_are_accepted() {
+    let dns_server = FakeDnsServer::start(&[
+        ("testdomain.com", Zone::WithMx),
+        ("small.example", Zone::WithOnlyA),
+    ])
+    .await;
+    let app = app_checking_with(&dns_server).await;
+    let server = app.get_server().await;
+
+    for email in ["test@testdomain.com", "test@small.example"] {
+        let resp = test::call_service(&server, subscription(email)).await;
+        assert_eq!(resp.status(), StatusCode::OK, "{} was rejected", email);
+    }
+    // One query for the MX records, and two when falling back to A records.
+    assert_eq!(dns_server.queries(), 3);
+
+    // Answers are cached.
+    let resp = test::call_service(&server, subscription("other@testdomain.com")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(dns_server.queries(), 3);
+}
+
+#[actix_web::test]
+async fn domains_without_mail_servers_are_rejected_with_a_suggestion() {
+    let dns_server = FakeDnsServer::start(&[
+        ("gmail.com", Zone::WithMx),
+        ("nomail.example", Zone::WithNullMx),
+    ])
+    .await;
+    let app = app_checking_with(&dns_server).await;
+    let server = app.get_server().await;
+
+    for (email, explanation) in [
+        (
+            "test@gmial.com",
+            "gmial.com does not receive emails, did you mean gmail.com?",
+        ),
+        (
+            "test@unknown.example",
+            "unknown.example does not receive emails",
+        ),
+        (
+            "test@nomail.example",
+            "nomail.example does not receive emails",
+        ),
+    ] {
+        let resp = test::call_service(&server, subscription(email)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert_eq!(body, explanation);
+    }
+    let count = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
+
+#[actix_web::test]
+async fn domains_are_accepted_when_the_resolver_does_not_answer() {
+    let dns_server = FakeDnsServer::start(&[("testdomain.com", Zone::Silent)]).await;
+    let app = app_checking_with(&dns_server).await;
+    let server = app.get_server().await;
+
+    let resp = test::call_service(&server, subscription("test@testdomain.com")).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(dns_server.queries(), 1);
+}
@@ -0,0 +1,247 @@
+use actix_web::{http::StatusCode, test};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{
+        add_test_user, assert_is_redirect_to, create_confirmed_subscriber, get_confirmation_link,
+        with_basic_auth,
+    },
+    init::TestApp,
+};
+
+async fn preferences_token(app: &TestApp) -> String {
+    sqlx::query!("SELECT preferences_token FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .preferences_token
+}
+
+async fn subscriber_emails(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT email FROM subscriptions ORDER BY email")
+        .fetch_all(app.get_db_conn())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.email)
+        .collect()
+}
+
+fn post_email_change(token: &str, email: &str) -> actix_http::Request {
+    test::TestRequest::post()
+        .uri("/preferences/email")
+        .set_form([("token", token), ("email", email)])
+        .to_request()
+}
+
+#[actix_web::test]
+async fn subscribers_can_move_to_a_verified_address() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    let token = preferences_token(&app).await;
+    let email_server = app.get_email_server();
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount_as_scoped(email_server)
+        .await;
+
+    let resp = test::call_service(&server, post_email_change(&token, "new@example.com")).await;
+    assert_is_redirect_to(&resp, &format!("/preferences?token={}", token));
+    // Nothing changes until the new address is verified.
+    assert_eq!(subscriber_emails(&app).await, ["test@testdomain.com"]);
+
+    let verification = email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let link = get_confirmation_link(&verification.body);
+    let verification: serde_json::Value = serde_json::from_slice(&verification.body).unwrap();
+    assert_eq!(
+        verification["Messages"][0]["To"][0]["Email"],
+        "new@example.com"
+    );
+    let resp = test::call_service(&server, test::TestRequest::get().uri(&link).to_request()).await;
+    assert_is_redirect_to(&resp, &format!("/preferences?token={}", token));
+    assert_eq!(subscriber_emails(&app).await, ["new@example.com"]);
+
+    let notice = email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let notice: serde_json::Value = serde_json::from_slice(&notice.body).unwrap();
+    assert_eq!(
+        notice["Messages"][0]["To"][0]["Email"],
+        "test@testdomain.com"
+    );
+
+    // The link only works once.
+    let resp = test::call_service(&server, test::TestRequest::get().uri(&link).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn an_address_taken_in_the_meantime_is_not_stolen() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    let token = preferences_token(&app).await;
+    let email_server = app.get_email_server();
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(email_server)
+        .await;
+
+    test::call_service(&server, post_email_change(&token, "new@example.com")).await;
+    let verification = email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let link = get_confirmation_link(&verification.body);
+    let resp = test::call_service(
+        &server,
+        test::TestRequest::post()
+            .uri("/subscriptions")
+            .set_form([("name", "Someone else"), ("email", "new@example.com")])
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = test::call_service(&server, test::TestRequest::get().uri(&link).to_request()).await;
+    assert_is_redirect_to(&resp, &format!("/preferences?token={}", token));
+    assert_eq!(
+        subscriber_emails(&app).await,
+        ["new@example.com", "test@testdomain.com"]
+    );
+}
+
+#[actix_web::test]
+async fn invalid_email_change_requests_are_rejected() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    let token = preferences_token(&app).await;
+
+    for email in ["not an address", "test@testdomain.com"] {
+        let resp = test::call_service(&server, post_email_change(&token, email)).await;
+        assert_is_redirect_to(&resp, &format!("/preferences?token={}", token));
+    }
+    let resp = test::call_service(&server, post_email_change("unknown", "new@example.com")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(
+        &server,
+        test::TestRequest::get()
+            .uri("/preferences/email/verify?token=unknown")
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let pending = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM email_change_requests")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(pending, 0);
+}
+
+#[actix_web::test]
+async fn subscriptions_cannot_move_to_addresses_that_could_not_sign_up() {
+    let app = TestApp::with_config(|config| config.signup.reject_role_addresses = true).await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    let token = preferences_token(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .id;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount_as_scoped(app.get_email_server())
+        .await;
+
+    let resp = test::call_service(&server, post_email_change(&token, "noreply@example.com")).await;
+    assert_is_redirect_to(&resp, &format!("/preferences?token={}", token));
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/subscribers/{}/change_email",
+            subscriber_id
+        ))
+        .set_json(serde_json::json!({"email": "noreply@example.com"}))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let pending = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM email_change_requests")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(pending, 0);
+}
+
+#[actix_web::test]
+async fn admins_can_start_an_email_change() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .id;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let email_server = app.get_email_server();
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount_as_scoped(email_server)
+        .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/subscribers/{}/change_email",
+            subscriber_id
+        ))
+        .set_json(serde_json::json!({"email": "new@example.com"}))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    // The admin cannot skip the verification.
+    assert_eq!(subscriber_emails(&app).await, ["test@testdomain.com"]);
+
+    let verification = email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let link = get_confirmation_link(&verification.body);
+    test::call_service(&server, test::TestRequest::get().uri(&link).to_request()).await;
+    assert_eq!(subscriber_emails(&app).await, ["new@example.com"]);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/admin/audit_log?subscriber_id={}", subscriber_id))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    let log: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(log["entries"][0]["action"], "request_email_change");
+}
@@ -0,0 +1,166 @@
+use actix_web::{http::StatusCode, test};
+use uuid::Uuid;
+
+use crate::{
+    helpers::{add_test_user, create_unconfirmed_subscriber, with_basic_auth},
+    init::TestApp,
+};
+
+async fn admin_call(app: &TestApp, request: test::TestRequest) -> (StatusCode, String) {
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let resp = test::call_service(
+        &server,
+        with_basic_auth(request.to_request(), &username, &password),
+    )
+    .await;
+    let status = resp.status();
+    let body = test::read_body(resp).await;
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+/// Imports confirmed subscribers with a `company` field, tagging Ann as `vip`.
+async fn create_subscribers(app: &TestApp) {
+    let req = test::TestRequest::post().uri("/admin/fields").set_json(
+        serde_json::json!({ "key": "company", "label": "Company", "field_type": "text" }),
+    );
+    let (status, _) = admin_call(app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    let req = test::TestRequest::post()
+        .uri("/admin/subscribers/import?mode=confirmed&consent=Tests")
+        .set_payload("email,name,company\nann@example.com,Ann,Acme\nbob@example.com,Bob,\n");
+    let (status, _) = admin_call(app, req).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let ann = sqlx::query!("SELECT id FROM subscriptions WHERE email = 'ann@example.com'")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/subscribers/{}/tags", ann.id))
+        .set_json(serde_json::json!({ "tags": ["vip", "beta"] }));
+    let (status, _) = admin_call(app, req).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn subscribers_are_exported_as_csv_with_their_tags_and_fields() {
+    let app = TestApp::new().await;
+    create_subscribers(&app).await;
+
+    let (status, body) = admin_call(
+        &app,
+        test::TestRequest::get().uri("/admin/subscribers/export?format=csv"),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let mut reader = csv::Reader::from_reader(body.as_bytes());
+    let header: Vec<_> = reader.headers().unwrap().iter().map(String::from).collect();
+    assert_eq!(
+        header,
+        [
+            "id",
+            "email",
+            "name",
+            "status",
+            "subscribed_at",
+            "confirmed_at",
+            "tags",
+            "consent_action",
+            "consent_recorded_at",
+            "consent_ip_address",
+            "consent_user_agent",
+            "consent_source",
+            "consent_text",
+            "company"
+        ]
+    );
+    let rows: Vec<_> = reader.records().map(Result::unwrap).collect();
+    assert_eq!(rows.len(), 2);
+    let ann = rows.iter().find(|r| &r[1] == "ann@example.com").unwrap();
+    assert_eq!(&ann[3], "confirmed");
+    assert!(!ann[5].is_empty());
+    assert_eq!(&ann[6], "beta,vip");
+    assert_eq!(&ann[13], "Acme");
+}
+
+#[actix_web::test]
+async fn segments_narrow_down_ndjson_exports() {
+    let app = TestApp::new().await;
+    create_subscribers(&app).await;
+    let req = test::TestRequest::post()
+        .uri("/admin/segments")
+        .set_json(serde_json::json!({ "name": "VIPs", "filter": { "include_tags": ["vip"] } }));
+    let (status, body) = admin_call(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let segment_id = body["segment_id"].as_str().unwrap();
+
+    let (status, body) = admin_call(
+        &app,
+        test::TestRequest::get().uri(&format!(
+            "/admin/subscribers/export?format=ndjson&segment_id={}",
+            segment_id
+        )),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let lines: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["email"], "ann@example.com");
+    assert_eq!(lines[0]["tags"], serde_json::json!(["beta", "vip"]));
+    assert_eq!(lines[0]["fields"]["company"], "Acme");
+}
+
+#[actix_web::test]
+async fn unconfirmed_subscribers_are_exported_with_their_status() {
+    let app = TestApp::new().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    let (status, body) = admin_call(
+        &app,
+        test::TestRequest::get().uri("/admin/subscribers/export?format=ndjson"),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let line: serde_json::Value = serde_json::from_str(body.trim()).unwrap();
+    assert_eq!(line["status"], "pending_confirmation");
+    assert_eq!(line["confirmed_at"], serde_json::Value::Null);
+}
+
+#[actix_web::test]
+async fn exports_of_unknown_lists_or_segments_are_rejected() {
+    let app = TestApp::new().await;
+
+    for parameter in ["list_id", "segment_id"] {
+        let (status, _) = admin_call(
+            &app,
+            test::TestRequest::get().uri(&format!(
+                "/admin/subscribers/export?{}={}",
+                parameter,
+                Uuid::new_v4()
+            )),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
+
+#[actix_web::test]
+async fn exports_require_authentication() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+
+    let req = test::TestRequest::get()
+        .uri("/admin/subscribers/export")
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
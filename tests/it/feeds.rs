@@ -0,0 +1,95 @@
+use actix_web::{http::StatusCode, test};
+
+use crate::{helpers::send_issue, init::TestApp};
+
+#[actix_web::test]
+async fn feeds_list_public_sent_issues_with_escaped_content() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    send_issue(&app, "Tips & tricks", true).await;
+    send_issue(&app, "Private issue", false).await;
+    let issue = sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE slug = 'tips-tricks'"
+    )
+    .fetch_one(app.get_db_conn())
+    .await
+    .unwrap();
+
+    let test_cases = vec![
+        ("/feed.rss", "application/rss+xml; charset=utf-8"),
+        ("/feed.atom", "application/atom+xml; charset=utf-8"),
+    ];
+    for (uri, content_type) in test_cases {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let resp = test::call_service(&server, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK, "{}", uri);
+        assert_eq!(resp.headers().get("content-type").unwrap(), content_type);
+        let body = test::read_body(resp).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("<title>Tips &amp; tricks</title>"), "{}", uri);
+        assert!(body.contains(&format!("urn:uuid:{}", issue.newsletter_issue_id)));
+        assert!(body.contains("/archive/tips-tricks"));
+        assert!(
+            body.contains("&lt;p&gt;Hello reader ()!&lt;/p&gt;"),
+            "{}",
+            uri
+        );
+        assert!(!body.contains("Private issue"), "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn feeds_answer_conditional_requests_with_304() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    send_issue(&app, "First issue", true).await;
+
+    for uri in ["/feed.rss", "/feed.atom"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let resp = test::call_service(&server, req).await;
+        let etag = resp.headers().get("etag").unwrap().clone();
+        let last_modified = resp.headers().get("last-modified").unwrap().clone();
+
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("If-None-Match", etag.clone()))
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED, "{}", uri);
+        assert!(test::read_body(resp).await.is_empty());
+
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("If-Modified-Since", last_modified))
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED, "{}", uri);
+
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("If-None-Match", "\"stale\""))
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), StatusCode::OK, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn feeds_change_when_an_issue_is_published() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    send_issue(&app, "First issue", true).await;
+    let req = test::TestRequest::get().uri("/feed.atom").to_request();
+    let resp = test::call_service(&server, req).await;
+    let etag = resp.headers().get("etag").unwrap().clone();
+
+    send_issue(&app, "Second issue", true).await;
+
+    let req = test::TestRequest::get()
+        .uri("/feed.atom")
+        .insert_header(("If-None-Match", etag))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
@@ -1,6 +1,4 @@
-use std::collections::HashSet;
-
-use actix_http::{body::BoxBody, Request};
+use actix_http::{body::BoxBody, header::HeaderValue, Request};
 use actix_web::{dev::ServiceResponse, http, test};
 use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
 use base64::{engine::general_purpose, Engine};
@@ -8,6 +6,12 @@ use serde::Serialize;
 use sqlx::PgPool;
 use tracing_actix_web::StreamSpan;
 use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::init::TestApp;
 
 pub fn get_confirmation_link(body: &[u8]) -> String {
     let body: serde_json::Value = serde_json::from_slice(body).unwrap();
@@ -86,3 +90,80 @@ pub fn assert_is_redirect_to(response: &ServiceResponse<StreamSpan<BoxBody>>, lo
     assert_eq!(response.status(), http::StatusCode::SEE_OTHER);
     assert_eq!(response.headers().get("location").unwrap(), location);
 }
+
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> String {
+    let server = app.get_server().await;
+    let email_server = app.get_email_server();
+
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(email_server)
+        .await;
+
+    let form = &[("email", "test@testdomain.com"), ("name", "Testing tester")];
+    let req = post_subscription_request(form);
+
+    test::call_service(&server, req).await;
+    let email_request = &email_server.received_requests().await.unwrap()[0];
+    get_confirmation_link(&email_request.body)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    let server = app.get_server().await;
+
+    let req = test::TestRequest::get()
+        .uri(&confirmation_link)
+        .to_request();
+
+    test::call_service(&server, req).await;
+}
+
+pub fn with_basic_auth(mut request: Request, username: &str, password: &str) -> Request {
+    let header_value = HeaderValue::from_str(&basic_auth(username, password)).unwrap();
+    request
+        .headers_mut()
+        .insert(actix_http::header::AUTHORIZATION, header_value);
+    request
+}
+
+/// Sends an issue (to nobody) and returns its slug.
+pub async fn send_issue(app: &TestApp, title: &str, public: bool) -> String {
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let req = post_newsletter(&serde_json::json!({
+        "title": title,
+        "content": {
+            "text": "Hello {{ name }}!",
+            "html": "<p>Hello {{ name }} ({{ email }})!</p>"
+        },
+        "track_opens": true,
+        "track_clicks": true,
+        "public": public
+    }));
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    assert_eq!(resp.status(), http::StatusCode::ACCEPTED);
+    app.wait_for_deliveries().await;
+
+    sqlx::query!("SELECT slug FROM newsletter_issues ORDER BY created_at DESC LIMIT 1")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .slug
+}
+
+/// Creates a mailing list through the admin API and returns its ID.
+pub async fn create_list(app: &TestApp, body: &serde_json::Value) -> Uuid {
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let req = test::TestRequest::post()
+        .uri("/admin/lists")
+        .set_json(body)
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    body["list_id"].as_str().unwrap().parse().unwrap()
+}
@@ -0,0 +1,310 @@
+use actix_web::{http::StatusCode, test};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::send_queue::MAX_SEND_ATTEMPTS;
+
+use crate::{
+    helpers::{add_test_user, create_confirmed_subscriber, create_list, with_basic_auth},
+    init::TestApp,
+};
+
+async fn import(app: &TestApp, query: &str, csv: &str) -> (StatusCode, serde_json::Value) {
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/subscribers/import?{}", query))
+        .insert_header(("Content-Type", "text/csv"))
+        .set_payload(csv.to_owned())
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    let status = resp.status();
+    let body = test::read_body(resp).await;
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+async fn create_company_field(app: &TestApp) {
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let req = test::TestRequest::post()
+        .uri("/admin/fields")
+        .set_json(serde_json::json!({ "key": "company", "label": "Company", "field_type": "text" }))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn confirmed_imports_add_valid_rows_and_report_the_others() {
+    let app = TestApp::new().await;
+    create_company_field(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let csv = "Email,Name,Company\r\n\
+        ann@example.com,Ann,Acme\r\n\
+        not-an-email,Bob,\r\n\
+        test@testdomain.com,Already There,\r\n\
+        carl@example.com,\"Carl, Jr\",\r\n\
+        ann@example.com,Ann again,\r\n\
+        dora@example.com,,\r\n";
+
+    let (status, body) = import(
+        &app,
+        "mode=confirmed&consent=Signed%20up%20at%20the%202026%20conference",
+        csv,
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["imported"], 2);
+    assert_eq!(body["duplicates"], 2);
+    let rows: Vec<_> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["row"].as_u64().unwrap())
+        .collect();
+    assert_eq!(rows, [3, 7]);
+
+    let ann = sqlx::query!(
+        r#"
+        SELECT s.status, m.status AS membership, v.value, i.consent_attestation
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        JOIN subscriber_field_values v ON v.subscriber_id = s.id
+        JOIN subscriber_imports i ON i.import_id = s.import_id
+        WHERE s.email = 'ann@example.com'
+        "#,
+    )
+    .fetch_one(app.get_db_conn())
+    .await
+    .unwrap();
+    assert_eq!(ann.status, "confirmed");
+    assert_eq!(ann.membership, "confirmed");
+    assert_eq!(ann.value, "Acme");
+    assert_eq!(
+        ann.consent_attestation.as_deref(),
+        Some("Signed up at the 2026 conference")
+    );
+    let carl = sqlx::query!("SELECT name FROM subscriptions WHERE email = 'carl@example.com'")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(carl.name, "Carl, Jr");
+    let existing =
+        sqlx::query!("SELECT name FROM subscriptions WHERE email = 'test@testdomain.com'")
+            .fetch_one(app.get_db_conn())
+            .await
+            .unwrap();
+    assert_eq!(existing.name, "Testing tester");
+}
+
+#[actix_web::test]
+async fn known_subscribers_are_added_to_the_list_they_are_imported_into() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app).await;
+    let list_id = create_list(&app, &serde_json::json!({ "name": "Events" })).await;
+
+    let (status, body) = import(
+        &app,
+        &format!(
+            "mode=confirmed&consent=Ticked%20the%20box&list_id={}",
+            list_id
+        ),
+        "email,name\nTest@TestDomain.com,Someone Else\n",
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["imported"], 1);
+    assert_eq!(body["duplicates"], 0);
+    let membership = sqlx::query!(
+        r#"
+        SELECT m.status, s.name, c.action, c.consent_text
+        FROM list_memberships m
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        JOIN consent_records c ON c.subscriber_id = s.id AND c.list_id = m.list_id
+        WHERE m.list_id = $1
+        "#,
+        list_id,
+    )
+    .fetch_one(app.get_db_conn())
+    .await
+    .unwrap();
+    assert_eq!(membership.status, "confirmed");
+    assert_eq!(membership.name, "Testing tester");
+    assert_eq!(membership.action, "import");
+    assert_eq!(membership.consent_text, "Ticked the box");
+
+    let (_, body) = import(
+        &app,
+        &format!("mode=double_opt_in&list_id={}", list_id),
+        "email,name\ntest@testdomain.com,Testing tester\n",
+    )
+    .await;
+    assert_eq!(body["imported"], 0);
+    assert_eq!(body["duplicates"], 1);
+}
+
+#[actix_web::test]
+async fn addresses_that_could_not_sign_up_are_not_imported() {
+    let app = TestApp::with_config(|config| config.signup.reject_role_addresses = true).await;
+    let csv = "email,name\n\
+        ann@example.com,Ann\n\
+        noreply@example.com,Robot\n";
+
+    let (status, body) = import(&app, "mode=confirmed&consent=Asked%20in%20person", csv).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["imported"], 1);
+    assert_eq!(body["errors"][0]["row"], 3);
+    assert_eq!(
+        body["errors"][0]["error"],
+        "noreply@ addresses cannot subscribe, please use a personal address"
+    );
+}
+
+#[actix_web::test]
+async fn confirmed_imports_require_a_consent_attestation() {
+    let app = TestApp::new().await;
+
+    for query in ["mode=confirmed", "mode=confirmed&consent=%20"] {
+        let (status, _) = import(&app, query, "email,name\nann@example.com,Ann\n").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    let count = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
+
+#[actix_web::test]
+async fn double_opt_in_imports_send_confirmation_emails() {
+    let app = TestApp::new().await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(app.get_email_server())
+        .await;
+
+    let (status, body) = import(
+        &app,
+        "mode=double_opt_in&columns=E-mail%20Address:email,Full%20Name:name",
+        "E-mail Address,Full Name,Notes\nann@example.com,Ann,x\nbob@example.com,Bob,y",
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["imported"], 2);
+    assert_eq!(body["confirmations_queued"], 2);
+    let statuses = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_all(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(statuses.len(), 2);
+    assert!(statuses.iter().all(|r| r.status == "pending_confirmation"));
+    app.wait_for_confirmations().await;
+    let sends = sqlx::query!("SELECT send_status FROM subscription_tokens")
+        .fetch_all(app.get_db_conn())
+        .await
+        .unwrap();
+    assert!(sends
+        .iter()
+        .all(|r| r.send_status.as_deref() == Some("sent")));
+}
+
+#[actix_web::test]
+async fn failed_confirmation_emails_do_not_fail_the_import() {
+    let app = TestApp::new().await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(400))
+        .expect(MAX_SEND_ATTEMPTS as u64)
+        .mount(app.get_email_server())
+        .await;
+
+    let (status, body) = import(
+        &app,
+        "mode=double_opt_in",
+        "email,name\nann@example.com,Ann",
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["imported"], 1);
+    assert_eq!(body["errors"], serde_json::json!([]));
+    app.wait_for_confirmations().await;
+    let send = sqlx::query!("SELECT send_status, send_attempts FROM subscription_tokens")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(send.send_status.as_deref(), Some("failed"));
+    assert_eq!(send.send_attempts, MAX_SEND_ATTEMPTS);
+}
+
+#[actix_web::test]
+async fn confirmation_emails_are_sent_again_when_the_provider_cannot_be_reached() {
+    let app = TestApp::new().await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(app.get_email_server())
+        .await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(app.get_email_server())
+        .await;
+
+    let (status, _) = import(
+        &app,
+        "mode=double_opt_in",
+        "email,name\nann@example.com,Ann",
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    app.wait_for_confirmations().await;
+    let send = sqlx::query!("SELECT send_status, send_attempts FROM subscription_tokens")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(send.send_status.as_deref(), Some("sent"));
+    assert_eq!(send.send_attempts, 2);
+}
+
+#[actix_web::test]
+async fn imports_with_unmapped_columns_are_rejected() {
+    let app = TestApp::new().await;
+
+    for (query, csv) in [
+        ("mode=double_opt_in", "address,name\nann@example.com,Ann\n"),
+        ("mode=double_opt_in&columns=missing:email", "email,name\n"),
+        ("mode=double_opt_in", ""),
+        ("mode=unknown", "email,name\n"),
+    ] {
+        let (status, _) = import(&app, query, csv).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", query);
+    }
+}
+
+#[actix_web::test]
+async fn imports_require_authentication() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+
+    let req = test::TestRequest::post()
+        .uri("/admin/subscribers/import?mode=double_opt_in")
+        .set_payload("email,name\nann@example.com,Ann\n")
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
@@ -8,9 +8,14 @@ use std::sync::Once;
 use tracing_actix_web::StreamSpan;
 use uuid::Uuid;
 use wiremock::MockServer;
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use zero2prod::address_policy::AddressPolicy;
+use zero2prod::bot_protection::BotProtection;
+use zero2prod::client_ip::TrustedProxies;
+use zero2prod::configuration::{get_configuration, DatabaseSettings, Settings};
 use zero2prod::email_client::EmailClient;
-use zero2prod::startup::{init_db, init_email_client, new_app, ApplicationBaseUrl, HmacSecret};
+use zero2prod::startup::{
+    init_db, init_email_client, new_app, ApplicationBaseUrl, HmacSecret, WebhookCredentials,
+};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 static TRACING: Once = Once::new();
@@ -21,11 +26,20 @@ pub struct TestApp {
     email_server: MockServer,
     base_url: ApplicationBaseUrl,
     hmac_secret: Secret<String>,
+    webhook_credentials: WebhookCredentials,
+    address_policy: AddressPolicy,
+    bot_protection: BotProtection,
+    trusted_proxies: TrustedProxies,
     // server: Box<dyn Service<Request, Response = ServiceResponse<StreamSpan<BoxBody>>, Error = actix_web::Error, Future = Box<dyn Future<Output = Result<ServiceResponse<StreamSpan<BoxBody>>, actix_web::Error>>>>>,
 }
 
 impl TestApp {
     pub async fn new() -> Self {
+        Self::with_config(|_| {}).await
+    }
+
+    /// Starts from the local configuration, with `customise` applied before anything is built.
+    pub async fn with_config(customise: impl FnOnce(&mut Settings)) -> Self {
         init_tracing();
         let email_server = MockServer::start().await;
 
@@ -33,6 +47,7 @@ impl TestApp {
         config.db.name = Uuid::new_v4().to_string();
         config.app.port = 0;
         config.email_client.base_url = email_server.uri();
+        customise(&mut config);
 
         init_test_db(&config.db).await;
 
@@ -40,6 +55,16 @@ impl TestApp {
         let email_client = init_email_client(&config.email_client);
         let base_url = ApplicationBaseUrl(config.app.base_url);
         let hmac_secret = config.app.hmac_secret;
+        let webhook_credentials = WebhookCredentials::from_settings(&config.email_client);
+        let address_policy = config
+            .signup
+            .address_policy()
+            .expect("Failed to load the signup domain lists");
+        let bot_protection = config
+            .signup
+            .bot_protection
+            .bot_protection(hmac_secret.clone());
+        let trusted_proxies = TrustedProxies(config.app.trusted_proxies);
 
         TestApp {
             db_pool,
@@ -47,6 +72,10 @@ impl TestApp {
             email_server,
             base_url,
             hmac_secret,
+            webhook_credentials,
+            address_policy,
+            bot_protection,
+            trusted_proxies,
         }
     }
 
@@ -60,7 +89,21 @@ impl TestApp {
         let email_client = Data::new(self.email_client.clone());
         let base_url = Data::new(self.base_url.clone());
         let hmac_secret = HmacSecret(self.hmac_secret.clone());
-        test::init_service(new_app(db_pool, email_client, base_url, hmac_secret)).await
+        let webhook_credentials = Data::new(self.webhook_credentials.clone());
+        let address_policy = Data::new(self.address_policy.clone());
+        let bot_protection = Data::new(self.bot_protection.clone());
+        let trusted_proxies = Data::new(self.trusted_proxies.clone());
+        test::init_service(new_app(
+            db_pool,
+            email_client,
+            base_url,
+            hmac_secret,
+            webhook_credentials,
+            address_policy,
+            bot_protection,
+            trusted_proxies,
+        ))
+        .await
     }
 
     pub fn get_email_server(&self) -> &MockServer {
@@ -70,6 +113,55 @@ impl TestApp {
     pub fn get_db_conn(&self) -> &PgPool {
         &self.db_pool
     }
+
+    pub fn get_webhook_credentials(&self) -> &WebhookCredentials {
+        &self.webhook_credentials
+    }
+
+    /// Waits for the issues being sent in the background to have no delivery left in the queue or
+    /// being sent.
+    pub async fn wait_for_deliveries(&self) {
+        for _ in 0..200 {
+            let queued = sqlx::query!(
+                r#"
+                SELECT COUNT(*) AS "count!"
+                FROM issue_deliveries
+                WHERE status IN ('queued', 'sending')
+                "#
+            )
+            .fetch_one(&self.db_pool)
+            .await
+            .unwrap()
+            .count;
+            if queued == 0 {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(25)).await;
+        }
+        panic!("Deliveries were still queued after 5 seconds");
+    }
+
+    /// Waits for the confirmation emails of imports to be sent.
+    pub async fn wait_for_confirmations(&self) {
+        for _ in 0..200 {
+            let queued = sqlx::query!(
+                r#"
+                SELECT COUNT(*) AS "count!"
+                FROM subscription_tokens
+                WHERE send_status IN ('queued', 'sending')
+                "#
+            )
+            .fetch_one(&self.db_pool)
+            .await
+            .unwrap()
+            .count;
+            if queued == 0 {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(25)).await;
+        }
+        panic!("Confirmation emails were still queued after 5 seconds");
+    }
 }
 
 fn init_tracing() {
@@ -0,0 +1,360 @@
+use actix_web::{http::StatusCode, test};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{
+        add_test_user, create_list, get_confirmation_link, post_newsletter,
+        post_subscription_request, with_basic_auth,
+    },
+    init::TestApp,
+};
+
+/// Subscribes `test@testdomain.com` to `list_id` and follows the confirmation link.
+async fn subscribe_and_confirm(app: &TestApp, list_id: Uuid) {
+    let server = app.get_server().await;
+    let email_server = app.get_email_server();
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(email_server)
+        .await;
+
+    let list_id = list_id.to_string();
+    let form = &[
+        ("email", "test@testdomain.com"),
+        ("name", "Testing tester"),
+        ("list_id", &list_id),
+    ];
+    let resp = test::call_service(&server, post_subscription_request(form)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let email_request = email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_link = get_confirmation_link(&email_request.body);
+    let req = test::TestRequest::get()
+        .uri(&confirmation_link)
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+async fn membership_statuses(app: &TestApp) -> Vec<(Uuid, String)> {
+    sqlx::query!("SELECT list_id, status FROM list_memberships ORDER BY subscribed_at")
+        .fetch_all(app.get_db_conn())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.list_id, r.status))
+        .collect()
+}
+
+#[actix_web::test]
+async fn subscribing_to_a_list_uses_its_sender_and_confirmation_template() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let list_id = create_list(
+        &app,
+        &serde_json::json!({
+            "name": "Weekly",
+            "sender_email": "weekly@example.com",
+            "confirmation": {
+                "subject": "Join {{ list_name }}",
+                "text": "Hi {{ name }}, confirm at {{ confirmation_link }}",
+                "html": "<a href=\"{{ confirmation_link }}\">Confirm</a>"
+            }
+        }),
+    )
+    .await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(app.get_email_server())
+        .await;
+
+    let list_id = list_id.to_string();
+    let form = &[
+        ("email", "test@testdomain.com"),
+        ("name", "Testing tester"),
+        ("list_id", &list_id),
+    ];
+    let resp = test::call_service(&server, post_subscription_request(form)).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let email_request = &app.get_email_server().received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let message = &body["Messages"][0];
+    assert_eq!(message["From"]["Email"], "weekly@example.com");
+    assert_eq!(message["Subject"], "Join Weekly");
+    assert!(message["TextPart"]
+        .as_str()
+        .unwrap()
+        .starts_with("Hi Testing tester, confirm at "));
+    get_confirmation_link(&email_request.body);
+}
+
+#[actix_web::test]
+async fn subscribing_to_an_unknown_list_is_rejected_with_400() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+
+    let list_id = Uuid::new_v4().to_string();
+    let form = &[
+        ("email", "test@testdomain.com"),
+        ("name", "Testing tester"),
+        ("list_id", &list_id),
+    ];
+    let resp = test::call_service(&server, post_subscription_request(form)).await;
+
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn confirmation_only_applies_to_the_list_subscribed_to() {
+    let app = TestApp::new().await;
+    let weekly = create_list(&app, &serde_json::json!({ "name": "Weekly" })).await;
+    let monthly = create_list(&app, &serde_json::json!({ "name": "Monthly" })).await;
+    subscribe_and_confirm(&app, weekly).await;
+
+    // Subscribing to a second list leaves the first one confirmed.
+    let server = app.get_server().await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(app.get_email_server())
+        .await;
+    let monthly_id = monthly.to_string();
+    let form = &[
+        ("email", "test@testdomain.com"),
+        ("name", "Testing tester"),
+        ("list_id", &monthly_id),
+    ];
+    test::call_service(&server, post_subscription_request(form)).await;
+
+    assert_eq!(
+        membership_statuses(&app).await,
+        vec![
+            (weekly, "confirmed".to_owned()),
+            (monthly, "pending_confirmation".to_owned()),
+        ]
+    );
+}
+
+#[actix_web::test]
+async fn issues_are_only_sent_to_the_confirmed_members_of_their_list() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let weekly = create_list(
+        &app,
+        &serde_json::json!({ "name": "Weekly", "sender_email": "weekly@example.com" }),
+    )
+    .await;
+    let monthly = create_list(&app, &serde_json::json!({ "name": "Monthly" })).await;
+    subscribe_and_confirm(&app, weekly).await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(app.get_email_server())
+        .await;
+    for list_id in [monthly, weekly] {
+        let newsletter_body = serde_json::json!({
+            "list_id": list_id,
+            "title": "Newsletter title",
+            "content": {
+                "text": "Unsubscribe: {{ unsubscribe_url }}",
+                "html": "<p>Newsletter content</p>"
+            }
+        });
+        let req = with_basic_auth(post_newsletter(&newsletter_body), &username, &password);
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        app.wait_for_deliveries().await;
+    }
+
+    let email_request = app
+        .get_email_server()
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Messages"][0]["From"]["Email"], "weekly@example.com");
+}
+
+#[actix_web::test]
+async fn publishing_to_an_unknown_list_is_rejected_with_400() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+
+    let newsletter_body = serde_json::json!({
+        "list_id": Uuid::new_v4(),
+        "title": "Newsletter title",
+        "content": { "text": "text", "html": "<p>html</p>" }
+    });
+    let req = with_basic_auth(post_newsletter(&newsletter_body), &username, &password);
+    let resp = test::call_service(&server, req).await;
+
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn the_unsubscribe_link_of_an_issue_only_leaves_its_list() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let weekly = create_list(&app, &serde_json::json!({ "name": "Weekly" })).await;
+    let monthly = create_list(&app, &serde_json::json!({ "name": "Monthly" })).await;
+    subscribe_and_confirm(&app, weekly).await;
+    subscribe_and_confirm(&app, monthly).await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(app.get_email_server())
+        .await;
+    let newsletter_body = serde_json::json!({
+        "list_id": weekly,
+        "title": "Newsletter title",
+        "content": {
+            "text": "Unsubscribe: {{ unsubscribe_url }}",
+            "html": "<p>Newsletter content</p>"
+        }
+    });
+    let req = with_basic_auth(post_newsletter(&newsletter_body), &username, &password);
+    test::call_service(&server, req).await;
+    app.wait_for_deliveries().await;
+    let email_request = app
+        .get_email_server()
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let unsubscribe_url = body["Messages"][0]["TextPart"]
+        .as_str()
+        .unwrap()
+        .strip_prefix("Unsubscribe: ")
+        .unwrap()
+        .to_owned();
+
+    assert_eq!(
+        body["Messages"][0]["Headers"],
+        serde_json::json!({
+            "List-Unsubscribe": format!("<{}>", unsubscribe_url),
+            "List-Unsubscribe-Post": "List-Unsubscribe=One-Click"
+        })
+    );
+
+    // Following the link only asks for confirmation, so scanners cannot unsubscribe anyone.
+    let req = test::TestRequest::get().uri(&unsubscribe_url).to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let page = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(page.contains("Weekly"));
+    assert!(page.contains(r#"method="post""#));
+    assert!(membership_statuses(&app)
+        .await
+        .iter()
+        .all(|(_, status)| status == "confirmed"));
+
+    // Mail clients unsubscribe in one click by posting to the link.
+    let req = test::TestRequest::post()
+        .uri(&unsubscribe_url)
+        .set_form([("List-Unsubscribe", "One-Click")])
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        membership_statuses(&app).await,
+        vec![
+            (weekly, "unsubscribed".to_owned()),
+            (monthly, "confirmed".to_owned()),
+        ]
+    );
+    let event = sqlx::query!(
+        r#"
+        SELECT list_id AS "list_id!", newsletter_issue_id AS "newsletter_issue_id!"
+        FROM subscription_events
+        WHERE event_type = 'unsubscribed'
+        "#
+    )
+    .fetch_one(app.get_db_conn())
+    .await
+    .unwrap();
+    assert_eq!(event.list_id, weekly);
+    let issue = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(event.newsletter_issue_id, issue.newsletter_issue_id);
+}
+
+#[actix_web::test]
+async fn unsubscribing_with_an_unknown_token_is_rejected_with_401() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+
+    for req in [
+        test::TestRequest::get(),
+        test::TestRequest::post().set_form([("List-Unsubscribe", "One-Click")]),
+    ] {
+        let req = req
+            .uri("/subscriptions/unsubscribe?token=unknown")
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[actix_web::test]
+async fn admins_can_list_the_mailing_lists_with_their_subscriber_counts() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let weekly = create_list(&app, &serde_json::json!({ "name": "Weekly" })).await;
+    subscribe_and_confirm(&app, weekly).await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+
+    let req = test::TestRequest::get().uri("/admin/lists").to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let lists = body.as_array().unwrap();
+    assert_eq!(lists.len(), 2);
+    assert_eq!(lists[0]["name"], "Newsletter");
+    assert_eq!(lists[1]["list_id"], weekly.to_string());
+    assert_eq!(lists[1]["confirmed"], 1);
+    assert_eq!(lists[1]["pending_confirmation"], 0);
+}
+
+#[actix_web::test]
+async fn creating_a_list_with_an_invalid_sender_is_rejected_with_400() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+
+    let req = test::TestRequest::post()
+        .uri("/admin/lists")
+        .set_json(serde_json::json!({ "name": "Weekly", "sender_email": "not-an-email" }))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
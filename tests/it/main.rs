@@ -1,7 +1,25 @@
+mod admin_newsletters;
+mod analytics;
+mod archive;
+mod bot_protection;
+mod consent;
+mod custom_fields;
+mod dns_check;
+mod email_change;
+mod exports;
+mod feeds;
 mod health_check;
 mod helpers;
+mod imports;
 mod init;
+mod lists;
 mod login;
 mod newsletter;
+mod preferences;
+mod privacy;
+mod segments;
+mod subscriber_management;
 mod subscriptions;
 mod subscriptions_confirm;
+mod tracking;
+mod webhooks;
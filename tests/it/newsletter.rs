@@ -4,11 +4,13 @@ use wiremock::{
     matchers::{any, method, path},
     Mock, ResponseTemplate,
 };
+use zero2prod::mailing_list::DEFAULT_LIST_ID;
+use zero2prod::send_queue::MAX_SEND_ATTEMPTS;
 
 use crate::{
     helpers::{
-        add_test_user, basic_auth, get_confirmation_link, post_newsletter,
-        post_subscription_request,
+        add_test_user, basic_auth, create_confirmed_subscriber, create_unconfirmed_subscriber,
+        post_newsletter, with_basic_auth,
     },
     init::TestApp,
 };
@@ -43,7 +45,8 @@ async fn newsletter_are_not_delivered_to_unconfirmed_subscribers() {
         .insert(actix_http::header::AUTHORIZATION, auth_header_val);
 
     let resp = test::call_service(&server, req).await;
-    assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+    assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+    app.wait_for_deliveries().await;
 }
 
 #[actix_web::test]
@@ -77,7 +80,8 @@ async fn newsletter_are_delivered_to_confirmed_subscribers() {
         .insert(actix_http::header::AUTHORIZATION, auth_header_val);
 
     let resp = test::call_service(&server, req).await;
-    assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+    assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+    app.wait_for_deliveries().await;
 }
 
 #[actix_web::test]
@@ -222,32 +226,211 @@ async fn invalid_password_is_rejected() {
     assert_eq!(Some(r#"Basic realm="publish""#), auth_header);
 }
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> String {
+#[actix_web::test]
+async fn deliveries_are_recorded_for_each_confirmed_subscriber() {
+    let app = TestApp::new().await;
     let server = app.get_server().await;
-    let email_server = app.get_email_server();
+    let pool = app.get_db_conn();
+
+    create_confirmed_subscriber(&app).await;
 
-    let _mock_guard = Mock::given(path("/v3.1/send"))
+    Mock::given(path("/v3.1/send"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Messages": [{"Status": "success", "To": [{"Email": "test@testdomain.com", "MessageID": 42}]}]
+        })))
         .expect(1)
-        .mount_as_scoped(email_server)
+        .mount(app.get_email_server())
         .await;
 
-    let form = &[("email", "test@testdomain.com"), ("name", "Testing tester")];
-    let req = post_subscription_request(form);
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter content",
+            "html": "<p>Newsletter content</p>"
+        }
+    });
 
-    test::call_service(&server, req).await;
-    let email_request = &email_server.received_requests().await.unwrap()[0];
-    get_confirmation_link(&email_request.body)
+    let (_, username, password) = add_test_user(pool).await;
+    let req = with_basic_auth(post_newsletter(&newsletter_body), &username, &password);
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+    app.wait_for_deliveries().await;
+
+    let delivery =
+        sqlx::query!("SELECT status, provider_message_id, attempts, sent_at FROM issue_deliveries")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+    assert_eq!(delivery.status, "sent");
+    assert_eq!(delivery.provider_message_id.as_deref(), Some("42"));
+    assert_eq!(delivery.attempts, 1);
+    assert!(delivery.sent_at.is_some());
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
+#[actix_web::test]
+async fn deliveries_are_retried_then_recorded_as_failed_without_aborting_the_send() {
+    let app = TestApp::new().await;
     let server = app.get_server().await;
+    let pool = app.get_db_conn();
 
-    let req = test::TestRequest::get()
-        .uri(&confirmation_link)
-        .to_request();
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(MAX_SEND_ATTEMPTS as u64)
+        .mount(app.get_email_server())
+        .await;
+
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter content",
+            "html": "<p>Newsletter content</p>"
+        }
+    });
+
+    let (_, username, password) = add_test_user(pool).await;
+    let req = with_basic_auth(post_newsletter(&newsletter_body), &username, &password);
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+    app.wait_for_deliveries().await;
+
+    let delivery = sqlx::query!("SELECT status, attempts, last_error FROM issue_deliveries")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "failed");
+    assert_eq!(delivery.attempts, MAX_SEND_ATTEMPTS);
+    assert!(delivery
+        .last_error
+        .unwrap()
+        .contains("Failed to send email to the subscriber test@testdomain.com"));
+}
+
+#[actix_web::test]
+async fn recipients_of_requests_that_went_through_are_not_sent_the_issue_again() {
+    // Every message gets its own request at this rate.
+    let app =
+        TestApp::with_config(|config| config.email_client.sends_per_second = Some(10.0)).await;
+    let server = app.get_server().await;
+    let pool = app.get_db_conn();
 
-    test::call_service(&server, req).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, status)
+        SELECT gen_random_uuid(), 'subscriber' || n || '@example.com', 'Subscriber ' || n, 'confirmed'
+        FROM generate_series(1, 2) AS n
+        "#
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, unsubscribe_token)
+        SELECT $1, id, 'confirmed', id::text
+        FROM subscriptions
+        "#,
+        DEFAULT_LIST_ID,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(app.get_email_server())
+        .await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(MAX_SEND_ATTEMPTS as u64)
+        .mount(app.get_email_server())
+        .await;
+
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter content",
+            "html": "<p>Newsletter content</p>"
+        }
+    });
+    let (_, username, password) = add_test_user(pool).await;
+    let req = with_basic_auth(post_newsletter(&newsletter_body), &username, &password);
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+    app.wait_for_deliveries().await;
+
+    let deliveries =
+        sqlx::query!("SELECT status, attempts FROM issue_deliveries ORDER BY status DESC")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+    assert_eq!(deliveries.len(), 2);
+    assert_eq!(deliveries[0].status, "sent");
+    assert_eq!(deliveries[0].attempts, 1);
+    assert_eq!(deliveries[1].status, "failed");
+    assert_eq!(deliveries[1].attempts, MAX_SEND_ATTEMPTS);
+}
+
+#[actix_web::test]
+async fn newsletter_is_sent_to_large_lists_in_batches() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let pool = app.get_db_conn();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, status)
+        SELECT gen_random_uuid(), 'subscriber' || n || '@example.com', 'Subscriber ' || n, 'confirmed'
+        FROM generate_series(1, 120) AS n
+        "#
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, unsubscribe_token)
+        SELECT $1, id, 'confirmed', id::text
+        FROM subscriptions
+        "#,
+        DEFAULT_LIST_ID,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(app.get_email_server())
+        .await;
+
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Hello {{name}}",
+            "html": "<p>Hello {{name}}</p>"
+        }
+    });
+
+    let (_, username, password) = add_test_user(pool).await;
+    let req = with_basic_auth(post_newsletter(&newsletter_body), &username, &password);
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+    app.wait_for_deliveries().await;
+
+    let sent =
+        sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_deliveries WHERE status = 'sent'"#)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+    assert_eq!(sent.count, 120);
 }
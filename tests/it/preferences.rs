@@ -0,0 +1,226 @@
+use actix_web::{http::StatusCode, test};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::mailing_list::DEFAULT_LIST_ID;
+
+use crate::{
+    helpers::{
+        add_test_user, assert_is_redirect_to, create_confirmed_subscriber, create_list,
+        post_newsletter, with_basic_auth,
+    },
+    init::TestApp,
+};
+
+async fn preferences_token(app: &TestApp) -> String {
+    sqlx::query!("SELECT preferences_token FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .preferences_token
+}
+
+fn post_preferences(form: &[(&str, &str)]) -> actix_http::Request {
+    test::TestRequest::post()
+        .uri("/preferences")
+        .set_form(form)
+        .to_request()
+}
+
+#[actix_web::test]
+async fn the_preference_center_shows_the_current_preferences() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    let token = preferences_token(&app).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/preferences?token={}", token))
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(body.contains(r#"value="Testing tester""#));
+    assert!(body.contains(&format!(r#"name="list:{}" checked"#, DEFAULT_LIST_ID)));
+    assert!(body.contains(r#"<option value="every_issue" selected>"#));
+}
+
+#[actix_web::test]
+async fn an_unknown_token_is_rejected_with_a_401() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+
+    let req = test::TestRequest::get()
+        .uri("/preferences?token=unknown")
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let form = &[
+        ("token", "unknown"),
+        ("name", "Someone"),
+        ("frequency", "weekly"),
+        ("action", "save"),
+    ];
+    let resp = test::call_service(&server, post_preferences(form)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn saving_updates_the_name_frequency_and_lists() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    let other_list = create_list(
+        &app,
+        &serde_json::json!({"name": "Weekly", "sender_email": "weekly@example.com"}),
+    )
+    .await;
+    let token = preferences_token(&app).await;
+
+    // Leaving the default list unticked unsubscribes from it, and lists that do not exist are
+    // ignored.
+    let list_key = format!("list:{}", other_list);
+    let unknown_list_key = format!("list:{}", Uuid::new_v4());
+    let form = &[
+        ("token", token.as_str()),
+        ("name", "New name"),
+        ("frequency", "monthly"),
+        ("action", "save"),
+        (list_key.as_str(), "on"),
+        (unknown_list_key.as_str(), "on"),
+    ];
+    let resp = test::call_service(&server, post_preferences(form)).await;
+    assert_is_redirect_to(&resp, &format!("/preferences?token={}", token));
+
+    let subscriber = sqlx::query!("SELECT name, frequency FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(subscriber.name, "New name");
+    assert_eq!(subscriber.frequency, "monthly");
+    let memberships = sqlx::query!("SELECT list_id, status FROM list_memberships")
+        .fetch_all(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(memberships.len(), 2);
+    for membership in memberships {
+        let expected = if membership.list_id == other_list {
+            "confirmed"
+        } else {
+            "unsubscribed"
+        };
+        assert_eq!(membership.status, expected);
+    }
+    let consents = sqlx::query!(
+        "SELECT list_id, action, source FROM consent_records WHERE action = 'join_from_preferences'"
+    )
+    .fetch_all(app.get_db_conn())
+    .await
+    .unwrap();
+    assert_eq!(consents.len(), 1);
+    assert_eq!(consents[0].list_id, other_list);
+    assert_eq!(consents[0].source.as_deref(), Some("preferences"));
+}
+
+#[actix_web::test]
+async fn invalid_values_are_not_saved() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    let token = preferences_token(&app).await;
+    let list_key = format!("list:{}", DEFAULT_LIST_ID);
+
+    for (name, frequency) in [("   ", "weekly"), ("Valid", "hourly"), ("a<b", "weekly")] {
+        let form = &[
+            ("token", token.as_str()),
+            ("name", name),
+            ("frequency", frequency),
+            ("action", "save"),
+            (list_key.as_str(), "on"),
+        ];
+        let resp = test::call_service(&server, post_preferences(form)).await;
+        assert_is_redirect_to(&resp, &format!("/preferences?token={}", token));
+    }
+
+    let subscriber = sqlx::query!("SELECT name, frequency FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(subscriber.name, "Testing tester");
+    assert_eq!(subscriber.frequency, "every_issue");
+}
+
+#[actix_web::test]
+async fn unsubscribing_from_everything_leaves_every_list() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    let token = preferences_token(&app).await;
+
+    let form = &[
+        ("token", token.as_str()),
+        ("name", "Testing tester"),
+        ("frequency", "every_issue"),
+        ("action", "unsubscribe_all"),
+    ];
+    let resp = test::call_service(&server, post_preferences(form)).await;
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+
+    let statuses = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_all(app.get_db_conn())
+        .await
+        .unwrap();
+    assert!(statuses.iter().all(|r| r.status == "unsubscribed"));
+}
+
+#[actix_web::test]
+async fn issues_link_to_the_preference_center_and_respect_the_frequency() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let email_server = app.get_email_server();
+    create_confirmed_subscriber(&app).await;
+    let token = preferences_token(&app).await;
+    sqlx::query!("UPDATE subscriptions SET frequency = 'weekly'")
+        .execute(app.get_db_conn())
+        .await
+        .unwrap();
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(email_server)
+        .await;
+
+    for title in ["First", "Second"] {
+        let req = post_newsletter(&serde_json::json!({
+            "title": title,
+            "content": {
+                "text": "Manage: {{ preferences_url }}",
+                "html": "<p>Manage: {{ preferences_url }}</p>"
+            }
+        }));
+        let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        app.wait_for_deliveries().await;
+    }
+
+    // The weekly subscriber only receives the first of the two issues.
+    let email_request = email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text = body["Messages"][0]["TextPart"].as_str().unwrap();
+    assert!(text.contains(&format!("/preferences?token={}", token)));
+    assert!(body["Messages"][0]["Subject"]
+        .as_str()
+        .unwrap()
+        .contains("First"));
+}
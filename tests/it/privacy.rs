@@ -0,0 +1,333 @@
+use actix_web::{http::StatusCode, test};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::configuration::BotProtectionSettings;
+
+use crate::{
+    helpers::{
+        add_test_user, assert_is_redirect_to, create_confirmed_subscriber, get_confirmation_link,
+        post_subscription_request, send_issue, with_basic_auth,
+    },
+    init::TestApp,
+};
+
+/// Makes a self-service request for `test@testdomain.com` and returns the link emailed to it.
+async fn request_link(app: &TestApp, kind: &str) -> String {
+    let server = app.get_server().await;
+    let email_server = app.get_email_server();
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(email_server)
+        .await;
+
+    let req = test::TestRequest::post()
+        .uri("/privacy/requests")
+        .set_form([("email", "test@testdomain.com"), ("kind", kind)])
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_is_redirect_to(&resp, "/privacy");
+
+    let email_request = email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    get_confirmation_link(&email_request.body)
+}
+
+async fn admin_call(app: &TestApp, request: test::TestRequest) -> (StatusCode, serde_json::Value) {
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let resp = test::call_service(
+        &server,
+        with_basic_auth(request.to_request(), &username, &password),
+    )
+    .await;
+    let status = resp.status();
+    let body = test::read_body(resp).await;
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[actix_web::test]
+async fn subscribers_can_download_their_data() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    let link = request_link(&app, "access").await;
+
+    let resp = test::call_service(&server, test::TestRequest::get().uri(&link).to_request()).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bundle: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(bundle["email"], "test@testdomain.com");
+    assert_eq!(bundle["subscriber"]["name"], "Testing tester");
+    assert_eq!(bundle["memberships"][0]["status"], "confirmed");
+    assert_eq!(bundle["subscription_events"][0]["event_type"], "confirmed");
+}
+
+#[actix_web::test]
+async fn requests_for_unknown_or_invalid_addresses_send_nothing() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(app.get_email_server())
+        .await;
+
+    for email in ["nobody@example.com", "not-an-email"] {
+        let req = test::TestRequest::post()
+            .uri("/privacy/requests")
+            .set_form([("email", email), ("kind", "access")])
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_is_redirect_to(&resp, "/privacy");
+    }
+}
+
+#[actix_web::test]
+async fn an_address_gets_a_limited_number_of_links_a_day() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    for _ in 0..3 {
+        request_link(&app, "access").await;
+    }
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount_as_scoped(app.get_email_server())
+        .await;
+
+    let req = test::TestRequest::post()
+        .uri("/privacy/requests")
+        .set_form([("email", "TEST@testdomain.com"), ("kind", "erasure")])
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+
+    assert_is_redirect_to(&resp, "/privacy");
+}
+
+#[actix_web::test]
+async fn requests_filling_in_the_honeypot_send_nothing() {
+    let app = TestApp::with_config(|config| {
+        config.signup.bot_protection = BotProtectionSettings {
+            honeypot_field: Some("website".into()),
+            ..Default::default()
+        }
+    })
+    .await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    let resp = test::call_service(
+        &server,
+        test::TestRequest::get().uri("/privacy").to_request(),
+    )
+    .await;
+    let page = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(page.contains(r#"name="website""#));
+    assert!(page.contains(r#"name="form_token""#));
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount_as_scoped(app.get_email_server())
+        .await;
+
+    let req = test::TestRequest::post()
+        .uri("/privacy/requests")
+        .set_form([
+            ("email", "test@testdomain.com"),
+            ("kind", "access"),
+            ("website", "http://spam.example"),
+        ])
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+
+    assert_is_redirect_to(&resp, "/privacy");
+}
+
+#[actix_web::test]
+async fn erasure_removes_personal_data_but_keeps_aggregates() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    {
+        let _mock_guard = Mock::given(path("/v3.1/send"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount_as_scoped(app.get_email_server())
+            .await;
+        send_issue(&app, "Hello", false).await;
+    }
+    let link = request_link(&app, "erasure").await;
+
+    // Following the link only asks for confirmation.
+    let resp = test::call_service(&server, test::TestRequest::get().uri(&link).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let count = sqlx::query!(
+        "SELECT COUNT(*) AS \"count!\" FROM subscriptions WHERE email = 'test@testdomain.com'"
+    )
+    .fetch_one(app.get_db_conn())
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(count, 1);
+
+    let token = link.split("token=").nth(1).unwrap();
+    let erase = || {
+        test::TestRequest::post()
+            .uri("/privacy/erase")
+            .set_form([("token", token)])
+            .to_request()
+    };
+    let resp = test::call_service(&server, erase()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let subscribers = sqlx::query!("SELECT id, email, name, status FROM subscriptions")
+        .fetch_all(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+    let anonymous = &subscribers[0];
+    assert!(anonymous.email.ends_with("@erased.invalid"));
+    assert_eq!(anonymous.name, "");
+    assert_eq!(anonymous.status, "erased");
+    let deliveries =
+        sqlx::query!("SELECT subscriber_id, provider_message_id FROM issue_deliveries")
+            .fetch_all(app.get_db_conn())
+            .await
+            .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].subscriber_id, anonymous.id);
+    assert_eq!(deliveries[0].provider_message_id, None);
+    let memberships = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM list_memberships")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(memberships, 0);
+
+    // The link cannot be used twice.
+    let resp = test::call_service(&server, erase()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn tokens_only_grant_what_was_asked_for() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    let link = request_link(&app, "access").await;
+    let token = link.split("token=").nth(1).unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/privacy/erase")
+        .set_form([("token", token)])
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get()
+        .uri("/privacy/access?token=unknown")
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn admins_can_access_and_erase_the_data_of_an_address() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app).await;
+
+    let (status, bundle) = admin_call(
+        &app,
+        test::TestRequest::get().uri("/admin/privacy/access?email=test@testdomain.com"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(bundle["subscriber"]["name"], "Testing tester");
+
+    let req = test::TestRequest::post()
+        .uri("/admin/privacy/erase")
+        .set_json(serde_json::json!({ "email": "test@testdomain.com" }));
+    let (status, _) = admin_call(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    let audit = sqlx::query!("SELECT action, subscriber_id FROM admin_audit_log")
+        .fetch_all(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(audit.len(), 1);
+    assert_eq!(audit[0].action, "delete");
+    assert!(audit[0].subscriber_id.is_some());
+
+    let (status, _) = admin_call(
+        &app,
+        test::TestRequest::get().uri("/admin/privacy/access?email=test@testdomain.com"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let req = test::TestRequest::post()
+        .uri("/admin/privacy/erase")
+        .set_json(serde_json::json!({ "email": "test@testdomain.com" }));
+    let (status, _) = admin_call(&app, req).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn erased_addresses_stay_suppressed() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email_hash, email, reason)
+        VALUES (hash_email('test@testdomain.com'), 'test@testdomain.com', 'hard_bounce')
+        "#
+    )
+    .execute(app.get_db_conn())
+    .await
+    .unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/admin/privacy/erase")
+        .set_json(serde_json::json!({ "email": "Test@TestDomain.com" }));
+    let (status, _) = admin_call(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    let suppression = sqlx::query!("SELECT email, reason FROM suppressions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(suppression.email, None);
+    assert_eq!(suppression.reason, "hard_bounce");
+
+    Mock::given(path("/v3.1/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(app.get_email_server())
+        .await;
+    let form = &[("email", "test@testdomain.com"), ("name", "Testing tester")];
+    let resp = test::call_service(&server, post_subscription_request(form)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn admin_privacy_endpoints_require_authentication() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+
+    let req = test::TestRequest::get()
+        .uri("/admin/privacy/access?email=test@testdomain.com")
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,318 @@
+use actix_web::{http::StatusCode, test};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::mailing_list::DEFAULT_LIST_ID;
+
+use crate::{
+    helpers::{add_test_user, post_newsletter, post_subscription_request, with_basic_auth},
+    init::TestApp,
+};
+
+/// Adds a subscriber confirmed on the default list, carrying `tags`.
+async fn add_confirmed_member(app: &TestApp, email: &str, tags: &[&str]) -> Uuid {
+    let pool = app.get_db_conn();
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, status) VALUES ($1, $2, 'Member', 'confirmed')",
+        subscriber_id,
+        email,
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, unsubscribe_token)
+        VALUES ($1, $2, 'confirmed', $3)
+        "#,
+        DEFAULT_LIST_ID,
+        subscriber_id,
+        subscriber_id.to_string(),
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    for tag in tags {
+        sqlx::query!(
+            "INSERT INTO subscriber_tags (subscriber_id, tag) VALUES ($1, $2)",
+            subscriber_id,
+            tag,
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+    subscriber_id
+}
+
+async fn admin_request(
+    app: &TestApp,
+    request: test::TestRequest,
+) -> actix_web::dev::ServiceResponse<tracing_actix_web::StreamSpan<actix_http::body::BoxBody>> {
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    test::call_service(
+        &server,
+        with_basic_auth(request.to_request(), &username, &password),
+    )
+    .await
+}
+
+async fn create_segment(app: &TestApp, filter: serde_json::Value) -> Uuid {
+    let req = test::TestRequest::post()
+        .uri("/admin/segments")
+        .set_json(serde_json::json!({ "name": "Segment", "filter": filter }));
+    let resp = admin_request(app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    body["segment_id"].as_str().unwrap().parse().unwrap()
+}
+
+/// Publishes an issue to `segment_id` and returns the addresses it was delivered to.
+async fn publish_to_segment(app: &TestApp, segment_id: Uuid) -> Vec<String> {
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(app.get_email_server())
+        .await;
+    let newsletter_body = serde_json::json!({
+        "segment_id": segment_id,
+        "title": "Newsletter title",
+        "content": { "text": "text", "html": "<p>html</p>" }
+    });
+    let req = with_basic_auth(post_newsletter(&newsletter_body), &username, &password);
+    let resp = test::call_service(&server, req).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    app.wait_for_deliveries().await;
+
+    sqlx::query!(
+        r#"
+        SELECT s.email
+        FROM issue_deliveries d
+        JOIN subscriptions s ON s.id = d.subscriber_id
+        ORDER BY s.email
+        "#
+    )
+    .fetch_all(app.get_db_conn())
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.email)
+    .collect()
+}
+
+#[actix_web::test]
+async fn tags_sent_with_the_signup_form_are_stored() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(app.get_email_server())
+        .await;
+
+    let form = &[
+        ("email", "test@testdomain.com"),
+        ("name", "Testing tester"),
+        ("tags", "Landing-Page, beta"),
+    ];
+    let resp = test::call_service(&server, post_subscription_request(form)).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let tags: Vec<_> = sqlx::query!("SELECT tag FROM subscriber_tags ORDER BY tag")
+        .fetch_all(app.get_db_conn())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.tag)
+        .collect();
+    assert_eq!(tags, vec!["beta", "landing-page"]);
+}
+
+#[actix_web::test]
+async fn signup_with_an_invalid_tag_is_rejected_with_400() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+
+    let form = &[
+        ("email", "test@testdomain.com"),
+        ("name", "Testing tester"),
+        ("tags", "not a tag"),
+    ];
+    let resp = test::call_service(&server, post_subscription_request(form)).await;
+
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn admins_can_tag_and_untag_subscribers() {
+    let app = TestApp::new().await;
+    let subscriber_id = add_confirmed_member(&app, "member@example.com", &["vip"]).await;
+    let tags_uri = format!("/admin/subscribers/{}/tags", subscriber_id);
+
+    let req = test::TestRequest::post()
+        .uri(&tags_uri)
+        .set_json(serde_json::json!({ "tags": ["Beta", "vip"] }));
+    assert_eq!(admin_request(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::delete().uri(&format!("{}/vip", tags_uri));
+    assert_eq!(admin_request(&app, req).await.status(), StatusCode::OK);
+
+    let resp = admin_request(&app, test::TestRequest::get().uri(&tags_uri)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["tags"], serde_json::json!(["beta"]));
+}
+
+#[actix_web::test]
+async fn tagging_an_unknown_subscriber_returns_404() {
+    let app = TestApp::new().await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/subscribers/{}/tags", Uuid::new_v4()))
+        .set_json(serde_json::json!({ "tags": ["vip"] }));
+    let resp = admin_request(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn invalid_segments_are_rejected_with_400() {
+    let app = TestApp::new().await;
+
+    let test_cases = vec![
+        (
+            serde_json::json!({ "include_tags": ["not valid"] }),
+            "an invalid tag",
+        ),
+        (
+            serde_json::json!({ "opened_within_days": 0 }),
+            "an empty window",
+        ),
+        (
+            serde_json::json!({ "unknown": true }),
+            "an unknown condition",
+        ),
+        (
+            serde_json::json!({
+                "subscribed_after": "2024-02-01T00:00:00Z",
+                "subscribed_before": "2024-01-01T00:00:00Z"
+            }),
+            "an empty range",
+        ),
+    ];
+    for (filter, description) in test_cases {
+        let req = test::TestRequest::post()
+            .uri("/admin/segments")
+            .set_json(serde_json::json!({ "name": "Segment", "filter": filter }));
+        let resp = admin_request(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", description);
+    }
+}
+
+#[actix_web::test]
+async fn admins_can_list_saved_segments() {
+    let app = TestApp::new().await;
+    let segment_id = create_segment(&app, serde_json::json!({ "include_tags": ["VIP"] })).await;
+
+    let resp = admin_request(&app, test::TestRequest::get().uri("/admin/segments")).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body[0]["segment_id"], segment_id.to_string());
+    assert_eq!(
+        body[0]["filter"]["include_tags"],
+        serde_json::json!(["vip"])
+    );
+}
+
+#[actix_web::test]
+async fn issues_sent_to_a_segment_only_reach_matching_subscribers() {
+    let app = TestApp::new().await;
+    add_confirmed_member(&app, "vip@example.com", &["vip"]).await;
+    add_confirmed_member(&app, "beta@example.com", &["beta"]).await;
+    add_confirmed_member(&app, "churned-vip@example.com", &["vip", "churned"]).await;
+    add_confirmed_member(&app, "untagged@example.com", &[]).await;
+    let segment_id = create_segment(
+        &app,
+        serde_json::json!({ "include_tags": ["vip", "beta"], "exclude_tags": ["churned"] }),
+    )
+    .await;
+
+    let recipients = publish_to_segment(&app, segment_id).await;
+
+    assert_eq!(recipients, vec!["beta@example.com", "vip@example.com"]);
+}
+
+#[actix_web::test]
+async fn segments_can_select_recently_engaged_subscribers() {
+    let app = TestApp::new().await;
+    let engaged = add_confirmed_member(&app, "engaged@example.com", &[]).await;
+    add_confirmed_member(&app, "idle@example.com", &[]).await;
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, list_id, title, text_content, html_content, slug, published_at
+        )
+        VALUES ($1, $2, 'Earlier issue', 'text', 'html', 'earlier-issue', NOW())
+        "#,
+        issue_id,
+        DEFAULT_LIST_ID,
+    )
+    .execute(app.get_db_conn())
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (newsletter_issue_id, subscriber_id, status, last_opened_at)
+        VALUES ($1, $2, 'sent', NOW() - INTERVAL '2 days')
+        "#,
+        issue_id,
+        engaged,
+    )
+    .execute(app.get_db_conn())
+    .await
+    .unwrap();
+    let segment_id = create_segment(&app, serde_json::json!({ "opened_within_days": 7 })).await;
+
+    publish_to_segment(&app, segment_id).await;
+
+    let recipients: Vec<_> = sqlx::query!(
+        r#"
+        SELECT s.email
+        FROM issue_deliveries d
+        JOIN subscriptions s ON s.id = d.subscriber_id
+        WHERE d.newsletter_issue_id <> $1
+        "#,
+        issue_id,
+    )
+    .fetch_all(app.get_db_conn())
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.email)
+    .collect();
+    assert_eq!(recipients, vec!["engaged@example.com"]);
+}
+
+#[actix_web::test]
+async fn publishing_to_an_unknown_segment_is_rejected_with_400() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+
+    let newsletter_body = serde_json::json!({
+        "segment_id": Uuid::new_v4(),
+        "title": "Newsletter title",
+        "content": { "text": "text", "html": "<p>html</p>" }
+    });
+    let req = with_basic_auth(post_newsletter(&newsletter_body), &username, &password);
+    let resp = test::call_service(&server, req).await;
+
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
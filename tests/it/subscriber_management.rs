@@ -0,0 +1,350 @@
+use actix_http::header;
+use actix_web::{http::StatusCode, test};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{
+        add_test_user, assert_is_redirect_to, create_unconfirmed_subscriber, get_confirmation_link,
+        with_basic_auth,
+    },
+    init::TestApp,
+};
+
+async fn admin_call(app: &TestApp, request: test::TestRequest) -> (StatusCode, String) {
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let resp = test::call_service(
+        &server,
+        with_basic_auth(request.to_request(), &username, &password),
+    )
+    .await;
+    let status = resp.status();
+    let body = test::read_body(resp).await;
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+async fn admin_json(app: &TestApp, request: test::TestRequest) -> (StatusCode, serde_json::Value) {
+    let (status, body) = admin_call(app, request).await;
+    (status, serde_json::from_str(&body).unwrap_or_default())
+}
+
+async fn get_subscriber_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = 'test@testdomain.com'")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .id
+}
+
+/// Imports Ann, Bob and Carla as confirmed subscribers.
+async fn import_subscribers(app: &TestApp) {
+    let req = test::TestRequest::post()
+        .uri("/admin/subscribers/import?mode=confirmed&consent=Tests")
+        .set_payload(
+            "email,name\nann@example.com,Ann Smith\nbob@example.com,Bob\n\
+            carla@example.com,Carla 100%\n",
+        );
+    let (status, _) = admin_call(app, req).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn subscribers_are_listed_a_page_at_a_time() {
+    let app = TestApp::new().await;
+    import_subscribers(&app).await;
+    create_unconfirmed_subscriber(&app).await;
+
+    let (status, body) = admin_json(
+        &app,
+        test::TestRequest::get().uri("/admin/subscribers?per_page=3"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["total"], 4);
+    assert_eq!(body["subscribers"].as_array().unwrap().len(), 3);
+    // Most recent first.
+    assert_eq!(body["subscribers"][0]["email"], "test@testdomain.com");
+
+    let (_, body) = admin_json(
+        &app,
+        test::TestRequest::get().uri("/admin/subscribers?per_page=3&page=2"),
+    )
+    .await;
+    assert_eq!(body["page"], 2);
+    assert_eq!(body["subscribers"].as_array().unwrap().len(), 1);
+
+    let (status, _) = admin_json(
+        &app,
+        test::TestRequest::get().uri("/admin/subscribers?page=0"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn subscribers_can_be_searched_and_filtered_by_status() {
+    let app = TestApp::new().await;
+    import_subscribers(&app).await;
+    create_unconfirmed_subscriber(&app).await;
+
+    for (query, expected) in [
+        ("search=SMITH", vec!["ann@example.com"]),
+        ("search=bob%40example", vec!["bob@example.com"]),
+        ("search=100%25", vec!["carla@example.com"]),
+        ("search=%25", vec!["carla@example.com"]),
+        ("status=pending_confirmation", vec!["test@testdomain.com"]),
+        ("search=example.com&status=pending_confirmation", vec![]),
+    ] {
+        let (status, body) = admin_json(
+            &app,
+            test::TestRequest::get().uri(&format!("/admin/subscribers?{}", query)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let emails: Vec<_> = body["subscribers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["email"].as_str().unwrap().to_owned())
+            .collect();
+        assert_eq!(emails, expected, "searching for {}", query);
+    }
+
+    let (status, _) = admin_json(
+        &app,
+        test::TestRequest::get().uri("/admin/subscribers?status=active"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn subscribers_have_html_pages() {
+    let app = TestApp::new().await;
+    import_subscribers(&app).await;
+    let ann = sqlx::query!("SELECT id FROM subscriptions WHERE email = 'ann@example.com'")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .id;
+
+    let (status, page) = admin_call(
+        &app,
+        test::TestRequest::get().uri("/admin/subscribers?format=html&search=ann"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains(&format!("/admin/subscribers/{}?format=html", ann)));
+    assert!(!page.contains("bob@example.com"));
+
+    let (status, page) = admin_call(
+        &app,
+        test::TestRequest::get().uri(&format!("/admin/subscribers/{}?format=html", ann)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains("ann@example.com"));
+    assert!(page.contains(&format!(
+        r#"action="/admin/subscribers/{}/unsubscribe?format=html""#,
+        ann
+    )));
+}
+
+#[actix_web::test]
+async fn admins_can_confirm_subscribers_by_hand() {
+    let app = TestApp::new().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    let confirm =
+        || test::TestRequest::post().uri(&format!("/admin/subscribers/{}/confirm", subscriber_id));
+
+    let (status, body) = admin_json(&app, confirm()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["lists"].as_array().unwrap().len(), 1);
+    let (status, _) = admin_json(&app, confirm()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, subscriber) = admin_json(
+        &app,
+        test::TestRequest::get().uri(&format!("/admin/subscribers/{}", subscriber_id)),
+    )
+    .await;
+    assert_eq!(subscriber["status"], "confirmed");
+    assert_eq!(subscriber["memberships"][0]["status"], "confirmed");
+    let events: Vec<_> = subscriber["history"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(events, ["subscribed", "confirmed", "admin_confirm"]);
+
+    let (_, log) = admin_json(&app, test::TestRequest::get().uri("/admin/audit_log")).await;
+    let entries = log["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["action"], "confirm");
+    assert_eq!(entries[0]["subscriber_id"], subscriber_id.to_string());
+}
+
+#[actix_web::test]
+async fn admins_can_unsubscribe_subscribers_from_a_page() {
+    let app = TestApp::new().await;
+    import_subscribers(&app).await;
+    let ann = sqlx::query!("SELECT id FROM subscriptions WHERE email = 'ann@example.com'")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .id;
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/subscribers/{}/unsubscribe?format=html",
+            ann
+        ))
+        .insert_header((header::ORIGIN, "http://127.0.0.1"))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+
+    assert_is_redirect_to(&resp, &format!("/admin/subscribers/{}?format=html", ann));
+    let status = sqlx::query!("SELECT status FROM subscriptions WHERE id = $1", ann)
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "unsubscribed");
+}
+
+#[actix_web::test]
+async fn actions_posted_from_other_sites_are_rejected() {
+    let app = TestApp::new().await;
+    import_subscribers(&app).await;
+    let ann = sqlx::query!("SELECT id FROM subscriptions WHERE email = 'ann@example.com'")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .id;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/subscribers/{}/delete?format=html", ann))
+        .insert_header((header::ORIGIN, "https://evil.example"));
+    let (status, _) = admin_call(&app, req).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let count = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM admin_audit_log")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
+
+#[actix_web::test]
+async fn admin_changes_posted_from_other_sites_are_rejected() {
+    let app = TestApp::new().await;
+    let issue_id = Uuid::new_v4();
+
+    for uri in [
+        format!("/admin/newsletters/{}/publish", issue_id),
+        format!("/admin/newsletters/{}/cancel", issue_id),
+        "/admin/subscribers/import?mode=confirmed&consent=Invented".to_string(),
+    ] {
+        let req = test::TestRequest::post()
+            .uri(&uri)
+            .insert_header((header::ORIGIN, "https://evil.example"))
+            .set_payload("email,name\nann@example.com,Ann Smith\n");
+        let (status, _) = admin_call(&app, req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+    }
+    let count = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
+
+#[actix_web::test]
+async fn confirmation_emails_can_be_sent_again() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    let email_server = app.get_email_server();
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(email_server)
+        .await;
+
+    let req = test::TestRequest::post().uri(&format!(
+        "/admin/subscribers/{}/resend_confirmation",
+        subscriber_id
+    ));
+    let (status, _) = admin_json(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let email_request = email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let link = get_confirmation_link(&email_request.body);
+    let resp = test::call_service(&server, test::TestRequest::get().uri(&link).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let req = test::TestRequest::post().uri(&format!(
+        "/admin/subscribers/{}/resend_confirmation",
+        subscriber_id
+    ));
+    let (status, _) = admin_json(&app, req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn deleted_subscribers_are_erased() {
+    let app = TestApp::new().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+
+    let req =
+        test::TestRequest::post().uri(&format!("/admin/subscribers/{}/delete", subscriber_id));
+    let (status, _) = admin_json(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = admin_json(
+        &app,
+        test::TestRequest::get().uri(&format!("/admin/subscribers/{}", subscriber_id)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, body) = admin_json(&app, test::TestRequest::get().uri("/admin/subscribers")).await;
+    assert_eq!(body["total"], 0);
+    let (_, log) = admin_json(
+        &app,
+        test::TestRequest::get().uri(&format!("/admin/audit_log?subscriber_id={}", subscriber_id)),
+    )
+    .await;
+    assert_eq!(log["entries"][0]["action"], "delete");
+}
+
+#[actix_web::test]
+async fn subscriber_management_requires_authentication() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+
+    for req in [
+        test::TestRequest::get().uri("/admin/subscribers"),
+        test::TestRequest::post().uri(&format!("/admin/subscribers/{}/delete", Uuid::new_v4())),
+        test::TestRequest::get().uri("/admin/audit_log"),
+    ] {
+        let resp = test::call_service(&server, req.to_request()).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    helpers::{get_confirmation_link, post_subscription_request},
+    helpers::{create_confirmed_subscriber, get_confirmation_link, post_subscription_request},
     init::TestApp,
 };
 use actix_web::{http::StatusCode, test};
@@ -179,4 +179,149 @@ async fn test_subscribe_fails_if_there_is_a_fatal_database_error() {
     assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
 }
 
-// TODO: Add test for duplicate email
+#[actix_web::test]
+async fn test_subscribing_twice_while_pending_sends_another_confirmation() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let email_server = app.get_email_server();
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(email_server)
+        .await;
+
+    let form = &[("email", "test@testdomain.com"), ("name", "Testing tester")];
+    for _ in 0..2 {
+        let resp = test::call_service(&server, post_subscription_request(form)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    let saved = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(saved.count, 1);
+}
+
+#[actix_web::test]
+async fn test_an_address_gets_a_limited_number_of_confirmations_a_day() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(app.get_email_server())
+        .await;
+
+    let form = &[("email", "test@testdomain.com"), ("name", "Testing tester")];
+    for _ in 0..5 {
+        let resp = test::call_service(&server, post_subscription_request(form)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
+
+#[actix_web::test]
+async fn test_subscribing_again_once_confirmed_sends_no_email() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app).await;
+    let server = app.get_server().await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(app.get_email_server())
+        .await;
+
+    let form = &[("email", "test@testdomain.com"), ("name", "Testing tester")];
+    let resp = test::call_service(&server, post_subscription_request(form)).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[actix_web::test]
+async fn test_addresses_differing_only_by_case_are_the_same_subscriber() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(app.get_email_server())
+        .await;
+
+    for email in ["Test@TestDomain.com", " test@testdomain.COM "] {
+        let form = &[("email", email), ("name", "Testing tester")];
+        let resp = test::call_service(&server, post_subscription_request(form)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    // The address is kept as first typed, but for the domain.
+    assert_eq!(saved[0].email, "Test@testdomain.com");
+}
+
+#[actix_web::test]
+async fn test_subscribe_rejects_blocked_domains_and_role_addresses() {
+    let blocked_domains =
+        std::env::temp_dir().join(format!("blocked-{}.txt", uuid::Uuid::new_v4()));
+    std::fs::write(&blocked_domains, "# Throwaway domains\nmailinator.com\n").unwrap();
+    let app = TestApp::with_config(|config| {
+        config.signup.blocked_domains_file = Some(blocked_domains.clone());
+        config.signup.reject_role_addresses = true;
+    })
+    .await;
+    let server = app.get_server().await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(app.get_email_server())
+        .await;
+
+    for (email, explanation) in [
+        (
+            "someone@Mailinator.com",
+            "Addresses at mailinator.com cannot subscribe",
+        ),
+        (
+            "noreply@testdomain.com",
+            "noreply@ addresses cannot subscribe",
+        ),
+    ] {
+        let form = &[("email", email), ("name", "Testing tester")];
+        let resp = test::call_service(&server, post_subscription_request(form)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(
+            body.contains(explanation),
+            "{} was rejected with {}",
+            email,
+            body
+        );
+    }
+
+    // The list is read again once it changes.
+    std::fs::write(&blocked_domains, "example.com\n").unwrap();
+    let form = &[
+        ("email", "someone@mailinator.com"),
+        ("name", "Testing tester"),
+    ];
+    let resp = test::call_service(&server, post_subscription_request(form)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
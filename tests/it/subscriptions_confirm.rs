@@ -6,7 +6,10 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::{helpers::get_confirmation_link, helpers::post_subscription_request, init::TestApp};
+use crate::{
+    helpers::{create_unconfirmed_subscriber, get_confirmation_link, post_subscription_request},
+    init::TestApp,
+};
 
 #[actix_web::test]
 async fn confirmations_without_token_are_rejected_with_400() {
@@ -95,3 +98,57 @@ async fn clicking_on_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "Testing tester");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[actix_web::test]
+async fn an_old_confirmation_link_does_not_bring_back_a_subscription_that_was_left() {
+    let app = TestApp::new().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+    let server = app.get_server().await;
+    let req = test::TestRequest::get()
+        .uri(&confirmation_link)
+        .to_request();
+    test::call_service(&server, req).await;
+
+    let token = sqlx::query!("SELECT unsubscribe_token FROM list_memberships")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap()
+        .unsubscribe_token;
+    let req = test::TestRequest::post()
+        .uri(&format!("/subscriptions/unsubscribe?token={}", token))
+        .to_request();
+    test::call_service(&server, req).await;
+
+    let req = test::TestRequest::get()
+        .uri(&confirmation_link)
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[actix_web::test]
+async fn following_the_confirmation_link_twice_succeeds_both_times() {
+    let app = TestApp::new().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+    let server = app.get_server().await;
+
+    for _ in 0..2 {
+        let req = test::TestRequest::get()
+            .uri(&confirmation_link)
+            .to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    let saved = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
@@ -0,0 +1,240 @@
+use actix_web::{http::StatusCode, test};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{add_test_user, create_confirmed_subscriber, post_newsletter, with_basic_auth},
+    init::TestApp,
+};
+
+/// Publishes an issue to a single confirmed subscriber and returns the message they received.
+async fn publish_issue(app: &TestApp, track_opens: bool, track_clicks: bool) -> serde_json::Value {
+    let server = app.get_server().await;
+    create_confirmed_subscriber(app).await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(app.get_email_server())
+        .await;
+
+    let req = post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter content, see https://example.com/post",
+            "html": "<html><body><p>Newsletter <a href=\"https://example.com/post\">content</a></p></body></html>"
+        },
+        "track_opens": track_opens,
+        "track_clicks": track_clicks
+    }));
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    app.wait_for_deliveries().await;
+
+    let requests = app.get_email_server().received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    body["Messages"][0].clone()
+}
+
+fn html_part(message: &serde_json::Value) -> String {
+    message["HTMLPart"].as_str().unwrap().to_owned()
+}
+
+fn get_tracking_path(body: &str, prefix: &str) -> String {
+    let links: Vec<_> = linkify::LinkFinder::new()
+        .links(body)
+        .filter(|l| *l.kind() == linkify::LinkKind::Url)
+        .filter(|l| l.as_str().contains(prefix))
+        .collect();
+    assert_eq!(links.len(), 1);
+    let url = reqwest::Url::parse(links[0].as_str()).unwrap();
+    url.path().to_owned()
+}
+
+#[actix_web::test]
+async fn tracked_issues_embed_a_pixel_that_does_not_identify_the_subscriber() {
+    let app = TestApp::new().await;
+
+    let html = html_part(&publish_issue(&app, true, false).await);
+
+    let pixel_path = get_tracking_path(&html, "/t/o/");
+    assert!(html.ends_with("</body></html>"));
+    assert!(!pixel_path.contains("test@testdomain.com"));
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert!(!pixel_path.contains(&subscriber.id.to_string()));
+}
+
+#[actix_web::test]
+async fn untracked_issues_do_not_embed_a_pixel() {
+    let app = TestApp::new().await;
+
+    let html = html_part(&publish_issue(&app, false, false).await);
+
+    assert_eq!(
+        html,
+        "<html><body><p>Newsletter <a href=\"https://example.com/post\">content</a></p></body></html>"
+    );
+}
+
+#[actix_web::test]
+async fn opens_are_recorded_once_per_recipient_with_first_and_last_times() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let pixel_path =
+        get_tracking_path(&html_part(&publish_issue(&app, true, false).await), "/t/o/");
+
+    for _ in 0..2 {
+        let req = test::TestRequest::get().uri(&pixel_path).to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get("content-type").unwrap(), "image/gif");
+    }
+
+    let delivery =
+        sqlx::query!("SELECT first_opened_at, last_opened_at, open_count FROM issue_deliveries")
+            .fetch_one(app.get_db_conn())
+            .await
+            .unwrap();
+    assert_eq!(delivery.open_count, 2);
+    assert!(delivery.first_opened_at.unwrap() < delivery.last_opened_at.unwrap());
+}
+
+#[actix_web::test]
+async fn unknown_tokens_still_return_the_pixel() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+
+    let req = test::TestRequest::get()
+        .uri("/t/o/not-a-real-token")
+        .to_request();
+    let resp = test::call_service(&server, req).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("content-type").unwrap(), "image/gif");
+}
+
+#[actix_web::test]
+async fn the_report_includes_open_counts() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let pixel_path =
+        get_tracking_path(&html_part(&publish_issue(&app, true, false).await), "/t/o/");
+    for _ in 0..3 {
+        let req = test::TestRequest::get().uri(&pixel_path).to_request();
+        test::call_service(&server, req).await;
+    }
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let issue = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/admin/newsletters/{}/report",
+            issue.newsletter_issue_id
+        ))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["opens"]["unique"], 1);
+    assert_eq!(body["opens"]["total"], 3);
+}
+
+#[actix_web::test]
+async fn tracked_links_redirect_to_the_original_url_and_record_the_click() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let message = publish_issue(&app, false, true).await;
+    let html_click_path = get_tracking_path(&html_part(&message), "/t/c/");
+    let text_click_path = get_tracking_path(message["TextPart"].as_str().unwrap(), "/t/c/");
+    assert_eq!(html_click_path, text_click_path);
+
+    let req = test::TestRequest::get().uri(&html_click_path).to_request();
+    let resp = test::call_service(&server, req).await;
+
+    assert_eq!(resp.status(), StatusCode::FOUND);
+    assert_eq!(
+        resp.headers().get("location").unwrap(),
+        "https://example.com/post"
+    );
+    let clicks = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM link_clicks")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(clicks.count, 1);
+}
+
+#[actix_web::test]
+async fn links_are_left_untouched_when_click_tracking_is_off() {
+    let app = TestApp::new().await;
+
+    let message = publish_issue(&app, false, false).await;
+
+    assert_eq!(
+        message["TextPart"],
+        "Newsletter content, see https://example.com/post"
+    );
+    assert!(!html_part(&message).contains("/t/c/"));
+}
+
+#[actix_web::test]
+async fn tampered_click_tokens_are_rejected() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let message = publish_issue(&app, false, true).await;
+    let click_path = get_tracking_path(&html_part(&message), "/t/c/");
+    let (payload, _) = click_path.rsplit_once('.').unwrap();
+
+    let test_cases = vec![
+        format!("{}.{}", payload, "00".repeat(32)),
+        format!("{}.not-hex", payload),
+        "/t/c/https%3A%2F%2Fevil.example.com".to_owned(),
+    ];
+    for uri in test_cases {
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let resp = test::call_service(&server, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn the_report_includes_per_link_click_counts() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+    let message = publish_issue(&app, false, true).await;
+    let click_path = get_tracking_path(&html_part(&message), "/t/c/");
+    for _ in 0..2 {
+        let req = test::TestRequest::get().uri(&click_path).to_request();
+        test::call_service(&server, req).await;
+    }
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let issue = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/admin/newsletters/{}/report",
+            issue.newsletter_issue_id
+        ))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, &username, &password)).await;
+
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["clicks"]["unique"], 1);
+    assert_eq!(body["clicks"]["total"], 2);
+    assert_eq!(
+        body["clicks"]["links"],
+        serde_json::json!([{"url": "https://example.com/post", "unique": 1, "total": 2}])
+    );
+}
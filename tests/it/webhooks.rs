@@ -0,0 +1,272 @@
+use actix_web::{http::StatusCode, test};
+use secrecy::ExposeSecret;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{
+        add_test_user, create_confirmed_subscriber, post_newsletter, post_subscription_request,
+        with_basic_auth,
+    },
+    init::TestApp,
+};
+
+async fn post_email_events(app: &TestApp, body: serde_json::Value) -> StatusCode {
+    let server = app.get_server().await;
+    let credentials = app.get_webhook_credentials();
+    let req = test::TestRequest::post()
+        .uri("/webhooks/email-events")
+        .set_json(body)
+        .to_request();
+    let req = with_basic_auth(
+        req,
+        &credentials.username,
+        credentials.password.expose_secret(),
+    );
+    test::call_service(&server, req).await.status()
+}
+
+fn event(event_type: &str, hard_bounce: bool) -> serde_json::Value {
+    serde_json::json!({
+        "event": event_type,
+        "time": 1433333949,
+        "MessageID": 42,
+        "email": "test@testdomain.com",
+        "hard_bounce": hard_bounce,
+        "error_related_to": "recipient",
+        "error": "user unknown"
+    })
+}
+
+async fn publish_newsletter(app: &TestApp) -> StatusCode {
+    let server = app.get_server().await;
+    let (_, username, password) = add_test_user(app.get_db_conn()).await;
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter content",
+            "html": "<p>Newsletter content</p>"
+        }
+    });
+    let req = with_basic_auth(post_newsletter(&newsletter_body), &username, &password);
+    let status = test::call_service(&server, req).await.status();
+    app.wait_for_deliveries().await;
+    status
+}
+
+#[actix_web::test]
+async fn email_events_require_the_webhook_credentials() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+
+    let req = test::TestRequest::post()
+        .uri("/webhooks/email-events")
+        .set_json(event("bounce", true))
+        .to_request();
+    let resp = test::call_service(&server, with_basic_auth(req, "mailjet", "wrong")).await;
+
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        resp.headers().get("WWW-Authenticate").unwrap(),
+        r#"Basic realm="webhooks""#
+    );
+}
+
+#[actix_web::test]
+async fn malformed_email_events_are_rejected_with_400() {
+    let app = TestApp::new().await;
+
+    let test_cases = vec![
+        (serde_json::json!("not an event"), "not an object"),
+        (
+            serde_json::json!({"event": "bounce"}),
+            "missing email and time",
+        ),
+    ];
+
+    for (body, error) in test_cases {
+        assert_eq!(
+            post_email_events(&app, body).await,
+            StatusCode::BAD_REQUEST,
+            "The webhook did not return with 400 Bad request for {}",
+            error
+        );
+    }
+}
+
+#[actix_web::test]
+async fn malformed_events_in_a_batch_are_skipped_and_the_others_applied() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app).await;
+
+    let events = serde_json::json!([{"time": 1}, event("spam", false), "not an event"]);
+    assert_eq!(post_email_events(&app, events).await, StatusCode::OK);
+
+    let recorded = sqlx::query!("SELECT event_type FROM email_events")
+        .fetch_all(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0].event_type, "spam");
+    let suppression = sqlx::query!("SELECT reason FROM suppressions")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(suppression.reason, "spam_complaint");
+}
+
+#[actix_web::test]
+async fn email_events_are_recorded_per_subscriber() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app).await;
+
+    let events = serde_json::json!([event("bounce", false), event("unsub", false)]);
+    assert_eq!(post_email_events(&app, events).await, StatusCode::OK);
+
+    let recorded = sqlx::query!(
+        r#"
+        SELECT e.event_type, e.provider_message_id, s.email
+        FROM email_events e
+        JOIN subscriptions s ON s.id = e.subscriber_id
+        ORDER BY e.event_type
+        "#
+    )
+    .fetch_all(app.get_db_conn())
+    .await
+    .unwrap();
+    assert_eq!(recorded.len(), 2);
+    assert_eq!(recorded[0].event_type, "bounce");
+    assert_eq!(recorded[0].provider_message_id.as_deref(), Some("42"));
+    assert_eq!(recorded[0].email, "test@testdomain.com");
+    assert_eq!(recorded[1].event_type, "unsub");
+}
+
+#[actix_web::test]
+async fn hard_bounces_and_complaints_are_suppressed_but_soft_bounces_are_not() {
+    let test_cases = vec![
+        (event("bounce", true), true, "a hard bounce"),
+        (event("spam", false), true, "a spam complaint"),
+        (event("bounce", false), false, "a soft bounce"),
+        (event("blocked", false), false, "a blocked message"),
+    ];
+
+    for (event, suppressed, description) in test_cases {
+        let app = TestApp::new().await;
+        create_confirmed_subscriber(&app).await;
+        assert_eq!(post_email_events(&app, event).await, StatusCode::OK);
+
+        Mock::given(path("/v3.1/send"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(if suppressed { 0 } else { 1 })
+            .named(description)
+            .mount(app.get_email_server())
+            .await;
+
+        assert_eq!(publish_newsletter(&app).await, StatusCode::ACCEPTED);
+    }
+}
+
+#[actix_web::test]
+async fn bounces_mark_the_matching_delivery_as_bounced() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app).await;
+
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Messages": [{"Status": "success", "To": [{"Email": "test@testdomain.com", "MessageID": 42}]}]
+        })))
+        .mount_as_scoped(app.get_email_server())
+        .await;
+    assert_eq!(publish_newsletter(&app).await, StatusCode::ACCEPTED);
+
+    assert_eq!(
+        post_email_events(&app, event("bounce", true)).await,
+        StatusCode::OK
+    );
+
+    let delivery = sqlx::query!("SELECT status FROM issue_deliveries")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "bounced");
+}
+
+#[actix_web::test]
+async fn confirmation_emails_are_not_sent_to_suppressed_addresses() {
+    let app = TestApp::new().await;
+    let server = app.get_server().await;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email_hash, email, reason)
+        VALUES (hash_email('test@testdomain.com'), 'test@testdomain.com', 'hard_bounce')
+        "#
+    )
+    .execute(app.get_db_conn())
+    .await
+    .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(app.get_email_server())
+        .await;
+
+    let form = &[("email", "Test@TestDomain.com"), ("name", "Testing tester")];
+    let resp = test::call_service(&server, post_subscription_request(form)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn provider_unsubscribes_stop_deliveries_and_are_attributed_to_the_issue() {
+    let app = TestApp::new().await;
+    create_confirmed_subscriber(&app).await;
+
+    let mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Messages": [{"Status": "success", "To": [{"Email": "test@testdomain.com", "MessageID": 42}]}]
+        })))
+        .expect(1)
+        .mount_as_scoped(app.get_email_server())
+        .await;
+    assert_eq!(publish_newsletter(&app).await, StatusCode::ACCEPTED);
+    drop(mock_guard);
+
+    assert_eq!(
+        post_email_events(&app, event("unsub", false)).await,
+        StatusCode::OK
+    );
+
+    let recorded = sqlx::query!(
+        r#"
+        SELECT s.status, e.newsletter_issue_id
+        FROM subscription_events e
+        JOIN subscriptions s ON s.id = e.subscriber_id
+        WHERE e.event_type = 'unsubscribed'
+        "#
+    )
+    .fetch_one(app.get_db_conn())
+    .await
+    .unwrap();
+    let issue = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(app.get_db_conn())
+        .await
+        .unwrap();
+    assert_eq!(recorded.status, "unsubscribed");
+    assert_eq!(
+        recorded.newsletter_issue_id,
+        Some(issue.newsletter_issue_id)
+    );
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(app.get_email_server())
+        .await;
+    assert_eq!(publish_newsletter(&app).await, StatusCode::ACCEPTED);
+}